@@ -0,0 +1,346 @@
+//! Async supervision on the tokio runtime, enabled by the `tokio` feature.
+
+use crate::capture::AsyncStreamCapture;
+use crate::command::{TimeoutClock, TimeoutCommand, TimeoutOptions, exit_reason};
+use crate::error::TimeoutError;
+use crate::events::{Delivery, Event, EventDispatcher};
+use crate::outcome::{SentSignal, TimeoutOutcome, TimeoutReason};
+use crate::signal::Signal;
+use crate::trace;
+use std::ffi::OsStr;
+use std::io;
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::time::{Instant, sleep, sleep_until};
+
+/// Run `program` with `args` under `options` on the current tokio runtime.
+///
+/// This is the async counterpart of [`run_with_timeout`](crate::run_with_timeout): deadlines
+/// are tracked with tokio timers and the command is reaped through `tokio::process`, so no
+/// threads are spawned. The command is killed if the returned future is dropped early.
+///
+/// ```
+/// use std::time::Duration;
+/// use timeout_cli::{TimeoutOptions, TimeoutResult, run_with_timeout_async};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let options = TimeoutOptions {
+///     timeout: Some(Duration::from_millis(100)),
+///     ..Default::default()
+/// };
+/// let outcome = run_with_timeout_async("sleep", ["5"], &options).await;
+/// assert_eq!(outcome.result, TimeoutResult::TimedOut);
+/// # }
+/// ```
+pub async fn run_with_timeout_async<P, I, S>(
+    program: P,
+    args: I,
+    options: &TimeoutOptions,
+) -> TimeoutOutcome
+where
+    P: AsRef<OsStr>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let start = Instant::now();
+    let verbose = options.verbose;
+
+    if let Err(e) = options.validate() {
+        debug_print!(verbose, "Invalid options: {}", e);
+        return TimeoutOutcome::failed(&e, start.elapsed());
+    }
+    if options.wait_for_group {
+        let e = TimeoutError::Unsupported(
+            "waiting for a process group is not supported by the async API",
+        );
+        return TimeoutOutcome::failed(&e, start.elapsed());
+    }
+    if options.new_session {
+        let e =
+            TimeoutError::Unsupported("starting a new session is not supported by the async API");
+        return TimeoutOutcome::failed(&e, start.elapsed());
+    }
+
+    let mut command = TimeoutCommand::new(program);
+    command.args(args).options(options.clone());
+    if command.get_program().to_string_lossy().trim().is_empty() {
+        return TimeoutOutcome::failed(&TimeoutError::EmptyCommand, start.elapsed());
+    }
+    let mut cmd = Command::from(command.build_command());
+    cmd.kill_on_drop(true);
+
+    let mut child = match trace::in_spawn_span(command.get_program(), || cmd.spawn()) {
+        Ok(child) => child,
+        Err(e) => {
+            debug_print!(verbose, "Failed to spawn command: {}", e);
+            let e = TimeoutError::from_spawn(command.get_program(), e);
+            return TimeoutOutcome::failed(&e, start.elapsed());
+        }
+    };
+    let mut events = EventDispatcher::start_async(options.events.clone());
+    if let Some(pid) = child.id() {
+        events.emit(Event::Spawn(pid));
+    }
+
+    let limit = options.capture_limit;
+    let stdout = child
+        .stdout
+        .take()
+        .map(|stdout| AsyncStreamCapture::start(stdout, limit));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|stderr| AsyncStreamCapture::start(stderr, limit));
+
+    let mut outcome = supervise(&mut child, options, start, &events)
+        .await
+        .unwrap_or_else(|e| {
+            debug_print!(verbose, "Error waiting for child: {}", e);
+            TimeoutOutcome::new(
+                TimeoutReason::SupervisionFailed { kind: e.kind() },
+                start.elapsed(),
+            )
+        });
+    events.emit(Event::Exit(outcome.result, outcome.elapsed));
+    let grace = options.timings.drain_grace;
+    events.finish_async(grace).await;
+
+    outcome.stdout = finish(stdout, grace).await;
+    outcome.stderr = finish(stderr, grace).await;
+    outcome
+}
+
+async fn finish(capture: Option<AsyncStreamCapture>, grace: Duration) -> Vec<u8> {
+    match capture {
+        Some(capture) => capture.finish(grace).await,
+        None => Vec::new(),
+    }
+}
+
+async fn supervise(
+    child: &mut Child,
+    options: &TimeoutOptions,
+    start: Instant,
+    events: &EventDispatcher,
+) -> io::Result<TimeoutOutcome> {
+    let verbose = options.verbose;
+    let term_deadline = match (options.clock, options.timeout) {
+        (TimeoutClock::Wall, Some(timeout)) => Some(start + timeout),
+        _ => None,
+    };
+    // Set once the timeout signal has gone out, so the kill-after period runs from then.
+    let mut kill_deadline = None;
+    let mut forwarded = ForwardedSignals::new(options.forward_signals)?;
+    let mut signal_sent = None;
+
+    loop {
+        tokio::select! {
+            status = child.wait() => {
+                let status = status?;
+                debug_print!(
+                    verbose,
+                    "Command exited: {}, term_sent: {}",
+                    status,
+                    signal_sent.is_some()
+                );
+                let reason = match signal_sent {
+                    Some(_) => TimeoutReason::TermExit,
+                    None => exit_reason(status, options),
+                };
+                return Ok(outcome(reason, Some(status), signal_sent, start));
+            }
+            _ = sleep_until_deadline(term_deadline), if signal_sent.is_none() => {
+                if let (Some(hook), Some(pid)) = (options.on_timeout.clone(), child.id()) {
+                    debug_print!(verbose, "Running timeout hook before signalling");
+                    let elapsed = start.elapsed();
+                    let _ = tokio::task::spawn_blocking(move || hook.call(pid, elapsed)).await;
+                }
+                let delivery = send_timeout_signal(child, options);
+                let sent_at = Instant::now();
+                kill_deadline = options.kill_after.map(|kill_after| sent_at + kill_after);
+                let elapsed = sent_at - start;
+                events.emit(Event::Signal(options.signal, elapsed));
+                events.emit(Event::Delivery(delivery));
+                debug_print!(verbose, "{} sent: {:?}", options.signal, delivery);
+                signal_sent = Some(SentSignal {
+                    signal: options.signal,
+                    elapsed,
+                });
+
+                if options.kill_after.is_none() {
+                    debug_print!(
+                        verbose,
+                        "No kill-after specified, waiting {:?} then killing",
+                        options.timings.term_grace
+                    );
+                    sleep(options.timings.term_grace).await;
+                    if let Ok(None) = child.try_wait() {
+                        events.emit(Event::Kill(start.elapsed()));
+                        let _ = child.kill().await;
+                    }
+                    let status = child.wait().await.ok();
+                    return Ok(outcome(TimeoutReason::TermExit, status, signal_sent, start));
+                }
+            }
+            _ = sleep_until_deadline(kill_deadline) => {
+                debug_print!(verbose, "Kill-after timeout reached");
+                let signal = options.kill_signal.unwrap_or(Signal::Kill);
+                let elapsed = start.elapsed();
+                events.emit(match signal {
+                    Signal::Kill => Event::Kill(elapsed),
+                    signal => Event::Signal(signal, elapsed),
+                });
+                let status = kill(child, options, signal, events).await;
+                let signal_sent = signal_sent.or(Some(SentSignal { signal, elapsed }));
+                return Ok(outcome(TimeoutReason::Killed, status, signal_sent, start));
+            }
+            signal = forwarded.recv() => {
+                debug_print!(verbose, "Forwarding {} to command", signal);
+                let _ = send(child, signal);
+            }
+        }
+    }
+}
+
+/// Send `SIGKILL`, or the escalation `signal`, repeating it as configured until the command is
+/// reaped.
+async fn kill(
+    child: &mut Child,
+    options: &TimeoutOptions,
+    signal: Signal,
+    events: &EventDispatcher,
+) -> Option<ExitStatus> {
+    let send_kill = |child: &mut Child| {
+        let sent = match signal {
+            Signal::Kill => child.start_kill(),
+            signal => send(child, signal),
+        };
+        events.emit(Event::Delivery(Delivery::new(signal, false, &sent)));
+    };
+    send_kill(child);
+    for attempt in 1..=options.kill_retries {
+        if let Ok(status) =
+            tokio::time::timeout(options.timings.kill_retry_interval, child.wait()).await
+        {
+            return status.ok();
+        }
+        debug_print!(
+            options.verbose,
+            "Command still running, retrying {} signal ({}/{})",
+            signal.name(),
+            attempt,
+            options.kill_retries
+        );
+        send_kill(child);
+    }
+    child.wait().await.ok()
+}
+
+/// Assemble the outcome of a command that has been reaped, without its output.
+fn outcome(
+    reason: TimeoutReason,
+    status: Option<ExitStatus>,
+    signal_sent: Option<SentSignal>,
+    start: Instant,
+) -> TimeoutOutcome {
+    let mut outcome = TimeoutOutcome::new(reason, start.elapsed());
+    if let Some(status) = status {
+        outcome = outcome.with_status(status);
+    }
+    outcome.signal_sent = signal_sent;
+    outcome
+}
+
+/// Sleep until `deadline`, or forever if there is none.
+async fn sleep_until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Send the timeout signal. On Windows a command in a process group of its own is asked to
+/// stop with `CTRL_BREAK_EVENT`, leaving terminating it to the kill-after escalation.
+fn send_timeout_signal(child: &mut Child, options: &TimeoutOptions) -> Delivery {
+    #[cfg(windows)]
+    if options.signals_group()
+        && options.signal != Signal::Kill
+        && let Some(pid) = child.id()
+        && crate::signal::request_stop(pid).is_ok()
+    {
+        return Delivery::new(options.signal, true, &Ok(()));
+    }
+    Delivery::new(options.signal, false, &send(child, options.signal))
+}
+
+fn send(child: &mut Child, signal: Signal) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        match child.id() {
+            Some(pid) => crate::signal::send_to_pid(pid, signal),
+            None => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = signal;
+        child.start_kill()
+    }
+}
+
+/// Signals received by this process that should be passed on to the command.
+struct ForwardedSignals {
+    #[cfg(unix)]
+    streams: Vec<(Signal, tokio::signal::unix::Signal)>,
+    #[cfg(not(unix))]
+    enabled: bool,
+}
+
+impl ForwardedSignals {
+    #[cfg(unix)]
+    fn new(enabled: bool) -> io::Result<Self> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut streams = Vec::new();
+        if enabled {
+            for (forwarded, kind) in [
+                (Signal::Int, SignalKind::interrupt()),
+                (Signal::Term, SignalKind::terminate()),
+                (Signal::Hup, SignalKind::hangup()),
+            ] {
+                streams.push((forwarded, signal(kind)?));
+            }
+        }
+        Ok(Self { streams })
+    }
+
+    #[cfg(not(unix))]
+    fn new(enabled: bool) -> io::Result<Self> {
+        Ok(Self { enabled })
+    }
+
+    /// Wait for the next forwarded signal.
+    #[cfg(unix)]
+    async fn recv(&mut self) -> Signal {
+        std::future::poll_fn(|cx| {
+            for (forwarded, stream) in &mut self.streams {
+                if stream.poll_recv(cx).is_ready() {
+                    return std::task::Poll::Ready(*forwarded);
+                }
+            }
+            std::task::Poll::Pending
+        })
+        .await
+    }
+
+    /// Wait for the next forwarded signal.
+    #[cfg(not(unix))]
+    async fn recv(&mut self) -> Signal {
+        if !self.enabled || tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        Signal::Int
+    }
+}
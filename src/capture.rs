@@ -0,0 +1,151 @@
+//! Collecting the command's stdout and stderr instead of inheriting them.
+
+use std::io::Read;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long to keep draining a captured stream, and to wait for event sinks to work through
+/// queued events, after the command has been reaped, unless
+/// [`Timings::drain_grace`](crate::Timings::drain_grace) says otherwise.
+///
+/// Once the command is gone its output is already sitting in the pipe, so this only matters
+/// when a background grandchild inherited the pipe and keeps it open.
+pub(crate) const DRAIN_GRACE: Duration = Duration::from_millis(200);
+
+/// What to connect the command's stdout and stderr to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// Share this process's stdout and stderr.
+    #[default]
+    Inherit,
+    /// Collect the output into [`TimeoutOutcome::stdout`](crate::TimeoutOutcome::stdout) and
+    /// [`TimeoutOutcome::stderr`](crate::TimeoutOutcome::stderr).
+    Piped,
+    /// Discard the output.
+    Null,
+    /// Pipe the output and leave reading it to the caller through
+    /// [`TimeoutChild::take_stdout`](crate::TimeoutChild::take_stdout) and
+    /// [`TimeoutChild::take_stderr`](crate::TimeoutChild::take_stderr). The one-shot
+    /// `run_with_timeout` functions have no child to hand out, so they treat this like
+    /// [`Piped`](Self::Piped).
+    Manual,
+}
+
+impl CaptureMode {
+    pub(crate) fn stdio(self) -> Stdio {
+        match self {
+            CaptureMode::Inherit => Stdio::inherit(),
+            CaptureMode::Piped | CaptureMode::Manual => Stdio::piped(),
+            CaptureMode::Null => Stdio::null(),
+        }
+    }
+}
+
+/// Append `chunk` to `buffer` without letting it grow past `limit`.
+fn append_limited(buffer: &Mutex<Vec<u8>>, chunk: &[u8], limit: Option<usize>) {
+    let mut buffer = buffer.lock().unwrap_or_else(|e| e.into_inner());
+    let room = limit.map_or(chunk.len(), |limit| limit.saturating_sub(buffer.len()));
+    buffer.extend_from_slice(&chunk[..room.min(chunk.len())]);
+}
+
+/// A stream being drained into memory by a reader thread.
+///
+/// The thread keeps reading past `limit` so the command never blocks on a full pipe, it just
+/// stops storing what it reads.
+pub(crate) struct StreamCapture {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    done: mpsc::Receiver<()>,
+}
+
+impl StreamCapture {
+    pub(crate) fn start<R: Read + Send + 'static>(mut reader: R, limit: Option<usize>) -> Self {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let (tx, done) = mpsc::channel();
+        let thread_buffer = buffer.clone();
+        thread::spawn(move || {
+            let mut chunk = [0u8; 8192];
+            loop {
+                match reader.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => append_limited(&thread_buffer, &chunk[..n], limit),
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+            }
+            let _ = tx.send(());
+        });
+        Self { buffer, done }
+    }
+
+    /// Wait up to `grace` for the stream to reach EOF and return whatever was collected.
+    pub(crate) fn finish(self, grace: Duration) -> Vec<u8> {
+        let _ = self.done.recv_timeout(grace);
+        let mut buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        std::mem::take(&mut *buffer)
+    }
+}
+
+/// The async counterpart of [`StreamCapture`], draining the stream on a tokio task.
+#[cfg(feature = "tokio")]
+pub(crate) struct AsyncStreamCapture {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncStreamCapture {
+    pub(crate) fn start<R>(mut reader: R, limit: Option<usize>) -> Self
+    where
+        R: tokio::io::AsyncRead + Unpin + Send + 'static,
+    {
+        use tokio::io::AsyncReadExt;
+
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let task_buffer = buffer.clone();
+        let task = tokio::spawn(async move {
+            let mut chunk = [0u8; 8192];
+            while let Ok(n) = reader.read(&mut chunk).await {
+                if n == 0 {
+                    break;
+                }
+                append_limited(&task_buffer, &chunk[..n], limit);
+            }
+        });
+        Self { buffer, task }
+    }
+
+    /// Wait up to `grace` for the stream to reach EOF and return whatever was collected.
+    pub(crate) async fn finish(self, grace: Duration) -> Vec<u8> {
+        let mut task = self.task;
+        if tokio::time::timeout(grace, &mut task).await.is_err() {
+            task.abort();
+        }
+        let mut buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        std::mem::take(&mut *buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_limited_truncates_at_limit() {
+        let buffer = Mutex::new(Vec::new());
+        append_limited(&buffer, b"hello", Some(8));
+        append_limited(&buffer, b"world", Some(8));
+        append_limited(&buffer, b"again", Some(8));
+        assert_eq!(buffer.into_inner().unwrap(), b"hellowor");
+    }
+
+    #[test]
+    fn test_append_limited_without_limit_keeps_everything() {
+        let buffer = Mutex::new(Vec::new());
+        append_limited(&buffer, b"hello", None);
+        append_limited(&buffer, b"world", None);
+        assert_eq!(buffer.into_inner().unwrap(), b"helloworld");
+    }
+}
@@ -0,0 +1,50 @@
+//! Replacing files so that readers never see them half-written.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Atomically replace `path` with `contents`, by writing a temporary file beside it and
+/// renaming it into place.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(temp_name);
+
+    let result = fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    match result.and_then(|()| fs::rename(&temp, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_replaces_existing_file() {
+        let path = std::env::temp_dir().join(format!("timeout-atomic-{}", std::process::id()));
+        fs::write(&path, "stale").unwrap();
+        write_atomic(&path, b"fresh").unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "fresh");
+    }
+
+    #[test]
+    fn test_write_atomic_into_missing_directory_fails() {
+        assert!(write_atomic(Path::new("/nonexistent/dir/file"), b"").is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! `--from-file` and `--stdin-commands`: running a list of commands, one after another or
+//! `-P` at a time, each with its own timeout.
+
+use super::exit_code::EXIT_TIMEOUT;
+use std::io::{self, BufRead};
+use std::time::Duration;
+
+/// One command from the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// The line it is on, counting from 1, or with NUL-delimited commands its position.
+    pub line: usize,
+    /// The line as written.
+    pub text: String,
+    /// The program and its arguments, or why the line could not be split into them.
+    pub argv: Result<Vec<String>, String>,
+}
+
+/// Reads commands one at a time as they are needed, skipping blank lines and `#` comments,
+/// so that a long list, or one still being written, is never held in memory whole. Each line
+/// is split into words the way a shell would split it, without expanding anything, or, with
+/// `shell`, handed to the shell as it is.
+pub struct Jobs<R> {
+    reader: R,
+    delimiter: u8,
+    shell: bool,
+    line: usize,
+}
+
+impl<R: BufRead> Jobs<R> {
+    /// Commands from `reader`, each ending with `delimiter`: a newline, or NUL for commands
+    /// that may contain newlines themselves.
+    pub fn new(reader: R, delimiter: u8, shell: bool) -> Self {
+        Self {
+            reader,
+            delimiter,
+            shell,
+            line: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Jobs<R> {
+    type Item = io::Result<Job>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut record = Vec::new();
+            match self.reader.read_until(self.delimiter, &mut record) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            self.line += 1;
+            if record.last() == Some(&self.delimiter) {
+                record.pop();
+            }
+            let text = String::from_utf8_lossy(&record);
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            let argv = if self.shell {
+                Ok(shell_argv(text))
+            } else {
+                shell_words::split(text).map_err(|e| e.to_string())
+            };
+            return Some(Ok(Job {
+                line: self.line,
+                text: text.to_string(),
+                argv,
+            }));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn shell_argv(text: &str) -> Vec<String> {
+    vec!["sh".to_string(), "-c".to_string(), text.to_string()]
+}
+
+#[cfg(not(unix))]
+fn shell_argv(text: &str) -> Vec<String> {
+    vec!["cmd".to_string(), "/C".to_string(), text.to_string()]
+}
+
+/// The line printed on stderr after each command, with `source` naming the option the
+/// commands came from.
+pub fn result_line(source: &str, job: &Job, elapsed: Duration, exit_code: u8) -> String {
+    format!(
+        "timeout: {}: line {}, {:.3}s, exit {}: {}",
+        source,
+        job.line,
+        elapsed.as_secs_f64(),
+        exit_code,
+        job.text
+    )
+}
+
+/// What the commands came to between them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub timed_out: usize,
+    /// The highest exit code seen.
+    worst: u8,
+}
+
+impl BatchSummary {
+    /// Count a command that timeout would have exited from with `exit_code`.
+    pub fn record(&mut self, exit_code: u8) {
+        match exit_code {
+            0 => self.succeeded += 1,
+            EXIT_TIMEOUT => self.timed_out += 1,
+            _ => self.failed += 1,
+        }
+        self.worst = self.worst.max(exit_code);
+    }
+
+    /// 0 if every command succeeded, 124 if any timed out, and otherwise the highest exit code.
+    pub fn exit_code(&self) -> u8 {
+        if self.timed_out > 0 {
+            EXIT_TIMEOUT
+        } else {
+            self.worst
+        }
+    }
+
+    /// The summary printed on stderr once every command has finished.
+    pub fn line(&self, source: &str) -> String {
+        format!(
+            "timeout: {}: {} commands, {} succeeded, {} failed, {} timed out",
+            source,
+            self.succeeded + self.failed + self.timed_out,
+            self.succeeded,
+            self.failed,
+            self.timed_out
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jobs(input: &str, delimiter: u8, shell: bool) -> Vec<Job> {
+        Jobs::new(input.as_bytes(), delimiter, shell)
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_jobs_splits_lines_and_skips_comments() {
+        let jobs = jobs(
+            "# nightly jobs\n\necho 'a b' c\n  sleep 1  \necho \"unterminated\n",
+            b'\n',
+            false,
+        );
+        let argv: Vec<_> = jobs
+            .iter()
+            .map(|job| (job.line, job.argv.clone()))
+            .collect();
+        assert_eq!(
+            argv,
+            [
+                (3, Ok(vec!["echo".into(), "a b".into(), "c".into()])),
+                (4, Ok(vec!["sleep".into(), "1".into()])),
+                (5, Err("missing closing quote".into())),
+            ]
+        );
+        assert_eq!(jobs[1].text, "sleep 1");
+    }
+
+    #[test]
+    fn test_jobs_split_on_nul() {
+        let jobs = jobs("printf 'a\nb'\0\0echo c", b'\0', false);
+        let argv: Vec<_> = jobs.into_iter().map(|job| (job.line, job.argv)).collect();
+        assert_eq!(
+            argv,
+            [
+                (1, Ok(vec!["printf".into(), "a\nb".into()])),
+                (3, Ok(vec!["echo".into(), "c".into()])),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_jobs_with_shell() {
+        let jobs = jobs("echo $HOME | wc -c\n", b'\n', true);
+        assert_eq!(
+            jobs[0].argv,
+            Ok(vec!["sh".into(), "-c".into(), "echo $HOME | wc -c".into()])
+        );
+    }
+
+    #[test]
+    fn test_result_line() {
+        let job = jobs("sleep 60", b'\n', false).remove(0);
+        assert_eq!(
+            result_line("from-file", &job, Duration::from_millis(30_001), 124),
+            "timeout: from-file: line 1, 30.001s, exit 124: sleep 60"
+        );
+    }
+
+    #[test]
+    fn test_summary_prefers_timeouts_then_the_highest_code() {
+        let mut summary = BatchSummary::default();
+        summary.record(0);
+        assert_eq!(summary.exit_code(), 0);
+        summary.record(127);
+        summary.record(2);
+        assert_eq!(summary.exit_code(), 127);
+        summary.record(124);
+        assert_eq!(summary.exit_code(), 124);
+        assert_eq!(
+            summary.line("from-file"),
+            "timeout: from-file: 4 commands, 1 succeeded, 2 failed, 1 timed out"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! `--color`: ANSI colouring of timeout's own messages on stderr.
+//!
+//! Only what timeout itself prints is coloured; the command's output is passed through
+//! untouched.
+
+use clap::ValueEnum;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// When to colour messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Only when stderr is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// The colours messages are painted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// Errors, timeouts and kills.
+    Red,
+    /// Warnings.
+    Yellow,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Decide once, at startup, whether messages are coloured.
+pub fn init(choice: ColorChoice) {
+    let enabled = match choice {
+        ColorChoice::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stderr().is_terminal()
+        }
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// `text` wrapped in the escape sequences for `color`, or unchanged if colour is off.
+pub fn paint(color: Color, text: &str) -> String {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    let code = match color {
+        Color::Red => "31",
+        Color::Yellow => "33",
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+/// Print an error message, prefixed with `timeout:`, to stderr, clear of any countdown line.
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::cli::countdown::above(|| {
+            eprintln!(
+                "{}",
+                $crate::cli::color::paint(
+                    $crate::cli::color::Color::Red,
+                    &format!("timeout: {}", format_args!($($arg)*)),
+                )
+            )
+        })
+    };
+}
+
+/// Print a warning, prefixed with `timeout: warning:`, to stderr, clear of any countdown line.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::cli::countdown::above(|| {
+            eprintln!(
+                "{}",
+                $crate::cli::color::paint(
+                    $crate::cli::color::Color::Yellow,
+                    &format!("timeout: warning: {}", format_args!($($arg)*)),
+                )
+            )
+        })
+    };
+}
+
+pub(crate) use {error, warning};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paint_follows_choice() {
+        init(ColorChoice::Always);
+        assert_eq!(paint(Color::Red, "timed out"), "\x1b[31mtimed out\x1b[0m");
+        assert_eq!(paint(Color::Yellow, "careful"), "\x1b[33mcareful\x1b[0m");
+        init(ColorChoice::Never);
+        assert_eq!(paint(Color::Red, "timed out"), "timed out");
+    }
+}
@@ -0,0 +1,299 @@
+//! Default options from the environment and a `.timeoutrc` file.
+//!
+//! Each long option can be given a default as a `TIMEOUT_<OPTION>` environment variable
+//! (`TIMEOUT_KILL_AFTER=5`) or as a key in a TOML file (`kill-after = 5`), read from
+//! `--config` or else `.timeoutrc` in the current directory. An option given on the command
+//! line wins over the environment, which wins over the file, which wins over the built-in
+//! default.
+
+use super::color::warning;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, Command};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The file read from the current directory when `--config` is not given.
+pub const DEFAULT_CONFIG_FILE: &str = ".timeoutrc";
+
+/// Prefix of the environment variables that supply defaults.
+pub const ENV_PREFIX: &str = "TIMEOUT_";
+
+/// Options that only make sense on the command line.
+const COMMAND_LINE_ONLY: [&str; 5] = ["config", "debug-info", "exec", "help", "version"];
+
+/// `argv` with the defaults from the environment and config file inserted ahead of the user's
+/// own arguments, for any option the command line leaves unset. If `argv` does not parse, it
+/// is returned as it is so that the error is reported as usual.
+pub fn with_defaults(command: Command, argv: Vec<OsString>) -> io::Result<Vec<OsString>> {
+    let Ok(matches) = command.clone().try_get_matches_from(&argv) else {
+        return Ok(argv);
+    };
+    // It takes nothing else, and reports the built-in defaults rather than the configured ones.
+    if let Ok(Some(true)) = matches.try_get_one::<bool>("debug_info") {
+        return Ok(argv);
+    }
+    let file = match matches.get_one::<PathBuf>("config") {
+        Some(path) => Some((path.clone(), read_table(path)?)),
+        None => match read_table(Path::new(DEFAULT_CONFIG_FILE)) {
+            Ok(table) => Some((PathBuf::from(DEFAULT_CONFIG_FILE), table)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        },
+    };
+
+    let configurable: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|arg| {
+            arg.get_long()
+                .is_some_and(|long| !COMMAND_LINE_ONLY.contains(&long))
+        })
+        .collect();
+    if let Some((path, table)) = &file {
+        for key in table.keys() {
+            if !configurable.iter().any(|arg| arg.get_long() == Some(key)) {
+                warning!(
+                    "ignoring unknown option '{}' in config file '{}'",
+                    key,
+                    path.display()
+                );
+            }
+        }
+    }
+
+    let given: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|arg| set_on_command_line(&matches, arg))
+        .collect();
+    let mut defaults = Vec::new();
+    for arg in configurable {
+        if given.contains(&arg) || conflicts_with_any(&command, arg, &given) {
+            continue;
+        }
+        let long = arg.get_long().unwrap_or_default();
+        if let Some(value) = std::env::var_os(env_name(long)) {
+            defaults.extend(env_args(arg, value));
+        } else if let Some((path, table)) = &file
+            && let Some(value) = table.get(long)
+        {
+            match file_args(arg, value) {
+                Some(args) => defaults.extend(args),
+                None => warning!(
+                    "ignoring invalid value for '{}' in config file '{}'",
+                    long,
+                    path.display()
+                ),
+            }
+        }
+    }
+
+    let mut argv = argv.into_iter();
+    Ok(argv
+        .next()
+        .into_iter()
+        .chain(defaults)
+        .chain(argv)
+        .collect())
+}
+
+/// The environment variable supplying a default for `--long`, e.g. `TIMEOUT_KILL_AFTER`.
+pub fn env_name(long: &str) -> String {
+    format!(
+        "{}{}",
+        ENV_PREFIX,
+        long.to_ascii_uppercase().replace('-', "_")
+    )
+}
+
+/// Read the config file at `path`, with errors naming it.
+fn read_table(path: &Path) -> io::Result<toml::Table> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("cannot read config file '{}': {}", path.display(), e),
+        )
+    })?;
+    contents.parse().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid config file '{}': {}", path.display(), e),
+        )
+    })
+}
+
+fn set_on_command_line(matches: &ArgMatches, arg: &Arg) -> bool {
+    matches!(
+        matches.value_source(arg.get_id().as_str()),
+        Some(ValueSource::CommandLine)
+    )
+}
+
+/// Whether `arg` and any of the `given` arguments cannot be used together, whichever of the two
+/// declares the conflict.
+fn conflicts_with_any(command: &Command, arg: &Arg, given: &[&Arg]) -> bool {
+    let conflicts = command.get_arg_conflicts_with(arg);
+    given.iter().any(|other| {
+        conflicts
+            .iter()
+            .any(|conflict| conflict.get_id() == other.get_id())
+            || command
+                .get_arg_conflicts_with(other)
+                .iter()
+                .any(|conflict| conflict.get_id() == arg.get_id())
+    })
+}
+
+/// The arguments for a flag or option from an environment variable. A flag is set by any
+/// value other than empty, `0` or `false`.
+fn env_args(arg: &Arg, value: OsString) -> Vec<OsString> {
+    let long = arg.get_long().unwrap_or_default();
+    if arg.get_action().takes_values() {
+        let mut option = OsString::from(format!("--{}=", long));
+        option.push(value);
+        vec![option]
+    } else if value.is_empty() || value == "0" || value.eq_ignore_ascii_case("false") {
+        Vec::new()
+    } else {
+        vec![OsString::from(format!("--{}", long))]
+    }
+}
+
+/// The arguments for a flag or option from a config file value, or `None` if the value does
+/// not suit it. Options that can be repeated take an array.
+fn file_args(arg: &Arg, value: &toml::Value) -> Option<Vec<OsString>> {
+    let long = arg.get_long().unwrap_or_default();
+    if !arg.get_action().takes_values() {
+        return match value {
+            toml::Value::Boolean(true) => Some(vec![OsString::from(format!("--{}", long))]),
+            toml::Value::Boolean(false) => Some(Vec::new()),
+            _ => None,
+        };
+    }
+    let values = match value {
+        toml::Value::Array(values) => values.iter().collect(),
+        value => vec![value],
+    };
+    values
+        .into_iter()
+        .map(|value| {
+            let value = match value {
+                toml::Value::String(value) => value.clone(),
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                _ => return None,
+            };
+            Some(OsString::from(format!("--{}={}", long, value)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> Command {
+        Command::new("timeout")
+            .arg(
+                Arg::new("config")
+                    .long("config")
+                    .value_parser(clap::value_parser!(PathBuf)),
+            )
+            .arg(Arg::new("kill_after").long("kill-after"))
+            .arg(
+                Arg::new("verbose")
+                    .long("verbose")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(Arg::new("tag").long("tag").action(clap::ArgAction::Append))
+            .arg(
+                Arg::new("race")
+                    .long("race")
+                    .action(clap::ArgAction::SetTrue)
+                    .conflicts_with("tag"),
+            )
+            .arg(Arg::new("seconds").required(true))
+    }
+
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("timeout-config-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn argv(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_file_supplies_unset_options() {
+        let path = config_file(
+            "unset",
+            "kill-after = 5\nverbose = true\ntag = [\"a\", \"b\"]\n",
+        );
+        let config = path.to_str().unwrap();
+        let resolved = with_defaults(command(), argv(&["timeout", "--config", config, "3"]));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            resolved.unwrap(),
+            argv(&[
+                "timeout",
+                "--kill-after=5",
+                "--verbose",
+                "--tag=a",
+                "--tag=b",
+                "--config",
+                config,
+                "3"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_command_line_overrides_file() {
+        let path = config_file("override", "kill-after = 5\nverbose = false\n");
+        let config = path.to_str().unwrap();
+        let args = ["timeout", "--config", config, "--kill-after", "1", "3"];
+        let resolved = with_defaults(command(), argv(&args));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(resolved.unwrap(), argv(&args));
+    }
+
+    #[test]
+    fn test_defaults_conflicting_with_the_command_line_are_skipped() {
+        let path = config_file("conflicts", "race = true\ntag = [\"a\"]\n");
+        let config = path.to_str().unwrap();
+        let race = ["timeout", "--config", config, "--race", "3"];
+        let resolved = with_defaults(command(), argv(&race));
+        assert_eq!(resolved.unwrap(), argv(&race));
+        let tag = ["timeout", "--config", config, "--tag", "b", "3"];
+        let resolved = with_defaults(command(), argv(&tag));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(resolved.unwrap(), argv(&tag));
+    }
+
+    #[test]
+    fn test_invalid_values_and_files() {
+        let path = config_file("invalid", "verbose = \"yes\"\nunknown = 1\n");
+        let config = path.to_str().unwrap();
+        let resolved = with_defaults(command(), argv(&["timeout", "--config", config, "3"]));
+        assert_eq!(
+            resolved.unwrap(),
+            argv(&["timeout", "--config", config, "3"])
+        );
+        fs::write(&path, "not toml").unwrap();
+        let resolved = with_defaults(command(), argv(&["timeout", "--config", config, "3"]));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(resolved.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let resolved = with_defaults(command(), argv(&["timeout", "--config", config, "3"]));
+        assert_eq!(resolved.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_env_name() {
+        assert_eq!(env_name("kill-after"), "TIMEOUT_KILL_AFTER");
+        assert_eq!(env_name("verbose"), "TIMEOUT_VERBOSE");
+    }
+}
@@ -0,0 +1,154 @@
+//! `--progress auto`: a single line on stderr, redrawn in place, counting down to the timeout
+//! while the command runs on a terminal.
+
+use super::duration::format_duration;
+use std::io::{self, IsTerminal, Write};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How often the line is redrawn.
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Moves back to the start of the line and erases it.
+const ERASE: &str = "\r\x1b[K";
+
+/// The countdown on screen, if one is drawn.
+static DRAWN: Mutex<Option<String>> = Mutex::new(None);
+
+/// The countdown shown after `elapsed`, cut down to fit a terminal `width` columns wide without
+/// wrapping.
+pub fn countdown_line(elapsed: Duration, timeout: Duration, pid: u32, width: usize) -> String {
+    let line = format!(
+        "timeout: {} left of {} ({} elapsed, pid {})",
+        format_duration(timeout.saturating_sub(elapsed)),
+        format_duration(timeout),
+        format_duration(elapsed),
+        pid
+    );
+    line.chars().take(width.saturating_sub(1)).collect()
+}
+
+/// Whether a countdown can be shown: only stderr on a terminal can have a line redrawn in
+/// place.
+pub fn available() -> bool {
+    io::stderr().is_terminal()
+}
+
+/// Run `print`, which writes whole lines to stderr, with the countdown erased first and drawn
+/// again afterwards, so that they do not end up on the same line.
+pub fn above<R>(print: impl FnOnce() -> R) -> R {
+    let drawn = lock();
+    if drawn.is_some() {
+        eprint!("{}", ERASE);
+    }
+    let result = print();
+    if let Some(line) = drawn.as_deref() {
+        eprint!("{}", line);
+    }
+    result
+}
+
+/// Keeps a [`countdown_line`] drawn at the bottom of stderr from a thread of its own, until
+/// dropped, when it is erased.
+#[derive(Debug)]
+pub struct Countdown {
+    running: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Countdown {
+    /// Start counting down to `timeout` for the command `pid`, started at `start`.
+    pub fn start(timeout: Duration, start: Instant, pid: u32) -> Self {
+        let (running, stopped) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let mut next = start;
+            loop {
+                let elapsed = start.elapsed();
+                if elapsed >= timeout {
+                    return;
+                }
+                // Drawing from the background would stop timeout with SIGTTOU, or scribble over
+                // whatever is in the foreground. The line comes back at the next redraw once
+                // timeout is resumed in the foreground.
+                if in_foreground() {
+                    let line = countdown_line(elapsed, timeout, pid, width());
+                    let mut drawn = lock();
+                    let _ = write!(io::stderr(), "{}{}", ERASE, line);
+                    *drawn = Some(line);
+                }
+                next += REDRAW_INTERVAL;
+                let wait = next.saturating_duration_since(Instant::now());
+                match stopped.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+            }
+        });
+        Self {
+            running: Some(running),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Countdown {
+    fn drop(&mut self) {
+        self.running.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        if lock().take().is_some() {
+            eprint!("{}", ERASE);
+        }
+    }
+}
+
+fn lock() -> MutexGuard<'static, Option<String>> {
+    DRAWN.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The width of the terminal stderr is on.
+fn width() -> usize {
+    #[cfg(unix)]
+    {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        // SAFETY: TIOCGWINSZ only writes a winsize to the pointer given.
+        let result = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) };
+        if result == 0 && size.ws_col > 0 {
+            return size.ws_col.into();
+        }
+    }
+    80
+}
+
+/// Whether timeout's process group is in the foreground of the terminal stderr is on.
+fn in_foreground() -> bool {
+    #[cfg(unix)]
+    {
+        // SAFETY: tcgetpgrp and getpgrp only look the groups up.
+        unsafe { libc::tcgetpgrp(libc::STDERR_FILENO) == libc::getpgrp() }
+    }
+    #[cfg(not(unix))]
+    {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_countdown_line() {
+        assert_eq!(
+            countdown_line(Duration::from_secs(30), Duration::from_secs(300), 4242, 80),
+            "timeout: 4m30s left of 5m0s (30.0s elapsed, pid 4242)"
+        );
+        assert_eq!(
+            countdown_line(Duration::from_secs(30), Duration::from_secs(300), 4242, 21),
+            "timeout: 4m30s left "
+        );
+    }
+}
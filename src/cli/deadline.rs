@@ -0,0 +1,61 @@
+//! `DEADLINE_EPOCH`: a deadline handed down by an outer timeout, so that commands nested inside
+//! it never outlive it.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The environment variable holding the deadline, in seconds since the Unix epoch.
+pub const DEADLINE_VAR: &str = "DEADLINE_EPOCH";
+
+/// Parse a deadline in seconds since the Unix epoch, such as `1723226400` or `1723226400.25`.
+pub fn parse_deadline_epoch(value: &str) -> Result<SystemTime, String> {
+    let seconds = value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .ok_or_else(|| format!("invalid {} '{}'", DEADLINE_VAR, value))?;
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .and_then(|since| UNIX_EPOCH.checked_add(since))
+        .ok_or_else(|| format!("{} '{}' is too far away", DEADLINE_VAR, value))
+}
+
+/// How long is left until `deadline`: nothing, once it has passed.
+pub fn remaining(deadline: SystemTime) -> Duration {
+    deadline
+        .duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO)
+}
+
+/// `at` as the value of [`DEADLINE_VAR`], to the millisecond.
+pub fn epoch(at: SystemTime) -> String {
+    let since = at.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+    format!("{}.{:03}", since.as_secs(), since.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_deadline_epoch() {
+        let at = |millis| Ok(UNIX_EPOCH + Duration::from_millis(millis));
+        assert_eq!(parse_deadline_epoch("1723226400"), at(1_723_226_400_000));
+        assert_eq!(
+            parse_deadline_epoch(" 1723226400.25\n"),
+            at(1_723_226_400_250)
+        );
+        assert!(parse_deadline_epoch("").is_err());
+        assert!(parse_deadline_epoch("soon").is_err());
+        assert!(parse_deadline_epoch("-5").is_err());
+        assert!(parse_deadline_epoch("inf").is_err());
+    }
+
+    #[test]
+    fn test_epoch_round_trips() {
+        let at = UNIX_EPOCH + Duration::from_millis(1_723_226_400_250);
+        assert_eq!(epoch(at), "1723226400.250");
+        assert_eq!(parse_deadline_epoch(&epoch(at)), Ok(at));
+        assert_eq!(remaining(UNIX_EPOCH), Duration::ZERO);
+    }
+}
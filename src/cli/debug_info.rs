@@ -0,0 +1,88 @@
+//! `--debug-info`: what timeout was built with and for, to paste into a bug report.
+//!
+//! Each line is `name: value`, and the names are kept as they are so support threads can be
+//! compared with each other.
+
+use std::time::Duration;
+use timeout_cli::{Signal, Timings};
+
+/// The report, given the grace the CLI's output relays get to drain once the command exits.
+pub fn debug_info(relay_drain_grace: Duration) -> String {
+    let timings = Timings::default();
+    let ms = |duration: Duration| format!("{}ms", duration.as_millis());
+    let signals = if cfg!(unix) {
+        "unix signals"
+    } else if cfg!(windows) {
+        "console control events for process groups, termination otherwise"
+    } else {
+        "termination only"
+    };
+    let features: Vec<&str> = [
+        ("tokio", cfg!(feature = "tokio")),
+        ("tracing", cfg!(feature = "tracing")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+    let rows = [
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        (
+            "platform",
+            format!(
+                "{} {} ({})",
+                std::env::consts::OS,
+                std::env::consts::ARCH,
+                std::env::consts::FAMILY
+            ),
+        ),
+        ("signal delivery", signals.to_string()),
+        ("default signal", Signal::default().to_string()),
+        ("poll interval", ms(timings.poll_interval)),
+        ("term grace", ms(timings.term_grace)),
+        ("kill retry interval", ms(timings.kill_retry_interval)),
+        ("drain grace", ms(timings.drain_grace)),
+        ("relay drain grace", ms(relay_drain_grace)),
+        (
+            "features",
+            if features.is_empty() {
+                "none".to_string()
+            } else {
+                features.join(", ")
+            },
+        ),
+    ];
+    rows.iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_info_names() {
+        let info = debug_info(Duration::from_millis(200));
+        let names: Vec<_> = info
+            .lines()
+            .map(|line| line.split_once(": ").unwrap().0)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "version",
+                "platform",
+                "signal delivery",
+                "default signal",
+                "poll interval",
+                "term grace",
+                "kill retry interval",
+                "drain grace",
+                "relay drain grace",
+                "features",
+            ]
+        );
+        assert!(info.contains("\nrelay drain grace: 200ms\n"));
+    }
+}
@@ -0,0 +1,202 @@
+//! `--notify`: a desktop notification when the command finishes.
+
+use super::duration::format_duration;
+use super::trigger::{Trigger, any_matches};
+use std::io;
+use std::time::Duration;
+use timeout_cli::{CaptureMode, TimeoutCommand, TimeoutOutcome, TimeoutResult, debug_print};
+
+/// How long the notification tool may take before it is sent `SIGTERM`.
+pub const NOTIFIER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Shows a desktop notification.
+pub trait Notifier {
+    /// Show a notification titled `summary` with `body` as its text.
+    fn notify(&self, summary: &str, body: &str) -> io::Result<()>;
+}
+
+/// Shows notifications with the platform's own tool: `osascript` on macOS, and elsewhere on
+/// Unix `notify-send`, which speaks the freedesktop notification protocol over D-Bus.
+///
+/// The tool never touches the terminal, so it works even when the command has taken it over.
+#[derive(Debug)]
+pub struct SystemNotifier;
+
+impl Notifier for SystemNotifier {
+    fn notify(&self, summary: &str, body: &str) -> io::Result<()> {
+        let mut command = notifier_command(summary, body)?;
+        command
+            .timeout(NOTIFIER_TIMEOUT)
+            .kill_after(Duration::from_secs(1))
+            .capture(CaptureMode::Null);
+        match command.spawn()?.wait()?.result {
+            TimeoutResult::Completed(0) => Ok(()),
+            result => Err(io::Error::other(format!(
+                "{} failed: {:?}",
+                command.get_program().to_string_lossy(),
+                result
+            ))),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn notifier_command(summary: &str, body: &str) -> io::Result<TimeoutCommand> {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut command = TimeoutCommand::new("osascript");
+    command.args([
+        "-e".to_string(),
+        format!(
+            "display notification {} with title {}",
+            quote(body),
+            quote(summary)
+        ),
+    ]);
+    Ok(command)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn notifier_command(summary: &str, body: &str) -> io::Result<TimeoutCommand> {
+    let mut command = TimeoutCommand::new("notify-send");
+    command.args(["--app-name=timeout", "--", summary, body]);
+    Ok(command)
+}
+
+#[cfg(not(unix))]
+fn notifier_command(_summary: &str, _body: &str) -> io::Result<TimeoutCommand> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "desktop notifications are only supported on Linux and macOS",
+    ))
+}
+
+/// If any of `triggers` matches `outcome`, tell the user how `command` ended through
+/// `notifier`. This is best-effort: failures are only reported in verbose mode.
+pub fn notify(
+    notifier: &dyn Notifier,
+    triggers: &[Trigger],
+    command: &str,
+    outcome: &TimeoutOutcome,
+    exit_code: u8,
+    verbose: bool,
+) {
+    if !any_matches(triggers, outcome) {
+        return;
+    }
+    let summary = format!("timeout: {}", command);
+    let body = describe(outcome, exit_code);
+    if let Err(e) = notifier.notify(&summary, &body) {
+        debug_print!(verbose, "Failed to show desktop notification: {}", e);
+    }
+}
+
+/// How the command ended, e.g. `command finished, exit 0, took 14m32s`.
+fn describe(outcome: &TimeoutOutcome, exit_code: u8) -> String {
+    let elapsed = format_duration(outcome.elapsed);
+    match outcome.result {
+        TimeoutResult::Completed(_) => {
+            format!("command finished, exit {}, took {}", exit_code, elapsed)
+        }
+        TimeoutResult::TimedOut => format!("command timed out after {}", elapsed),
+        TimeoutResult::Killed => format!("command timed out and was killed after {}", elapsed),
+        TimeoutResult::Cancelled => format!("command was cancelled after {}", elapsed),
+        TimeoutResult::Detached => format!("command left running after {}", elapsed),
+        TimeoutResult::NotFound | TimeoutResult::CannotInvoke | TimeoutResult::InternalError => {
+            format!("command could not be run, exit {}", exit_code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use timeout_cli::TimeoutReason;
+
+    /// Records notifications instead of showing them, optionally failing like a missing daemon.
+    #[derive(Default)]
+    struct MockNotifier {
+        shown: Mutex<Vec<(String, String)>>,
+        fail: bool,
+    }
+
+    impl Notifier for MockNotifier {
+        fn notify(&self, summary: &str, body: &str) -> io::Result<()> {
+            if self.fail {
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+            self.shown
+                .lock()
+                .unwrap()
+                .push((summary.to_string(), body.to_string()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_notify_on_timeout() {
+        let notifier = MockNotifier::default();
+        let outcome = TimeoutOutcome::new(TimeoutReason::TermExit, Duration::from_secs(3600));
+        notify(
+            &notifier,
+            &[Trigger::Timeout],
+            "cargo",
+            &outcome,
+            124,
+            false,
+        );
+        assert_eq!(
+            *notifier.shown.lock().unwrap(),
+            [(
+                "timeout: cargo".to_string(),
+                "command timed out after 1h0m0s".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_notify_skips_unselected_outcome() {
+        let notifier = MockNotifier::default();
+        let outcome = TimeoutOutcome::new(TimeoutReason::TermExit, Duration::ZERO);
+        notify(
+            &notifier,
+            &[Trigger::Success],
+            "cargo",
+            &outcome,
+            124,
+            false,
+        );
+        assert!(notifier.shown.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_notify_failure_is_ignored() {
+        let notifier = MockNotifier {
+            fail: true,
+            ..MockNotifier::default()
+        };
+        let outcome = TimeoutOutcome::new(TimeoutReason::TermExit, Duration::ZERO);
+        notify(
+            &notifier,
+            &[Trigger::Timeout],
+            "cargo",
+            &outcome,
+            124,
+            false,
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_describe_completed() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        let outcome = TimeoutOutcome::new(TimeoutReason::CompletedInTime, Duration::from_secs(872))
+            .with_status(ExitStatus::from_raw(0));
+        assert_eq!(
+            describe(&outcome, 0),
+            "command finished, exit 0, took 14m32s"
+        );
+    }
+}
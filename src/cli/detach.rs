@@ -0,0 +1,101 @@
+//! `--detach`: carrying on in the background, out of reach of the shell that started timeout.
+
+use std::fs::File;
+use std::io::{self, PipeWriter, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::{Mutex, PoisonError};
+
+/// Where the daemon tells the original process how it got on, until it has.
+static REPORT: Mutex<Option<PipeWriter>> = Mutex::new(None);
+
+/// Turn this process into a daemon: fork, start a new session without a controlling
+/// terminal, fork again so that it can never get one, and connect stdin and stdout to the null
+/// device. The working directory is kept, so relative paths mean what they did.
+///
+/// Returns only in the daemon, which keeps the original stderr so that whatever goes wrong
+/// before the command starts is reported as usual, until [`ready`] or [`report`]. The original
+/// process waits for that and exits with 0 once the command is about to start, or with the
+/// exit code of whatever went wrong before that.
+///
+/// Must be called before any thread is started: only the calling thread survives a fork.
+pub fn detach() -> io::Result<()> {
+    let null = File::options().read(true).write(true).open("/dev/null")?;
+    // Not inherited by the command.
+    let (mut reader, writer) = io::pipe()?;
+
+    // SAFETY: no other thread is running, so nothing can be left half-done in the child.
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => {}
+        middle => {
+            drop(writer);
+            let mut status = 0;
+            // SAFETY: waits for the process just forked, which exits as soon as it has forked
+            // the daemon.
+            if unsafe { libc::waitpid(middle, &mut status, 0) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            let code = if !libc::WIFEXITED(status) {
+                super::exit_code::EXIT_TIMEOUT_FAIL.into()
+            } else if libc::WEXITSTATUS(status) != 0 {
+                libc::WEXITSTATUS(status)
+            } else {
+                // A daemon that goes away without a word has failed somehow.
+                let mut code = [super::exit_code::EXIT_TIMEOUT_FAIL];
+                let _ = reader.read(&mut code);
+                code[0].into()
+            };
+            std::process::exit(code);
+        }
+    }
+
+    // SAFETY: setsid and fork only act on this process, which is still single-threaded.
+    unsafe {
+        if libc::setsid() == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        match libc::fork() {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => {}
+            // Nothing of the middle process's is worth cleaning up, and flushing would write
+            // out anything buffered a second time.
+            _ => libc::_exit(0),
+        }
+    }
+
+    drop(reader);
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO] {
+        // SAFETY: `null` is open, and `fd` is one of the standard descriptors.
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    *REPORT.lock().unwrap_or_else(PoisonError::into_inner) = Some(writer);
+    Ok(())
+}
+
+/// In the daemon, connect stderr to the null device too and let the original process exit
+/// with 0, once the command is about to start. Does nothing if that has already happened or
+/// timeout did not detach.
+pub fn ready() {
+    let mut report = REPORT.lock().unwrap_or_else(PoisonError::into_inner);
+    if report.is_none() {
+        return;
+    }
+    if let Ok(null) = File::options().write(true).open("/dev/null") {
+        // SAFETY: `null` is open, and stderr is one of the standard descriptors.
+        unsafe { libc::dup2(null.as_raw_fd(), libc::STDERR_FILENO) };
+    }
+    if let Some(mut writer) = report.take() {
+        let _ = writer.write_all(&[0]);
+    }
+}
+
+/// In the daemon, have the original process exit with `code` if the command never got as far
+/// as starting. Does nothing once [`ready`] has been called or if timeout did not detach.
+pub fn report(code: u8) {
+    let writer = REPORT.lock().unwrap_or_else(PoisonError::into_inner).take();
+    if let Some(mut writer) = writer {
+        let _ = writer.write_all(&[code]);
+    }
+}
@@ -0,0 +1,68 @@
+//! Parsing of duration arguments such as `90`, `1.5s`, `10m` or `2h`.
+
+use std::time::Duration;
+
+/// Parse a non-negative number followed by an optional unit: `s` (the default), `m`, `h` or `d`.
+pub fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let arg = arg.trim();
+    let (number, unit) = match arg.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => arg.split_at(index),
+        None => (arg, "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        "d" => 24.0 * 60.0 * 60.0,
+        _ => {
+            return Err(format!(
+                "invalid duration unit '{}' (use s, m, h or d)",
+                unit
+            ));
+        }
+    };
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", arg))?;
+    Duration::try_from_secs_f64(value * multiplier)
+        .map_err(|_| format!("invalid duration '{}'", arg))
+}
+
+/// A duration the way a person would say it: `4.2s`, `14m32s` or `1h0m5s`.
+pub fn format_duration(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, _) => format!("{:.1}s", elapsed.as_secs_f64()),
+        (0, minutes, seconds) => format!("{}m{}s", minutes, seconds),
+        (hours, minutes, seconds) => format!("{}h{}m{}s", hours, minutes, seconds),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_parse_duration_rejects_garbage() {
+        for arg in ["", "s", "-1", "5x", "5ms", "1.2.3", "inf", "NaN"] {
+            assert!(parse_duration(arg).is_err(), "{arg} parsed");
+        }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(4200)), "4.2s");
+        assert_eq!(format_duration(Duration::from_secs(872)), "14m32s");
+        assert_eq!(format_duration(Duration::from_secs(3605)), "1h0m5s");
+    }
+}
@@ -0,0 +1,129 @@
+//! `--every`: running the command on a fixed schedule, each run with its own timeout.
+
+use super::duration::parse_duration;
+use super::timestamp::{iso8601, parse_iso8601};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Parse the `--every` interval, which has to be more than zero.
+pub fn parse_interval(arg: &str) -> Result<Duration, String> {
+    let interval = parse_duration(arg)?;
+    if interval.is_zero() {
+        return Err("the interval must be more than zero".to_string());
+    }
+    Ok(interval)
+}
+
+/// Parse an `--until` deadline: a duration from now (`1h`) or a UTC time
+/// (`2024-08-09T18:00:00Z`).
+pub fn parse_deadline(arg: &str) -> Result<SystemTime, String> {
+    if let Ok(duration) = parse_duration(arg) {
+        return SystemTime::now()
+            .checked_add(duration)
+            .ok_or_else(|| format!("deadline '{}' is too far away", arg));
+    }
+    parse_iso8601(arg.trim()).ok_or_else(|| {
+        format!(
+            "invalid deadline '{}' (use a duration such as 1h or a UTC time such as 2024-08-09T18:00:00Z)",
+            arg
+        )
+    })
+}
+
+/// When runs start, and when they stop.
+#[derive(Debug, Clone, Copy)]
+pub struct Schedule {
+    pub interval: Duration,
+    pub max_runs: Option<u32>,
+    /// No run starts at or after this time.
+    pub until: Option<SystemTime>,
+}
+
+impl Schedule {
+    /// When the run after one that started at `previous` should start, given that it is now
+    /// `now`, and how many starts are skipped to get there. That is one interval on, unless the
+    /// previous run overran it, in which case it is the first start still to come, so that
+    /// runs stay on the same schedule rather than drifting.
+    pub fn next_start(&self, previous: Instant, now: Instant) -> (Instant, u32) {
+        let behind = now.saturating_duration_since(previous);
+        let intervals = behind.as_nanos().div_ceil(self.interval.as_nanos()).max(1);
+        let intervals = u32::try_from(intervals).unwrap_or(u32::MAX);
+        let next = previous
+            .checked_add(self.interval.saturating_mul(intervals))
+            .unwrap_or(now);
+        (next, intervals - 1)
+    }
+
+    /// Whether run number `run`, counting from 1, may start at `at`.
+    pub fn allows(&self, run: u32, at: SystemTime) -> bool {
+        self.max_runs.is_none_or(|max| run <= max) && self.until.is_none_or(|until| at < until)
+    }
+}
+
+/// The line printed on stderr after each run.
+pub fn run_line(run: u32, started_at: SystemTime, elapsed: Duration, exit_code: u8) -> String {
+    format!(
+        "timeout: every: run {} at {}, {:.3}s, exit {}",
+        run,
+        iso8601(started_at),
+        elapsed.as_secs_f64(),
+        exit_code
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn schedule() -> Schedule {
+        Schedule {
+            interval: Duration::from_secs(60),
+            max_runs: None,
+            until: None,
+        }
+    }
+
+    #[test]
+    fn test_next_start_keeps_to_the_schedule() {
+        let start = Instant::now();
+        let secs = |secs| start + Duration::from_secs(secs);
+        assert_eq!(schedule().next_start(start, secs(10)), (secs(60), 0));
+        assert_eq!(schedule().next_start(start, secs(60)), (secs(60), 0));
+        assert_eq!(schedule().next_start(start, secs(61)), (secs(120), 1));
+        assert_eq!(schedule().next_start(start, secs(200)), (secs(240), 3));
+    }
+
+    #[test]
+    fn test_allows_stops_at_max_runs_and_deadline() {
+        let until = UNIX_EPOCH + Duration::from_secs(1000);
+        let schedule = Schedule {
+            max_runs: Some(3),
+            until: Some(until),
+            ..schedule()
+        };
+        assert!(schedule.allows(3, UNIX_EPOCH));
+        assert!(!schedule.allows(4, UNIX_EPOCH));
+        assert!(!schedule.allows(2, until));
+    }
+
+    #[test]
+    fn test_parse_interval_and_deadline() {
+        assert_eq!(parse_interval("0.5"), Ok(Duration::from_millis(500)));
+        assert!(parse_interval("0").is_err());
+        assert_eq!(
+            parse_deadline("2024-08-09T18:00:00Z"),
+            Ok(UNIX_EPOCH + Duration::from_secs(1_723_226_400))
+        );
+        assert!(parse_deadline("1h").unwrap() > SystemTime::now() + Duration::from_secs(3000));
+        assert!(parse_deadline("tomorrow").is_err());
+    }
+
+    #[test]
+    fn test_run_line() {
+        let started_at = UNIX_EPOCH + Duration::from_millis(1_723_206_896_789);
+        assert_eq!(
+            run_line(3, started_at, Duration::from_millis(1234), 124),
+            "timeout: every: run 3 at 2024-08-09T12:34:56.789Z, 1.234s, exit 124"
+        );
+    }
+}
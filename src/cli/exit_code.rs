@@ -0,0 +1,163 @@
+//! Mapping a supervised run's outcome to the exit code of `timeout` itself.
+
+use std::io;
+use timeout_cli::{TimeoutOutcome, TimeoutReason};
+
+// Exit codes following GNU timeout convention
+pub const EXIT_TIMEOUT: u8 = 124; // Command timed out
+pub const EXIT_TIMEOUT_FAIL: u8 = 125; // timeout command itself failed
+pub const EXIT_CANNOT_INVOKE: u8 = 126; // Command found but cannot be invoked
+pub const EXIT_NOT_FOUND: u8 = 127; // Command not found
+pub const EXIT_KILLED: u8 = 137; // Command killed with KILL signal (128+9)
+
+/// The exit code `timeout` should finish with after `outcome`.
+///
+/// A command that finished on its own passes its exit code through, or 1 if it has none that
+/// fits (e.g. it was terminated by a signal we did not send).
+pub fn exit_code(outcome: &TimeoutOutcome) -> u8 {
+    match outcome.reason {
+        TimeoutReason::CompletedInTime => outcome
+            .status
+            .and_then(|status| status.code())
+            .and_then(|code| u8::try_from(code).ok())
+            .unwrap_or(1),
+        TimeoutReason::TermExit | TimeoutReason::Cancelled => EXIT_TIMEOUT,
+        TimeoutReason::Killed => 128 + killing_signal(outcome),
+        TimeoutReason::Detached => 0,
+        TimeoutReason::SpawnFailed {
+            kind: io::ErrorKind::NotFound,
+        } => EXIT_NOT_FOUND,
+        TimeoutReason::SpawnFailed {
+            kind: io::ErrorKind::PermissionDenied | io::ErrorKind::IsADirectory,
+        } => EXIT_CANNOT_INVOKE,
+        TimeoutReason::SpawnFailed {
+            kind: io::ErrorKind::TimedOut,
+        } => EXIT_TIMEOUT,
+        TimeoutReason::SpawnFailed { .. } | TimeoutReason::SupervisionFailed { .. } => {
+            EXIT_TIMEOUT_FAIL
+        }
+    }
+}
+
+/// Like [`exit_code`], but a command that had to be killed is reported with the bare number of
+/// the signal that killed it (9) rather than 128 plus that number, for `--raw-signal-exit`.
+pub fn raw_signal_exit_code(outcome: &TimeoutOutcome) -> u8 {
+    match outcome.reason {
+        TimeoutReason::Killed => killing_signal(outcome),
+        _ => exit_code(outcome),
+    }
+}
+
+/// The number of the signal a killed command died of: 9 for `SIGKILL`, or that of the signal
+/// given with `--kill-after DURATION:SIGNAL`.
+fn killing_signal(outcome: &TimeoutOutcome) -> u8 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = outcome
+            .status
+            .and_then(|status| status.signal())
+            .and_then(|signal| u8::try_from(signal).ok())
+            .filter(|&signal| signal < 128)
+        {
+            return signal;
+        }
+    }
+    EXIT_KILLED - 128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn outcome(reason: TimeoutReason) -> TimeoutOutcome {
+        TimeoutOutcome::new(reason, Duration::ZERO)
+    }
+
+    #[test]
+    fn test_exit_code_for_every_reason() {
+        let cases = [
+            (TimeoutReason::TermExit, EXIT_TIMEOUT),
+            (TimeoutReason::Cancelled, EXIT_TIMEOUT),
+            (TimeoutReason::Killed, EXIT_KILLED),
+            (TimeoutReason::Detached, 0),
+            (
+                TimeoutReason::SpawnFailed {
+                    kind: io::ErrorKind::NotFound,
+                },
+                EXIT_NOT_FOUND,
+            ),
+            (
+                TimeoutReason::SpawnFailed {
+                    kind: io::ErrorKind::PermissionDenied,
+                },
+                EXIT_CANNOT_INVOKE,
+            ),
+            (
+                TimeoutReason::SpawnFailed {
+                    kind: io::ErrorKind::IsADirectory,
+                },
+                EXIT_CANNOT_INVOKE,
+            ),
+            (
+                TimeoutReason::SpawnFailed {
+                    kind: io::ErrorKind::TimedOut,
+                },
+                EXIT_TIMEOUT,
+            ),
+            (
+                TimeoutReason::SpawnFailed {
+                    kind: io::ErrorKind::InvalidInput,
+                },
+                EXIT_TIMEOUT_FAIL,
+            ),
+            (
+                TimeoutReason::SupervisionFailed {
+                    kind: io::ErrorKind::Other,
+                },
+                EXIT_TIMEOUT_FAIL,
+            ),
+            (TimeoutReason::CompletedInTime, 1),
+        ];
+        for (reason, code) in cases {
+            assert_eq!(exit_code(&outcome(reason)), code, "{reason:?}");
+        }
+    }
+
+    #[test]
+    fn test_raw_signal_exit_code() {
+        assert_eq!(raw_signal_exit_code(&outcome(TimeoutReason::Killed)), 9);
+        assert_eq!(
+            raw_signal_exit_code(&outcome(TimeoutReason::TermExit)),
+            EXIT_TIMEOUT
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_code_passes_through_command_status() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        let completed =
+            |raw| outcome(TimeoutReason::CompletedInTime).with_status(ExitStatus::from_raw(raw));
+        assert_eq!(exit_code(&completed(0)), 0);
+        assert_eq!(exit_code(&completed(3 << 8)), 3);
+        assert_eq!(exit_code(&completed(255 << 8)), 255);
+        // Terminated by a signal we did not send
+        assert_eq!(exit_code(&completed(libc::SIGSEGV)), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_code_for_kill_after_signal() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        let killed = |raw| outcome(TimeoutReason::Killed).with_status(ExitStatus::from_raw(raw));
+        assert_eq!(exit_code(&killed(libc::SIGKILL)), EXIT_KILLED);
+        assert_eq!(exit_code(&killed(libc::SIGQUIT)), 128 + 3);
+        assert_eq!(raw_signal_exit_code(&killed(libc::SIGQUIT)), 3);
+    }
+}
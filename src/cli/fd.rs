@@ -0,0 +1,74 @@
+//! File descriptors inherited from the caller by number, as used by `--status-fd`.
+
+use std::io;
+
+/// A file descriptor the caller opened for us, e.g. with a `3>file` shell redirection.
+///
+/// It belongs to whoever opened it, so it is never closed here.
+#[derive(Debug)]
+pub struct InheritedFd {
+    #[cfg(unix)]
+    file: std::mem::ManuallyDrop<std::fs::File>,
+}
+
+impl InheritedFd {
+    /// Borrow `fd`, failing unless it is open and its access mode allows writing.
+    #[cfg(unix)]
+    pub fn open(fd: i32) -> io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        // SAFETY: F_GETFL only reads the descriptor's flags, and fails cleanly on one that is
+        // not open.
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if flags & libc::O_ACCMODE == libc::O_RDONLY {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "not open for writing",
+            ));
+        }
+        // SAFETY: fcntl just confirmed `fd` is an open descriptor.
+        let file = unsafe { std::fs::File::from_raw_fd(fd) };
+        Ok(Self {
+            file: std::mem::ManuallyDrop::new(file),
+        })
+    }
+
+    /// File descriptors cannot be inherited by number outside Unix.
+    #[cfg(not(unix))]
+    pub fn open(_fd: i32) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only supported on Unix",
+        ))
+    }
+
+    /// Write `line` and a newline in a single write, so lines from different threads never
+    /// interleave.
+    pub fn write_line(&self, line: &str) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::io::Write;
+
+            (&*self.file).write_all(format!("{}\n", line).as_bytes())
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = line;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_open_rejects_closed_fd() {
+        assert!(InheritedFd::open(-1).is_err());
+    }
+}
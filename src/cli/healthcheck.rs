@@ -0,0 +1,130 @@
+//! `--healthcheck-cmd`: probing a long-running command every so often, and stopping it once it
+//! has stopped answering.
+
+use super::color::error;
+use super::hooks::shell;
+use super::relay::{MatchAction, OutputMatch};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::Duration;
+use timeout_cli::{StdinMode, TimeoutHandle, TimeoutResult, debug_print};
+
+/// How long the probe may survive its `SIGTERM` before it is killed.
+const PROBE_KILL_AFTER: Duration = Duration::from_secs(1);
+
+/// What `--healthcheck-cmd` and the options that go with it ask for.
+#[derive(Debug, Clone)]
+pub struct HealthCheck {
+    /// The probe, run with the shell.
+    pub script: String,
+    /// How long to wait before each probe.
+    pub interval: Duration,
+    /// How long each probe may run before it counts as failed.
+    pub timeout: Duration,
+    /// How many probes in a row have to fail for the command to be stopped.
+    pub failures: u32,
+}
+
+/// Runs a [`HealthCheck`] from a thread of its own while the command runs, and stops the
+/// command with the timeout signal once enough probes in a row have failed, unless its output
+/// matched or it was found ready first.
+#[derive(Debug)]
+pub struct HealthWatch {
+    failed: Arc<OnceLock<()>>,
+    probe: Arc<Mutex<Option<TimeoutHandle>>>,
+    _running: Sender<()>,
+}
+
+impl HealthWatch {
+    /// Start probing, an interval after the command started. Probe output is only shown with
+    /// `verbose`.
+    pub fn start(check: HealthCheck, watch: OutputMatch, verbose: bool) -> Self {
+        let failed = Arc::new(OnceLock::new());
+        let probe = Arc::new(Mutex::new(None));
+        let (running, stopped) = mpsc::channel();
+        let (found, current) = (Arc::clone(&failed), Arc::clone(&probe));
+        std::thread::spawn(move || {
+            let mut failures = 0;
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(check.interval) {
+                if check.probe(&current, verbose) {
+                    failures = 0;
+                    continue;
+                }
+                failures += 1;
+                debug_print!(
+                    verbose,
+                    "Health check failed ({}/{})",
+                    failures,
+                    check.failures
+                );
+                if failures < check.failures {
+                    continue;
+                }
+                if watch.claim(MatchAction::Kill, &check.script) {
+                    let _ = found.set(());
+                    error!(
+                        "health check failed {} times in a row, stopping the command: {}",
+                        failures, check.script
+                    );
+                    watch.act(MatchAction::Kill);
+                }
+                return;
+            }
+        });
+        Self {
+            failed,
+            probe,
+            _running: running,
+        }
+    }
+
+    /// Whether the command was stopped for failing its health checks. Dropping the watch stops
+    /// the probing, and kills a probe still running.
+    pub fn failed(&self) -> bool {
+        self.failed.get().is_some()
+    }
+}
+
+impl Drop for HealthWatch {
+    fn drop(&mut self) {
+        if let Some(probe) = lock(&self.probe).take() {
+            probe.kill_now();
+        }
+    }
+}
+
+impl HealthCheck {
+    /// Run the probe once, keeping its handle in `current` while it runs. Whether it exited
+    /// with 0 in time.
+    fn probe(&self, current: &Mutex<Option<TimeoutHandle>>, verbose: bool) -> bool {
+        let mut command = shell(&self.script);
+        command
+            .timeout(self.timeout)
+            .kill_after(PROBE_KILL_AFTER)
+            .stdin(StdinMode::Null)
+            .discard_stdout(!verbose)
+            .discard_stderr(!verbose);
+        debug_print!(verbose, "Running health check: {}", self.script);
+        let child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                debug_print!(verbose, "Failed to run health check: {}", e);
+                return false;
+            }
+        };
+        *lock(current) = Some(child.handle());
+        let result = child.wait().map(|outcome| outcome.result);
+        *lock(current) = None;
+        match result {
+            Ok(TimeoutResult::Completed(0)) => true,
+            result => {
+                debug_print!(verbose, "Health check came to {:?}", result);
+                false
+            }
+        }
+    }
+}
+
+fn lock(probe: &Mutex<Option<TimeoutHandle>>) -> MutexGuard<'_, Option<TimeoutHandle>> {
+    probe.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,84 @@
+//! `--heartbeat`: saying every so often that the command is still running, for CI systems that
+//! give up on jobs that go quiet.
+
+use super::countdown;
+use super::duration::format_duration;
+use super::relay::LineGate;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// The line printed at each interval, with what is left of `timeout` if there is one.
+pub fn heartbeat_line(elapsed: Duration, timeout: Option<Duration>, pid: u32) -> String {
+    match timeout {
+        Some(timeout) => format!(
+            "timeout: still running ({} elapsed, {} remaining, pid {})",
+            format_duration(elapsed),
+            format_duration(timeout.saturating_sub(elapsed)),
+            pid
+        ),
+        None => format!(
+            "timeout: still running ({} elapsed, pid {})",
+            format_duration(elapsed),
+            pid
+        ),
+    }
+}
+
+/// Writes a [`heartbeat_line`] through a [`LineGate`] every interval from a thread of its own,
+/// until dropped.
+#[derive(Debug)]
+pub struct Heartbeat {
+    _running: Sender<()>,
+}
+
+impl Heartbeat {
+    /// Start reporting on the command `pid`, started at `start` with `timeout` to run, each
+    /// line prefixed with `prefix`.
+    pub fn start(
+        interval: Duration,
+        timeout: Option<Duration>,
+        start: Instant,
+        pid: u32,
+        prefix: String,
+        gate: LineGate,
+    ) -> Self {
+        let (running, stopped) = mpsc::channel();
+        std::thread::spawn(move || {
+            // Kept to the schedule, so that a line held back by the gate does not make the
+            // others drift.
+            let mut next = start + interval;
+            loop {
+                let wait = next.saturating_duration_since(Instant::now());
+                match stopped.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+                let line = heartbeat_line(start.elapsed(), timeout, pid);
+                countdown::above(|| gate.write_line(&format!("{}{}", prefix, line)));
+                next += interval;
+            }
+        });
+        Self { _running: running }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_line() {
+        assert_eq!(
+            heartbeat_line(
+                Duration::from_secs(120),
+                Some(Duration::from_secs(600)),
+                4242
+            ),
+            "timeout: still running (2m0s elapsed, 8m0s remaining, pid 4242)"
+        );
+        assert_eq!(
+            heartbeat_line(Duration::from_secs(5), None, 4242),
+            "timeout: still running (5.0s elapsed, pid 4242)"
+        );
+    }
+}
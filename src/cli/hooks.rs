@@ -0,0 +1,170 @@
+//! Shell commands run as the main command times out or finishes, such as `--on-timeout`.
+
+use super::color::error;
+use std::io;
+use std::time::Duration;
+use timeout_cli::{TimeoutCommand, TimeoutOutcome, TimeoutResult, debug_print};
+
+/// How long a hook may run before it is sent `SIGTERM`, unless `--hook-timeout` says otherwise.
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a hook may survive its `SIGTERM` before it is killed.
+pub const HOOK_KILL_AFTER: Duration = Duration::from_secs(1);
+
+/// Run `--on-timeout`'s `script` as the timeout expires, before the command is signalled, with
+/// `TIMEOUT_PID`, `TIMEOUT_COMMAND` and `TIMEOUT_ELAPSED` (in seconds) describing the command.
+/// However it ends, it is only reported on stderr.
+pub fn run_on_timeout(
+    script: &str,
+    pid: u32,
+    command: &str,
+    elapsed: Duration,
+    timeout: Duration,
+    verbose: bool,
+) {
+    let env = [
+        ("TIMEOUT_PID", pid.to_string()),
+        ("TIMEOUT_COMMAND", command.to_string()),
+        ("TIMEOUT_ELAPSED", format!("{:.3}", elapsed.as_secs_f64())),
+    ];
+    let _ = run_hook("on-timeout", script, &env, timeout, verbose);
+}
+
+/// The hooks to run once the command has finished.
+#[derive(Debug)]
+pub struct Hooks {
+    /// Run after the command exited on its own with status 0.
+    pub on_success: Option<String>,
+    /// Run after any other outcome: a non-zero exit, a timeout or a failure to start.
+    pub on_failure: Option<String>,
+    /// Run after every outcome, once the other hooks have finished.
+    pub on_exit: Option<String>,
+    /// How long each hook may run before it is sent `SIGTERM`.
+    pub timeout: Duration,
+    /// Treat a hook that fails, times out or cannot be started as a failure of timeout itself.
+    pub failures_fatal: bool,
+}
+
+impl Hooks {
+    /// Run whichever hooks apply to `outcome` for `command`.
+    ///
+    /// Each hook gets `TIMEOUT_EXIT_CODE` (the code timeout is about to exit with) and, if the
+    /// command was started, `TIMEOUT_PID`; `--on-exit` also gets `TIMEOUT_REASON`,
+    /// `TIMEOUT_ELAPSED_MS` and `TIMEOUT_COMMAND`. Fails if an `--on-success` or `--on-failure`
+    /// hook could not be started at all, or with [`failures_fatal`](Self::failures_fatal) if
+    /// any hook did not succeed.
+    pub fn run(
+        &self,
+        outcome: &TimeoutOutcome,
+        pid: Option<u32>,
+        exit_code: u8,
+        command: &str,
+        verbose: bool,
+    ) -> io::Result<()> {
+        let mut env = vec![("TIMEOUT_EXIT_CODE", exit_code.to_string())];
+        if let Some(pid) = pid {
+            env.push(("TIMEOUT_PID", pid.to_string()));
+        }
+        let mut exit_env = env.clone();
+        exit_env.extend([
+            ("TIMEOUT_REASON", reason_name(outcome.result).to_string()),
+            (
+                "TIMEOUT_ELAPSED_MS",
+                outcome.elapsed.as_millis().to_string(),
+            ),
+            ("TIMEOUT_COMMAND", command.to_string()),
+        ]);
+
+        let succeeded = outcome.result == TimeoutResult::Completed(0);
+        let hooks = [
+            ("on-success", &self.on_success, succeeded, &env),
+            ("on-failure", &self.on_failure, !succeeded, &env),
+            ("on-exit", &self.on_exit, true, &exit_env),
+        ];
+        let mut failed = false;
+        for (name, script, applies, env) in hooks {
+            if let (Some(script), true) = (script, applies) {
+                failed |= match run_hook(name, script, env, self.timeout, verbose) {
+                    Ok(ok) => !ok && self.failures_fatal,
+                    Err(_) => name != "on-exit" || self.failures_fatal,
+                };
+            }
+        }
+        if failed {
+            Err(io::Error::other("a hook failed"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The `TIMEOUT_REASON` given to `--on-exit`: `completed`, `timeout`, `killed`, `cancelled`,
+/// `detached`, `not-found`, `cannot-invoke` or `error`.
+fn reason_name(result: TimeoutResult) -> &'static str {
+    match result {
+        TimeoutResult::Completed(_) => "completed",
+        TimeoutResult::TimedOut => "timeout",
+        TimeoutResult::Killed => "killed",
+        TimeoutResult::Cancelled => "cancelled",
+        TimeoutResult::Detached => "detached",
+        TimeoutResult::NotFound => "not-found",
+        TimeoutResult::CannotInvoke => "cannot-invoke",
+        TimeoutResult::InternalError => "error",
+    }
+}
+
+/// Run the hook `script` through the shell with `env` added to its environment, under its own
+/// `timeout`. Its output goes to timeout's stdout and stderr.
+///
+/// A hook that fails or times out is reported on stderr as `timeout: <name> hook ...` and
+/// `false` returned; an error is returned only if it could not be started.
+fn run_hook(
+    name: &str,
+    script: &str,
+    env: &[(&str, String)],
+    timeout: Duration,
+    verbose: bool,
+) -> io::Result<bool> {
+    let mut command = shell(script);
+    command
+        .timeout(timeout)
+        .kill_after(HOOK_KILL_AFTER)
+        .verbose(verbose);
+    for (key, value) in env {
+        command.env(key, value);
+    }
+    debug_print!(verbose, "Running {} hook: {}", name, script);
+
+    let outcome = command
+        .spawn()
+        .and_then(|child| child.wait())
+        .inspect_err(|e| {
+            error!("failed to run {} hook: {}", name, e);
+        })?;
+    match outcome.result {
+        TimeoutResult::Completed(0) => return Ok(true),
+        TimeoutResult::Completed(code) => {
+            error!("{} hook exited with status {}", name, code);
+        }
+        TimeoutResult::TimedOut | TimeoutResult::Killed => {
+            error!("{} hook timed out after {:?}", name, timeout);
+        }
+        result => error!("{} hook failed: {:?}", name, result),
+    }
+    Ok(false)
+}
+
+/// `script` as a command run with the shell: `sh -c`, or `cmd /C` on Windows.
+#[cfg(unix)]
+pub fn shell(script: &str) -> TimeoutCommand {
+    let mut command = TimeoutCommand::new("sh");
+    command.args(["-c", script]);
+    command
+}
+
+#[cfg(not(unix))]
+pub fn shell(script: &str) -> TimeoutCommand {
+    let mut command = TimeoutCommand::new("cmd");
+    command.args(["/C", script]);
+    command
+}
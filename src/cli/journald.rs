@@ -0,0 +1,154 @@
+//! `--journald` logging of the command's lifecycle as structured journal entries.
+
+use super::report_fd::result_name;
+use super::syslog::Severity;
+use super::verbose;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use timeout_cli::{EventSink, Signal, TimeoutResult};
+
+/// Where journald accepts entries in its native protocol.
+pub const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Sends each lifecycle event to the journal with `MESSAGE` worded as `--verbose` words it,
+/// plus `TIMEOUT_*` fields: `TIMEOUT_EVENT` (`spawn`, `signal`, `kill` or `exit`),
+/// `TIMEOUT_COMMAND`, and as they apply `TIMEOUT_PID`, `TIMEOUT_SIGNAL`, `TIMEOUT_ELAPSED_MS`,
+/// `TIMEOUT_RESULT` and `TIMEOUT_EXIT_CODE`.
+#[derive(Debug)]
+pub struct Journald {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    command: String,
+}
+
+impl Journald {
+    /// Connect to journald's native socket at `path`, logging entries about `command`.
+    #[cfg(unix)]
+    pub fn open(path: &Path, command: String) -> io::Result<Self> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self { socket, command })
+    }
+
+    /// There is no journal outside Unix.
+    #[cfg(not(unix))]
+    pub fn open(_path: &Path, _command: String) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only supported on Unix",
+        ))
+    }
+
+    /// Log one entry for `event` with `message` at `severity` and any further `fields`.
+    /// Failures are ignored, as there is nowhere better to report them.
+    pub fn log(&self, severity: Severity, event: &str, message: &str, fields: &[(&str, String)]) {
+        let mut entry = vec![
+            ("MESSAGE", message.to_string()),
+            ("PRIORITY", (severity as u8).to_string()),
+            ("SYSLOG_IDENTIFIER", "timeout".to_string()),
+            ("TIMEOUT_EVENT", event.to_string()),
+            ("TIMEOUT_COMMAND", self.command.clone()),
+        ];
+        entry.extend(fields.iter().cloned());
+        let datagram = encode(&entry);
+        #[cfg(unix)]
+        let _ = self.socket.send(&datagram);
+        #[cfg(not(unix))]
+        let _ = datagram;
+    }
+}
+
+/// Serialise `fields` in journald's native protocol: `KEY=value` lines, except that values
+/// containing a newline are written as the key, a newline, the value's length as a 64-bit
+/// little-endian integer, the value and a newline.
+fn encode(fields: &[(&str, String)]) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    for (key, value) in fields {
+        datagram.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            datagram.push(b'\n');
+            datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            datagram.push(b'=');
+        }
+        datagram.extend_from_slice(value.as_bytes());
+        datagram.push(b'\n');
+    }
+    datagram
+}
+
+fn elapsed_ms(elapsed: Duration) -> (&'static str, String) {
+    ("TIMEOUT_ELAPSED_MS", elapsed.as_millis().to_string())
+}
+
+impl EventSink for Journald {
+    fn on_spawn(&self, pid: u32) {
+        self.log(
+            Severity::Info,
+            "spawn",
+            &verbose::spawned(pid),
+            &[("TIMEOUT_PID", pid.to_string())],
+        );
+    }
+
+    fn on_signal(&self, signal: Signal, elapsed: Duration) {
+        self.log(
+            Severity::Warning,
+            "signal",
+            &verbose::signalled(signal, elapsed),
+            &[("TIMEOUT_SIGNAL", signal.to_string()), elapsed_ms(elapsed)],
+        );
+    }
+
+    fn on_kill(&self, elapsed: Duration) {
+        self.log(
+            Severity::Warning,
+            "kill",
+            &verbose::killed(elapsed),
+            &[elapsed_ms(elapsed)],
+        );
+    }
+
+    fn on_exit(&self, result: TimeoutResult, elapsed: Duration) {
+        let mut fields = vec![
+            ("TIMEOUT_RESULT", result_name(result).to_string()),
+            elapsed_ms(elapsed),
+        ];
+        if let TimeoutResult::Completed(code) = result {
+            fields.push(("TIMEOUT_EXIT_CODE", code.to_string()));
+        }
+        self.log(
+            Severity::of_exit(result),
+            "exit",
+            &verbose::exited(result, elapsed),
+            &fields,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_simple_fields() {
+        let fields = [
+            ("MESSAGE", "hello".to_string()),
+            ("TIMEOUT_RESULT", "timed_out".to_string()),
+        ];
+        assert_eq!(
+            encode(&fields),
+            b"MESSAGE=hello\nTIMEOUT_RESULT=timed_out\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_multiline_value() {
+        let fields = [("TIMEOUT_COMMAND", "sh -c 'a\nb'".to_string())];
+        let mut expected = b"TIMEOUT_COMMAND\n".to_vec();
+        expected.extend_from_slice(&11u64.to_le_bytes());
+        expected.extend_from_slice(b"sh -c 'a\nb'\n");
+        assert_eq!(encode(&fields), expected);
+    }
+}
@@ -0,0 +1,49 @@
+//! `--login-shell`: running the command through a login shell, so that it sees the environment
+//! set up by the user's profile files.
+
+use std::path::Path;
+use timeout_cli::TimeoutCommand;
+
+/// Shells known to take `-l` and `-c` and to run the script handed to them the POSIX way.
+const POSIX_SHELLS: &[&str] = &["sh", "bash", "dash", "zsh", "ksh", "mksh", "ash", "yash"];
+
+/// Replaces the shell with the command once the profile has been read, handing it its
+/// arguments untouched, so nothing needs quoting and the command gets timeout's signals itself.
+const SCRIPT: &str = r#"exec "$@""#;
+
+/// The shell to use: `shell` (the user's `$SHELL`) if it is one of the [`POSIX_SHELLS`], as
+/// others such as fish or csh would not understand the script, and `sh` otherwise.
+pub fn login_shell(shell: Option<&str>) -> &str {
+    shell
+        .filter(|shell| {
+            Path::new(shell)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| POSIX_SHELLS.contains(&name))
+        })
+        .unwrap_or("sh")
+}
+
+/// `program` with `args`, run through `shell` as a login shell.
+pub fn command(shell: &str, program: &str, args: &[String]) -> TimeoutCommand {
+    let mut command = TimeoutCommand::new(shell);
+    command.args(["-l", "-c", SCRIPT, "sh", program]).args(args);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login_shell() {
+        assert_eq!(login_shell(Some("/bin/bash")), "/bin/bash");
+        assert_eq!(
+            login_shell(Some("/usr/local/bin/zsh")),
+            "/usr/local/bin/zsh"
+        );
+        assert_eq!(login_shell(Some("/usr/bin/fish")), "sh");
+        assert_eq!(login_shell(Some("")), "sh");
+        assert_eq!(login_shell(None), "sh");
+    }
+}
@@ -0,0 +1,178 @@
+//! Prometheus metrics in the text exposition format, for node_exporter's textfile collector.
+
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use timeout_cli::{TimeoutOutcome, TimeoutReason};
+
+/// A `name=value` label attached to every metric, from `--metrics-label`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub name: String,
+    pub value: String,
+}
+
+/// Parse a `name=value` label, checking the name is a valid Prometheus label name.
+pub fn parse_label(arg: &str) -> Result<Label, String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{}'", arg))?;
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__");
+    if !valid {
+        return Err(format!("invalid label name '{}'", name));
+    }
+    Ok(Label {
+        name: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Render the gauges describing the run that ended with `outcome` at `finished_at`.
+pub fn render(
+    outcome: &TimeoutOutcome,
+    exit_code: u8,
+    labels: &[Label],
+    finished_at: SystemTime,
+) -> String {
+    let timed_out = matches!(
+        outcome.reason,
+        TimeoutReason::TermExit | TimeoutReason::Killed
+    );
+    let timestamp = finished_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let gauges = [
+        (
+            "timeout_last_run_duration_seconds",
+            "Wall-clock duration of the last run in seconds.",
+            outcome.elapsed.as_secs_f64(),
+        ),
+        (
+            "timeout_last_run_exit_code",
+            "Exit code timeout returned for the last run.",
+            f64::from(exit_code),
+        ),
+        (
+            "timeout_last_run_timed_out",
+            "Whether the last run hit its timeout (1) or not (0).",
+            f64::from(u8::from(timed_out)),
+        ),
+        (
+            "timeout_last_run_timestamp_seconds",
+            "Unix time at which the last run finished.",
+            timestamp,
+        ),
+    ];
+
+    let labels = format_labels(labels);
+    let mut out = String::new();
+    for (name, help, value) in gauges {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    }
+    out
+}
+
+/// Format labels as `{name="value",...}`, or nothing when there are none.
+fn format_labels(labels: &[Label]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|label| format!("{}=\"{}\"", label.name, escape(&label.value)))
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+/// Escape a label value as the exposition format requires.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    /// A minimal exposition-format parser: checks every sample follows its own HELP and TYPE
+    /// lines and returns the samples keyed by `name{labels}`.
+    fn parse(text: &str) -> HashMap<String, f64> {
+        let mut samples = HashMap::new();
+        let mut described = Vec::new();
+        let mut typed = Vec::new();
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# HELP ") {
+                described.push(rest.split_once(' ').unwrap().0.to_string());
+            } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                assert_eq!(kind, "gauge");
+                typed.push(name.to_string());
+            } else {
+                let (series, value) = line.rsplit_once(' ').unwrap();
+                let name = series.split('{').next().unwrap();
+                assert_eq!(described.last().map(String::as_str), Some(name), "{line}");
+                assert_eq!(typed.last().map(String::as_str), Some(name), "{line}");
+                samples.insert(series.to_string(), value.parse().unwrap());
+            }
+        }
+        assert!(text.ends_with('\n'));
+        samples
+    }
+
+    #[test]
+    fn test_render_timed_out_run() {
+        let outcome = TimeoutOutcome::new(TimeoutReason::TermExit, Duration::from_millis(1500));
+        let labels = [parse_label("job=backup").unwrap()];
+        let finished_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let samples = parse(&render(&outcome, 124, &labels, finished_at));
+
+        assert_eq!(samples.len(), 4);
+        assert_eq!(
+            samples["timeout_last_run_duration_seconds{job=\"backup\"}"],
+            1.5
+        );
+        assert_eq!(samples["timeout_last_run_exit_code{job=\"backup\"}"], 124.0);
+        assert_eq!(samples["timeout_last_run_timed_out{job=\"backup\"}"], 1.0);
+        assert_eq!(
+            samples["timeout_last_run_timestamp_seconds{job=\"backup\"}"],
+            1_700_000_000.0
+        );
+    }
+
+    #[test]
+    fn test_render_without_labels() {
+        let outcome = TimeoutOutcome::new(TimeoutReason::CompletedInTime, Duration::ZERO);
+        let samples = parse(&render(&outcome, 0, &[], UNIX_EPOCH));
+        assert_eq!(samples["timeout_last_run_timed_out"], 0.0);
+        assert_eq!(samples["timeout_last_run_exit_code"], 0.0);
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        let labels = [Label {
+            name: "job".to_string(),
+            value: "a\"b\\c\nd".to_string(),
+        }];
+        assert_eq!(format_labels(&labels), r#"{job="a\"b\\c\nd"}"#);
+    }
+
+    #[test]
+    fn test_parse_label_rejects_invalid_names() {
+        assert!(parse_label("job").is_err());
+        assert!(parse_label("1job=x").is_err());
+        assert!(parse_label("__name__=x").is_err());
+        assert!(parse_label("my-job=x").is_err());
+        assert_eq!(parse_label("job=a=b").unwrap().value, "a=b");
+    }
+}
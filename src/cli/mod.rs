@@ -0,0 +1,58 @@
+//! Pieces of the `timeout` binary that are not part of the library API.
+
+pub mod atomic;
+pub mod batch;
+pub mod color;
+pub mod config;
+pub mod countdown;
+pub mod deadline;
+pub mod debug_info;
+pub mod desktop;
+#[cfg(unix)]
+pub mod detach;
+pub mod duration;
+pub mod every;
+pub mod exit_code;
+pub mod fd;
+pub mod healthcheck;
+pub mod heartbeat;
+pub mod hooks;
+pub mod journald;
+#[cfg(unix)]
+pub mod login_shell;
+pub mod metrics;
+pub mod notify;
+pub mod output_files;
+pub mod pid_file;
+pub mod port;
+pub mod progress;
+#[cfg(unix)]
+pub mod pty;
+pub mod quiet;
+pub mod race;
+pub mod ready;
+pub mod relay;
+pub mod report;
+pub mod report_fd;
+#[cfg(unix)]
+pub mod resize;
+pub mod respawn;
+pub mod retry;
+pub mod sd_notify;
+pub mod signal_sent;
+pub mod signals;
+pub mod size;
+pub mod stats;
+pub mod statsd;
+pub mod status_fd;
+pub mod syslog;
+pub mod tail;
+pub mod time;
+pub mod timestamp;
+pub mod trigger;
+pub mod umask;
+#[cfg(unix)]
+pub mod user;
+pub mod verbose;
+pub mod warnings;
+pub mod webhook;
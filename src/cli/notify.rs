@@ -0,0 +1,28 @@
+//! `--notify-pid`: signalling another process as `timeout` exits.
+
+use std::io;
+use timeout_cli::Signal;
+
+/// Send `signal` to the process `pid`. A process that has already gone counts as notified.
+#[cfg(unix)]
+pub fn notify_pid(pid: u32, signal: Signal) -> io::Result<()> {
+    // SAFETY: kill(2) has no memory-safety requirements; `pid` is always positive, so it never
+    // addresses a process group.
+    if unsafe { libc::kill(pid as libc::pid_t, signal.as_raw()) } == 0 {
+        return Ok(());
+    }
+    let error = io::Error::last_os_error();
+    if error.raw_os_error() == Some(libc::ESRCH) {
+        return Ok(());
+    }
+    Err(error)
+}
+
+/// Other processes cannot be signalled outside Unix.
+#[cfg(not(unix))]
+pub fn notify_pid(_pid: u32, _signal: Signal) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "only supported on Unix",
+    ))
+}
@@ -0,0 +1,121 @@
+//! Files for the command's output: `--tee` and `--tee-stderr` to copy it to as it is relayed,
+//! and `--stdout-file` and `--stderr-file` to send it to instead.
+
+use super::color::warning;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// How to open the output files.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OpenMode {
+    /// Make any missing parent directories.
+    pub create_dirs: bool,
+    /// Add to the end of an existing file instead of truncating it.
+    pub append: bool,
+}
+
+/// Files for the command's stdout and stderr, opened once for the whole run so that every
+/// attempt, step or job adds to them rather than starting them afresh.
+#[derive(Debug, Default)]
+pub struct OutputFiles {
+    stdout: Option<File>,
+    stderr: Option<File>,
+}
+
+impl OutputFiles {
+    /// Open the files as `mode` says, creating them if need be. The same path given for both
+    /// streams is opened once, so the two are interleaved as they arrive rather than
+    /// overwriting each other.
+    /// On failure, returns the path that could not be opened with the error.
+    pub fn open<'a>(
+        stdout: Option<&'a Path>,
+        stderr: Option<&'a Path>,
+        mode: OpenMode,
+    ) -> Result<Self, (&'a Path, io::Error)> {
+        let stdout_file = match stdout {
+            Some(path) => Some(create(path, mode).map_err(|e| (path, e))?),
+            None => None,
+        };
+        let stderr_file = match (stderr, &stdout_file) {
+            (Some(path), Some(file)) if Some(path) == stdout => {
+                Some(file.try_clone().map_err(|e| (path, e))?)
+            }
+            (Some(path), _) => Some(create(path, mode).map_err(|e| (path, e))?),
+            (None, _) => None,
+        };
+        Ok(Self {
+            stdout: stdout_file,
+            stderr: stderr_file,
+        })
+    }
+
+    /// A handle to the stdout file, for one run.
+    pub fn stdout(&self) -> Option<File> {
+        self.stdout.as_ref().and_then(handle)
+    }
+
+    /// A handle to the stderr file, for one run.
+    pub fn stderr(&self) -> Option<File> {
+        self.stderr.as_ref().and_then(handle)
+    }
+}
+
+fn create(path: &Path, mode: OpenMode) -> io::Result<File> {
+    if mode.create_dirs
+        && let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.create(true).write(true);
+    if mode.append {
+        options.append(true);
+    } else {
+        options.truncate(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o644);
+    }
+    options.open(path)
+}
+
+fn handle(file: &File) -> Option<File> {
+    file.try_clone()
+        .inspect_err(|e| warning!("cannot write the command's output to its file: {}", e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_same_path_for_both_streams_is_shared_and_append_keeps_contents() {
+        let dir = std::env::temp_dir().join(format!("timeout-output-files-{}", std::process::id()));
+        let path = dir.join("nested").join("out.log");
+        assert!(OutputFiles::open(Some(&path), None, OpenMode::default()).is_err());
+
+        let mode = OpenMode {
+            create_dirs: true,
+            append: false,
+        };
+        let files = OutputFiles::open(Some(&path), Some(&path), mode).unwrap();
+        files.stdout().unwrap().write_all(b"out\n").unwrap();
+        files.stderr().unwrap().write_all(b"err\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "out\nerr\n");
+
+        let mode = OpenMode {
+            create_dirs: false,
+            append: true,
+        };
+        let files = OutputFiles::open(Some(&path), None, mode).unwrap();
+        files.stdout().unwrap().write_all(b"more\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "out\nerr\nmore\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
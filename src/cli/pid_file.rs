@@ -0,0 +1,125 @@
+//! `--pid-file`: recording the command's process ID for as long as it runs.
+
+use super::atomic::write_atomic;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A file holding the command's PID, removed when this is dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Check that `path` may be used: its directory must exist, and it must not name a
+    /// process that is still running, unless `overwrite` is set. A file left behind by a
+    /// process that has gone is stale and will simply be replaced.
+    pub fn check(path: &Path, overwrite: bool) -> io::Result<()> {
+        match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            Some(dir) if !fs::metadata(dir)?.is_dir() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotADirectory,
+                    format!("'{}' is not a directory", dir.display()),
+                ));
+            }
+            _ => {}
+        }
+        if overwrite {
+            return Ok(());
+        }
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match contents.trim().parse() {
+            Ok(pid) if is_running(pid) => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("it belongs to running process {}", pid),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write `pid` to `path`, syncing it to disk before returning.
+    pub fn create(path: &Path, pid: u32) -> io::Result<Self> {
+        write_atomic(path, format!("{}\n", pid).as_bytes())?;
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether a process with ID `pid` exists.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // SAFETY: signal 0 only checks whether the process exists and may be signalled.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Without a way to check, assume the process is still running.
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("timeout-pid-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_pid_file_is_removed_on_drop() {
+        let path = temp_path("drop");
+        let pid_file = PidFile::create(&path, 42).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "42\n");
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_check_refuses_live_pid() {
+        let path = temp_path("live");
+        fs::write(&path, format!("{}\n", std::process::id())).unwrap();
+        let error = PidFile::check(&path, false).unwrap_err();
+        assert!(PidFile::check(&path, true).is_ok());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_accepts_stale_file() {
+        let path = temp_path("stale");
+        fs::write(&path, "not a pid\n").unwrap();
+        assert!(PidFile::check(&path, false).is_ok());
+        fs::write(&path, format!("{}\n", i32::MAX)).unwrap();
+        assert!(PidFile::check(&path, false).is_ok());
+        fs::remove_file(&path).unwrap();
+        assert!(PidFile::check(&path, false).is_ok());
+    }
+
+    #[test]
+    fn test_check_refuses_missing_directory() {
+        let path = temp_path("missing").join("pid");
+        let error = PidFile::check(&path, true).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,105 @@
+//! `--until-port`: finishing once a TCP port starts accepting connections, for commands that
+//! start a server.
+
+use std::fmt;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How long each connection attempt may take before the address is given up on until the next
+/// poll.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A `HOST:PORT` to connect to: a hostname, an IPv4 address or an IPv6 address in brackets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortAddress {
+    host: String,
+    port: u16,
+}
+
+impl fmt::Display for PortAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Parse an `--until-port` address, such as `localhost:5432`, `127.0.0.1:8080` or `[::1]:80`.
+pub fn parse_port_address(arg: &str) -> Result<PortAddress, String> {
+    let invalid = |why: &str| format!("invalid address '{}': {}", arg, why);
+    let (host, port) = arg
+        .rsplit_once(':')
+        .ok_or_else(|| invalid("expected HOST:PORT"))?;
+    let host = match host.strip_prefix('[') {
+        Some(host) => host
+            .strip_suffix(']')
+            .ok_or_else(|| invalid("missing ']'"))?,
+        None if host.contains(':') => return Err(invalid("put an IPv6 address in brackets")),
+        None => host,
+    };
+    if host.is_empty() {
+        return Err(invalid("missing host"));
+    }
+    let port = port
+        .parse()
+        .map_err(|_| invalid(&format!("invalid port '{}'", port)))?;
+    Ok(PortAddress {
+        host: host.to_string(),
+        port,
+    })
+}
+
+impl PortAddress {
+    /// Try each address the host resolves to in turn, returning the first that accepts a
+    /// connection. A name that does not resolve yet is no different from a closed port.
+    pub fn probe(&self) -> Option<SocketAddr> {
+        let addresses = (self.host.as_str(), self.port).to_socket_addrs().ok()?;
+        addresses
+            .into_iter()
+            .find(|address| TcpStream::connect_timeout(address, CONNECT_TIMEOUT).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_parse_port_address() {
+        let address = |host: &str, port| {
+            Ok(PortAddress {
+                host: host.to_string(),
+                port,
+            })
+        };
+        assert_eq!(
+            parse_port_address("localhost:5432"),
+            address("localhost", 5432)
+        );
+        assert_eq!(parse_port_address("10.0.0.1:80"), address("10.0.0.1", 80));
+        assert_eq!(parse_port_address("[::1]:8080"), address("::1", 8080));
+        assert!(parse_port_address("localhost").is_err());
+        assert!(parse_port_address(":80").is_err());
+        assert!(parse_port_address("::1:80").is_err());
+        assert!(parse_port_address("[::1:80").is_err());
+        assert!(parse_port_address("localhost:http").is_err());
+        assert!(parse_port_address("localhost:70000").is_err());
+        assert_eq!(
+            parse_port_address("[::1]:8080").unwrap().to_string(),
+            "[::1]:8080"
+        );
+    }
+
+    #[test]
+    fn test_probe_finds_a_listening_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let address = parse_port_address(&format!("localhost:{}", port)).unwrap();
+        assert_eq!(address.probe(), Some(listener.local_addr().unwrap()));
+        drop(listener);
+        assert_eq!(address.probe(), None);
+    }
+}
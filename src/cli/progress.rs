@@ -0,0 +1,100 @@
+//! `--progress`: saying every so often how long the command has left before it times out.
+
+use super::duration::format_duration;
+use super::every::parse_interval;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// How `--progress` reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// A [`progress_line`] every interval.
+    Every(Duration),
+    /// A [`Countdown`](super::countdown::Countdown) when stderr is a terminal, nothing
+    /// otherwise.
+    Auto,
+}
+
+/// Parse a `--progress` argument: an interval (`30s`) or `auto`.
+pub fn parse_progress(arg: &str) -> Result<ProgressMode, String> {
+    if arg.trim() == "auto" {
+        return Ok(ProgressMode::Auto);
+    }
+    parse_interval(arg).map(ProgressMode::Every)
+}
+
+impl ProgressMode {
+    /// The interval between lines, if there is one.
+    pub fn interval(self) -> Option<Duration> {
+        match self {
+            ProgressMode::Every(interval) => Some(interval),
+            ProgressMode::Auto => None,
+        }
+    }
+}
+
+/// The line printed on stderr at each interval.
+pub fn progress_line(elapsed: Duration, timeout: Duration) -> String {
+    format!(
+        "timeout: progress: {} elapsed, {} left of {}",
+        format_duration(elapsed),
+        format_duration(timeout.saturating_sub(elapsed)),
+        format_duration(timeout)
+    )
+}
+
+/// Prints a [`progress_line`] every interval from a thread of its own, until dropped.
+#[derive(Debug)]
+pub struct Progress {
+    _running: Sender<()>,
+}
+
+impl Progress {
+    /// Start reporting on a command started at `start` with `timeout` to run, each line
+    /// prefixed with `prefix`.
+    pub fn start(interval: Duration, timeout: Duration, start: Instant, prefix: String) -> Self {
+        let (running, stopped) = mpsc::channel();
+        std::thread::spawn(move || {
+            // Kept to the schedule, so that a slow stderr does not make the reports drift.
+            let mut next = start + interval;
+            loop {
+                let wait = next.saturating_duration_since(Instant::now());
+                match stopped.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+                let elapsed = start.elapsed();
+                if elapsed >= timeout {
+                    return;
+                }
+                eprintln!("{}{}", prefix, progress_line(elapsed, timeout));
+                next += interval;
+            }
+        });
+        Self { _running: running }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_progress() {
+        assert_eq!(
+            parse_progress("30s"),
+            Ok(ProgressMode::Every(Duration::from_secs(30)))
+        );
+        assert_eq!(parse_progress("auto"), Ok(ProgressMode::Auto));
+        assert!(parse_progress("0").is_err());
+        assert!(parse_progress("sometimes").is_err());
+    }
+
+    #[test]
+    fn test_progress_line() {
+        assert_eq!(
+            progress_line(Duration::from_secs(30), Duration::from_secs(120)),
+            "timeout: progress: 30.0s elapsed, 1m30s left of 2m0s"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! `--pty`: running the command on a pseudo-terminal of its own, for programs that behave
+//! differently, or refuse to run at all, when their output is not a terminal.
+
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+
+/// What a terminal in its default, canonical mode takes as end of file (`^D`).
+const EOF_CHAR: u8 = 0x04;
+
+/// The master end of the pseudo-terminal timeout's stdin is currently passed on to.
+static INPUT: Mutex<Option<File>> = Mutex::new(None);
+
+/// Whether timeout's stdin has reached end of file.
+static INPUT_ENDED: AtomicBool = AtomicBool::new(false);
+
+/// A pseudo-terminal: the command gets the `slave` end as its stdin, stdout and stderr, and
+/// timeout reads and writes the `master` end.
+#[derive(Debug)]
+pub struct Pty {
+    pub master: File,
+    pub slave: File,
+}
+
+impl Pty {
+    /// Open a pseudo-terminal the size of timeout's own terminal, if it has one.
+    pub fn open() -> io::Result<Self> {
+        let mut master = -1;
+        let mut slave = -1;
+        let size = window_size();
+        let size = size
+            .as_ref()
+            .map_or(ptr::null(), |size| size as *const libc::winsize);
+        // SAFETY: openpty fills in the two descriptors and only reads the terminal settings and
+        // size, which are either null or point to a live value.
+        let result =
+            unsafe { libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), size) };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for fd in [master, slave] {
+            // SAFETY: `fd` was just opened. Other commands must not inherit either end; the
+            // command's own copies of the slave are made by dup2, which clears the flag.
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
+        // SAFETY: both descriptors were just opened and nothing else owns them.
+        Ok(unsafe {
+            Self {
+                master: File::from_raw_fd(master),
+                slave: File::from_raw_fd(slave),
+            }
+        })
+    }
+}
+
+/// The size of timeout's own terminal, if it has one.
+pub fn window_size() -> Option<libc::winsize> {
+    [libc::STDIN_FILENO, libc::STDOUT_FILENO]
+        .into_iter()
+        .find_map(|fd| {
+            let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+            // SAFETY: TIOCGWINSZ only writes a winsize to the pointer given.
+            let result = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) };
+            (result == 0).then_some(size)
+        })
+}
+
+/// Passes timeout's stdin on to a pseudo-terminal's master end until dropped.
+///
+/// Stdin is read by a single thread for the rest of the process's life, so that nothing typed
+/// between two runs of the command is lost to a reader left over from the first. Once stdin
+/// ends, each terminal is sent its end-of-file character so that the command's reads end too.
+#[derive(Debug)]
+pub struct Input;
+
+impl Input {
+    /// Start passing stdin on to `master`.
+    pub fn forward(mut master: File) -> Self {
+        static START: Once = Once::new();
+
+        if INPUT_ENDED.load(Ordering::Relaxed) {
+            let _ = master.write_all(&[EOF_CHAR]);
+        }
+        *input() = Some(master);
+        START.call_once(|| {
+            std::thread::spawn(|| {
+                let mut chunk = [0u8; 4096];
+                loop {
+                    let data = match io::stdin().read(&mut chunk) {
+                        Ok(0) => {
+                            INPUT_ENDED.store(true, Ordering::Relaxed);
+                            &[EOF_CHAR][..]
+                        }
+                        Ok(n) => &chunk[..n],
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(_) => break,
+                    };
+                    // Written through a handle of its own, so that a command not reading its
+                    // input cannot keep the lock from whoever wants to close the master.
+                    let master = input().as_ref().and_then(|master| master.try_clone().ok());
+                    if let Some(mut master) = master {
+                        let _ = master.write_all(data);
+                    }
+                    if INPUT_ENDED.load(Ordering::Relaxed) {
+                        break;
+                    }
+                }
+            });
+        });
+        Self
+    }
+}
+
+impl Drop for Input {
+    /// Close timeout's last handle on the master end.
+    fn drop(&mut self) {
+        *input() = None;
+    }
+}
+
+fn input() -> MutexGuard<'static, Option<File>> {
+    INPUT.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Keeps timeout's own terminal in raw mode, so that each key goes straight to the command's
+/// terminal to be dealt with there, until dropped.
+pub struct RawMode {
+    saved: libc::termios,
+}
+
+impl RawMode {
+    /// Put stdin into raw mode, if it is a terminal.
+    pub fn enable() -> Option<Self> {
+        if !io::stdin().is_terminal() {
+            return None;
+        }
+        // SAFETY: tcgetattr and tcsetattr only read and write the termios given.
+        unsafe {
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                return None;
+            }
+            let mut raw = saved;
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return None;
+            }
+            Some(Self { saved })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: puts back the settings read in `enable`.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.saved) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slave_writes_reach_the_master() {
+        let mut pty = Pty::open().unwrap();
+        assert!(pty.slave.is_terminal());
+        pty.slave.write_all(b"hello\n").unwrap();
+        let mut output = [0u8; 7];
+        pty.master.read_exact(&mut output).unwrap();
+        // The terminal turns each newline into a carriage return and a newline.
+        assert_eq!(&output, b"hello\r\n");
+    }
+}
@@ -0,0 +1,101 @@
+//! `--quiet-success`: holding the command's output back, to be passed on only if the command
+//! does not succeed.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// The command's stdout and stderr, held back in the order they were written.
+#[derive(Debug, Clone, Default)]
+pub struct HeldOutput {
+    chunks: Arc<Mutex<Vec<Chunk>>>,
+}
+
+/// A run of output from one of the streams.
+#[derive(Debug)]
+struct Chunk {
+    stderr: bool,
+    data: Vec<u8>,
+}
+
+impl HeldOutput {
+    /// Where to write the command's stdout.
+    pub fn stdout(&self) -> HeldStream {
+        HeldStream {
+            output: self.clone(),
+            stderr: false,
+        }
+    }
+
+    /// Where to write the command's stderr.
+    pub fn stderr(&self) -> HeldStream {
+        HeldStream {
+            output: self.clone(),
+            stderr: true,
+        }
+    }
+
+    /// Pass everything held back on to timeout's own stdout and stderr, and forget it.
+    pub fn release(&self) {
+        self.release_to(&mut io::stdout(), &mut io::stderr());
+    }
+
+    fn release_to(&self, stdout: &mut impl Write, stderr: &mut impl Write) {
+        let chunks = std::mem::take(&mut *self.lock());
+        for chunk in chunks {
+            let out: &mut dyn Write = if chunk.stderr { stderr } else { stdout };
+            // As with the relays, a destination that has gone is no reason to fail.
+            let _ = out.write_all(&chunk.data).and_then(|()| out.flush());
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Chunk>> {
+        self.chunks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// One of the command's streams, written into a [`HeldOutput`].
+#[derive(Debug)]
+pub struct HeldStream {
+    output: HeldOutput,
+    stderr: bool,
+}
+
+impl Write for HeldStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut chunks = self.output.lock();
+        match chunks.last_mut() {
+            Some(chunk) if chunk.stderr == self.stderr => chunk.data.extend_from_slice(buf),
+            _ => chunks.push(Chunk {
+                stderr: self.stderr,
+                data: buf.to_vec(),
+            }),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_keeps_the_order_of_the_streams() {
+        let held = HeldOutput::default();
+        let (mut stdout, mut stderr) = (held.stdout(), held.stderr());
+        stdout.write_all(b"one\n").unwrap();
+        stdout.write_all(b"two\n").unwrap();
+        stderr.write_all(b"oops\n").unwrap();
+        stdout.write_all(b"three\n").unwrap();
+        assert_eq!(held.lock().len(), 3);
+
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        held.release_to(&mut out, &mut err);
+        assert_eq!(out, b"one\ntwo\nthree\n");
+        assert_eq!(err, b"oops\n");
+        assert!(held.lock().is_empty());
+    }
+}
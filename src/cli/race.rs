@@ -0,0 +1,78 @@
+//! `--race`: running several commands at once under one timeout, and stopping the rest as soon
+//! as one of them wins.
+
+use clap::ValueEnum;
+use std::time::Duration;
+
+/// Separates the commands given to `--race`.
+pub const SEPARATOR: &str = "---";
+
+/// What wins a race.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RaceMode {
+    /// The first command to finish, however it finished.
+    #[default]
+    First,
+    /// The first command to exit with 0.
+    Success,
+}
+
+/// Split `argv` into the racing commands. A command given as a single argument, e.g.
+/// `'curl a'`, is split into words the way a shell would split it, without expanding anything.
+pub fn commands(argv: &[String]) -> Result<Vec<Vec<String>>, String> {
+    let empty = || format!("{} must be between two commands", SEPARATOR);
+    argv.split(|arg| arg == SEPARATOR)
+        .map(|command| match command {
+            [] => Err(empty()),
+            [line] => match shell_words::split(line) {
+                Ok(words) if words.is_empty() => Err(empty()),
+                Ok(words) => Ok(words),
+                Err(e) => Err(format!("cannot parse '{}': {}", line, e)),
+            },
+            command => Ok(command.to_vec()),
+        })
+        .collect()
+}
+
+/// The line printed on stderr once command `number`, counting from 1, has won.
+pub fn result_line(number: usize, command: &[String], elapsed: Duration, exit_code: u8) -> String {
+    format!(
+        "timeout: race: command {} won, {:.3}s, exit {}: {}",
+        number,
+        elapsed.as_secs_f64(),
+        exit_code,
+        shell_words::join(command)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_commands_split_on_separator() {
+        assert_eq!(
+            commands(&argv(&["curl a", "---", "curl", "b c", "---", "true"])),
+            Ok(vec![
+                argv(&["curl", "a"]),
+                argv(&["curl", "b c"]),
+                argv(&["true"]),
+            ])
+        );
+        assert!(commands(&argv(&["true", "---"])).is_err());
+        assert!(commands(&argv(&["true", "---", " "])).is_err());
+        assert!(commands(&argv(&["echo 'unterminated"])).is_err());
+    }
+
+    #[test]
+    fn test_result_line() {
+        assert_eq!(
+            result_line(2, &argv(&["curl", "b c"]), Duration::from_millis(312), 0),
+            "timeout: race: command 2 won, 0.312s, exit 0: curl 'b c'"
+        );
+    }
+}
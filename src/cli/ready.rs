@@ -0,0 +1,64 @@
+//! Telling that the command is ready other than by its output, for `--until-port` and
+//! `--until-file`: something checked over and over from a thread of its own while the command
+//! runs.
+
+use super::relay::{MatchAction, OutputMatch};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Why the run ended when a [`ReadyWatch`] found the command ready, as reported in the JSON
+/// summary, and what it found.
+pub type Ready = (&'static str, String);
+
+/// Checks whether the command is ready every interval, and stops the command, or detaches
+/// from it, once it is, unless its output matched or another watch found it ready first.
+#[derive(Debug)]
+pub struct ReadyWatch {
+    ready: Arc<OnceLock<Ready>>,
+    _running: Sender<()>,
+}
+
+impl ReadyWatch {
+    /// Start calling `check` every `interval`. Once it returns what it found, `watch` is claimed
+    /// and acted on with `action`, and the run is put down to `reason`.
+    pub fn start<F>(
+        reason: &'static str,
+        interval: Duration,
+        watch: OutputMatch,
+        action: MatchAction,
+        mut check: F,
+    ) -> Self
+    where
+        F: FnMut() -> Option<String> + Send + 'static,
+    {
+        let ready = Arc::new(OnceLock::new());
+        let (running, stopped) = mpsc::channel();
+        let found = Arc::clone(&ready);
+        std::thread::spawn(move || {
+            loop {
+                if let Some(what) = check() {
+                    if watch.claim(action, &what) {
+                        let _ = found.set((reason, what));
+                        watch.act(action);
+                    }
+                    return;
+                }
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+            }
+        });
+        Self {
+            ready,
+            _running: running,
+        }
+    }
+
+    /// What was found, if the command was found ready before anything else counted. Dropping
+    /// the watch stops the checks.
+    pub fn ready(&self) -> Option<Ready> {
+        self.ready.get().cloned()
+    }
+}
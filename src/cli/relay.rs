@@ -0,0 +1,705 @@
+//! Forwarding the command's output line by line so it can be decorated on the way through.
+
+use super::color::{error, warning};
+use super::report::JsonTarget;
+use super::tail::TailRecorder;
+use clap::ValueEnum;
+use regex::Regex;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, mpsc};
+use std::thread;
+use std::time::Duration;
+use timeout_cli::TimeoutHandle;
+
+/// Lines longer than this are forwarded in pieces rather than buffered whole.
+const MAX_LINE: usize = 64 * 1024;
+
+/// Ends a line the command was stopped in the middle of.
+const CUT_OFF_MARKER: &[u8] = b"\n[output truncated by timeout]\n";
+
+/// Produces the text inserted at the start of each line.
+pub type Prefix = Box<dyn FnMut() -> String + Send>;
+
+/// A cap on how many bytes of the command's output its relays forward between them. Once it is
+/// reached the first relay to notice writes a notice, and the rest of the output is dropped.
+#[derive(Debug, Clone)]
+pub struct OutputLimit {
+    limit: u64,
+    used: Arc<AtomicU64>,
+    truncated: Arc<AtomicBool>,
+}
+
+impl OutputLimit {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            used: Arc::new(AtomicU64::new(0)),
+            truncated: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Take up to `len` bytes from what is left, returning how many may be forwarded.
+    fn claim(&self, len: usize) -> usize {
+        let mut granted = 0;
+        let _ = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                granted = (self.limit - used).min(len as u64);
+                Some(used + granted)
+            });
+        granted as usize
+    }
+
+    /// Whether the limit has been reached for the first time, i.e. the notice is still due.
+    fn first_truncation(&self) -> bool {
+        !self.truncated.swap(true, Ordering::Relaxed)
+    }
+
+    /// The line written once output has been cut off.
+    fn notice(&self) -> String {
+        format!("[timeout: output truncated after {} bytes]\n", self.limit)
+    }
+}
+
+/// What to do with the command once a line of its output matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchAction {
+    /// `--kill-on-match`: say so, and stop the command exactly as if its timeout had expired.
+    Kill,
+    /// `--until-pattern`: the command has got where it needed to, so stop it quietly.
+    Finish,
+    /// `--until-pattern` with `--until-detach`: leave the command running.
+    Detach,
+}
+
+/// Which of the command's streams `--kill-on-match` and `--until-pattern` watch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PatternStream {
+    /// Both stdout and stderr.
+    #[default]
+    Both,
+    /// Only stdout.
+    Stdout,
+    /// Only stderr.
+    Stderr,
+}
+
+impl PatternStream {
+    pub fn stdout(self) -> bool {
+        self != PatternStream::Stderr
+    }
+
+    pub fn stderr(self) -> bool {
+        self != PatternStream::Stdout
+    }
+}
+
+/// Watches the lines passing through the relays for `--kill-on-match` and `--until-pattern`,
+/// and acts on the command at the first line that matches any of them. Other ways of telling
+/// that the command is done, such as `--until-port`, [`claim`](Self::claim) it too, so that
+/// only the first of them counts.
+#[derive(Debug, Clone)]
+pub struct OutputMatch {
+    patterns: Vec<(Regex, MatchAction)>,
+    handle: TimeoutHandle,
+    matched: Arc<OnceLock<(MatchAction, String)>>,
+}
+
+impl OutputMatch {
+    pub fn new(patterns: Vec<(Regex, MatchAction)>, handle: TimeoutHandle) -> Self {
+        Self {
+            patterns,
+            handle,
+            matched: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// The first line that matched, if one has, and what it was acted on with.
+    pub fn matched(&self) -> Option<(MatchAction, &str)> {
+        self.matched
+            .get()
+            .map(|(action, line)| (*action, line.as_str()))
+    }
+
+    /// Record `what` as the first match, unless something matched before it. Returns whether
+    /// it was the first, in which case it is up to the caller to [`act`](Self::act) on it.
+    pub fn claim(&self, action: MatchAction, what: &str) -> bool {
+        self.matched.set((action, what.to_string())).is_ok()
+    }
+
+    /// Stop the command, or detach from it, as `action` says.
+    pub fn act(&self, action: MatchAction) {
+        match action {
+            MatchAction::Kill | MatchAction::Finish => self.handle.cancel(),
+            MatchAction::Detach => self.handle.detach(),
+        }
+    }
+
+    fn check(&self, piece: &[u8]) {
+        if self.matched.get().is_some() {
+            return;
+        }
+        let line = String::from_utf8_lossy(piece);
+        let line = line.trim_end_matches(['\n', '\r']);
+        let Some((pattern, action)) = self
+            .patterns
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(line))
+        else {
+            return;
+        };
+        if !self.claim(*action, line) {
+            return;
+        }
+        if *action == MatchAction::Kill {
+            error!(
+                "output matched '{}', stopping the command: {}",
+                pattern, line
+            );
+        }
+        self.act(*action);
+    }
+}
+
+/// Keeps timeout's own lines, such as `--heartbeat`'s, from landing in the middle of a line of
+/// the command's output relayed to the same stream.
+#[derive(Debug, Clone)]
+pub struct LineGate {
+    state: Arc<Mutex<GateState>>,
+}
+
+#[derive(Debug)]
+struct GateState {
+    target: JsonTarget,
+    /// Whether the relay has written part of a line and not yet the rest of it.
+    mid_line: bool,
+    /// Lines waiting for the relay to finish its current one.
+    waiting: Vec<String>,
+}
+
+impl GateState {
+    fn write_waiting(&mut self) {
+        for line in self.waiting.drain(..) {
+            let _ = self.target.write_line(&line);
+        }
+    }
+}
+
+impl LineGate {
+    pub fn new(target: JsonTarget) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(GateState {
+                target,
+                mid_line: false,
+                waiting: Vec::new(),
+            })),
+        }
+    }
+
+    /// Write `line` and a newline to the gate's stream now if the relayed output is between
+    /// lines, or as soon as the relay finishes the line it is part way through.
+    pub fn write_line(&self, line: &str) {
+        let mut state = self.lock();
+        state.waiting.push(line.to_string());
+        if !state.mid_line {
+            state.write_waiting();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, GateState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Splits a byte stream into lines and writes each one to `out` with a prefix.
+///
+/// Lines that are not valid UTF-8 are passed through untouched, as are the continuations of
+/// lines longer than [`MAX_LINE`]. A trailing partial line is written by [`finish`](Self::finish).
+pub struct LineRelay<W: Write> {
+    out: W,
+    prefix: Prefix,
+    pending: Vec<u8>,
+    mid_line: bool,
+    after_cr: bool,
+    cr_lines: bool,
+    broken: bool,
+    unbuffered: bool,
+    limit: Option<OutputLimit>,
+    watch: Option<OutputMatch>,
+    tee: Option<File>,
+    tail: Option<TailRecorder>,
+    gate: Option<LineGate>,
+    /// Whether the stream itself, as opposed to what was forwarded of it, ends part way through
+    /// a line so far.
+    cut: bool,
+}
+
+impl<W: Write> LineRelay<W> {
+    pub fn new(out: W, prefix: Prefix) -> Self {
+        Self {
+            out,
+            prefix,
+            pending: Vec::new(),
+            mid_line: false,
+            after_cr: false,
+            cr_lines: false,
+            broken: false,
+            unbuffered: false,
+            limit: None,
+            watch: None,
+            tee: None,
+            tail: None,
+            gate: None,
+            cut: false,
+        }
+    }
+
+    /// Hold lines written through `gate` back while a line of this stream is part written.
+    pub fn gate(mut self, gate: Option<LineGate>) -> Self {
+        self.gate = gate;
+        self
+    }
+
+    /// Also write the stream to `tee` exactly as it arrives, before any line is decorated,
+    /// limited or held back. Nothing is buffered on the way, so a command killed mid-line
+    /// leaves everything it wrote in the file.
+    pub fn tee(mut self, tee: Option<File>) -> Self {
+        self.tee = tee;
+        self
+    }
+
+    /// Keep the last lines in `tail`, whether or not they are forwarded.
+    pub fn tail(mut self, tail: Option<TailRecorder>) -> Self {
+        self.tail = tail;
+        self
+    }
+
+    /// Check each line against `watch`, whether or not it is forwarded.
+    pub fn watch(mut self, watch: Option<OutputMatch>) -> Self {
+        self.watch = watch;
+        self
+    }
+
+    /// Stop forwarding output once `limit` is used up.
+    pub fn limit(mut self, limit: Option<OutputLimit>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Treat a carriage return on its own as the end of a line too, so that each redraw of a
+    /// progress line is forwarded as it is drawn and gets a prefix of its own.
+    pub fn carriage_return_lines(mut self, cr_lines: bool) -> Self {
+        self.cr_lines = cr_lines;
+        self
+    }
+
+    /// Forward partial lines as soon as they arrive and flush after every chunk, instead of
+    /// holding output back until a newline.
+    pub fn unbuffered(mut self, unbuffered: bool) -> Self {
+        self.unbuffered = unbuffered;
+        self
+    }
+
+    /// Feed a chunk of output into the relay.
+    pub fn write(&mut self, chunk: &[u8]) {
+        if let Some(tee) = &mut self.tee
+            && let Err(e) = tee.write_all(chunk)
+        {
+            warning!(
+                "cannot copy output to the tee file, no longer trying: {}",
+                e
+            );
+            self.tee = None;
+        }
+        self.cut = !chunk.ends_with(b"\n");
+        self.pending.extend_from_slice(chunk);
+        while let Some(end) = self.line_end() {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.emit(&line);
+        }
+        if self.pending.len() >= MAX_LINE || (self.unbuffered && !self.pending.is_empty()) {
+            let piece = std::mem::take(&mut self.pending);
+            self.emit(&piece);
+        }
+        if self.unbuffered && !self.broken && self.out.flush().is_err() {
+            self.broken = true;
+        }
+    }
+
+    /// Write out any partial line left at the end of the stream. When the command was
+    /// `stopped` by the timeout in the middle of a line, that line is ended with a marker saying
+    /// so, both where it is forwarded and in the tee file.
+    pub fn finish(&mut self, stopped: bool) {
+        if !self.pending.is_empty() {
+            let piece = std::mem::take(&mut self.pending);
+            self.emit(&piece);
+        }
+        if stopped && self.cut {
+            if let Some(tee) = &mut self.tee {
+                let _ = tee.write_all(CUT_OFF_MARKER);
+            }
+            // Unless --max-output cut it off first, with a notice of its own.
+            if self.mid_line && !self.broken && self.out.write_all(CUT_OFF_MARKER).is_err() {
+                self.broken = true;
+            }
+            self.mid_line = false;
+        }
+        if let Some(tail) = &mut self.tail {
+            tail.finish();
+        }
+        if !self.broken {
+            let _ = self.out.flush();
+        }
+        // The line left unfinished never will be, and whatever waited on it is out of date.
+        if let Some(gate) = &self.gate {
+            let mut gate = gate.lock();
+            gate.mid_line = false;
+            gate.waiting.clear();
+        }
+    }
+
+    /// Whether nothing needs the rest of the stream any more: the destination has gone, e.g. a
+    /// pipe into `head` that has exited, and there is no tee file or pattern to feed.
+    pub fn abandoned(&self) -> bool {
+        self.broken && self.tee.is_none() && self.watch.is_none()
+    }
+
+    /// Where the first complete line in `pending` ends, keeping `\r\n` together.
+    fn line_end(&self) -> Option<usize> {
+        let end = self
+            .pending
+            .iter()
+            .position(|&b| b == b'\n' || (self.cr_lines && b == b'\r'))?;
+        match self.pending.get(end..end + 2) {
+            Some(b"\r\n") => Some(end + 1),
+            _ => Some(end),
+        }
+    }
+
+    fn emit(&mut self, piece: &[u8]) {
+        if let Some(watch) = &self.watch {
+            watch.check(piece);
+        }
+        if let Some(tail) = &mut self.tail {
+            tail.record(piece);
+        }
+        let allowed = self
+            .limit
+            .as_ref()
+            .map_or(piece.len(), |limit| limit.claim(piece.len()));
+        let mut buffer = Vec::with_capacity(allowed + 32);
+        if allowed > 0 {
+            let line_start = !self.mid_line || (self.after_cr && !piece.starts_with(b"\n"));
+            if line_start && std::str::from_utf8(piece).is_ok() {
+                buffer.extend_from_slice((self.prefix)().as_bytes());
+            }
+            buffer.extend_from_slice(&piece[..allowed]);
+            self.mid_line = !piece[..allowed].ends_with(b"\n");
+            self.after_cr = self.cr_lines && piece[..allowed].ends_with(b"\r");
+        }
+        if allowed < piece.len()
+            && let Some(limit) = &self.limit
+            && limit.first_truncation()
+        {
+            if self.mid_line {
+                buffer.push(b'\n');
+                self.mid_line = false;
+            }
+            buffer.extend_from_slice(limit.notice().as_bytes());
+        }
+        if buffer.is_empty() {
+            return;
+        }
+
+        let gate = self.gate.clone();
+        let mut gate = gate.as_ref().map(LineGate::lock);
+        // Once the destination is gone keep consuming the stream, so the command does not block
+        // on a full pipe, but stop trying to write it anywhere.
+        if !self.broken && self.out.write_all(&buffer).is_err() {
+            self.broken = true;
+        }
+        if let Some(gate) = &mut gate {
+            let _ = self.out.flush();
+            gate.mid_line = self.mid_line && !self.broken;
+            if !gate.mid_line {
+                gate.write_waiting();
+            }
+        }
+    }
+}
+
+/// A reader thread feeding a [`LineRelay`].
+pub struct RelayThread {
+    done: mpsc::Receiver<Box<dyn FnOnce(bool) + Send>>,
+}
+
+impl RelayThread {
+    pub fn spawn<R, W>(mut reader: R, mut relay: LineRelay<W>) -> Self
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let (tx, done) = mpsc::channel();
+        thread::spawn(move || {
+            let mut chunk = [0u8; 8192];
+            loop {
+                match reader.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        relay.write(&chunk[..n]);
+                        // Closing our end lets the command's next write fail with SIGPIPE, as
+                        // it would have had it written to the closed pipe itself.
+                        if relay.abandoned() {
+                            break;
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+            }
+            // Finished once whoever waits knows whether the command was stopped.
+            let finish: Box<dyn FnOnce(bool) + Send> =
+                Box::new(move |stopped| relay.finish(stopped));
+            let _ = tx.send(finish);
+        });
+        Self { done }
+    }
+
+    /// Wait up to `grace` for the stream to be fully relayed, then write out what is left of
+    /// it, saying so if it was cut off because the command was `stopped` by the timeout.
+    pub fn finish(self, grace: Duration, stopped: bool) {
+        if let Ok(finish) = self.done.recv_timeout(grace) {
+            finish(stopped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relay() -> LineRelay<Vec<u8>> {
+        LineRelay::new(Vec::new(), Box::new(|| "> ".to_string()))
+    }
+
+    #[test]
+    fn test_prefixes_each_line() {
+        let mut relay = relay();
+        relay.write(b"one\ntwo\n");
+        relay.finish(false);
+        assert_eq!(relay.out, b"> one\n> two\n");
+    }
+
+    #[test]
+    fn test_joins_lines_split_across_chunks() {
+        let mut relay = relay();
+        relay.write(b"on");
+        relay.write(b"e\ntw");
+        relay.write(b"o\n");
+        relay.finish(false);
+        assert_eq!(relay.out, b"> one\n> two\n");
+    }
+
+    #[test]
+    fn test_flushes_partial_line_on_finish() {
+        let mut relay = relay();
+        relay.write(b"one\npartial");
+        relay.finish(false);
+        assert_eq!(relay.out, b"> one\n> partial");
+    }
+
+    #[test]
+    fn test_marks_a_line_cut_off_by_the_timeout() {
+        let mut cut = relay();
+        cut.write(b"one\npartial");
+        cut.finish(true);
+        assert_eq!(
+            cut.out,
+            b"> one\n> partial\n[output truncated by timeout]\n"
+        );
+
+        let mut whole = relay();
+        whole.write(b"one\n");
+        whole.finish(true);
+        assert_eq!(whole.out, b"> one\n");
+    }
+
+    #[test]
+    fn test_passes_binary_lines_through() {
+        let mut relay = relay();
+        relay.write(b"\xff\xfe\nok\n");
+        relay.finish(false);
+        assert_eq!(relay.out, b"\xff\xfe\n> ok\n");
+    }
+
+    #[test]
+    fn test_long_line_prefixed_once() {
+        let mut relay = relay();
+        let long = vec![b'x'; MAX_LINE * 2 + 10];
+        relay.write(&long);
+        relay.write(b"\n");
+        relay.finish(false);
+        assert_eq!(relay.out.len(), long.len() + 3);
+        assert!(relay.out.starts_with(b"> x"));
+        assert_eq!(relay.out.iter().filter(|&&b| b == b'>').count(), 1);
+    }
+
+    #[test]
+    fn test_carriage_return_lines_are_prefixed_after_each_return() {
+        let mut relay = relay().carriage_return_lines(true);
+        relay.write(b"10%\r50%\r");
+        assert_eq!(relay.out, b"> 10%\r> 50%\r");
+        relay.write(b"100%\r\ndone\r\n");
+        relay.finish(false);
+        assert_eq!(relay.out, b"> 10%\r> 50%\r> 100%\r\n> done\r\n");
+    }
+
+    #[test]
+    fn test_carriage_return_then_newline_across_chunks() {
+        let mut relay = relay().carriage_return_lines(true);
+        relay.write(b"one\r");
+        relay.write(b"\ntwo\n");
+        relay.finish(false);
+        assert_eq!(relay.out, b"> one\r\n> two\n");
+    }
+
+    #[test]
+    fn test_limit_truncates_with_one_notice() {
+        let limit = OutputLimit::new(6);
+        let mut stdout = relay().limit(Some(limit.clone()));
+        let mut stderr = relay().limit(Some(limit));
+        stdout.write(b"one\n");
+        stderr.write(b"two\nthree\n");
+        stdout.write(b"four\n");
+        stdout.finish(false);
+        stderr.finish(false);
+        assert_eq!(stdout.out, b"> one\n");
+        assert_eq!(
+            stderr.out,
+            b"> tw\n[timeout: output truncated after 6 bytes]\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_watch_cancels_at_first_matching_line() {
+        let child = timeout_cli::TimeoutCommand::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        let watch = OutputMatch::new(
+            vec![
+                (Regex::new("FATAL").unwrap(), MatchAction::Kill),
+                (Regex::new("ready").unwrap(), MatchAction::Finish),
+            ],
+            child.handle(),
+        );
+        let mut relay = relay().watch(Some(watch.clone()));
+        relay.write(b"starting\nFATAL: disk full\nFATAL: again\nready\n");
+        relay.finish(false);
+        assert_eq!(
+            watch.matched(),
+            Some((MatchAction::Kill, "FATAL: disk full"))
+        );
+        assert_eq!(
+            child.wait().unwrap().result,
+            timeout_cli::TimeoutResult::Cancelled
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_watch_can_detach_and_reads_binary_output() {
+        let child = timeout_cli::TimeoutCommand::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        let pid = child.pid();
+        let watch = OutputMatch::new(
+            vec![(Regex::new("^listening on").unwrap(), MatchAction::Detach)],
+            child.handle(),
+        );
+        let mut relay = relay().watch(Some(watch.clone()));
+        relay.write(b"\xff\xfe\x00binary\nlistening on :8080\n");
+        assert_eq!(
+            watch.matched(),
+            Some((MatchAction::Detach, "listening on :8080"))
+        );
+        assert_eq!(
+            child.wait().unwrap().result,
+            timeout_cli::TimeoutResult::Detached
+        );
+        // SAFETY: kill only sends a signal, to the command left running.
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+    }
+
+    /// Records how many times it was flushed.
+    #[derive(Default)]
+    struct CountingWriter {
+        data: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_unbuffered_forwards_partial_lines_immediately() {
+        let mut relay = LineRelay::new(CountingWriter::default(), Box::new(|| "> ".to_string()))
+            .unbuffered(true);
+        relay.write(b"one\npar");
+        assert_eq!(relay.out.data, b"> one\n> par");
+        assert_eq!(relay.out.flushes, 1);
+        relay.write(b"tial\n");
+        relay.finish(false);
+        assert_eq!(relay.out.data, b"> one\n> partial\n");
+    }
+
+    #[test]
+    fn test_buffered_holds_partial_lines() {
+        let mut relay = LineRelay::new(CountingWriter::default(), Box::new(|| "> ".to_string()));
+        relay.write(b"one\npar");
+        assert_eq!(relay.out.data, b"> one\n");
+        assert_eq!(relay.out.flushes, 0);
+    }
+
+    /// A destination that has gone away, like a pipe whose reader has exited.
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    #[test]
+    fn test_abandoned_once_nothing_needs_the_stream() {
+        let mut relay = LineRelay::new(ClosedPipe, Box::new(String::new));
+        assert!(!relay.abandoned());
+        relay.write(b"line\n");
+        assert!(relay.abandoned());
+
+        // Still copied to the tee file, so still read.
+        let path = std::env::temp_dir().join(format!("timeout-relay-{}", std::process::id()));
+        let tee = File::create(&path).unwrap();
+        let mut relay = LineRelay::new(ClosedPipe, Box::new(String::new)).tee(Some(tee));
+        relay.write(b"line\n");
+        assert!(!relay.abandoned());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+//! The JSON summary of a run written by `--json`.
+
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+use timeout_cli::{Signal, TimeoutError, TimeoutOutcome, TimeoutReason};
+
+use super::atomic::write_atomic;
+use super::respawn::RespawnSummary;
+use super::stats::UsageSummary;
+use super::timestamp;
+
+/// Where `--json` writes its report, `--print-pid` the PID or `--heartbeat` its lines.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum JsonTarget {
+    /// timeout's own stderr
+    #[default]
+    Stderr,
+    /// timeout's own stdout
+    Stdout,
+}
+
+impl JsonTarget {
+    /// Write `line` followed by a newline.
+    pub fn write_line(self, line: &str) -> io::Result<()> {
+        match self {
+            JsonTarget::Stderr => writeln!(io::stderr().lock(), "{}", line),
+            JsonTarget::Stdout => writeln!(io::stdout().lock(), "{}", line),
+        }
+    }
+}
+
+/// A machine-readable summary of one run of the command.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// The command that was run.
+    pub command: String,
+    /// Its arguments.
+    pub args: Vec<String>,
+    /// Its process ID, or `null` if it was never started.
+    pub pid: Option<u32>,
+    /// When it was started, as an ISO 8601 UTC timestamp.
+    pub started_at: String,
+    /// Milliseconds between starting the command and it being reaped.
+    pub elapsed_ms: u64,
+    /// Whether the timeout expired before the command exited.
+    pub timed_out: bool,
+    /// The first signal sent to the command, e.g. `SIGTERM`.
+    pub signal_sent: Option<String>,
+    /// Whether the command had to be sent `SIGKILL`.
+    pub kill_sent: bool,
+    /// The command's exit code, if it exited normally.
+    pub exit_code: Option<i32>,
+    /// The signal that terminated the command, if one did.
+    pub term_signal: Option<i32>,
+    /// Why the run ended; see [`reason_name`], or `output_matched` when `--kill-on-match`
+    /// stopped the command, or `port_open`, `file_exists` or `file_gone` when `--until-port`,
+    /// `--until-file` or `--until-file-gone` did, or `unhealthy` when `--healthcheck-cmd` did, or
+    /// `premature_exit` when the command exited before `--min-runtime`.
+    pub reason: &'static str,
+    /// What went wrong, when the command could not be run or supervised.
+    pub error: Option<String>,
+    /// How many times the command was run, with `--retries`; the rest of the report describes
+    /// the last attempt.
+    pub attempts: u32,
+    /// What the `--respawn` window came to, only present in that mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub respawn: Option<RespawnSummary>,
+    /// What the command used of the machine, only present with `--stats`, and only when that
+    /// could be had.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageSummary>,
+}
+
+impl Report {
+    /// Summarise `outcome` for `command` started at `started_at`.
+    pub fn new(
+        command: &str,
+        args: &[String],
+        pid: Option<u32>,
+        started_at: SystemTime,
+        outcome: &TimeoutOutcome,
+        error: Option<&TimeoutError>,
+    ) -> Self {
+        #[cfg(unix)]
+        let term_signal = {
+            use std::os::unix::process::ExitStatusExt;
+            outcome.status.and_then(|status| status.signal())
+        };
+        #[cfg(not(unix))]
+        let term_signal = None;
+
+        Self {
+            command: command.to_string(),
+            args: args.to_vec(),
+            pid,
+            started_at: timestamp::iso8601(started_at),
+            elapsed_ms: u64::try_from(outcome.elapsed.as_millis()).unwrap_or(u64::MAX),
+            timed_out: matches!(
+                outcome.reason,
+                TimeoutReason::TermExit
+                    | TimeoutReason::Killed
+                    | TimeoutReason::SpawnFailed {
+                        kind: io::ErrorKind::TimedOut
+                    }
+            ),
+            signal_sent: outcome.signal_sent.map(|sent| sent.signal.to_string()),
+            kill_sent: outcome.reason == TimeoutReason::Killed
+                || outcome
+                    .signal_sent
+                    .is_some_and(|sent| sent.signal == Signal::Kill),
+            exit_code: outcome.status.and_then(|status| status.code()),
+            term_signal,
+            reason: reason_name(outcome.reason),
+            error: error.map(ToString::to_string),
+            attempts: 1,
+            respawn: None,
+            usage: None,
+        }
+    }
+
+    /// The report as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// The line `--report` prints on stderr once timeout is about to exit with `exit_code`,
+    /// e.g. `timeout: report: reason=timed_out exit=124`.
+    pub fn summary_line(&self, exit_code: u8) -> String {
+        format!("timeout: report: reason={} exit={}", self.reason, exit_code)
+    }
+
+    /// Atomically replace `path` with the report.
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        write_atomic(path, format!("{}\n", self.to_json()).as_bytes())
+    }
+}
+
+/// The `reason` reported for an outcome: `completed`, `timed_out`, `killed`, `cancelled`,
+/// `detached`, `not_found`, `permission_denied`, `is_a_directory`, `spawn_timed_out`,
+/// `spawn_failed` or `supervision_failed`.
+pub fn reason_name(reason: TimeoutReason) -> &'static str {
+    match reason {
+        TimeoutReason::CompletedInTime => "completed",
+        TimeoutReason::TermExit => "timed_out",
+        TimeoutReason::Killed => "killed",
+        TimeoutReason::Cancelled => "cancelled",
+        TimeoutReason::Detached => "detached",
+        TimeoutReason::SpawnFailed {
+            kind: io::ErrorKind::NotFound,
+        } => "not_found",
+        TimeoutReason::SpawnFailed {
+            kind: io::ErrorKind::PermissionDenied,
+        } => "permission_denied",
+        TimeoutReason::SpawnFailed {
+            kind: io::ErrorKind::IsADirectory,
+        } => "is_a_directory",
+        TimeoutReason::SpawnFailed {
+            kind: io::ErrorKind::TimedOut,
+        } => "spawn_timed_out",
+        TimeoutReason::SpawnFailed { .. } => "spawn_failed",
+        TimeoutReason::SupervisionFailed { .. } => "supervision_failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_not_found_report() {
+        let error = TimeoutError::from_spawn("missing", io::Error::from(io::ErrorKind::NotFound));
+        let outcome = TimeoutOutcome::failed(&error, Duration::from_millis(3));
+        let report = Report::new(
+            "missing",
+            &["a".to_string()],
+            None,
+            UNIX_EPOCH,
+            &outcome,
+            Some(&error),
+        );
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["command"], "missing");
+        assert_eq!(json["args"], serde_json::json!(["a"]));
+        assert_eq!(json["pid"], serde_json::Value::Null);
+        assert_eq!(json["started_at"], "1970-01-01T00:00:00.000Z");
+        assert_eq!(json["elapsed_ms"], 3);
+        assert_eq!(json["timed_out"], false);
+        assert_eq!(json["kill_sent"], false);
+        assert_eq!(json["exit_code"], serde_json::Value::Null);
+        assert_eq!(json["reason"], "not_found");
+        assert!(json["error"].is_string());
+    }
+
+    #[test]
+    fn test_killed_report() {
+        let outcome = TimeoutOutcome::new(TimeoutReason::Killed, Duration::from_secs(2));
+        let report = Report::new("sleep", &[], Some(42), UNIX_EPOCH, &outcome, None);
+        assert!(report.timed_out);
+        assert!(report.kill_sent);
+        assert_eq!(report.reason, "killed");
+        assert_eq!(report.error, None);
+        assert_eq!(
+            report.summary_line(137),
+            "timeout: report: reason=killed exit=137"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! `--report-fd` streaming of lifecycle events to an inherited file descriptor.
+
+use super::fd::InheritedFd;
+use std::io;
+use std::time::Duration;
+use timeout_cli::{EventSink, Signal, TimeoutResult};
+
+/// Writes one `key=value` line per lifecycle event, e.g. `spawned pid=1234`,
+/// `timeout signal=SIGTERM elapsed_ms=5000`, `kill elapsed_ms=6000` and
+/// `exit result=completed code=0 elapsed_ms=12`.
+#[derive(Debug)]
+pub struct ReportFd(InheritedFd);
+
+impl ReportFd {
+    /// Stream events to `fd`, failing as [`InheritedFd::open`] does.
+    pub fn open(fd: i32) -> io::Result<Self> {
+        InheritedFd::open(fd).map(Self)
+    }
+
+    fn write(&self, line: String) {
+        // There is nowhere left to report a failure to report.
+        let _ = self.0.write_line(&line);
+    }
+}
+
+impl EventSink for ReportFd {
+    fn on_spawn(&self, pid: u32) {
+        self.write(format!("spawned pid={}", pid));
+    }
+
+    fn on_signal(&self, signal: Signal, elapsed: Duration) {
+        self.write(format!(
+            "timeout signal={} elapsed_ms={}",
+            signal,
+            elapsed.as_millis()
+        ));
+    }
+
+    fn on_kill(&self, elapsed: Duration) {
+        self.write(format!("kill elapsed_ms={}", elapsed.as_millis()));
+    }
+
+    fn on_exit(&self, result: TimeoutResult, elapsed: Duration) {
+        self.write(exit_line(result, elapsed));
+    }
+}
+
+/// The line reported when supervision finishes.
+fn exit_line(result: TimeoutResult, elapsed: Duration) -> String {
+    let result = match result {
+        TimeoutResult::Completed(code) => format!("completed code={}", code),
+        result => result_name(result).to_string(),
+    };
+    format!("exit result={} elapsed_ms={}", result, elapsed.as_millis())
+}
+
+/// The name `result` is reported under, e.g. `timed_out`.
+pub fn result_name(result: TimeoutResult) -> &'static str {
+    match result {
+        TimeoutResult::Completed(_) => "completed",
+        TimeoutResult::TimedOut => "timed_out",
+        TimeoutResult::Killed => "killed",
+        TimeoutResult::Cancelled => "cancelled",
+        TimeoutResult::Detached => "detached",
+        TimeoutResult::NotFound => "not_found",
+        TimeoutResult::CannotInvoke => "permission_denied",
+        TimeoutResult::InternalError => "error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_line() {
+        assert_eq!(
+            exit_line(TimeoutResult::Completed(3), Duration::from_millis(12)),
+            "exit result=completed code=3 elapsed_ms=12"
+        );
+        assert_eq!(
+            exit_line(TimeoutResult::TimedOut, Duration::from_secs(1)),
+            "exit result=timed_out elapsed_ms=1000"
+        );
+    }
+}
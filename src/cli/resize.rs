@@ -0,0 +1,150 @@
+//! Passing terminal resizes (`SIGWINCH`) on to a command in a process group of its own, which
+//! the terminal does not tell about them, or to the pseudo-terminal a `--pty` command runs on.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use timeout_cli::self_pipe::SelfPipe;
+
+/// The commands running, to forward resizes to, with the master end of the pseudo-terminal
+/// each one runs on, if any.
+static COMMANDS: Mutex<Vec<(u32, Option<File>)>> = Mutex::new(Vec::new());
+
+/// The pipe from the signal handler to the forwarding thread.
+static WAKE: SelfPipe = SelfPipe::new();
+
+/// Catch `SIGWINCH` for the rest of the process's life and pass each one on to the process
+/// groups of the commands given to [`forward_to`]. Installing it again does nothing.
+pub fn install() -> io::Result<()> {
+    extern "C" fn on_winch(_: libc::c_int) {
+        WAKE.wake(0);
+    }
+
+    if WAKE.is_open() {
+        return Ok(());
+    }
+    // Only the latest size matters, so one pass covers every resize read at once.
+    WAKE.open("timeout-resize", |_| {
+        commands()
+            .iter()
+            .for_each(|(pid, master)| forward(*pid, master.as_ref()))
+    })?;
+    // SAFETY: the handler only wakes the pipe.
+    let previous =
+        unsafe { libc::signal(libc::SIGWINCH, on_winch as *const () as libc::sighandler_t) };
+    if previous == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Forward resizes to the command `pid` until the returned guard is dropped. A command running
+/// on a pseudo-terminal is given its `master` end: the new size is set on that, and the
+/// terminal itself then tells the command.
+pub fn forward_to(pid: u32, master: Option<File>) -> Forwarding {
+    commands().push((pid, master));
+    Forwarding { pid }
+}
+
+/// Keeps resizes going to a command; see [`forward_to`].
+#[derive(Debug)]
+pub struct Forwarding {
+    pid: u32,
+}
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        commands().retain(|(pid, _)| *pid != self.pid);
+    }
+}
+
+fn commands() -> MutexGuard<'static, Vec<(u32, Option<File>)>> {
+    COMMANDS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Give the pseudo-terminal `master` the size of timeout's own terminal or, without one, send
+/// `SIGWINCH` to the process group of `pid`, unless it is timeout's own: the terminal has
+/// already told that one, if it is in the foreground.
+fn forward(pid: u32, master: Option<&File>) {
+    if let Some(master) = master {
+        if let Some(size) = super::pty::window_size() {
+            let _ = set_window_size(master, &size);
+        }
+        return;
+    }
+    // SAFETY: getpgid, getpgrp and killpg only look up and signal processes.
+    unsafe {
+        let group = libc::getpgid(pid as libc::pid_t);
+        if group > 0 && group != libc::getpgrp() {
+            libc::killpg(group, libc::SIGWINCH);
+        }
+    }
+}
+
+/// Set the size of the terminal `file` is open on, which sends `SIGWINCH` to its foreground
+/// process group if the size changed.
+fn set_window_size(file: &File, size: &libc::winsize) -> io::Result<()> {
+    // SAFETY: TIOCSWINSZ only reads the winsize given.
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::TIOCSWINSZ, size) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_resize_reaches_a_command_in_its_own_group() {
+        install().unwrap();
+        let mut child = Command::new("sh")
+            .args([
+                "-c",
+                "trap 'echo resized; exit 0' WINCH; echo ready; sleep 5 >/dev/null & wait",
+            ])
+            .process_group(0)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        let mut ready = [0u8; 6];
+        stdout.read_exact(&mut ready).unwrap();
+        let _forwarding = forward_to(child.id(), None);
+
+        // SAFETY: raise only sends a signal, which the handler installed above catches.
+        unsafe { libc::raise(libc::SIGWINCH) };
+        let start = Instant::now();
+        let status = child.wait().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(4));
+        assert!(status.success());
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "resized\n");
+    }
+
+    #[test]
+    fn test_size_is_set_on_the_pseudo_terminal() {
+        let pty = crate::cli::pty::Pty::open().unwrap();
+        let size = libc::winsize {
+            ws_row: 42,
+            ws_col: 123,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        set_window_size(&pty.master, &size).unwrap();
+        let mut seen: libc::winsize = unsafe { std::mem::zeroed() };
+        // SAFETY: TIOCGWINSZ only writes a winsize to the pointer given.
+        assert_eq!(
+            unsafe { libc::ioctl(pty.slave.as_raw_fd(), libc::TIOCGWINSZ, &mut seen) },
+            0
+        );
+        assert_eq!((seen.ws_row, seen.ws_col), (42, 123));
+    }
+}
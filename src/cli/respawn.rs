@@ -0,0 +1,59 @@
+//! `--respawn`: what restarting the command for the whole window came to.
+
+use serde::Serialize;
+use std::time::Duration;
+
+/// How many times the command ran in the `--respawn` window, and how many of those failed.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RespawnSummary {
+    /// Runs started, including the one stopped when the window ended.
+    pub iterations: u32,
+    /// Runs that exited with a non-zero code or could not be started.
+    pub failures: u32,
+    /// Milliseconds from the start of the first run to the end of the last.
+    pub total_ms: u64,
+    /// Whether the window ran out, as opposed to `--stop-on-failure` or a signal ending it
+    /// early.
+    #[serde(skip)]
+    pub window_ended: bool,
+}
+
+impl RespawnSummary {
+    /// Record the total runtime.
+    pub fn finish(&mut self, total: Duration) {
+        self.total_ms = u64::try_from(total.as_millis()).unwrap_or(u64::MAX);
+    }
+
+    /// The summary as printed on stderr.
+    pub fn line(&self) -> String {
+        format!(
+            "timeout: respawn: {} iterations, {} failed, {:.3}s total",
+            self.iterations,
+            self.failures,
+            Duration::from_millis(self.total_ms).as_secs_f64()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_line() {
+        let mut summary = RespawnSummary {
+            iterations: 57,
+            failures: 2,
+            ..RespawnSummary::default()
+        };
+        summary.finish(Duration::from_millis(10_004));
+        assert_eq!(
+            summary.line(),
+            "timeout: respawn: 57 iterations, 2 failed, 10.004s total"
+        );
+        assert_eq!(
+            serde_json::to_string(&summary).unwrap(),
+            r#"{"iterations":57,"failures":2,"total_ms":10004}"#
+        );
+    }
+}
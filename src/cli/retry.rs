@@ -0,0 +1,157 @@
+//! `--retries` and `--retry-on`: which outcomes the command is run again after, and how long
+//! to wait before each new attempt.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use timeout_cli::{TimeoutOutcome, TimeoutResult};
+
+/// An outcome worth another attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetryOn {
+    /// The timeout expired, whatever exit code that leads to.
+    Timeout,
+    /// timeout would exit with one of these codes.
+    Codes(RangeInclusive<u8>),
+}
+
+impl RetryOn {
+    /// Whether an attempt that ended in `outcome`, for which timeout would exit with
+    /// `exit_code`, should be retried.
+    pub fn matches(&self, outcome: &TimeoutOutcome, exit_code: u8) -> bool {
+        match self {
+            RetryOn::Timeout => matches!(
+                outcome.result,
+                TimeoutResult::TimedOut | TimeoutResult::Killed
+            ),
+            RetryOn::Codes(codes) => codes.contains(&exit_code),
+        }
+    }
+}
+
+/// Parse `timeout`, an exit code (`111`) or an inclusive range of them (`1-10`).
+pub fn parse_retry_on(arg: &str) -> Result<RetryOn, String> {
+    let arg = arg.trim();
+    if arg.eq_ignore_ascii_case("timeout") {
+        return Ok(RetryOn::Timeout);
+    }
+    let code = |code: &str| {
+        code.trim()
+            .parse::<u8>()
+            .map_err(|_| format!("invalid exit code '{}' (use 0-255 or 'timeout')", code))
+    };
+    let codes = match arg.split_once('-') {
+        Some((start, end)) => code(start)?..=code(end)?,
+        None => code(arg)?..=code(arg)?,
+    };
+    if codes.is_empty() {
+        return Err(format!("empty exit code range '{}'", arg));
+    }
+    Ok(RetryOn::Codes(codes))
+}
+
+/// Whether any of `conditions` asks for another attempt; with none given, only a timeout
+/// does.
+pub fn should_retry(conditions: &[RetryOn], outcome: &TimeoutOutcome, exit_code: u8) -> bool {
+    if conditions.is_empty() {
+        return RetryOn::Timeout.matches(outcome, exit_code);
+    }
+    conditions
+        .iter()
+        .any(|condition| condition.matches(outcome, exit_code))
+}
+
+/// How long to wait before each retry: `initial`, doubling each time up to `max`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Option<Duration>,
+    /// Wait a random time between half the delay and all of it, so that many runs failing
+    /// together do not all retry together.
+    pub jitter: bool,
+}
+
+impl Backoff {
+    /// The delay before retry number `retry`, counting from 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let delay = self.initial.saturating_mul(factor);
+        let delay = self.max.map_or(delay, |max| delay.min(max));
+        if self.jitter {
+            delay / 2 + (delay / 2).mul_f64(random_fraction())
+        } else {
+            delay
+        }
+    }
+}
+
+/// A number in `[0, 1)` that differs from run to run.
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().hash_one(std::process::id()) >> 11;
+    bits as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use timeout_cli::TimeoutReason;
+
+    #[test]
+    fn test_parse_retry_on() {
+        assert_eq!(parse_retry_on("timeout"), Ok(RetryOn::Timeout));
+        assert_eq!(parse_retry_on("TIMEOUT"), Ok(RetryOn::Timeout));
+        assert_eq!(parse_retry_on("111"), Ok(RetryOn::Codes(111..=111)));
+        assert_eq!(parse_retry_on("1-10"), Ok(RetryOn::Codes(1..=10)));
+        for arg in ["", "x", "256", "-1", "10-1", "1-", "1-2-3"] {
+            assert!(parse_retry_on(arg).is_err(), "{arg} parsed");
+        }
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let timed_out = TimeoutOutcome::new(TimeoutReason::TermExit, Duration::ZERO);
+        let exited = TimeoutOutcome::new(TimeoutReason::CompletedInTime, Duration::ZERO);
+        assert!(should_retry(&[], &timed_out, 124));
+        assert!(!should_retry(&[], &exited, 124));
+        let codes = [RetryOn::Codes(1..=10)];
+        assert!(should_retry(&codes, &exited, 3));
+        assert!(!should_retry(&codes, &exited, 0));
+        assert!(!should_retry(&codes, &timed_out, 124));
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let backoff = Backoff {
+            initial: Duration::from_secs(2),
+            max: Some(Duration::from_secs(60)),
+            jitter: false,
+        };
+        let delays: Vec<_> = (1..=7)
+            .map(|retry| backoff.delay(retry).as_secs())
+            .collect();
+        assert_eq!(delays, [2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(Backoff::default().delay(3), Duration::ZERO);
+        let unbounded = Backoff {
+            max: None,
+            ..backoff
+        };
+        assert_eq!(unbounded.delay(100), unbounded.delay(33));
+        assert!(unbounded.delay(33) > Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_backoff_jitter_stays_within_half() {
+        let backoff = Backoff {
+            initial: Duration::from_secs(4),
+            max: None,
+            jitter: true,
+        };
+        for _ in 0..20 {
+            let delay = backoff.delay(1);
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+        }
+    }
+}
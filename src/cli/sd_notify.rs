@@ -0,0 +1,236 @@
+//! `--sd-notify`: telling systemd about the command's progress through `NOTIFY_SOCKET`.
+
+use std::ffi::OsStr;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError, mpsc};
+use std::time::Duration;
+use timeout_cli::{EventSink, Signal, TimeoutResult};
+
+/// The variable systemd sets to the path of its notification socket.
+pub const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+
+/// Reports to systemd as a `Type=notify` service would: `READY=1` once the command has been
+/// spawned, `WATCHDOG=1` at half the `WATCHDOG_USEC` interval while it runs, and `STOPPING=1`
+/// when the timeout signal or `SIGKILL` is first sent.
+///
+/// With `passthrough`, `READY=1` is left for the command itself to send; systemd only accepts
+/// it from the command with `NotifyAccess=all`.
+#[derive(Debug)]
+pub struct SdNotify {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    passthrough: bool,
+    watchdog_interval: Option<Duration>,
+    /// Dropping this stops the watchdog thread.
+    watchdog: Mutex<Option<mpsc::Sender<()>>>,
+    stopping: AtomicBool,
+}
+
+impl SdNotify {
+    /// Connect to the socket systemd gave us, if any, using the watchdog interval it asked
+    /// for.
+    pub fn from_env(passthrough: bool) -> io::Result<Option<Self>> {
+        let Some(socket) = std::env::var_os(NOTIFY_SOCKET) else {
+            return Ok(None);
+        };
+        let watchdog_interval = watchdog_interval(
+            std::env::var("WATCHDOG_USEC").ok().as_deref(),
+            std::env::var("WATCHDOG_PID").ok().as_deref(),
+            std::process::id(),
+        );
+        Self::open(&socket, watchdog_interval, passthrough).map(Some)
+    }
+
+    /// Connect to the notification socket at `path`, which on Linux may start with `@` for
+    /// an abstract socket, feeding the watchdog every `watchdog_interval` if given.
+    #[cfg(unix)]
+    pub fn open(
+        path: &OsStr,
+        watchdog_interval: Option<Duration>,
+        passthrough: bool,
+    ) -> io::Result<Self> {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+        let address = match path.as_bytes().strip_prefix(b"@") {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name)?
+            }
+            _ => SocketAddr::from_pathname(path)?,
+        };
+        let socket = UnixDatagram::unbound()?;
+        socket.connect_addr(&address)?;
+        // A stalled systemd must never hold up supervision; a message it has no room for is
+        // simply dropped.
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            passthrough,
+            watchdog_interval,
+            watchdog: Mutex::new(None),
+            stopping: AtomicBool::new(false),
+        })
+    }
+
+    /// There is no systemd outside Unix.
+    #[cfg(not(unix))]
+    pub fn open(
+        _path: &OsStr,
+        _watchdog_interval: Option<Duration>,
+        _passthrough: bool,
+    ) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only supported on Unix",
+        ))
+    }
+
+    /// Send `state`, e.g. `READY=1`. Failures are ignored, as systemd going away must not
+    /// affect the command.
+    pub fn notify(&self, state: &str) {
+        #[cfg(unix)]
+        let _ = self.socket.send(state.as_bytes());
+        #[cfg(not(unix))]
+        let _ = state;
+    }
+
+    /// Send `STOPPING=1`, but only the first time.
+    fn stopping(&self) {
+        if !self.stopping.swap(true, Ordering::Relaxed) {
+            self.notify("STOPPING=1");
+        }
+    }
+
+    /// Send `WATCHDOG=1` every `interval` on another thread until [`watchdog`](Self::watchdog)
+    /// is cleared.
+    #[cfg(unix)]
+    fn start_watchdog(&self, interval: Duration) {
+        let Ok(socket) = self.socket.try_clone() else {
+            return;
+        };
+        let (tx, rx) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+                let _ = socket.send(b"WATCHDOG=1");
+            }
+        });
+        *self.watchdog.lock().unwrap_or_else(PoisonError::into_inner) = Some(tx);
+    }
+}
+
+impl EventSink for SdNotify {
+    fn on_spawn(&self, _pid: u32) {
+        if !self.passthrough {
+            self.notify("READY=1");
+        }
+        #[cfg(unix)]
+        if let Some(interval) = self.watchdog_interval {
+            self.start_watchdog(interval);
+        }
+    }
+
+    fn on_signal(&self, _signal: Signal, _elapsed: Duration) {
+        self.stopping();
+    }
+
+    fn on_kill(&self, _elapsed: Duration) {
+        self.stopping();
+    }
+
+    fn on_exit(&self, _result: TimeoutResult, _elapsed: Duration) {
+        self.watchdog
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+}
+
+/// How often to feed the watchdog given `WATCHDOG_USEC` and `WATCHDOG_PID`: half the
+/// timeout, as systemd recommends, or `None` if there is no watchdog or it is meant for
+/// another process than `own_pid`.
+pub fn watchdog_interval(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if let Some(pid) = pid
+        && pid.parse() != Ok(own_pid)
+    {
+        return None;
+    }
+    match usec?.parse() {
+        Ok(0) | Err(_) => None,
+        Ok(usec) => Some(Duration::from_micros(usec) / 2),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    /// A bound socket standing in for systemd, and its path.
+    fn systemd(name: &str) -> (UnixDatagram, std::path::PathBuf) {
+        let path =
+            std::env::temp_dir().join(format!("timeout-sd-notify-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        (socket, path)
+    }
+
+    fn recv(socket: &UnixDatagram) -> String {
+        let mut buf = [0; 64];
+        let len = socket.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    }
+
+    #[test]
+    fn test_lifecycle_notifications() {
+        let (systemd, path) = systemd("lifecycle");
+        let notify =
+            SdNotify::open(path.as_os_str(), Some(Duration::from_millis(20)), false).unwrap();
+        notify.on_spawn(1);
+        assert_eq!(recv(&systemd), "READY=1");
+        assert_eq!(recv(&systemd), "WATCHDOG=1");
+        notify.on_exit(TimeoutResult::Completed(0), Duration::ZERO);
+        // Drain any watchdog pings sent before it was stopped.
+        std::thread::sleep(Duration::from_millis(100));
+        systemd.set_nonblocking(true).unwrap();
+        while systemd.recv(&mut [0; 64]).is_ok() {}
+        systemd.set_nonblocking(false).unwrap();
+
+        notify.on_signal(Signal::Term, Duration::ZERO);
+        notify.on_kill(Duration::ZERO);
+        assert_eq!(recv(&systemd), "STOPPING=1");
+        systemd.set_nonblocking(true).unwrap();
+        assert!(systemd.recv(&mut [0; 64]).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_passthrough_leaves_readiness_to_command() {
+        let (systemd, path) = systemd("passthrough");
+        let notify = SdNotify::open(path.as_os_str(), None, true).unwrap();
+        notify.on_spawn(1);
+        notify.on_signal(Signal::Term, Duration::ZERO);
+        assert_eq!(recv(&systemd), "STOPPING=1");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_watchdog_interval() {
+        assert_eq!(
+            watchdog_interval(Some("3000000"), None, 7),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            watchdog_interval(Some("3000000"), Some("7"), 7),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(watchdog_interval(Some("3000000"), Some("8"), 7), None);
+        assert_eq!(watchdog_interval(Some("0"), None, 7), None);
+        assert_eq!(watchdog_interval(None, None, 7), None);
+    }
+}
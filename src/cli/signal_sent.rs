@@ -0,0 +1,111 @@
+//! `--print-signal-sent`: confirming on stderr which signal reached the command, or why it did
+//! not.
+
+use super::color::warning;
+use super::countdown;
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use timeout_cli::{Delivery, EventSink, Signal};
+
+/// Prints a line for each signal sent to the command: a [`delivered`] line when it was handed
+/// over, a warning with [`undelivered`] when it was not.
+#[derive(Debug, Default)]
+pub struct DeliverySink {
+    pid: AtomicU32,
+}
+
+impl EventSink for DeliverySink {
+    fn on_spawn(&self, pid: u32) {
+        self.pid.store(pid, Ordering::Relaxed);
+    }
+
+    fn on_delivery(&self, delivery: Delivery) {
+        let pid = self.pid.load(Ordering::Relaxed);
+        if delivery.delivered() {
+            countdown::above(|| eprintln!("timeout: {}", delivered(delivery, pid)));
+        } else {
+            warning!("{}", undelivered(delivery, pid));
+        }
+    }
+}
+
+pub fn delivered(delivery: Delivery, pid: u32) -> String {
+    format!(
+        "sent {} to {}",
+        signal(delivery.signal),
+        target(delivery, pid)
+    )
+}
+
+pub fn undelivered(delivery: Delivery, pid: u32) -> String {
+    let error = match delivery.error {
+        Some(errno) if errno >= 0 => io::Error::from_raw_os_error(errno),
+        _ => io::Error::other("unknown error"),
+    };
+    let mut line = format!(
+        "could not send {} to {}: {}",
+        signal(delivery.signal),
+        target(delivery, pid),
+        error
+    );
+    #[cfg(unix)]
+    if delivery.error == Some(libc::ESRCH) {
+        line.push_str(" (it had already exited)");
+    }
+    line
+}
+
+/// The signal with its number, e.g. `SIGTERM (15)`, where signals have numbers.
+fn signal(signal: Signal) -> String {
+    #[cfg(unix)]
+    {
+        format!("{} ({})", signal, signal.as_raw())
+    }
+    #[cfg(not(unix))]
+    {
+        signal.to_string()
+    }
+}
+
+fn target(delivery: Delivery, pid: u32) -> String {
+    if delivery.group {
+        format!("process group {}", pid)
+    } else {
+        format!("PID {}", pid)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delivery_lines() {
+        let delivery = |signal, group, error| Delivery {
+            signal,
+            group,
+            error,
+        };
+        assert_eq!(
+            delivered(delivery(Signal::Term, false, None), 1234),
+            "sent SIGTERM (15) to PID 1234"
+        );
+        assert_eq!(
+            delivered(delivery(Signal::Kill, true, None), 1234),
+            "sent SIGKILL (9) to process group 1234"
+        );
+        assert!(
+            undelivered(delivery(Signal::Term, false, Some(libc::ESRCH)), 1234).starts_with(
+                "could not send SIGTERM (15) to PID 1234: No such process (os error 3)"
+            )
+        );
+        assert!(
+            undelivered(delivery(Signal::Term, false, Some(libc::ESRCH)), 1234)
+                .ends_with("(it had already exited)")
+        );
+        assert_eq!(
+            undelivered(delivery(Signal::Int, false, Some(libc::EPERM)), 1234),
+            "could not send SIGINT (2) to PID 1234: Operation not permitted (os error 1)"
+        );
+    }
+}
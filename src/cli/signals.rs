@@ -0,0 +1,286 @@
+//! Signals on the command line, and surviving fatal signals sent to `timeout` itself long
+//! enough to shut down cleanly.
+
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use timeout_cli::{Signal, TimeoutHandle};
+
+const SIGNALS: [Signal; 8] = [
+    Signal::Hup,
+    Signal::Int,
+    Signal::Quit,
+    Signal::Kill,
+    Signal::Usr1,
+    Signal::Usr2,
+    Signal::Alrm,
+    Signal::Term,
+];
+
+/// Parse a signal given by name, with or without the `SIG` prefix and in any case (`TERM`,
+/// `sigusr1`), or on Unix by number (`15`).
+pub fn parse_signal(arg: &str) -> Result<Signal, String> {
+    let name = arg.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS
+        .into_iter()
+        .find(|signal| signal.name() == name || is_number_of(*signal, arg))
+        .ok_or_else(|| format!("unknown signal '{}'", arg))
+}
+
+/// The signals `--shield` catches when none are named.
+pub const SHIELDED_BY_DEFAULT: [&str; 4] = ["INT", "QUIT", "TERM", "HUP"];
+
+/// Parse a signal for `--shield`, which cannot be `KILL`.
+pub fn parse_shielded_signal(arg: &str) -> Result<Signal, String> {
+    match parse_signal(arg)? {
+        Signal::Kill => Err("KILL cannot be caught".to_string()),
+        signal => Ok(signal),
+    }
+}
+
+/// `--kill-after`: how many seconds after the timeout signal to escalate, and with which signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KillAfter {
+    pub secs: u64,
+    pub signal: Signal,
+}
+
+/// Parse a `--kill-after` value: a number of seconds, optionally followed by `:` and the signal
+/// to escalate with instead of `KILL`, e.g. `3:QUIT`.
+pub fn parse_kill_after(arg: &str) -> Result<KillAfter, String> {
+    let (secs, signal) = match arg.split_once(':') {
+        Some((secs, signal)) => (secs, Some(signal)),
+        None => (arg, None),
+    };
+    let secs = secs
+        .parse()
+        .map_err(|e| format!("invalid duration '{}': {}", secs, e))?;
+    let signal = match signal {
+        Some(signal) => parse_signal(signal)?,
+        None => Signal::Kill,
+    };
+    Ok(KillAfter { secs, signal })
+}
+
+#[cfg(unix)]
+fn is_number_of(signal: Signal, arg: &str) -> bool {
+    arg.parse() == Ok(signal.as_raw())
+}
+
+#[cfg(not(unix))]
+fn is_number_of(_signal: Signal, _arg: &str) -> bool {
+    false
+}
+
+/// `SIGINT`, `SIGTERM` and `SIGHUP`, caught for the rest of the process's life so that
+/// `timeout` can finish through its normal exit path. Each cancels the commands being
+/// supervised, so they are sent the timeout signal, and cuts short any [`sleep`](Self::sleep)
+/// between attempts.
+///
+/// Signals given to `--shield` are caught too, but only passed on to the commands, leaving
+/// `timeout` itself to carry on.
+#[derive(Debug, Clone, Default)]
+pub struct FatalSignals {
+    state: Arc<(Mutex<Caught>, Condvar)>,
+}
+
+#[derive(Debug, Default)]
+struct Caught {
+    /// The commands being supervised, with their PIDs.
+    handles: Vec<(TimeoutHandle, u32)>,
+    caught: bool,
+}
+
+impl FatalSignals {
+    /// Install the handlers, passing the `shield` signals on rather than cancelling.
+    #[cfg(unix)]
+    pub fn install(shield: &[Signal]) -> io::Result<Self> {
+        use timeout_cli::self_pipe::SelfPipe;
+
+        // The handler passes the signal number, which always fits in a byte, on to a thread
+        // that does the cancelling.
+        static WAKE: SelfPipe = SelfPipe::new();
+
+        extern "C" fn on_signal(signal: libc::c_int) {
+            WAKE.wake(signal as u8);
+        }
+
+        let signals = Self::default();
+        let caught = signals.clone();
+        let forwarded = shield.to_vec();
+        WAKE.open("timeout-signals", move |bytes| {
+            for &byte in bytes {
+                match forwarded
+                    .iter()
+                    .find(|signal| signal.as_raw() == libc::c_int::from(byte))
+                {
+                    Some(&signal) => caught.forward(signal),
+                    None => caught.catch(),
+                }
+            }
+        })?;
+
+        let fatal = [Signal::Int, Signal::Term, Signal::Hup];
+        for signal in fatal.iter().chain(shield).map(|signal| signal.as_raw()) {
+            // SAFETY: the handler only wakes the pipe.
+            let previous =
+                unsafe { libc::signal(signal, on_signal as *const () as libc::sighandler_t) };
+            if previous == libc::SIG_ERR {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(signals)
+    }
+
+    /// Signals cannot be intercepted this way outside Unix; Ctrl+C still ends `timeout` at
+    /// once.
+    #[cfg(not(unix))]
+    pub fn install(_shield: &[Signal]) -> io::Result<Self> {
+        Ok(Self::default())
+    }
+
+    /// Cancel the command behind `handle`, whose PID is `pid`, when a signal arrives, or
+    /// straight away if one already has, along with any others still running.
+    pub fn supervise(&self, handle: TimeoutHandle, pid: u32) {
+        let mut state = self.lock();
+        if state.caught {
+            handle.cancel();
+        }
+        state.handles.retain(|(handle, _)| handle.is_running());
+        state.handles.push((handle, pid));
+    }
+
+    /// Cancel every command being supervised, and any supervised from now on, just as a
+    /// signal arriving would.
+    pub fn cancel_all(&self) {
+        self.catch();
+    }
+
+    /// Whether a signal has arrived, or [`cancel_all`](Self::cancel_all) was called.
+    pub fn caught(&self) -> bool {
+        self.lock().caught
+    }
+
+    /// Wait for `duration`, returning `false` early if a signal arrives.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let (_, arrived) = &*self.state;
+        let (state, _) = arrived
+            .wait_timeout_while(self.lock(), duration, |state| !state.caught)
+            .unwrap_or_else(PoisonError::into_inner);
+        !state.caught
+    }
+
+    fn catch(&self) {
+        let mut state = self.lock();
+        state.caught = true;
+        for (handle, _) in &state.handles {
+            handle.cancel();
+        }
+        self.state.1.notify_all();
+    }
+
+    /// Pass a shielded `signal` on to every command still running. A command in timeout's own
+    /// process group, in the terminal's foreground, has already had Ctrl+C or Ctrl+\ from the
+    /// terminal, so is not sent `SIGINT` or `SIGQUIT` a second time.
+    #[cfg(unix)]
+    fn forward(&self, signal: Signal) {
+        let from_terminal = matches!(signal, Signal::Int | Signal::Quit) && in_foreground();
+        for (handle, pid) in &self.lock().handles {
+            if !handle.is_running() {
+                continue;
+            }
+            // SAFETY: getpgid, getpgrp and kill only look up and signal processes.
+            unsafe {
+                if from_terminal && libc::getpgid(*pid as libc::pid_t) == libc::getpgrp() {
+                    continue;
+                }
+                libc::kill(*pid as libc::pid_t, signal.as_raw());
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Caught> {
+        self.state.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Whether timeout's process group is the foreground one of the terminal it runs on, if any.
+#[cfg(unix)]
+fn in_foreground() -> bool {
+    [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO]
+        .into_iter()
+        // SAFETY: tcgetpgrp and getpgrp only look the groups up.
+        .any(|fd| unsafe { libc::tcgetpgrp(fd) == libc::getpgrp() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("TERM"), Ok(Signal::Term));
+        assert_eq!(parse_signal("sigusr1"), Ok(Signal::Usr1));
+        assert_eq!(parse_signal("SIGKILL"), Ok(Signal::Kill));
+        #[cfg(unix)]
+        assert_eq!(parse_signal("1"), Ok(Signal::Hup));
+        assert!(parse_signal("SIGFOO").is_err());
+        assert!(parse_signal("").is_err());
+    }
+
+    #[test]
+    fn test_parse_kill_after() {
+        let kill_after = |secs, signal| Ok(KillAfter { secs, signal });
+        assert_eq!(parse_kill_after("5"), kill_after(5, Signal::Kill));
+        assert_eq!(parse_kill_after("3:QUIT"), kill_after(3, Signal::Quit));
+        assert_eq!(parse_kill_after("0:sigint"), kill_after(0, Signal::Int));
+        assert_eq!(
+            parse_kill_after("3s:QUIT"),
+            Err("invalid duration '3s': invalid digit found in string".to_string())
+        );
+        assert_eq!(
+            parse_kill_after("3:FOO"),
+            Err("unknown signal 'FOO'".to_string())
+        );
+        assert_eq!(parse_kill_after("3:"), Err("unknown signal ''".to_string()));
+    }
+
+    #[test]
+    fn test_sleep_is_cut_short_by_a_signal() {
+        let signals = FatalSignals::default();
+        assert!(signals.sleep(Duration::from_millis(10)));
+        let caught = signals.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            caught.catch();
+        });
+        let start = std::time::Instant::now();
+        assert!(!signals.sleep(Duration::from_secs(10)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(signals.caught());
+    }
+
+    #[test]
+    fn test_catch_cancels_every_supervised_command() {
+        let signals = FatalSignals::default();
+        let children: Vec<_> = (0..2)
+            .map(|_| {
+                let child = timeout_cli::TimeoutCommand::new("sleep")
+                    .arg("5")
+                    .spawn()
+                    .unwrap();
+                signals.supervise(child.handle(), child.pid());
+                child
+            })
+            .collect();
+        signals.catch();
+        for child in children {
+            assert_eq!(
+                child.wait().unwrap().result,
+                timeout_cli::TimeoutResult::Cancelled
+            );
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Parsing of size arguments such as `4096`, `512K` or `10MiB`.
+
+/// Parse a whole number of bytes followed by an optional unit: `K`, `M` or `G` for powers of
+/// 1024, in either case and optionally followed by `B` or `iB`.
+pub fn parse_size(arg: &str) -> Result<u64, String> {
+    let arg = arg.trim();
+    let (number, unit) = match arg.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => arg.split_at(index),
+        None => (arg, ""),
+    };
+    let unit = unit.to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => {
+            return Err(format!("invalid size unit in '{}' (use K, M or G)", arg));
+        }
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size '{}'", arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size_units() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("512K"), Ok(512 * 1024));
+        assert_eq!(parse_size("10MiB"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("1gb"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse_size("3B"), Ok(3));
+    }
+
+    #[test]
+    fn test_parse_size_rejects_garbage() {
+        for arg in ["", "K", "-1", "1.5M", "5T", "5KK", "99999999999999999999G"] {
+            assert!(parse_size(arg).is_err(), "{arg} parsed");
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! `--stats`: what the command used of the machine, printed on stderr once it has been reaped.
+
+use serde::Serialize;
+use std::time::Duration;
+use timeout_cli::ResourceUsage;
+
+use super::report::Report;
+
+/// The resource usage folded into the `--json` report.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSummary {
+    /// Milliseconds of CPU time spent in the command's own code.
+    pub user_cpu_ms: u64,
+    /// Milliseconds of CPU time the kernel spent working for the command.
+    pub system_cpu_ms: u64,
+    /// The most memory resident at once, in bytes.
+    pub max_rss_bytes: u64,
+    /// How often the command gave up the CPU to wait.
+    pub voluntary_context_switches: u64,
+    /// How often the command was made to give up the CPU.
+    pub involuntary_context_switches: u64,
+}
+
+impl From<ResourceUsage> for UsageSummary {
+    fn from(usage: ResourceUsage) -> Self {
+        let millis = |time: Duration| u64::try_from(time.as_millis()).unwrap_or(u64::MAX);
+        Self {
+            user_cpu_ms: millis(usage.user_time),
+            system_cpu_ms: millis(usage.system_time),
+            max_rss_bytes: usage.max_rss,
+            voluntary_context_switches: usage.voluntary_switches,
+            involuntary_context_switches: usage.involuntary_switches,
+        }
+    }
+}
+
+/// The block printed on stderr for the run `report` describes, with `usage` if it could be had,
+/// which it cannot for a command that never started or outside Unix.
+pub fn stats_block(report: &Report, usage: Option<ResourceUsage>) -> String {
+    let unavailable = || "unavailable".to_string();
+    let seconds = |time: Duration| format!("{:.3}s", time.as_secs_f64());
+    let signals = match (&report.signal_sent, report.kill_sent) {
+        (Some(signal), true) if signal != "SIGKILL" => format!("{}, then SIGKILL", signal),
+        (Some(signal), _) => signal.clone(),
+        (None, true) => "SIGKILL".to_string(),
+        (None, false) => "none".to_string(),
+    };
+    let rows = [
+        (
+            "wall time",
+            seconds(Duration::from_millis(report.elapsed_ms)),
+        ),
+        (
+            "user CPU",
+            usage.map_or_else(unavailable, |usage| seconds(usage.user_time)),
+        ),
+        (
+            "system CPU",
+            usage.map_or_else(unavailable, |usage| seconds(usage.system_time)),
+        ),
+        (
+            "max RSS",
+            usage.map_or_else(unavailable, |usage| format_bytes(usage.max_rss)),
+        ),
+        (
+            "context switches",
+            usage.map_or_else(unavailable, |usage| {
+                format!(
+                    "{} voluntary, {} involuntary",
+                    usage.voluntary_switches, usage.involuntary_switches
+                )
+            }),
+        ),
+        (
+            "timed out",
+            if report.timed_out { "yes" } else { "no" }.to_string(),
+        ),
+        ("signals sent", signals),
+    ];
+    let mut block = format!("timeout: stats for '{}':", report.command);
+    for (name, value) in rows {
+        block.push_str(&format!("\n  {:<18}{}", format!("{}:", name), value));
+    }
+    block
+}
+
+/// `bytes` in the largest binary unit that keeps it at 1 or more, e.g. `52.3 MiB`.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+    use timeout_cli::{TimeoutOutcome, TimeoutReason};
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(2048), "2.0 KiB");
+        assert_eq!(format_bytes(52_428_800 + 314_573), "50.3 MiB");
+        assert_eq!(format_bytes(3 << 30), "3.0 GiB");
+    }
+
+    #[test]
+    fn test_stats_block() {
+        let outcome = TimeoutOutcome::new(TimeoutReason::Killed, Duration::from_millis(2013));
+        let report = Report::new(
+            "sleep",
+            &[],
+            Some(1),
+            SystemTime::UNIX_EPOCH,
+            &outcome,
+            None,
+        );
+        let usage = ResourceUsage {
+            user_time: Duration::from_millis(412),
+            system_time: Duration::from_millis(31),
+            max_rss: 4 << 20,
+            voluntary_switches: 120,
+            involuntary_switches: 8,
+        };
+        assert_eq!(
+            stats_block(&report, Some(usage)),
+            "timeout: stats for 'sleep':\n  \
+             wall time:        2.013s\n  \
+             user CPU:         0.412s\n  \
+             system CPU:       0.031s\n  \
+             max RSS:          4.0 MiB\n  \
+             context switches: 120 voluntary, 8 involuntary\n  \
+             timed out:        yes\n  \
+             signals sent:     SIGKILL"
+        );
+        assert!(stats_block(&report, None).contains("max RSS:          unavailable"));
+    }
+}
@@ -0,0 +1,147 @@
+//! Reporting the run to a statsd server over UDP.
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use timeout_cli::{TimeoutOutcome, TimeoutReason, debug_print};
+
+/// How long exiting may be held up by resolving the statsd host and sending the metrics.
+pub const STATSD_SEND_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Where and how to send metrics, from `--statsd` and its companion options.
+#[derive(Debug, Clone)]
+pub struct Statsd {
+    /// The server's `host:port`.
+    pub address: String,
+    /// Prepended to every metric name, e.g. `jobs.backup`.
+    pub prefix: String,
+    /// DogStatsD `key:value` tags, sent only when `dogstatsd` is set.
+    pub tags: Vec<String>,
+    /// Use the DogStatsD dialect, which carries tags.
+    pub dogstatsd: bool,
+}
+
+/// Check a DogStatsD tag contains none of the characters that delimit the packet format.
+pub fn parse_tag(arg: &str) -> Result<String, String> {
+    if arg.is_empty() || arg.contains(['|', ',', '#', '\n']) {
+        return Err(format!("invalid tag '{}'", arg));
+    }
+    Ok(arg.to_string())
+}
+
+impl Statsd {
+    /// Render the metrics for the run as one newline-separated packet: a `duration_ms` timer,
+    /// an `exit_code` gauge and, if the command timed out, a `timeout` counter increment.
+    pub fn render(&self, outcome: &TimeoutOutcome, exit_code: u8) -> String {
+        let timed_out = matches!(
+            outcome.reason,
+            TimeoutReason::TermExit | TimeoutReason::Killed
+        );
+        let mut metrics = vec![
+            ("duration_ms", outcome.elapsed.as_millis().to_string(), "ms"),
+            ("exit_code", exit_code.to_string(), "g"),
+        ];
+        if timed_out {
+            metrics.push(("timeout", "1".to_string(), "c"));
+        }
+
+        let tags = if self.dogstatsd && !self.tags.is_empty() {
+            format!("|#{}", self.tags.join(","))
+        } else {
+            String::new()
+        };
+        metrics
+            .into_iter()
+            .map(|(name, value, kind)| {
+                format!("{}.{}:{}|{}{}", self.prefix, name, value, kind, tags)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Send the metrics for the run, giving up after [`STATSD_SEND_TIMEOUT`]. Failures are only
+    /// reported in verbose mode.
+    pub fn send(&self, outcome: &TimeoutOutcome, exit_code: u8, verbose: bool) {
+        let packet = self.render(outcome, exit_code);
+        let address = self.address.clone();
+        let (tx, rx) = mpsc::channel();
+        // Resolving the host can block for much longer than we are willing to wait, so do it
+        // on a thread that is simply abandoned if it takes too long.
+        thread::spawn(move || {
+            let _ = tx.send(send_packet(&address, packet.as_bytes()));
+        });
+        match rx.recv_timeout(STATSD_SEND_TIMEOUT) {
+            Ok(Ok(())) => debug_print!(verbose, "Sent statsd metrics to {}", self.address),
+            Ok(Err(e)) => debug_print!(
+                verbose,
+                "Failed to send statsd metrics to {}: {}",
+                self.address,
+                e
+            ),
+            Err(_) => debug_print!(
+                verbose,
+                "Timed out sending statsd metrics to {}",
+                self.address
+            ),
+        }
+    }
+}
+
+fn send_packet(address: &str, packet: &[u8]) -> io::Result<()> {
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for host"))?;
+    let local: SocketAddr = if address.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(address)?;
+    socket.send(packet)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statsd(dogstatsd: bool) -> Statsd {
+        Statsd {
+            address: "127.0.0.1:8125".to_string(),
+            prefix: "jobs.backup".to_string(),
+            tags: vec!["env:prod".to_string(), "team:ops".to_string()],
+            dogstatsd,
+        }
+    }
+
+    #[test]
+    fn test_render_plain() {
+        let outcome = TimeoutOutcome::new(TimeoutReason::TermExit, Duration::from_millis(1500));
+        assert_eq!(
+            statsd(false).render(&outcome, 124),
+            "jobs.backup.duration_ms:1500|ms\njobs.backup.exit_code:124|g\njobs.backup.timeout:1|c"
+        );
+    }
+
+    #[test]
+    fn test_render_dogstatsd_tags() {
+        let outcome =
+            TimeoutOutcome::new(TimeoutReason::CompletedInTime, Duration::from_millis(20));
+        assert_eq!(
+            statsd(true).render(&outcome, 0),
+            "jobs.backup.duration_ms:20|ms|#env:prod,team:ops\njobs.backup.exit_code:0|g|#env:prod,team:ops"
+        );
+    }
+
+    #[test]
+    fn test_parse_tag() {
+        assert_eq!(parse_tag("env:prod"), Ok("env:prod".to_string()));
+        for tag in ["", "a|b", "a,b", "#a"] {
+            assert!(parse_tag(tag).is_err(), "{tag} accepted");
+        }
+    }
+}
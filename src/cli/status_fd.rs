@@ -0,0 +1,49 @@
+//! `--status-fd` reporting of the final result to an inherited file descriptor.
+
+use super::fd::InheritedFd;
+use std::io;
+use timeout_cli::TimeoutResult;
+
+/// A file descriptor, inherited from the caller, that receives one status line before exit.
+#[derive(Debug)]
+pub struct StatusFd(InheritedFd);
+
+impl StatusFd {
+    /// Take `fd` for the status line, failing as [`InheritedFd::open`] does.
+    pub fn open(fd: i32) -> io::Result<Self> {
+        InheritedFd::open(fd).map(Self)
+    }
+
+    /// Write the status line for `result`.
+    pub fn report(&mut self, result: TimeoutResult) -> io::Result<()> {
+        self.0.write_line(&status_line(result))
+    }
+}
+
+/// The machine-readable status for `result`, e.g. `OK 0` or `TIMEOUT`.
+pub fn status_line(result: TimeoutResult) -> String {
+    match result {
+        TimeoutResult::Completed(code) => format!("OK {}", code),
+        TimeoutResult::TimedOut => "TIMEOUT".to_string(),
+        TimeoutResult::Killed => "KILLED".to_string(),
+        TimeoutResult::Cancelled => "CANCELLED".to_string(),
+        TimeoutResult::Detached => "DETACHED".to_string(),
+        TimeoutResult::NotFound => "NOT_FOUND".to_string(),
+        TimeoutResult::CannotInvoke => "CANNOT_INVOKE".to_string(),
+        TimeoutResult::InternalError => "ERROR".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_line() {
+        assert_eq!(status_line(TimeoutResult::Completed(0)), "OK 0");
+        assert_eq!(status_line(TimeoutResult::Completed(3)), "OK 3");
+        assert_eq!(status_line(TimeoutResult::TimedOut), "TIMEOUT");
+        assert_eq!(status_line(TimeoutResult::Killed), "KILLED");
+        assert_eq!(status_line(TimeoutResult::NotFound), "NOT_FOUND");
+    }
+}
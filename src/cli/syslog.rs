@@ -0,0 +1,175 @@
+//! `--syslog` logging of the command's lifecycle to the local syslog daemon.
+
+use super::verbose;
+use clap::ValueEnum;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use timeout_cli::{EventSink, Signal, TimeoutResult};
+
+/// Where the local syslog daemon listens on most Linux systems.
+pub const DEFAULT_SYSLOG_SOCKET: &str = "/dev/log";
+
+/// The syslog facility messages are filed under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Facility {
+    Kern,
+    User,
+    Mail,
+    Daemon,
+    Auth,
+    Syslog,
+    Lpr,
+    News,
+    Uucp,
+    Cron,
+    Authpriv,
+    Ftp,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl Facility {
+    fn code(self) -> u8 {
+        match self {
+            Facility::Kern => 0,
+            Facility::User => 1,
+            Facility::Mail => 2,
+            Facility::Daemon => 3,
+            Facility::Auth => 4,
+            Facility::Syslog => 5,
+            Facility::Lpr => 6,
+            Facility::News => 7,
+            Facility::Uucp => 8,
+            Facility::Cron => 9,
+            Facility::Authpriv => 10,
+            Facility::Ftp => 11,
+            Facility::Local0 => 16,
+            Facility::Local1 => 17,
+            Facility::Local2 => 18,
+            Facility::Local3 => 19,
+            Facility::Local4 => 20,
+            Facility::Local5 => 21,
+            Facility::Local6 => 22,
+            Facility::Local7 => 23,
+        }
+    }
+}
+
+/// How serious a message is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Err = 3,
+    Warning = 4,
+    Info = 6,
+}
+
+impl Severity {
+    /// Normal completion is `info`, a timeout `warning` and a failure `err`.
+    pub fn of_exit(result: TimeoutResult) -> Self {
+        match result {
+            TimeoutResult::Completed(_) | TimeoutResult::Detached => Severity::Info,
+            TimeoutResult::TimedOut | TimeoutResult::Killed | TimeoutResult::Cancelled => {
+                Severity::Warning
+            }
+            TimeoutResult::NotFound
+            | TimeoutResult::CannotInvoke
+            | TimeoutResult::InternalError => Severity::Err,
+        }
+    }
+}
+
+/// Sends each lifecycle event to syslog, worded as `--verbose` words it: spawning and normal
+/// completion at `info`, timeouts and kills at `warning`, and failures at `err`.
+#[derive(Debug)]
+pub struct Syslog {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    facility: Facility,
+    tag: String,
+}
+
+impl Syslog {
+    /// Connect to the syslog daemon listening on the datagram socket at `path`.
+    #[cfg(unix)]
+    pub fn open(path: &Path, facility: Facility, tag: String) -> io::Result<Self> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self {
+            socket,
+            facility,
+            tag,
+        })
+    }
+
+    /// There is no local syslog socket outside Unix.
+    #[cfg(not(unix))]
+    pub fn open(_path: &Path, _facility: Facility, _tag: String) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only supported on Unix",
+        ))
+    }
+
+    /// Log `message` at `severity`. Failures are ignored, as there is nowhere better to
+    /// report them.
+    pub fn log(&self, severity: Severity, message: &str) {
+        let line = format_message(self.facility, severity, &self.tag, message);
+        #[cfg(unix)]
+        let _ = self.socket.send(line.as_bytes());
+        #[cfg(not(unix))]
+        let _ = line;
+    }
+}
+
+/// Format a message the way the local syslog socket expects: `<PRI>TAG[PID]: MESSAGE`. The
+/// daemon adds the timestamp and hostname itself.
+fn format_message(facility: Facility, severity: Severity, tag: &str, message: &str) -> String {
+    format!(
+        "<{}>{}[{}]: {}",
+        u16::from(facility.code()) * 8 + severity as u16,
+        tag,
+        std::process::id(),
+        message
+    )
+}
+
+impl EventSink for Syslog {
+    fn on_spawn(&self, pid: u32) {
+        self.log(Severity::Info, &verbose::spawned(pid));
+    }
+
+    fn on_signal(&self, signal: Signal, elapsed: Duration) {
+        self.log(Severity::Warning, &verbose::signalled(signal, elapsed));
+    }
+
+    fn on_kill(&self, elapsed: Duration) {
+        self.log(Severity::Warning, &verbose::killed(elapsed));
+    }
+
+    fn on_exit(&self, result: TimeoutResult, elapsed: Duration) {
+        self.log(Severity::of_exit(result), &verbose::exited(result, elapsed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_message_priority() {
+        let line = format_message(Facility::Local3, Severity::Warning, "backup", "timed out");
+        assert_eq!(
+            line,
+            format!("<156>backup[{}]: timed out", std::process::id())
+        );
+        let line = format_message(Facility::User, Severity::Info, "timeout", "ok");
+        assert!(line.starts_with("<14>timeout["));
+    }
+}
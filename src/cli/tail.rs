@@ -0,0 +1,205 @@
+//! `--tail-on-timeout`: keeping the last lines of the command's output to show once it has
+//! timed out.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// How many lines are kept when only `only-when-quiet` is given.
+pub const DEFAULT_LINES: usize = 100;
+
+/// How much of each line is kept; the rest is dropped, so that one enormous line cannot use up
+/// memory.
+const MAX_TAIL_LINE: usize = 4096;
+
+/// What `--tail-on-timeout` asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TailOnTimeout {
+    pub lines: usize,
+    /// Leave the tail out when the output has been going to a terminal all along.
+    pub only_when_quiet: bool,
+}
+
+/// Parse a `--tail-on-timeout` value: a number of lines, `only-when-quiet`, or both as in
+/// `50:only-when-quiet`.
+pub fn parse_tail_on_timeout(arg: &str) -> Result<TailOnTimeout, String> {
+    let (lines, mode) = match arg.split_once(':') {
+        Some((lines, mode)) => (Some(lines), Some(mode)),
+        None if arg.starts_with(|c: char| c.is_ascii_digit()) => (Some(arg), None),
+        None => (None, Some(arg)),
+    };
+    let only_when_quiet = match mode {
+        None => false,
+        Some("only-when-quiet") => true,
+        Some(mode) => return Err(format!("unknown mode '{}' (use only-when-quiet)", mode)),
+    };
+    let lines = match lines {
+        Some(lines) => match lines.parse() {
+            Ok(0) => return Err("must keep at least one line".to_string()),
+            Ok(lines) => lines,
+            Err(e) => return Err(format!("invalid number of lines '{}': {}", lines, e)),
+        },
+        None => DEFAULT_LINES,
+    };
+    Ok(TailOnTimeout {
+        lines,
+        only_when_quiet,
+    })
+}
+
+/// The last lines of the command's output, shared by the relays of its stdout and stderr.
+#[derive(Debug, Clone)]
+pub struct OutputTail {
+    capacity: usize,
+    lines: Arc<Mutex<VecDeque<Vec<u8>>>>,
+}
+
+impl OutputTail {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// Something to feed one of the command's streams to. Lines are added to the tail once
+    /// they are complete, so those of the two streams are never mixed up.
+    pub fn recorder(&self) -> TailRecorder {
+        TailRecorder {
+            tail: self.clone(),
+            line: Vec::new(),
+            cut: 0,
+        }
+    }
+
+    /// The block printed after a timeout: the lines kept, oldest first, between two marker
+    /// lines. Empty if the command printed nothing.
+    pub fn block(&self) -> Vec<u8> {
+        let lines = self.lock();
+        if lines.is_empty() {
+            return Vec::new();
+        }
+        let mut block = format!(
+            "timeout: ---- last {} lines before timeout ----\n",
+            lines.len()
+        )
+        .into_bytes();
+        for line in lines.iter() {
+            block.extend_from_slice(line);
+        }
+        block.extend_from_slice(b"timeout: ---- end of output ----\n");
+        block
+    }
+
+    fn push(&self, line: Vec<u8>) {
+        let mut lines = self.lock();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<Vec<u8>>> {
+        self.lines.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Splits one of the command's streams into lines for an [`OutputTail`].
+#[derive(Debug)]
+pub struct TailRecorder {
+    tail: OutputTail,
+    line: Vec<u8>,
+    cut: usize,
+}
+
+impl TailRecorder {
+    /// Add a piece of output, which may hold several lines or only part of one.
+    pub fn record(&mut self, piece: &[u8]) {
+        for part in piece.split_inclusive(|&b| b == b'\n') {
+            let (text, ended) = match part.strip_suffix(b"\n") {
+                Some(text) => (text, true),
+                None => (part, false),
+            };
+            let room = MAX_TAIL_LINE.saturating_sub(self.line.len());
+            let (kept, dropped) = text.split_at(room.min(text.len()));
+            self.line.extend_from_slice(kept);
+            self.cut += dropped.len();
+            if ended {
+                self.end_line();
+            }
+        }
+    }
+
+    /// Add whatever is left of a last line that never ended.
+    pub fn finish(&mut self) {
+        if !self.line.is_empty() || self.cut > 0 {
+            self.end_line();
+        }
+    }
+
+    fn end_line(&mut self) {
+        let mut line = std::mem::take(&mut self.line);
+        if self.cut > 0 {
+            line.extend_from_slice(format!("[... {} more bytes]", self.cut).as_bytes());
+            self.cut = 0;
+        }
+        line.push(b'\n');
+        self.tail.push(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tail_on_timeout() {
+        let tail = |lines, only_when_quiet| {
+            Ok(TailOnTimeout {
+                lines,
+                only_when_quiet,
+            })
+        };
+        assert_eq!(parse_tail_on_timeout("100"), tail(100, false));
+        assert_eq!(parse_tail_on_timeout("only-when-quiet"), tail(100, true));
+        assert_eq!(parse_tail_on_timeout("20:only-when-quiet"), tail(20, true));
+        assert!(parse_tail_on_timeout("0").is_err());
+        assert!(parse_tail_on_timeout("20:loud").is_err());
+        assert!(parse_tail_on_timeout("many").is_err());
+    }
+
+    #[test]
+    fn test_keeps_the_last_lines_of_both_streams() {
+        let tail = OutputTail::new(3);
+        let mut stdout = tail.recorder();
+        let mut stderr = tail.recorder();
+        stdout.record(b"one\ntwo\nthr");
+        stderr.record(b"oops\n");
+        stdout.record(b"ee\n");
+        stdout.record(b"four");
+        stdout.finish();
+        assert_eq!(
+            tail.block(),
+            b"timeout: ---- last 3 lines before timeout ----\n\
+              oops\nthree\nfour\n\
+              timeout: ---- end of output ----\n"
+        );
+    }
+
+    #[test]
+    fn test_long_lines_are_cut() {
+        let tail = OutputTail::new(2);
+        let mut recorder = tail.recorder();
+        for _ in 0..1000 {
+            recorder.record(&[b'x'; 1000]);
+        }
+        recorder.record(b"\nshort\n");
+        let lines = tail.lock();
+        let expected = format!(
+            "{}[... {} more bytes]\n",
+            "x".repeat(MAX_TAIL_LINE),
+            1_000_000 - MAX_TAIL_LINE
+        );
+        assert_eq!(lines[0], expected.as_bytes());
+        assert_eq!(lines[1], b"short\n");
+    }
+}
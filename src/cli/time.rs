@@ -0,0 +1,78 @@
+//! `--time`: the `real`, `user` and `sys` times of the command, printed on stderr the way the
+//! shell's `time` keyword and `time -p` print them, so either can be parsed as usual.
+
+use clap::ValueEnum;
+use std::time::Duration;
+use timeout_cli::ResourceUsage;
+
+/// How the times are laid out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TimeFormat {
+    /// As bash's `time` keyword prints them: a blank line, then `real\t0m1.003s` and so on.
+    #[default]
+    Classic,
+    /// As `time -p` prints them: `real 1.00` and so on.
+    Posix,
+}
+
+impl TimeFormat {
+    /// The block for a command that ran for `real`, using `usage` if it could be had; without
+    /// it the CPU times are given as zero.
+    pub fn block(self, real: Duration, usage: Option<ResourceUsage>) -> String {
+        let usage = usage.unwrap_or_default();
+        let rows = [
+            ("real", real),
+            ("user", usage.user_time),
+            ("sys", usage.system_time),
+        ];
+        let lines: Vec<String> = rows
+            .iter()
+            .map(|(name, time)| match self {
+                TimeFormat::Classic => format!("{}\t{}", name, minutes_and_seconds(*time)),
+                TimeFormat::Posix => format!("{} {}", name, seconds(*time)),
+            })
+            .collect();
+        match self {
+            TimeFormat::Classic => format!("\n{}", lines.join("\n")),
+            TimeFormat::Posix => lines.join("\n"),
+        }
+    }
+}
+
+/// `0m1.003s`, with the milliseconds cut off rather than rounded, as bash does.
+fn minutes_and_seconds(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{}m{}.{:03}s", secs / 60, secs % 60, time.subsec_millis())
+}
+
+/// `1.00`, with the hundredths cut off rather than rounded, as `time -p` does.
+fn seconds(time: Duration) -> String {
+    format!("{}.{:02}", time.as_secs(), time.subsec_millis() / 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_blocks() {
+        let usage = ResourceUsage {
+            user_time: Duration::from_micros(412_999),
+            system_time: Duration::from_millis(31),
+            ..ResourceUsage::default()
+        };
+        let real = Duration::from_millis(61_005);
+        assert_eq!(
+            TimeFormat::Classic.block(real, Some(usage)),
+            "\nreal\t1m1.005s\nuser\t0m0.412s\nsys\t0m0.031s"
+        );
+        assert_eq!(
+            TimeFormat::Posix.block(real, Some(usage)),
+            "real 61.00\nuser 0.41\nsys 0.03"
+        );
+        assert_eq!(
+            TimeFormat::Posix.block(Duration::from_millis(1999), None),
+            "real 1.99\nuser 0.00\nsys 0.00"
+        );
+    }
+}
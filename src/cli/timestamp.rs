@@ -0,0 +1,202 @@
+//! Wall-clock timestamps for decorating output.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Format `time` as an ISO 8601 UTC timestamp with millisecond precision,
+/// e.g. `2024-08-09T12:34:56.789Z`.
+pub fn iso8601(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let seconds_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// How `--timestamps` stamps each line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TimestampStyle {
+    /// The local time of day, e.g. `[12:31:05.123]`
+    #[default]
+    Absolute,
+    /// Time since the command started, e.g. `[+4.512s]`
+    Relative,
+}
+
+/// Format `time` as the local time of day with millisecond precision, e.g. `12:31:05.123`.
+/// Where the local time zone cannot be found, UTC is used.
+pub fn time_of_day(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (hour, minute, second) =
+        local_time_of_day(secs).unwrap_or((secs % 86_400 / 3600, secs % 3600 / 60, secs % 60));
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        hour,
+        minute,
+        second,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(unix)]
+fn local_time_of_day(secs: u64) -> Option<(u64, u64, u64)> {
+    let time = libc::time_t::try_from(secs).ok()?;
+    // SAFETY: an all-zero tm is a valid value for localtime_r(3) to fill in.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // SAFETY: both pointers are valid for the duration of the call.
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return None;
+    }
+    Some((tm.tm_hour as u64, tm.tm_min as u64, tm.tm_sec as u64))
+}
+
+#[cfg(not(unix))]
+fn local_time_of_day(_secs: u64) -> Option<(u64, u64, u64)> {
+    None
+}
+
+/// Format `elapsed` as an offset in seconds with millisecond precision, e.g. `+4.512s`.
+pub fn offset(elapsed: Duration) -> String {
+    format!("+{}.{:03}s", elapsed.as_secs(), elapsed.subsec_millis())
+}
+
+/// Parse an ISO 8601 UTC timestamp as [`iso8601`] writes it, with or without the
+/// milliseconds, e.g. `2024-08-09T12:34:56Z`.
+pub fn parse_iso8601(text: &str) -> Option<SystemTime> {
+    let (date, time) = text.strip_suffix('Z')?.split_once('T')?;
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, digits))
+            if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            (time, format!("0.{}", digits).parse().ok()?)
+        }
+        Some(_) => return None,
+        None => (time, 0.0),
+    };
+    let [year, month, day] = fields(date, '-')?;
+    let [hour, minute, second] = fields(time, ':')?;
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = days_from_civil(i64::from(year), month, day);
+    // Going back and forth rejects days the month does not have.
+    if civil_from_days(days) != (i64::from(year), month, day) {
+        return None;
+    }
+    let secs = days * 86_400 + i64::from(hour * 3600 + minute * 60 + second);
+    UNIX_EPOCH.checked_add(
+        Duration::from_secs(u64::try_from(secs).ok()?) + Duration::from_secs_f64(fraction),
+    )
+}
+
+/// `N` unsigned numbers separated by `separator`.
+fn fields<const N: usize>(text: &str, separator: char) -> Option<[u32; N]> {
+    let fields: Vec<u32> = text
+        .split(separator)
+        .map(|field| {
+            field
+                .bytes()
+                .all(|b| b.is_ascii_digit())
+                .then(|| field.parse().ok())
+                .flatten()
+        })
+        .collect::<Option<_>>()?;
+    fields.try_into().ok()
+}
+
+/// Convert a proleptic Gregorian (year, month, day) into days since the Unix epoch.
+///
+/// This is Howard Hinnant's `days_from_civil` algorithm, the inverse of [`civil_from_days`].
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Convert days since the Unix epoch into a proleptic Gregorian (year, month, day).
+///
+/// This is Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iso8601_epoch() {
+        assert_eq!(iso8601(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_iso8601_known_instant() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_723_206_896_789);
+        assert_eq!(iso8601(time), "2024-08-09T12:34:56.789Z");
+    }
+
+    #[test]
+    fn test_iso8601_leap_day() {
+        let time = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(iso8601(time), "2000-02-29T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_time_of_day_shape() {
+        let stamp = time_of_day(UNIX_EPOCH + Duration::from_millis(1_723_206_896_789));
+        assert_eq!(stamp.len(), 12, "{stamp}");
+        assert!(stamp.ends_with(":56.789"), "{stamp}");
+    }
+
+    #[test]
+    fn test_offset() {
+        assert_eq!(offset(Duration::ZERO), "+0.000s");
+        assert_eq!(offset(Duration::from_millis(4_512)), "+4.512s");
+        assert_eq!(offset(Duration::from_millis(61_005)), "+61.005s");
+    }
+
+    #[test]
+    fn test_parse_iso8601_round_trips() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_723_206_896_789);
+        assert_eq!(parse_iso8601(&iso8601(time)), Some(time));
+        assert_eq!(
+            parse_iso8601("2000-02-29T00:00:00Z"),
+            Some(UNIX_EPOCH + Duration::from_secs(951_782_400))
+        );
+        for text in [
+            "",
+            "2024-08-09T12:34:56",
+            "2024-08-09 12:34:56Z",
+            "2023-02-29T00:00:00Z",
+            "2024-13-01T00:00:00Z",
+            "2024-08-09T24:00:00Z",
+            "2024-08-09T12:34:56.Z",
+            "1969-12-31T23:59:59Z",
+            "+2024-08-09T12:34:56Z",
+        ] {
+            assert_eq!(parse_iso8601(text), None, "{text} parsed");
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Selecting which outcomes a notification is sent for, as in `--webhook-on`.
+
+use timeout_cli::{TimeoutOutcome, TimeoutReason, TimeoutResult};
+
+/// A kind of outcome a notification can be sent for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Trigger {
+    /// The command timed out
+    Timeout,
+    /// Any outcome other than a zero exit, timeouts included
+    Failure,
+    /// The command exited with status 0
+    Success,
+}
+
+/// Whether any of `triggers` matches `outcome`.
+pub fn any_matches(triggers: &[Trigger], outcome: &TimeoutOutcome) -> bool {
+    let timed_out = matches!(
+        outcome.reason,
+        TimeoutReason::TermExit | TimeoutReason::Killed
+    );
+    let succeeded = outcome.result == TimeoutResult::Completed(0);
+    triggers.iter().any(|trigger| match trigger {
+        Trigger::Timeout => timed_out,
+        Trigger::Failure => !succeeded,
+        Trigger::Success => succeeded,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_triggers() {
+        let timed_out = TimeoutOutcome::new(TimeoutReason::TermExit, Duration::ZERO);
+        assert!(any_matches(&[Trigger::Timeout], &timed_out));
+        assert!(any_matches(&[Trigger::Failure], &timed_out));
+        assert!(!any_matches(&[Trigger::Success], &timed_out));
+        assert!(!any_matches(&[], &timed_out));
+    }
+}
@@ -0,0 +1,31 @@
+//! `--umask`: the file-creation mask to start the command with.
+
+/// Parse an octal mode such as `022` or `0077` into a mask of permission bits.
+pub fn parse_umask(arg: &str) -> Result<u32, String> {
+    let digits = arg.trim();
+    match u32::from_str_radix(digits, 8) {
+        Ok(mask) if mask <= 0o777 && digits.bytes().all(|b| b.is_ascii_digit()) => Ok(mask),
+        _ => Err(format!(
+            "invalid umask '{}': expected an octal mode from 000 to 777, such as 022",
+            arg
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_umask() {
+        assert_eq!(parse_umask("022"), Ok(0o022));
+        assert_eq!(parse_umask("0077"), Ok(0o077));
+        assert_eq!(parse_umask("7"), Ok(0o007));
+        assert_eq!(parse_umask("777"), Ok(0o777));
+        assert!(parse_umask("1000").is_err());
+        assert!(parse_umask("089").is_err());
+        assert!(parse_umask("+22").is_err());
+        assert!(parse_umask("u=rwx").is_err());
+        assert!(parse_umask("").is_err());
+    }
+}
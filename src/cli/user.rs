@@ -0,0 +1,155 @@
+//! `--user` and `--group`: looking up who to run the command as.
+
+use std::ffi::CString;
+use std::io;
+
+/// A user to run the command as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct User {
+    pub uid: u32,
+    /// The user's primary group, if they have a passwd entry.
+    pub gid: Option<u32>,
+}
+
+/// Look up `name`, which may also be a numeric user ID.
+pub fn lookup_user(name: &str) -> io::Result<User> {
+    if let Ok(uid) = name.parse() {
+        // A numeric ID is valid even without a passwd entry; it just has no primary group.
+        // SAFETY: `passwd` passes valid pointers and the size of the buffer.
+        let entry = passwd(|pwd, buf, len, result| unsafe {
+            libc::getpwuid_r(uid, pwd, buf, len, result)
+        })?;
+        return Ok(User {
+            uid,
+            gid: entry.map(|(_, gid)| gid),
+        });
+    }
+    let c_name = c_string(name)?;
+    // SAFETY: as above, and `c_name` outlives the call.
+    let entry = passwd(|pwd, buf, len, result| unsafe {
+        libc::getpwnam_r(c_name.as_ptr(), pwd, buf, len, result)
+    })?;
+    entry
+        .map(|(uid, gid)| User {
+            uid,
+            gid: Some(gid),
+        })
+        .ok_or_else(|| not_found("user", name))
+}
+
+/// Look up the group `name`, which may also be a numeric group ID.
+pub fn lookup_group(name: &str) -> io::Result<u32> {
+    if let Ok(gid) = name.parse() {
+        return Ok(gid);
+    }
+    let c_name = c_string(name)?;
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: an all-zero group is a valid value for the lookup to fill in.
+        let mut group: libc::group = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        // SAFETY: every pointer refers to a live local and `buf.len()` is the size of `buf`.
+        let error = unsafe {
+            libc::getgrnam_r(
+                c_name.as_ptr(),
+                &mut group,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        match error {
+            0 if result.is_null() => return Err(not_found("group", name)),
+            0 => return Ok(group.gr_gid),
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            error => return Err(io::Error::from_raw_os_error(error)),
+        }
+    }
+}
+
+/// Resolve `--user` and `--group` to the user and group IDs to run the command with. Without
+/// `--group`, a named user runs in their primary group.
+pub fn resolve(user: Option<&str>, group: Option<&str>) -> io::Result<(Option<u32>, Option<u32>)> {
+    let user = user.map(lookup_user).transpose()?;
+    let gid = match group {
+        Some(group) => Some(lookup_group(group)?),
+        None => user.and_then(|user| user.gid),
+    };
+    let uid = user.map(|user| user.uid);
+    check_privilege(uid, gid)?;
+    Ok((uid, gid))
+}
+
+/// Check that this process may switch to `uid` and `gid`, which without root only allows
+/// keeping the IDs it already has.
+pub fn check_privilege(uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    // SAFETY: these calls cannot fail and touch no memory.
+    let (euid, egid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    if euid == 0 || (uid.is_none_or(|uid| uid == euid) && gid.is_none_or(|gid| gid == egid)) {
+        Ok(())
+    } else {
+        Err(io::Error::from(io::ErrorKind::PermissionDenied))
+    }
+}
+
+/// Call a `getpw*_r` function through `lookup`, growing the buffer until the entry fits, and
+/// return the entry's user and group IDs.
+fn passwd(
+    lookup: impl Fn(
+        *mut libc::passwd,
+        *mut libc::c_char,
+        libc::size_t,
+        *mut *mut libc::passwd,
+    ) -> libc::c_int,
+) -> io::Result<Option<(u32, u32)>> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: an all-zero passwd is a valid value for the lookup to fill in.
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        match lookup(&mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) {
+            0 if result.is_null() => return Ok(None),
+            0 => return Ok(Some((pwd.pw_uid, pwd.pw_gid))),
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            error => return Err(io::Error::from_raw_os_error(error)),
+        }
+    }
+}
+
+fn c_string(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "contains NUL"))
+}
+
+fn not_found(kind: &str, name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no such {} '{}'", kind, name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_root() {
+        assert_eq!(
+            lookup_user("root").unwrap(),
+            User {
+                uid: 0,
+                gid: Some(0)
+            }
+        );
+        assert_eq!(lookup_user("0").unwrap().uid, 0);
+        assert_eq!(lookup_group("0").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_lookup_unknown_names() {
+        let error = lookup_user("no-such-user-12345").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(error.to_string(), "no such user 'no-such-user-12345'");
+        let error = lookup_group("no-such-group-12345").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
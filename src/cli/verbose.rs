@@ -0,0 +1,45 @@
+//! `--verbose` reporting of the command's lifecycle.
+
+use super::color::{Color, paint};
+use std::time::Duration;
+use timeout_cli::{EventSink, Signal, TimeoutResult, debug_print};
+
+/// Prints each lifecycle event as a debug line on stderr.
+#[derive(Debug)]
+pub struct VerboseSink;
+
+impl EventSink for VerboseSink {
+    fn on_spawn(&self, pid: u32) {
+        debug_print!(true, "{}", spawned(pid));
+    }
+
+    fn on_signal(&self, signal: Signal, elapsed: Duration) {
+        debug_print!(true, "{}", paint(Color::Red, &signalled(signal, elapsed)));
+    }
+
+    fn on_kill(&self, elapsed: Duration) {
+        debug_print!(true, "{}", paint(Color::Red, &killed(elapsed)));
+    }
+
+    fn on_exit(&self, result: TimeoutResult, elapsed: Duration) {
+        debug_print!(true, "{}", exited(result, elapsed));
+    }
+}
+
+// The wording of each event, shared with the other human-readable logs such as `--syslog`.
+
+pub fn spawned(pid: u32) -> String {
+    format!("Command spawned successfully with PID: {}", pid)
+}
+
+pub fn signalled(signal: Signal, elapsed: Duration) -> String {
+    format!("Sent {} to command after {:?}", signal, elapsed)
+}
+
+pub fn killed(elapsed: Duration) -> String {
+    format!("Sent KILL signal to command after {:?}", elapsed)
+}
+
+pub fn exited(result: TimeoutResult, elapsed: Duration) -> String {
+    format!("Command finished after {:?}: {:?}", elapsed, result)
+}
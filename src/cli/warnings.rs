@@ -0,0 +1,144 @@
+//! `--soft` and `--warn-at`: warning on stderr that the command is taking longer than it
+//! should, while leaving it to run until the timeout proper.
+
+use super::color::{Color, paint};
+use super::countdown;
+use super::duration::{format_duration, parse_duration};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// The warning printed once the command has run for `soft`, with `timeout` to go before it is
+/// stopped.
+pub fn soft_line(program: &str, soft: Duration, timeout: Duration) -> String {
+    format!(
+        "'{}' is still running after its soft timeout of {}, it will be stopped at {}",
+        program,
+        format_duration(soft),
+        format_duration(timeout)
+    )
+}
+
+/// A `--warn-at` threshold: a share of the timeout or a time after the command started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarnAt {
+    /// A percentage of the timeout, more than 0 and less than 100.
+    Percent(f64),
+    /// A fixed time.
+    After(Duration),
+}
+
+/// Parse a `--warn-at` threshold: a percentage (`80%`) or a duration (`45s`).
+pub fn parse_warn_at(arg: &str) -> Result<WarnAt, String> {
+    let Some(percent) = arg.trim().strip_suffix('%') else {
+        return parse_duration(arg).map(WarnAt::After);
+    };
+    match percent.trim().parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent < 100.0 => Ok(WarnAt::Percent(percent)),
+        _ => Err(format!(
+            "invalid percentage '{}' (use more than 0% and less than 100%)",
+            arg
+        )),
+    }
+}
+
+impl WarnAt {
+    /// When the threshold passes for a command given `timeout`, and the warning to print then
+    /// about `program`. Nothing for a threshold the command would be stopped before reaching.
+    pub fn warning(self, program: &str, timeout: Duration) -> Option<(Duration, String)> {
+        let (at, percent) = match self {
+            WarnAt::Percent(percent) => (timeout.mul_f64(percent / 100.0), percent),
+            WarnAt::After(at) => (
+                at,
+                at.as_secs_f64() * 100.0 / timeout.as_secs_f64().max(f64::MIN_POSITIVE),
+            ),
+        };
+        (at < timeout).then(|| {
+            let line = format!(
+                "'{}' has used {}% of its {} budget ({} elapsed)",
+                program,
+                percent.round(),
+                format_duration(timeout),
+                format_duration(at)
+            );
+            (at, line)
+        })
+    }
+}
+
+/// Prints warnings on stderr at set times after the command started, from a thread of its own,
+/// until dropped.
+#[derive(Debug)]
+pub struct Warnings {
+    _running: Sender<()>,
+}
+
+impl Warnings {
+    /// Start waiting to print each of `warnings` once its time has passed since `start`, in the
+    /// order of their times, each line prefixed with `prefix`.
+    pub fn start(start: Instant, mut warnings: Vec<(Duration, String)>, prefix: String) -> Self {
+        warnings.sort_by_key(|(at, _)| *at);
+        let (running, stopped) = mpsc::channel();
+        std::thread::spawn(move || {
+            for (at, warning) in warnings {
+                let wait = (start + at).saturating_duration_since(Instant::now());
+                match stopped.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+                let line = format!("timeout: warning: {}", warning);
+                countdown::above(|| eprintln!("{}{}", prefix, paint(Color::Yellow, &line)));
+            }
+        });
+        Self { _running: running }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_soft_line() {
+        assert_eq!(
+            soft_line(
+                "backup.sh",
+                Duration::from_secs(25),
+                Duration::from_secs(30)
+            ),
+            "'backup.sh' is still running after its soft timeout of 25.0s, it will be stopped at 30.0s"
+        );
+    }
+
+    #[test]
+    fn test_parse_warn_at() {
+        assert_eq!(parse_warn_at("80%"), Ok(WarnAt::Percent(80.0)));
+        assert_eq!(parse_warn_at("12.5%"), Ok(WarnAt::Percent(12.5)));
+        assert_eq!(
+            parse_warn_at("45s"),
+            Ok(WarnAt::After(Duration::from_secs(45)))
+        );
+        assert!(parse_warn_at("0%").is_err());
+        assert!(parse_warn_at("100%").is_err());
+        assert!(parse_warn_at("much%").is_err());
+        assert!(parse_warn_at("soon").is_err());
+    }
+
+    #[test]
+    fn test_warn_at_warning() {
+        let timeout = Duration::from_secs(600);
+        assert_eq!(
+            WarnAt::Percent(80.0).warning("backup.sh", timeout),
+            Some((
+                Duration::from_secs(480),
+                "'backup.sh' has used 80% of its 10m0s budget (8m0s elapsed)".to_string()
+            ))
+        );
+        assert_eq!(
+            WarnAt::After(Duration::from_secs(45))
+                .warning("backup.sh", timeout)
+                .map(|(_, line)| line),
+            Some("'backup.sh' has used 8% of its 10m0s budget (45.0s elapsed)".to_string())
+        );
+        assert_eq!(WarnAt::After(timeout).warning("backup.sh", timeout), None);
+    }
+}
@@ -0,0 +1,218 @@
+//! `--webhook`: POSTing the JSON summary of the run to an HTTP endpoint.
+
+use super::trigger::{Trigger, any_matches};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use timeout_cli::{TimeoutOutcome, debug_print};
+
+/// How many times a failed delivery is retried.
+pub const WEBHOOK_RETRIES: u32 = 1;
+
+/// A plain `http://` URL, split into what is needed to make the request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpUrl {
+    /// The host as it appears in the URL, including brackets for IPv6 addresses.
+    pub host: String,
+    pub port: u16,
+    /// The path and query, always starting with `/`.
+    pub path: String,
+}
+
+impl fmt::Display for HttpUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}:{}{}", self.host, self.port, self.path)
+    }
+}
+
+/// Parse an `http://host[:port][/path]` URL. HTTPS is not supported.
+pub fn parse_url(arg: &str) -> Result<HttpUrl, String> {
+    let rest = arg
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("unsupported URL '{}': only http:// URLs are supported", arg))?;
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let path = if path.starts_with('?') {
+        format!("/{}", path)
+    } else {
+        path.to_string()
+    };
+    // The port follows the last colon, unless that colon is inside an IPv6 address.
+    let (host, port) = match authority.rfind(':') {
+        Some(index) if !authority[index..].contains(']') => {
+            let port = authority[index + 1..]
+                .parse()
+                .map_err(|_| format!("invalid port in URL '{}'", arg))?;
+            (&authority[..index], port)
+        }
+        _ => (authority, 80),
+    };
+    if host.is_empty() || host.contains('@') {
+        return Err(format!("invalid host in URL '{}'", arg));
+    }
+    Ok(HttpUrl {
+        host: host.to_string(),
+        port,
+        path,
+    })
+}
+
+/// Parse a `Name: value` header.
+pub fn parse_header(arg: &str) -> Result<(String, String), String> {
+    let (name, value) = arg
+        .split_once(':')
+        .ok_or_else(|| format!("expected 'Name: value', got '{}'", arg))?;
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    if !valid || value.contains(['\r', '\n']) {
+        return Err(format!("invalid header '{}'", arg));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// Where and when to deliver the summary, from `--webhook` and its companion options.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub url: HttpUrl,
+    pub triggers: Vec<Trigger>,
+    pub headers: Vec<(String, String)>,
+    /// How long each attempt may take, including resolving the host.
+    pub timeout: Duration,
+}
+
+impl Webhook {
+    /// Whether the webhook should be sent after `outcome`.
+    pub fn applies(&self, outcome: &TimeoutOutcome) -> bool {
+        any_matches(&self.triggers, outcome)
+    }
+
+    /// POST `body`, retrying once. Fails with the last error if no attempt got a 2xx response.
+    pub fn send(&self, body: &str, verbose: bool) -> io::Result<()> {
+        let request = self.request(body);
+        let mut attempt = 0;
+        loop {
+            match self.attempt(request.clone()) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < WEBHOOK_RETRIES => {
+                    debug_print!(verbose, "Webhook attempt {} failed: {}", attempt + 1, e);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The raw HTTP request carrying `body`.
+    fn request(&self, body: &str) -> Vec<u8> {
+        let host = if self.url.port == 80 {
+            self.url.host.clone()
+        } else {
+            format!("{}:{}", self.url.host, self.url.port)
+        };
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: timeout-cli/{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.url.path,
+            host,
+            env!("CARGO_PKG_VERSION"),
+            body.len()
+        );
+        for (name, value) in &self.headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        request.push_str(body);
+        request.into_bytes()
+    }
+
+    /// Make one attempt, giving up after [`timeout`](Self::timeout).
+    fn attempt(&self, request: Vec<u8>) -> io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let url = self.url.clone();
+        let timeout = self.timeout;
+        // Resolving the host cannot be given a timeout, so the attempt runs on a thread that is
+        // abandoned if it takes too long.
+        thread::spawn(move || {
+            let _ = tx.send(post(&url, &request, timeout));
+        });
+        rx.recv_timeout(self.timeout)
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")))
+    }
+}
+
+/// Send `request` to `url` and check for a 2xx status.
+fn post(url: &HttpUrl, request: &[u8], timeout: Duration) -> io::Result<()> {
+    let host = url.host.trim_start_matches('[').trim_end_matches(']');
+    let address = (host, url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for host"))?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(request)?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response"))?;
+    if (200..300).contains(&status) {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "server responded with {}",
+            status
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            parse_url("http://example.com"),
+            Ok(HttpUrl {
+                host: "example.com".to_string(),
+                port: 80,
+                path: "/".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_url("http://127.0.0.1:8080/hooks/run?x=1"),
+            Ok(HttpUrl {
+                host: "127.0.0.1".to_string(),
+                port: 8080,
+                path: "/hooks/run?x=1".to_string(),
+            })
+        );
+        assert_eq!(parse_url("http://[::1]:9000").unwrap().host, "[::1]");
+        assert_eq!(parse_url("http://[::1]").unwrap().port, 80);
+        assert!(parse_url("https://example.com").is_err());
+        assert!(parse_url("http://:80/").is_err());
+        assert!(parse_url("http://example.com:port/").is_err());
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            parse_header("Authorization: Bearer abc"),
+            Ok(("Authorization".to_string(), "Bearer abc".to_string()))
+        );
+        assert!(parse_header("no colon").is_err());
+        assert!(parse_header("Bad Name: x").is_err());
+        assert!(parse_header("X-Test: a\r\nInjected: b").is_err());
+    }
+}
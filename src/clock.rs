@@ -0,0 +1,61 @@
+//! Where the timer gets the time from, so that tests can move it on by hand instead of
+//! sleeping through real timeouts.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The time source behind the timeout and kill-after deadlines.
+pub(crate) trait Clock: fmt::Debug + Send + Sync {
+    /// The current time.
+    fn now(&self) -> Instant;
+
+    /// How long the timer should sleep to reach `deadline`, or `None` to sleep until it is
+    /// woken because something changed.
+    fn sleep_for(&self, deadline: Instant) -> Option<Duration>;
+}
+
+/// The monotonic clock, used outside of tests.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_for(&self, deadline: Instant) -> Option<Duration> {
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// A clock that stands still until [`advance`](Self::advance)d. Whatever is waiting on it must
+/// be woken afterwards, e.g. with [`Control::update`](crate::supervisor::Control::update).
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct ManualClock {
+    now: std::sync::Mutex<Instant>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub(crate) fn new() -> Self {
+        Self {
+            now: std::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    pub(crate) fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep_for(&self, _deadline: Instant) -> Option<Duration> {
+        None
+    }
+}
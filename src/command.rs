@@ -0,0 +1,1440 @@
+//! Builder for running a command under a timeout.
+
+use crate::capture::{CaptureMode, DRAIN_GRACE, StreamCapture};
+use crate::clock::{Clock, SystemClock};
+use crate::error::TimeoutError;
+use crate::events::{Event, EventDispatcher, EventSink, TimeoutHook};
+use crate::outcome::{TimeoutOutcome, TimeoutReason};
+use crate::signal::Signal;
+use crate::supervisor::{Control, Supervisor, TimeoutHandle};
+use crate::trace;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, Write};
+use std::process::{Child, ChildStderr, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Delay between repeated `SIGKILL`s when [`TimeoutOptions::kill_retries`] is set, unless
+/// [`Timings::kill_retry_interval`] says otherwise.
+pub const KILL_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a command has to exit after the timeout signal before it is sent `SIGKILL`, when
+/// no [`TimeoutOptions::kill_after`] is set, unless [`Timings::term_grace`] says otherwise.
+pub const TERM_GRACE: Duration = Duration::from_millis(100);
+
+/// How often to check whether the command has exited when nothing will say so.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Settings controlling how a command is supervised.
+#[derive(Debug, Clone, Default)]
+pub struct TimeoutOptions {
+    /// Send [`signal`](Self::signal) once this much time has elapsed. `None` lets the command
+    /// run for as long as it likes.
+    pub timeout: Option<Duration>,
+    /// Also send `SIGKILL` if the command is still running this long after the timeout signal
+    /// was sent.
+    pub kill_after: Option<Duration>,
+    /// Send this at [`kill_after`](Self::kill_after) instead of `SIGKILL`.
+    pub kill_signal: Option<Signal>,
+    /// Signal sent when the timeout expires.
+    pub signal: Signal,
+    /// Send `SIGKILL` up to this many more times, [`KILL_RETRY_INTERVAL`] apart, if the command
+    /// has not been reaped after the first one.
+    pub kill_retries: u32,
+    /// Which clock the timeout is measured against.
+    pub clock: TimeoutClock,
+    /// What to connect the command's stdout and stderr to.
+    pub capture: CaptureMode,
+    /// What to connect the command's stdin to.
+    pub stdin: StdinMode,
+    /// With [`CaptureMode::Piped`], keep at most this many bytes of each stream.
+    pub capture_limit: Option<usize>,
+    /// Send the command's stderr wherever its stdout goes, as `2>&1` would, so that the two
+    /// share one pipe and keep the order they were written in. Only supported on Unix.
+    pub merge_stderr: bool,
+    /// Connect the command's stdout to the null device, whatever [`capture`](Self::capture)
+    /// says.
+    pub discard_stdout: bool,
+    /// Connect the command's stderr to the null device, whatever [`capture`](Self::capture)
+    /// says.
+    pub discard_stderr: bool,
+    /// Forward `SIGINT`, `SIGTERM` and `SIGHUP` received by this process to the command while
+    /// it runs. Only honoured by the async API, which installs the handlers for the lifetime of
+    /// the process once enabled.
+    pub forward_signals: bool,
+    /// Receives lifecycle events as the command is spawned, signalled and reaped.
+    pub events: Option<Arc<dyn EventSink>>,
+    /// Runs when the timeout expires, before the signal is sent.
+    pub on_timeout: Option<TimeoutHook>,
+    /// Start the command in a process group of its own, send signals to the whole group, and
+    /// once the command exits keep waiting until every other process in the group has exited
+    /// too. The timeout still runs from when the command was spawned. Only supported on Unix,
+    /// and not by the async API.
+    pub wait_for_group: bool,
+    /// Start the command in a session of its own, with its stdin as its controlling terminal
+    /// if that is a terminal, and send signals to the session's process group. Only supported
+    /// on Unix, and not by the async API.
+    pub new_session: bool,
+    /// How often and how long supervision waits on things. The defaults suit most uses.
+    pub timings: Timings,
+    /// Print debug information to stderr.
+    pub verbose: bool,
+}
+
+/// The intervals and grace periods supervision works with, for embedders that need to trade
+/// responsiveness for wakeups or the other way round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// How often to check whether the command has exited when nothing will say so: on Unix
+    /// when `SIGCHLD` cannot be relied on, while waiting for the rest of a process group, and
+    /// elsewhere than Unix. 10ms by default; must be more than zero.
+    pub poll_interval: Duration,
+    /// How long to wait for the event sinks and for captured output to be read once the command
+    /// has been reaped, in case something it left running still holds its pipes open. 200ms by
+    /// default.
+    pub drain_grace: Duration,
+    /// How far apart repeated `SIGKILL`s are with [`TimeoutOptions::kill_retries`].
+    /// [`KILL_RETRY_INTERVAL`] by default.
+    pub kill_retry_interval: Duration,
+    /// How long the command has to exit after the timeout signal before it is sent `SIGKILL`,
+    /// when no [`TimeoutOptions::kill_after`] is set. [`TERM_GRACE`] by default.
+    pub term_grace: Duration,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self {
+            poll_interval: POLL_INTERVAL,
+            drain_grace: DRAIN_GRACE,
+            kill_retry_interval: KILL_RETRY_INTERVAL,
+            term_grace: TERM_GRACE,
+        }
+    }
+}
+
+/// The clock a timeout is measured against.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutClock {
+    /// Real time elapsed since the command was spawned.
+    #[default]
+    Wall,
+    /// CPU time consumed by the command, enforced by the kernel through `RLIMIT_CPU`.
+    ///
+    /// The limit is rounded up to whole seconds. When it is reached the command receives
+    /// `SIGXCPU`, followed by `SIGKILL` once [`kill_after`](TimeoutOptions::kill_after) more
+    /// seconds of CPU time (one second if unset) have been used. Only supported on Unix.
+    Cpu,
+}
+
+/// What to connect the command's stdin to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StdinMode {
+    /// Share this process's stdin.
+    #[default]
+    Inherit,
+    /// Connect it to the null device, so reads see end of file at once.
+    Null,
+}
+
+impl StdinMode {
+    fn stdio(self) -> Stdio {
+        match self {
+            StdinMode::Inherit => Stdio::inherit(),
+            StdinMode::Null => Stdio::null(),
+        }
+    }
+}
+
+impl TimeoutOptions {
+    /// Check for option combinations that cannot be honoured.
+    pub fn validate(&self) -> Result<(), TimeoutError> {
+        if self.kill_after.is_some() && self.timeout.is_none() {
+            return Err(TimeoutError::InvalidOptions(
+                "kill_after requires a timeout",
+            ));
+        }
+        if self.clock == TimeoutClock::Cpu && self.kill_signal.is_some_and(|s| s != Signal::Kill) {
+            return Err(TimeoutError::InvalidOptions(
+                "a CPU-time limit always escalates with SIGKILL",
+            ));
+        }
+        if self.clock == TimeoutClock::Cpu && !cfg!(unix) {
+            return Err(TimeoutError::Unsupported(
+                "CPU-time limits are only supported on Unix",
+            ));
+        }
+        if self.merge_stderr && !cfg!(unix) {
+            return Err(TimeoutError::Unsupported(
+                "merging stderr into stdout is only supported on Unix",
+            ));
+        }
+        if self.wait_for_group && !cfg!(unix) {
+            return Err(TimeoutError::Unsupported(
+                "waiting for a process group is only supported on Unix",
+            ));
+        }
+        if self.new_session && !cfg!(unix) {
+            return Err(TimeoutError::Unsupported(
+                "starting a new session is only supported on Unix",
+            ));
+        }
+        if self.timings.poll_interval.is_zero() {
+            return Err(TimeoutError::InvalidOptions(
+                "the poll interval must be more than zero",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether signals go to the command's whole process group rather than just to it.
+    ///
+    /// On Windows a command with a `kill_after` gets a process group of its own, so that the
+    /// timeout signal can ask it to stop rather than terminate it straight away.
+    pub(crate) fn signals_group(&self) -> bool {
+        self.wait_for_group || self.new_session || (cfg!(windows) && self.kill_after.is_some())
+    }
+}
+
+/// A command to run under a timeout, configured in the style of [`std::process::Command`].
+///
+/// ```
+/// use std::time::Duration;
+/// use timeout_cli::{Signal, TimeoutCommand, TimeoutResult};
+///
+/// let outcome = TimeoutCommand::new("sleep")
+///     .args(["5"])
+///     .timeout(Duration::from_millis(100))
+///     .kill_after(Duration::from_secs(1))
+///     .signal(Signal::Term)
+///     .spawn()?
+///     .wait()?;
+/// assert_eq!(outcome.result, TimeoutResult::TimedOut);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TimeoutCommand {
+    program: OsString,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    removed_envs: Vec<OsString>,
+    #[cfg(unix)]
+    uid: Option<u32>,
+    #[cfg(unix)]
+    gid: Option<u32>,
+    #[cfg(unix)]
+    umask: Option<u32>,
+    options: TimeoutOptions,
+    stdin_file: Option<Arc<File>>,
+    stdin_data: Option<Arc<[u8]>>,
+    stdout_file: Option<Arc<File>>,
+    stderr_file: Option<Arc<File>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl TimeoutCommand {
+    /// Create a command for `program` with no arguments and no timeout.
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
+        Self {
+            program: program.as_ref().to_owned(),
+            args: Vec::new(),
+            envs: Vec::new(),
+            removed_envs: Vec::new(),
+            #[cfg(unix)]
+            uid: None,
+            #[cfg(unix)]
+            gid: None,
+            #[cfg(unix)]
+            umask: None,
+            options: TimeoutOptions::default(),
+            stdin_file: None,
+            stdin_data: None,
+            stdout_file: None,
+            stderr_file: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Measure the timeout against `clock` instead of the system clock.
+    #[cfg(test)]
+    pub(crate) fn clock_source(&mut self, clock: Arc<dyn Clock>) -> &mut Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Append a single argument.
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Append several arguments.
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Set an environment variable for the command, on top of those it inherits, in place of
+    /// any value set for it before.
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) -> &mut Self {
+        self.removed_envs.retain(|removed| removed != key.as_ref());
+        self.envs.retain(|(set, _)| set != key.as_ref());
+        self.envs
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    /// Stop the command from inheriting the environment variable `key`, and forget any value
+    /// set for it with [`env`](Self::env).
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+        self.envs.retain(|(set, _)| set != key.as_ref());
+        self.removed_envs.push(key.as_ref().to_owned());
+        self
+    }
+
+    /// Run the command as user `uid`. Changing user normally requires root; when running as
+    /// root, the command also loses any supplementary groups.
+    #[cfg(unix)]
+    pub fn uid(&mut self, uid: u32) -> &mut Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Run the command with `gid` as its group.
+    #[cfg(unix)]
+    pub fn gid(&mut self, gid: u32) -> &mut Self {
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Start the command with `mask` as its file-creation mask, in place of timeout's own.
+    #[cfg(unix)]
+    pub fn umask(&mut self, mask: u32) -> &mut Self {
+        self.umask = Some(mask);
+        self
+    }
+
+    /// Signal the command once `timeout` has elapsed.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Send `SIGKILL` if the command survives `kill_after` past the moment the timeout signal
+    /// was sent.
+    ///
+    /// Requires [`timeout`](Self::timeout) to be set as well.
+    pub fn kill_after(&mut self, kill_after: Duration) -> &mut Self {
+        self.options.kill_after = Some(kill_after);
+        self
+    }
+
+    /// Escalate with `signal` instead of `SIGKILL` once [`kill_after`](Self::kill_after) has
+    /// passed, e.g. [`Signal::Quit`] for a core dump of a hung process. Unlike `SIGKILL` it can be
+    /// caught or ignored, in which case supervision lasts until the command exits after all.
+    /// [`TimeoutChild::kill_now`] still sends `SIGKILL`.
+    pub fn kill_signal(&mut self, signal: Signal) -> &mut Self {
+        self.options.kill_signal = Some(signal);
+        self
+    }
+
+    /// Signal sent when the timeout expires, `SIGTERM` by default.
+    pub fn signal(&mut self, signal: Signal) -> &mut Self {
+        self.options.signal = signal;
+        self
+    }
+
+    /// Repeat the `SIGKILL`, or the [`kill_signal`](Self::kill_signal), up to `retries` times
+    /// while the command has not been reaped.
+    pub fn kill_retries(&mut self, retries: u32) -> &mut Self {
+        self.options.kill_retries = retries;
+        self
+    }
+
+    /// Poll, drain, retry and wait before killing with `timings` instead of the defaults.
+    pub fn timings(&mut self, timings: Timings) -> &mut Self {
+        self.options.timings = timings;
+        self
+    }
+
+    /// Measure the timeout against `clock`, wall-clock time by default.
+    pub fn clock(&mut self, clock: TimeoutClock) -> &mut Self {
+        self.options.clock = clock;
+        self
+    }
+
+    /// Connect the command's stdout and stderr according to `capture`.
+    pub fn capture(&mut self, capture: CaptureMode) -> &mut Self {
+        self.options.capture = capture;
+        self
+    }
+
+    /// Connect the command's stdin according to `stdin`.
+    pub fn stdin(&mut self, stdin: StdinMode) -> &mut Self {
+        self.options.stdin = stdin;
+        self
+    }
+
+    /// Keep at most `limit` bytes of each captured stream.
+    pub fn capture_limit(&mut self, limit: usize) -> &mut Self {
+        self.options.capture_limit = Some(limit);
+        self
+    }
+
+    /// Send the command's stderr to the same place as its stdout. See
+    /// [`TimeoutOptions::merge_stderr`]. With [`CaptureMode::Piped`] both streams end up in
+    /// [`TimeoutOutcome::stdout`](crate::TimeoutOutcome::stdout), and with
+    /// [`CaptureMode::Manual`] there is no stderr pipe to take.
+    pub fn merge_stderr(&mut self, merge_stderr: bool) -> &mut Self {
+        self.options.merge_stderr = merge_stderr;
+        self
+    }
+
+    /// Throw the command's stdout away, leaving its stderr as [`capture`](Self::capture) says.
+    pub fn discard_stdout(&mut self, discard: bool) -> &mut Self {
+        self.options.discard_stdout = discard;
+        self
+    }
+
+    /// Throw the command's stderr away, leaving its stdout as [`capture`](Self::capture) says.
+    pub fn discard_stderr(&mut self, discard: bool) -> &mut Self {
+        self.options.discard_stderr = discard;
+        self
+    }
+
+    /// Give the command `file` as its stdin, in place of what [`stdin`](Self::stdin) says.
+    /// Clones of the command share it, and with it the position reached in the file.
+    pub fn stdin_file(&mut self, file: File) -> &mut Self {
+        self.stdin_file = Some(Arc::new(file));
+        self
+    }
+
+    /// Write `data` to the command's stdin from a thread of its own, then close it so the
+    /// command sees end of file. Takes the place of the [`stdin_file`](Self::stdin_file) and of
+    /// what [`stdin`](Self::stdin) says. A command that exits or is killed without reading it
+    /// all just ends the writing.
+    pub fn stdin_bytes(&mut self, data: impl Into<Arc<[u8]>>) -> &mut Self {
+        self.stdin_data = Some(data.into());
+        self
+    }
+
+    /// Connect the command's stdout straight to `file`, in place of what
+    /// [`capture`](Self::capture) says, unless it is discarded. Clones of the command share it.
+    pub fn stdout_file(&mut self, file: File) -> &mut Self {
+        self.stdout_file = Some(Arc::new(file));
+        self
+    }
+
+    /// Connect the command's stderr straight to `file`, in place of what
+    /// [`capture`](Self::capture) says, unless it is discarded. Clones of the command share it.
+    pub fn stderr_file(&mut self, file: File) -> &mut Self {
+        self.stderr_file = Some(Arc::new(file));
+        self
+    }
+
+    /// Report lifecycle events to `sink`.
+    pub fn events<S: EventSink + 'static>(&mut self, sink: S) -> &mut Self {
+        self.options.events = Some(Arc::new(sink));
+        self
+    }
+
+    /// Run `hook` with the command's PID and elapsed time when the timeout expires, before
+    /// the signal is sent. See [`TimeoutHook`].
+    pub fn on_timeout(
+        &mut self,
+        hook: impl Fn(u32, Duration) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.options.on_timeout = Some(TimeoutHook::new(hook));
+        self
+    }
+
+    /// Run the command in its own process group and wait for the whole group to exit. See
+    /// [`TimeoutOptions::wait_for_group`].
+    pub fn wait_for_group(&mut self, wait_for_group: bool) -> &mut Self {
+        self.options.wait_for_group = wait_for_group;
+        self
+    }
+
+    /// Run the command in a session of its own. See [`TimeoutOptions::new_session`].
+    pub fn new_session(&mut self, new_session: bool) -> &mut Self {
+        self.options.new_session = new_session;
+        self
+    }
+
+    /// Print debug information to stderr while supervising.
+    pub fn verbose(&mut self, verbose: bool) -> &mut Self {
+        self.options.verbose = verbose;
+        self
+    }
+
+    /// Replace all supervision settings at once.
+    pub fn options(&mut self, options: TimeoutOptions) -> &mut Self {
+        self.options = options;
+        self
+    }
+
+    /// The program that will be run.
+    pub fn get_program(&self) -> &OsStr {
+        &self.program
+    }
+
+    /// The arguments that will be passed to the program.
+    pub fn get_args(&self) -> impl Iterator<Item = &OsStr> {
+        self.args.iter().map(OsString::as_os_str)
+    }
+
+    /// The environment variables set with [`env`](Self::env), in the order they were added.
+    pub fn get_envs(&self) -> impl Iterator<Item = (&OsStr, &OsStr)> {
+        self.envs
+            .iter()
+            .map(|(key, value)| (key.as_os_str(), value.as_os_str()))
+    }
+
+    /// The current supervision settings.
+    pub fn get_options(&self) -> &TimeoutOptions {
+        &self.options
+    }
+
+    /// Spawn the command and start supervising it in the background.
+    ///
+    /// Fails with [`TimeoutError::InvalidOptions`] or [`TimeoutError::Unsupported`] if the
+    /// options cannot be honoured, with [`TimeoutError::SpawnTimedOut`] if starting the command
+    /// is still blocked after a wall-clock timeout has run out, or with whatever else stopped it
+    /// starting. A command started after its deadline is signalled at once instead.
+    pub fn spawn(&self) -> Result<TimeoutChild, TimeoutError> {
+        self.options.validate()?;
+        let verbose = self.options.verbose;
+        // An empty program name gets a platform-dependent error from the OS.
+        if self.program.to_string_lossy().trim().is_empty() {
+            return Err(TimeoutError::EmptyCommand);
+        }
+
+        let mut cmd = self.build_command();
+        self.redirect(&mut cmd)
+            .map_err(|e| TimeoutError::from_spawn(&self.program, e))?;
+
+        // The timeout runs from before the spawn, so a spawn that hangs, e.g. on a stalled
+        // network filesystem, cannot outlast it.
+        let start = self.clock.now();
+        let spawn_timeout = match self.options.clock {
+            TimeoutClock::Wall => self.options.timeout,
+            TimeoutClock::Cpu => None,
+        };
+        let mut child = trace::in_spawn_span(&self.program, || spawn_within(cmd, spawn_timeout))
+            .inspect_err(|e| {
+                debug_print!(verbose, "Failed to spawn command: {}", e);
+            })
+            .map_err(|e| TimeoutError::from_spawn(&self.program, e))?;
+        let pid = child.id();
+        if let (Some(data), Some(mut stdin)) = (self.stdin_data.clone(), child.stdin.take()) {
+            // Blocks while the command is not reading, so never on the supervising thread. A
+            // broken pipe only means the command is done with its input.
+            thread::spawn(move || {
+                let _ = stdin.write_all(&data);
+            });
+        }
+        let events = EventDispatcher::start(self.options.events.clone());
+        events.emit(Event::Spawn(pid));
+
+        let term_deadline = match (self.options.clock, self.options.timeout) {
+            (TimeoutClock::Wall, Some(timeout)) => Some(start + timeout),
+            _ => None,
+        };
+        // The kill deadline is only set once the timeout signal has been sent.
+        let control = Arc::new(Control::new(self.clock.clone(), term_deadline, None));
+        {
+            let control = control.clone();
+            thread::spawn(move || control.run_timer(verbose));
+        }
+
+        let (stdout, stderr) = match self.options.capture {
+            CaptureMode::Piped => {
+                let limit = self.options.capture_limit;
+                (
+                    child
+                        .stdout
+                        .take()
+                        .map(|stdout| StreamCapture::start(stdout, limit)),
+                    child
+                        .stderr
+                        .take()
+                        .map(|stderr| StreamCapture::start(stderr, limit)),
+                )
+            }
+            _ => (None, None),
+        };
+        let child_stdout = child.stdout.take();
+        let child_stderr = child.stderr.take();
+
+        let (tx, rx) = mpsc::channel();
+        let supervisor = Supervisor {
+            child,
+            options: self.options.clone(),
+            start,
+            control: control.clone(),
+            events,
+            stdout,
+            stderr,
+            reaped: None,
+            reaped_at: None,
+        };
+        let supervisor = thread::spawn(move || {
+            let _ = tx.send(supervisor.run());
+        });
+
+        Ok(TimeoutChild {
+            pid,
+            handle: TimeoutHandle::new(control),
+            stdout: child_stdout,
+            stderr: child_stderr,
+            result: rx,
+            supervisor,
+        })
+    }
+
+    /// Connect the command to the [`stdin_file`](Self::stdin_file), and its output to the
+    /// [`stdout_file`](Self::stdout_file) and [`stderr_file`](Self::stderr_file), if given and
+    /// not discarded.
+    fn redirect(&self, cmd: &mut Command) -> io::Result<()> {
+        if self.stdin_data.is_some() {
+            cmd.stdin(Stdio::piped());
+        } else if let Some(file) = self.stdin_file.as_deref() {
+            cmd.stdin(file.try_clone()?);
+        }
+        if let Some(file) = self.stdout_file.as_deref()
+            && !self.options.discard_stdout
+        {
+            cmd.stdout(file.try_clone()?);
+        }
+        if let Some(file) = self.stderr_file.as_deref()
+            && !self.options.discard_stderr
+        {
+            cmd.stderr(file.try_clone()?);
+        }
+        Ok(())
+    }
+
+    /// Build the underlying process command, including any pre-exec setup the options need.
+    pub(crate) fn build_command(&self) -> Command {
+        let verbose = self.options.verbose;
+        // Quoted and escaped exactly as the command will receive them, to help with quoting
+        // problems: timeout passes them on untouched, so any mangling happened before it.
+        for (i, arg) in std::iter::once(self.get_program())
+            .chain(self.get_args())
+            .enumerate()
+        {
+            debug_print!(verbose, "argv[{}]: {:?}", i, arg);
+        }
+        let mut cmd = Command::new(&self.program);
+        for key in &self.removed_envs {
+            cmd.env_remove(key);
+        }
+        cmd.args(&self.args)
+            .envs(self.envs.iter().map(|(key, value)| (key, value)))
+            .stdin(self.options.stdin.stdio())
+            .stdout(if self.options.discard_stdout {
+                Stdio::null()
+            } else {
+                self.options.capture.stdio()
+            })
+            .stderr(if self.options.discard_stderr {
+                Stdio::null()
+            } else if self.options.merge_stderr {
+                // Pointed at stdout in the child, below, so no pipe of its own is needed.
+                Stdio::inherit()
+            } else {
+                self.options.capture.stdio()
+            });
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+
+            if let Some(gid) = self.gid {
+                cmd.gid(gid);
+            }
+            if let Some(uid) = self.uid {
+                cmd.uid(uid);
+            }
+            if let Some(mask) = self.umask {
+                // SAFETY: umask is async-signal-safe, cannot fail and touches no memory shared
+                // with the parent.
+                unsafe {
+                    cmd.pre_exec(move || {
+                        libc::umask(mask as libc::mode_t);
+                        Ok(())
+                    });
+                }
+            }
+            if self.options.new_session {
+                // SAFETY: setsid, isatty and ioctl are async-signal-safe and touch no memory
+                // shared with the parent. The session is also a new process group, so it must
+                // not be asked for with process_group, which would make setsid fail.
+                unsafe {
+                    cmd.pre_exec(|| {
+                        if libc::setsid() == -1 {
+                            return Err(io::Error::last_os_error());
+                        }
+                        if libc::isatty(libc::STDIN_FILENO) == 1
+                            && libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY as _, 0) == -1
+                        {
+                            return Err(io::Error::last_os_error());
+                        }
+                        Ok(())
+                    });
+                }
+            } else if self.options.wait_for_group {
+                cmd.process_group(0);
+            }
+            if self.options.merge_stderr && !self.options.discard_stderr {
+                // SAFETY: dup2 is async-signal-safe and touches no memory shared with the parent.
+                unsafe {
+                    cmd.pre_exec(|| {
+                        if libc::dup2(libc::STDOUT_FILENO, libc::STDERR_FILENO) == -1 {
+                            Err(io::Error::last_os_error())
+                        } else {
+                            Ok(())
+                        }
+                    });
+                }
+            }
+        }
+
+        #[cfg(windows)]
+        if self.options.signals_group() {
+            use std::os::windows::process::CommandExt;
+
+            cmd.creation_flags(crate::signal::CREATE_NEW_PROCESS_GROUP);
+        }
+
+        #[cfg(unix)]
+        if let (TimeoutClock::Cpu, Some(timeout)) = (self.options.clock, self.options.timeout) {
+            use std::os::unix::process::CommandExt;
+
+            let soft = ceil_secs(timeout);
+            let hard = soft + self.options.kill_after.map_or(1, ceil_secs);
+            debug_print!(
+                verbose,
+                "Limiting CPU time to {}s (hard limit {}s)",
+                soft,
+                hard
+            );
+            let limit = libc::rlimit {
+                rlim_cur: soft as libc::rlim_t,
+                rlim_max: hard as libc::rlim_t,
+            };
+            // SAFETY: setrlimit is async-signal-safe and touches no memory shared with the parent.
+            unsafe {
+                cmd.pre_exec(move || {
+                    if libc::setrlimit(libc::RLIMIT_CPU, &limit) == 0 {
+                        Ok(())
+                    } else {
+                        Err(io::Error::last_os_error())
+                    }
+                });
+            }
+        }
+
+        debug_print!(
+            verbose,
+            "Spawning command: {} {:?}",
+            self.program.to_string_lossy(),
+            self.args
+        );
+        cmd
+    }
+}
+
+/// A running command spawned by [`TimeoutCommand::spawn`].
+///
+/// Dropping the handle without calling [`wait`](Self::wait) leaves the command running under
+/// supervision in the background.
+#[derive(Debug)]
+pub struct TimeoutChild {
+    pid: u32,
+    handle: TimeoutHandle,
+    stdout: Option<ChildStdout>,
+    stderr: Option<ChildStderr>,
+    result: mpsc::Receiver<io::Result<TimeoutOutcome>>,
+    supervisor: thread::JoinHandle<()>,
+}
+
+impl TimeoutChild {
+    /// Process ID of the command.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Take the command's stdout pipe when spawned with [`CaptureMode::Manual`].
+    ///
+    /// The caller must keep reading it, or the command will block once the pipe fills up.
+    pub fn take_stdout(&mut self) -> Option<ChildStdout> {
+        self.stdout.take()
+    }
+
+    /// Take the command's stderr pipe when spawned with [`CaptureMode::Manual`].
+    ///
+    /// The caller must keep reading it, or the command will block once the pipe fills up.
+    pub fn take_stderr(&mut self) -> Option<ChildStderr> {
+        self.stderr.take()
+    }
+
+    /// Kill the command immediately instead of waiting for the timeout.
+    ///
+    /// The outcome reported by [`wait`](Self::wait) will be [`TimeoutResult::Killed`](crate::TimeoutResult::Killed) unless the
+    /// command had already exited.
+    pub fn kill_now(&self) {
+        self.handle.kill_now();
+    }
+
+    /// A handle for cancelling or detaching from the command, usable from other threads.
+    pub fn handle(&self) -> TimeoutHandle {
+        self.handle.clone()
+    }
+
+    /// Wait for the command to exit or be terminated.
+    pub fn wait(self) -> Result<TimeoutOutcome, TimeoutError> {
+        let outcome = self.result.recv().unwrap_or_else(|_| {
+            Err(io::Error::other(
+                "supervisor thread exited without a result",
+            ))
+        });
+        let _ = self.supervisor.join();
+        outcome.map_err(TimeoutError::WaitFailed)
+    }
+}
+
+/// Run `program` with `args` under `options` and wait for it to finish.
+///
+/// Failures to spawn or supervise the command are folded into the outcome as
+/// [`TimeoutResult::NotFound`](crate::TimeoutResult::NotFound), [`TimeoutResult::CannotInvoke`](crate::TimeoutResult::CannotInvoke) or
+/// [`TimeoutResult::InternalError`](crate::TimeoutResult::InternalError).
+pub fn run_with_timeout<P, I, S>(program: P, args: I, options: &TimeoutOptions) -> TimeoutOutcome
+where
+    P: AsRef<OsStr>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let start = Instant::now();
+    let mut options = options.clone();
+    if options.capture == CaptureMode::Manual {
+        options.capture = CaptureMode::Piped;
+    }
+    let result = TimeoutCommand::new(program)
+        .args(args)
+        .options(options)
+        .spawn()
+        .and_then(TimeoutChild::wait);
+    match result {
+        Ok(outcome) => outcome,
+        Err(e) => TimeoutOutcome::failed(&e, start.elapsed()),
+    }
+}
+
+/// Classify the exit status of a command that finished before any timeout signal was sent.
+pub(crate) fn exit_reason(status: ExitStatus, options: &TimeoutOptions) -> TimeoutReason {
+    #[cfg(unix)]
+    if options.clock == TimeoutClock::Cpu {
+        use std::os::unix::process::ExitStatusExt;
+
+        match status.signal() {
+            Some(libc::SIGXCPU) => {
+                debug_print!(options.verbose, "CPU time limit exceeded");
+                return TimeoutReason::TermExit;
+            }
+            Some(libc::SIGKILL) => {
+                debug_print!(options.verbose, "CPU time hard limit exceeded");
+                return TimeoutReason::Killed;
+            }
+            _ => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (status, options);
+    TimeoutReason::CompletedInTime
+}
+
+/// Round `duration` up to whole seconds, as `RLIMIT_CPU` only has second granularity.
+#[cfg(unix)]
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+/// How much longer than the timeout starting the command may take before the spawn counts as
+/// hung rather than just late, e.g. because the timeout was already up before it began. An
+/// ordinary spawn finishes well within it, and the supervisor then signals the command for the
+/// deadline it has missed.
+const SPAWN_GRACE: Duration = Duration::from_millis(100);
+
+/// Start `cmd`, failing with [`io::ErrorKind::TimedOut`] if the spawn is still blocked
+/// [`SPAWN_GRACE`] after `timeout`. A command that does start after that is killed as soon as
+/// it has.
+fn spawn_within(mut cmd: Command, timeout: Option<Duration>) -> io::Result<Child> {
+    let Some(timeout) = timeout else {
+        return cmd.spawn();
+    };
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        if let Err(mpsc::SendError(Ok(mut child))) = tx.send(cmd.spawn()) {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    });
+    match rx.recv_timeout(timeout.saturating_add(SPAWN_GRACE)) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "timed out starting the command",
+        )),
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            Err(io::Error::other("the command could not be started"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outcome::TimeoutResult;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_spawn_within_gives_up_on_a_slow_spawn() {
+        use std::os::unix::process::CommandExt;
+
+        let mut cmd = Command::new("true");
+        // SAFETY: sleep(3) is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(|| {
+                libc::sleep(2);
+                Ok(())
+            });
+        }
+        let start = Instant::now();
+        let error = spawn_within(cmd, Some(Duration::from_millis(200))).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let mut child = spawn_within(Command::new("true"), Some(Duration::from_secs(5))).unwrap();
+        assert!(child.wait().unwrap().success());
+
+        // A timeout already up is not a hung spawn.
+        let mut child = spawn_within(Command::new("true"), Some(Duration::ZERO)).unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_new_has_no_args_or_timeout() {
+        let cmd = TimeoutCommand::new("echo");
+        assert_eq!(cmd.get_program(), "echo");
+        assert_eq!(cmd.get_args().count(), 0);
+        assert_eq!(cmd.get_options().timeout, None);
+        assert_eq!(cmd.get_options().kill_after, None);
+        assert_eq!(cmd.get_options().signal, Signal::Term);
+    }
+
+    #[test]
+    fn test_arg_and_args_append_in_order() {
+        let mut cmd = TimeoutCommand::new("echo");
+        cmd.arg("a").args(["b", "c"]).arg("d");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(args, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_env_is_passed_to_command() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "test \"$TIMEOUT_TEST_VAR\" = value"])
+            .env("TIMEOUT_TEST_VAR", "value")
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+    }
+
+    #[test]
+    fn test_env_remove_hides_variable() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "test -z \"${HOME+set}${TIMEOUT_TEST_VAR+set}\""])
+            .env("TIMEOUT_TEST_VAR", "value")
+            .env_remove("TIMEOUT_TEST_VAR")
+            .env_remove("HOME")
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+    }
+
+    #[test]
+    fn test_setters_update_options() {
+        let mut cmd = TimeoutCommand::new("echo");
+        cmd.timeout(Duration::from_secs(3))
+            .kill_after(Duration::from_secs(1))
+            .signal(Signal::Int)
+            .verbose(true);
+        let options = cmd.get_options();
+        assert_eq!(options.timeout, Some(Duration::from_secs(3)));
+        assert_eq!(options.kill_after, Some(Duration::from_secs(1)));
+        assert_eq!(options.signal, Signal::Int);
+        assert!(options.verbose);
+    }
+
+    #[test]
+    fn test_options_replaces_previous_settings() {
+        let mut cmd = TimeoutCommand::new("echo");
+        cmd.timeout(Duration::from_secs(3)).signal(Signal::Hup);
+        cmd.options(TimeoutOptions {
+            kill_after: Some(Duration::from_secs(2)),
+            ..Default::default()
+        });
+        assert_eq!(cmd.get_options().timeout, None);
+        assert_eq!(cmd.get_options().signal, Signal::Term);
+        assert_eq!(cmd.get_options().kill_after, Some(Duration::from_secs(2)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_null_stdin_reads_end_of_file() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "read line; echo \"[$line]\""])
+            .timeout(Duration::from_secs(5))
+            .stdin(StdinMode::Null)
+            .capture(CaptureMode::Piped)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.stdout, b"[]\n");
+    }
+
+    #[test]
+    fn test_kill_after_without_timeout_is_rejected() {
+        let err = TimeoutCommand::new("echo")
+            .kill_after(Duration::from_secs(1))
+            .spawn()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_zero_poll_interval_is_rejected() {
+        let err = TimeoutCommand::new("echo")
+            .timings(Timings {
+                poll_interval: Duration::ZERO,
+                ..Timings::default()
+            })
+            .spawn()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_custom_timings_still_supervise() {
+        let timings = Timings {
+            poll_interval: Duration::from_millis(1),
+            drain_grace: Duration::from_millis(20),
+            kill_retry_interval: Duration::from_millis(10),
+            term_grace: Duration::from_millis(50),
+        };
+        let outcome = TimeoutCommand::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_millis(100))
+            .timings(timings)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::TimedOut);
+        assert_eq!(Timings::default().kill_retry_interval, KILL_RETRY_INTERVAL);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_term_grace_delays_the_kill() {
+        let start = Instant::now();
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "trap '' TERM; sleep 5"])
+            .timeout(Duration::from_millis(100))
+            .timings(Timings {
+                term_grace: Duration::from_millis(600),
+                ..Timings::default()
+            })
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(700));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_debug_includes_program_and_options() {
+        let mut cmd = TimeoutCommand::new("cargo");
+        cmd.arg("test").timeout(Duration::from_secs(300));
+        let debug = format!("{:?}", cmd);
+        assert!(debug.contains("cargo"), "{debug}");
+        assert!(debug.contains("test"), "{debug}");
+        assert!(debug.contains("300s"), "{debug}");
+    }
+
+    #[test]
+    fn test_spawn_without_timeout_completes() {
+        let child = TimeoutCommand::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        assert!(child.pid() > 0);
+        let outcome = child.wait().unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(3));
+    }
+
+    #[test]
+    fn test_spawn_missing_program_fails() {
+        let err = TimeoutCommand::new("definitely_nonexistent_command_12345")
+            .spawn()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_timeout_terminates_command() {
+        let outcome = TimeoutCommand::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_millis(100))
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::TimedOut);
+        assert!(outcome.elapsed < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_timeout_with_kill_after_and_responsive_command() {
+        let outcome = TimeoutCommand::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_millis(100))
+            .kill_after(Duration::from_secs(2))
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::TimedOut);
+        assert!(outcome.elapsed < Duration::from_secs(1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kill_after_escalates_when_signal_ignored() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "trap '' USR1; sleep 5"])
+            .timeout(Duration::from_millis(200))
+            .kill_after(Duration::from_millis(200))
+            .signal(Signal::Usr1)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Killed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kill_signal_replaces_sigkill() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "trap '' TERM; exec sleep 5"])
+            .timeout(Duration::from_millis(100))
+            .kill_after(Duration::from_millis(100))
+            .kill_signal(Signal::Int)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Killed);
+        assert_eq!(outcome.status.unwrap().signal(), Some(libc::SIGINT));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cpu_clock_ignores_idle_time() {
+        let outcome = TimeoutCommand::new("sleep")
+            .arg("2")
+            .timeout(Duration::from_secs(1))
+            .clock(TimeoutClock::Cpu)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cpu_clock_stops_busy_command() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "while :; do :; done"])
+            .timeout(Duration::from_secs(1))
+            .clock(TimeoutClock::Cpu)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::TimedOut);
+    }
+
+    #[test]
+    fn test_kill_retries_stop_once_reaped() {
+        let child = TimeoutCommand::new("sleep")
+            .arg("5")
+            .kill_retries(20)
+            .spawn()
+            .unwrap();
+        child.kill_now();
+        let outcome = child.wait().unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Killed);
+        assert!(outcome.elapsed < KILL_RETRY_INTERVAL * 5);
+    }
+
+    #[test]
+    fn test_kill_now_kills_command() {
+        let child = TimeoutCommand::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_secs(5))
+            .spawn()
+            .unwrap();
+        child.kill_now();
+        let outcome = child.wait().unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Killed);
+        assert!(outcome.elapsed < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_piped_capture_collects_both_streams() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "echo out; echo err >&2"])
+            .timeout(Duration::from_secs(5))
+            .capture(CaptureMode::Piped)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert_eq!(outcome.stdout, b"out\n");
+        assert_eq!(outcome.stderr, b"err\n");
+    }
+
+    #[test]
+    fn test_piped_capture_larger_than_pipe_buffer() {
+        // 1 MiB per stream is far beyond any pipe buffer, so the command would block forever
+        // if the streams were only read after it exited.
+        let outcome = TimeoutCommand::new("sh")
+            .args([
+                "-c",
+                "head -c 1048576 /dev/zero; head -c 1048576 /dev/zero >&2",
+            ])
+            .timeout(Duration::from_secs(10))
+            .capture(CaptureMode::Piped)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert_eq!(outcome.stdout.len(), 1 << 20);
+        assert_eq!(outcome.stderr.len(), 1 << 20);
+    }
+
+    #[test]
+    fn test_capture_limit_caps_each_stream() {
+        let outcome = TimeoutCommand::new("sh")
+            .args([
+                "-c",
+                "head -c 1048576 /dev/zero; head -c 1048576 /dev/zero >&2",
+            ])
+            .timeout(Duration::from_secs(10))
+            .capture(CaptureMode::Piped)
+            .capture_limit(1000)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert_eq!(outcome.stdout.len(), 1000);
+        assert_eq!(outcome.stderr.len(), 1000);
+    }
+
+    #[test]
+    fn test_piped_capture_keeps_partial_output_on_timeout() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "echo before; exec sleep 5"])
+            .timeout(Duration::from_millis(300))
+            .capture(CaptureMode::Piped)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::TimedOut);
+        assert_eq!(outcome.stdout, b"before\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_merge_stderr_keeps_write_order() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "echo 1; echo 2 >&2; echo 3; echo 4 >&2"])
+            .timeout(Duration::from_secs(5))
+            .capture(CaptureMode::Piped)
+            .merge_stderr(true)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.stdout, b"1\n2\n3\n4\n");
+        assert!(outcome.stderr.is_empty());
+    }
+
+    #[test]
+    fn test_discard_one_stream() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "echo out; echo err >&2"])
+            .timeout(Duration::from_secs(5))
+            .capture(CaptureMode::Piped)
+            .discard_stdout(true)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert!(outcome.stdout.is_empty());
+        assert_eq!(outcome.stderr, b"err\n");
+    }
+
+    #[test]
+    fn test_output_files_replace_capture() {
+        let path = std::env::temp_dir().join(format!("timeout-output-{}", std::process::id()));
+        let file = File::create(&path).unwrap();
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "echo out; echo err >&2"])
+            .timeout(Duration::from_secs(5))
+            .capture(CaptureMode::Piped)
+            .stdout_file(file.try_clone().unwrap())
+            .stderr_file(file)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert!(outcome.stdout.is_empty() && outcome.stderr.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "out\nerr\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_stdin_file_is_read_by_the_command() {
+        let path = std::env::temp_dir().join(format!("timeout-stdin-{}", std::process::id()));
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        let outcome = TimeoutCommand::new("cat")
+            .timeout(Duration::from_secs(5))
+            .capture(CaptureMode::Piped)
+            .stdin_file(File::open(&path).unwrap())
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(outcome.stdout, b"one\ntwo\n");
+    }
+
+    #[test]
+    fn test_stdin_bytes_end_with_end_of_file() {
+        let outcome = TimeoutCommand::new("cat")
+            .timeout(Duration::from_secs(5))
+            .capture(CaptureMode::Piped)
+            .stdin_bytes(&b"payload"[..])
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert_eq!(outcome.stdout, b"payload");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_new_session_leads_its_own_session() {
+        let child = TimeoutCommand::new("sleep")
+            .arg("5")
+            .new_session(true)
+            .spawn()
+            .unwrap();
+        let pid = child.pid() as libc::pid_t;
+        // SAFETY: getsid only looks the process up.
+        assert_eq!(unsafe { libc::getsid(pid) }, pid);
+        child.kill_now();
+        assert_eq!(child.wait().unwrap().result, TimeoutResult::Killed);
+    }
+
+    #[test]
+    fn test_null_capture_discards_output() {
+        let outcome = TimeoutCommand::new("echo")
+            .arg("discarded")
+            .capture(CaptureMode::Null)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert!(outcome.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_manual_capture_hands_out_pipes() {
+        let mut child = TimeoutCommand::new("sh")
+            .args(["-c", "echo out; echo err >&2"])
+            .capture(CaptureMode::Manual)
+            .spawn()
+            .unwrap();
+        let mut stdout = child.take_stdout().unwrap();
+        let mut stderr = child.take_stderr().unwrap();
+        assert!(child.take_stdout().is_none());
+
+        let mut out = String::new();
+        let mut err = String::new();
+        std::io::Read::read_to_string(&mut stdout, &mut out).unwrap();
+        std::io::Read::read_to_string(&mut stderr, &mut err).unwrap();
+        let outcome = child.wait().unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert_eq!(out, "out\n");
+        assert_eq!(err, "err\n");
+        assert!(outcome.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_run_with_timeout_collects_manual_capture() {
+        let options = TimeoutOptions {
+            capture: CaptureMode::Manual,
+            ..Default::default()
+        };
+        let outcome = run_with_timeout("echo", ["collected"], &options);
+        assert_eq!(outcome.stdout, b"collected\n");
+    }
+
+    #[test]
+    fn test_run_with_timeout_reports_not_found() {
+        let outcome = run_with_timeout(
+            "definitely_nonexistent_command_12345",
+            [""; 0],
+            &TimeoutOptions::default(),
+        );
+        assert_eq!(outcome.result, TimeoutResult::NotFound);
+    }
+}
@@ -0,0 +1,189 @@
+//! Why a command could not be run or supervised.
+
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// An error from spawning or supervising a command, for matching on what went wrong.
+///
+/// The [`Display`](fmt::Display) text is what the `timeout` binary prints after `timeout: `.
+/// Converting to [`io::Error`] keeps the corresponding [`kind`](Self::kind), so `?` still works
+/// in functions returning [`io::Result`].
+///
+/// ```
+/// use timeout_cli::{TimeoutCommand, TimeoutError};
+///
+/// match TimeoutCommand::new("no-such-command-here").spawn() {
+///     Err(TimeoutError::NotFound { program }) => assert_eq!(program, "no-such-command-here"),
+///     other => panic!("unexpected {:?}", other.map(|child| child.pid())),
+/// }
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TimeoutError {
+    /// The options cannot be honoured together, e.g. a `kill_after` without a timeout.
+    InvalidOptions(&'static str),
+    /// The options ask for something this platform or API does not support.
+    Unsupported(&'static str),
+    /// The program name is empty or only whitespace.
+    EmptyCommand,
+    /// The program could not be found.
+    NotFound {
+        /// The program that was asked for.
+        program: String,
+    },
+    /// The program was found but may not be executed.
+    PermissionDenied {
+        /// The program that was asked for.
+        program: String,
+    },
+    /// The program is a directory.
+    IsADirectory {
+        /// The program that was asked for.
+        program: String,
+    },
+    /// The timeout ran out before the program had even started.
+    SpawnTimedOut {
+        /// The program that was asked for.
+        program: String,
+    },
+    /// Starting the program failed for some other reason.
+    SpawnFailed {
+        /// The program that was asked for.
+        program: String,
+        /// What starting it failed with.
+        source: io::Error,
+    },
+    /// Waiting for the command failed after it had started.
+    WaitFailed(io::Error),
+}
+
+impl TimeoutError {
+    /// Classify `error`, returned when trying to start `program`.
+    ///
+    /// Executing a directory fails with `EACCES` on most systems, so a program given as a path
+    /// to a directory is reported as [`IsADirectory`](Self::IsADirectory), as a shell would.
+    pub fn from_spawn(program: impl AsRef<OsStr>, error: io::Error) -> Self {
+        let path = Path::new(program.as_ref());
+        let program = program.as_ref().to_string_lossy().into_owned();
+        match error.kind() {
+            io::ErrorKind::PermissionDenied | io::ErrorKind::IsADirectory
+                if program.contains(std::path::is_separator) && path.is_dir() =>
+            {
+                TimeoutError::IsADirectory { program }
+            }
+            io::ErrorKind::IsADirectory => TimeoutError::IsADirectory { program },
+            io::ErrorKind::NotFound => TimeoutError::NotFound { program },
+            io::ErrorKind::PermissionDenied => TimeoutError::PermissionDenied { program },
+            io::ErrorKind::TimedOut => TimeoutError::SpawnTimedOut { program },
+            _ => TimeoutError::SpawnFailed {
+                program,
+                source: error,
+            },
+        }
+    }
+
+    /// The closest [`io::ErrorKind`], as kept in
+    /// [`TimeoutReason::SpawnFailed`](crate::TimeoutReason::SpawnFailed).
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            TimeoutError::InvalidOptions(_) => io::ErrorKind::InvalidInput,
+            TimeoutError::Unsupported(_) => io::ErrorKind::Unsupported,
+            TimeoutError::EmptyCommand | TimeoutError::NotFound { .. } => io::ErrorKind::NotFound,
+            TimeoutError::PermissionDenied { .. } => io::ErrorKind::PermissionDenied,
+            TimeoutError::IsADirectory { .. } => io::ErrorKind::IsADirectory,
+            TimeoutError::SpawnTimedOut { .. } => io::ErrorKind::TimedOut,
+            TimeoutError::SpawnFailed { source, .. } | TimeoutError::WaitFailed(source) => {
+                source.kind()
+            }
+        }
+    }
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeoutError::InvalidOptions(message) | TimeoutError::Unsupported(message) => {
+                f.write_str(message)
+            }
+            TimeoutError::EmptyCommand => f.write_str("empty command"),
+            TimeoutError::NotFound { program } => write!(
+                f,
+                "failed to run command '{}': No such file or directory",
+                program
+            ),
+            TimeoutError::PermissionDenied { program } => {
+                write!(f, "failed to run command '{}': Permission denied", program)
+            }
+            TimeoutError::IsADirectory { program } => write!(f, "'{}' is a directory", program),
+            TimeoutError::SpawnTimedOut { program } => {
+                write!(f, "timed out starting command '{}'", program)
+            }
+            TimeoutError::SpawnFailed { program, source } => {
+                write!(f, "failed to run command '{}': {}", program, source)
+            }
+            TimeoutError::WaitFailed(source) => {
+                write!(f, "error waiting for child process: {}", source)
+            }
+        }
+    }
+}
+
+impl Error for TimeoutError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TimeoutError::SpawnFailed { source, .. } | TimeoutError::WaitFailed(source) => {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<TimeoutError> for io::Error {
+    fn from(error: TimeoutError) -> Self {
+        io::Error::new(error.kind(), error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_spawn_classifies_by_kind() {
+        let error = TimeoutError::from_spawn("cmd", io::Error::from(io::ErrorKind::NotFound));
+        assert!(matches!(&error, TimeoutError::NotFound { program } if program == "cmd"));
+        assert_eq!(
+            error.to_string(),
+            "failed to run command 'cmd': No such file or directory"
+        );
+
+        let error = TimeoutError::from_spawn("cmd", io::Error::other("boom"));
+        assert_eq!(error.to_string(), "failed to run command 'cmd': boom");
+        assert_eq!(error.source().unwrap().to_string(), "boom");
+    }
+
+    #[test]
+    fn test_directory_given_as_a_path() {
+        let dir = std::env::temp_dir();
+        let error =
+            TimeoutError::from_spawn(&dir, io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(error.kind(), io::ErrorKind::IsADirectory);
+        assert_eq!(
+            error.to_string(),
+            format!("'{}' is a directory", dir.display())
+        );
+    }
+
+    #[test]
+    fn test_into_io_error_keeps_kind_and_message() {
+        let error = io::Error::from(TimeoutError::SpawnTimedOut {
+            program: "cmd".to_string(),
+        });
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(error.to_string(), "timed out starting command 'cmd'");
+    }
+}
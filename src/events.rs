@@ -0,0 +1,393 @@
+//! Callbacks for observing a supervised command's lifecycle.
+
+use crate::outcome::TimeoutResult;
+use crate::signal::Signal;
+use crate::trace::PhaseSpans;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How many events may be waiting for a slow [`EventSink`] before further ones are dropped.
+pub const EVENT_QUEUE_CAPACITY: usize = 64;
+
+/// Receives lifecycle events for a supervised command, e.g. to drive a progress UI or write
+/// structured logs. Every method defaults to doing nothing.
+///
+/// Events are delivered in order on a dedicated thread, or on tokio's blocking pool for
+/// `run_with_timeout_async`, never where the supervision is done, so a slow or blocked
+/// callback cannot hold up the timeout signal or the kill escalation. If the sink falls more
+/// than [`EVENT_QUEUE_CAPACITY`] events behind, newer events are dropped, and events still
+/// queued shortly after the command has been reaped are abandoned.
+///
+/// ```
+/// use std::time::Duration;
+/// use timeout_cli::{EventSink, TimeoutCommand};
+///
+/// #[derive(Debug)]
+/// struct Announce;
+///
+/// impl EventSink for Announce {
+///     fn on_spawn(&self, pid: u32) {
+///         println!("started {pid}");
+///     }
+/// }
+///
+/// TimeoutCommand::new("true")
+///     .timeout(Duration::from_secs(1))
+///     .events(Announce)
+///     .spawn()?
+///     .wait()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub trait EventSink: fmt::Debug + Send + Sync {
+    /// The command was spawned with process ID `pid`.
+    fn on_spawn(&self, _pid: u32) {}
+
+    /// `signal` was sent to the command, `elapsed` after it was spawned.
+    fn on_signal(&self, _signal: Signal, _elapsed: Duration) {}
+
+    /// `SIGKILL` was sent to the command, `elapsed` after it was spawned.
+    fn on_kill(&self, _elapsed: Duration) {}
+
+    /// Sending a signal to the command, announced by [`on_signal`](Self::on_signal) or
+    /// [`on_kill`](Self::on_kill), came to `delivery`: whether it reached the command or why
+    /// not. Repeated for each `kill_retries` attempt.
+    fn on_delivery(&self, _delivery: Delivery) {}
+
+    /// Supervision finished with `result`, `elapsed` after the command was spawned.
+    fn on_exit(&self, _result: TimeoutResult, _elapsed: Duration) {}
+}
+
+macro_rules! forward_event_sink {
+    ($($pointer:ident),*) => {$(
+        impl<T: EventSink + ?Sized> EventSink for $pointer<T> {
+            fn on_spawn(&self, pid: u32) {
+                (**self).on_spawn(pid);
+            }
+
+            fn on_signal(&self, signal: Signal, elapsed: Duration) {
+                (**self).on_signal(signal, elapsed);
+            }
+
+            fn on_kill(&self, elapsed: Duration) {
+                (**self).on_kill(elapsed);
+            }
+
+            fn on_delivery(&self, delivery: Delivery) {
+                (**self).on_delivery(delivery);
+            }
+
+            fn on_exit(&self, result: TimeoutResult, elapsed: Duration) {
+                (**self).on_exit(result, elapsed);
+            }
+        }
+    )*};
+}
+
+forward_event_sink!(Arc, Box);
+
+/// Every sink in the list receives each event, in order.
+impl<S: EventSink> EventSink for Vec<S> {
+    fn on_spawn(&self, pid: u32) {
+        self.iter().for_each(|sink| sink.on_spawn(pid));
+    }
+
+    fn on_signal(&self, signal: Signal, elapsed: Duration) {
+        self.iter().for_each(|sink| sink.on_signal(signal, elapsed));
+    }
+
+    fn on_kill(&self, elapsed: Duration) {
+        self.iter().for_each(|sink| sink.on_kill(elapsed));
+    }
+
+    fn on_delivery(&self, delivery: Delivery) {
+        self.iter().for_each(|sink| sink.on_delivery(delivery));
+    }
+
+    fn on_exit(&self, result: TimeoutResult, elapsed: Duration) {
+        self.iter().for_each(|sink| sink.on_exit(result, elapsed));
+    }
+}
+
+/// How sending a signal to the command went, as passed to [`EventSink::on_delivery`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delivery {
+    /// The signal sent. Elsewhere than on Unix, where the command can only be terminated, this
+    /// is `KILL` unless a console control event was sent to its process group.
+    pub signal: Signal,
+    /// Whether it was sent to the command's whole process group rather than to the command alone.
+    pub group: bool,
+    /// The OS error number if it could not be sent, such as `ESRCH` when the process had
+    /// already gone, or `-1` for an error without one.
+    pub error: Option<i32>,
+}
+
+impl Delivery {
+    pub(crate) fn new(signal: Signal, group: bool, result: &io::Result<()>) -> Self {
+        Self {
+            signal,
+            group,
+            error: result
+                .as_ref()
+                .err()
+                .map(|e| e.raw_os_error().unwrap_or(-1)),
+        }
+    }
+
+    /// Whether the signal was handed to the command, or to its process group.
+    pub fn delivered(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// A callback run when the timeout expires, before the timeout signal is sent, so that the
+/// still-running command can be inspected (e.g. to dump its thread stacks). It receives the
+/// command's PID and the time elapsed since it was spawned.
+///
+/// Unlike an [`EventSink`] it runs on the supervising thread and the signal waits for it to
+/// return, so it must bound its own running time. It is not called when the command is
+/// cancelled through a [`TimeoutHandle`](crate::TimeoutHandle).
+#[derive(Clone)]
+pub struct TimeoutHook(Arc<dyn Fn(u32, Duration) + Send + Sync>);
+
+impl TimeoutHook {
+    pub fn new(hook: impl Fn(u32, Duration) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    pub(crate) fn call(&self, pid: u32, elapsed: Duration) {
+        (self.0)(pid, elapsed);
+    }
+}
+
+impl fmt::Debug for TimeoutHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TimeoutHook(..)")
+    }
+}
+
+/// A lifecycle event queued for delivery to an [`EventSink`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Event {
+    Spawn(u32),
+    Signal(Signal, Duration),
+    Kill(Duration),
+    Delivery(Delivery),
+    Exit(TimeoutResult, Duration),
+}
+
+impl Event {
+    fn deliver(self, sink: &dyn EventSink) {
+        match self {
+            Event::Spawn(pid) => sink.on_spawn(pid),
+            Event::Signal(signal, elapsed) => sink.on_signal(signal, elapsed),
+            Event::Kill(elapsed) => sink.on_kill(elapsed),
+            Event::Delivery(delivery) => sink.on_delivery(delivery),
+            Event::Exit(result, elapsed) => sink.on_exit(result, elapsed),
+        }
+    }
+}
+
+/// Delivers events to an [`EventSink`] through a bounded queue, on a thread of its own or, for
+/// the async API, from a tokio task, and keeps the `tracing` spans for them up to date.
+#[derive(Debug)]
+pub(crate) struct EventDispatcher {
+    queue: Option<Queue>,
+    done: Option<Done>,
+    spans: PhaseSpans,
+}
+
+/// The sending end of the queue, for whichever way the events are delivered.
+#[derive(Debug)]
+enum Queue {
+    Thread(mpsc::SyncSender<Event>),
+    #[cfg(feature = "tokio")]
+    Task(tokio::sync::mpsc::Sender<Event>),
+}
+
+/// How to tell that every queued event has been delivered.
+#[derive(Debug)]
+enum Done {
+    Thread(mpsc::Receiver<()>),
+    #[cfg(feature = "tokio")]
+    Task(tokio::task::JoinHandle<()>),
+}
+
+impl EventDispatcher {
+    /// Start delivering to `sink` on a thread of its own, or do nothing at all without one.
+    pub(crate) fn start(sink: Option<Arc<dyn EventSink>>) -> Self {
+        let Some(sink) = sink else {
+            return Self::without_sink();
+        };
+        let (queue, events) = mpsc::sync_channel::<Event>(EVENT_QUEUE_CAPACITY);
+        let (tx, done) = mpsc::channel();
+        thread::spawn(move || {
+            for event in events {
+                event.deliver(&*sink);
+            }
+            let _ = tx.send(());
+        });
+        Self {
+            queue: Some(Queue::Thread(queue)),
+            done: Some(Done::Thread(done)),
+            spans: PhaseSpans::default(),
+        }
+    }
+
+    /// Start delivering to `sink` from a task on the current tokio runtime, each event on its
+    /// blocking pool in turn, or do nothing at all without one.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn start_async(sink: Option<Arc<dyn EventSink>>) -> Self {
+        let Some(sink) = sink else {
+            return Self::without_sink();
+        };
+        let (queue, mut events) = tokio::sync::mpsc::channel::<Event>(EVENT_QUEUE_CAPACITY);
+        let done = tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let sink = Arc::clone(&sink);
+                let _ = tokio::task::spawn_blocking(move || event.deliver(&*sink)).await;
+            }
+        });
+        Self {
+            queue: Some(Queue::Task(queue)),
+            done: Some(Done::Task(done)),
+            spans: PhaseSpans::default(),
+        }
+    }
+
+    fn without_sink() -> Self {
+        Self {
+            queue: None,
+            done: None,
+            spans: PhaseSpans::default(),
+        }
+    }
+
+    /// Queue `event` without blocking, dropping it if the sink is too far behind.
+    pub(crate) fn emit(&self, event: Event) {
+        self.spans.record(event);
+        match &self.queue {
+            Some(Queue::Thread(queue)) => {
+                let _ = queue.try_send(event);
+            }
+            #[cfg(feature = "tokio")]
+            Some(Queue::Task(queue)) => {
+                let _ = queue.try_send(event);
+            }
+            None => {}
+        }
+    }
+
+    /// Stop accepting events and wait up to `grace` for the queued ones to be delivered.
+    pub(crate) fn finish(&mut self, grace: Duration) {
+        self.queue = None;
+        if let Some(Done::Thread(done)) = self.done.take() {
+            let _ = done.recv_timeout(grace);
+        }
+    }
+
+    /// [`finish`](Self::finish) for a dispatcher started with
+    /// [`start_async`](Self::start_async), waiting on the runtime instead of blocking it.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn finish_async(&mut self, grace: Duration) {
+        self.queue = None;
+        if let Some(Done::Task(done)) = self.done.take() {
+            let _ = tokio::time::timeout(grace, done).await;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::capture::DRAIN_GRACE;
+    use std::sync::Mutex;
+
+    /// Records every event it receives, for asserting on the exact sequence.
+    #[derive(Debug, Default)]
+    pub(crate) struct RecordingSink {
+        pub(crate) events: Mutex<Vec<String>>,
+    }
+
+    impl RecordingSink {
+        pub(crate) fn take(&self) -> Vec<String> {
+            std::mem::take(&mut *self.events.lock().unwrap())
+        }
+
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl EventSink for RecordingSink {
+        fn on_spawn(&self, _pid: u32) {
+            self.record("spawn".to_string());
+        }
+
+        fn on_signal(&self, signal: Signal, _elapsed: Duration) {
+            self.record(format!("signal {}", signal));
+        }
+
+        fn on_kill(&self, _elapsed: Duration) {
+            self.record("kill".to_string());
+        }
+
+        fn on_exit(&self, result: TimeoutResult, _elapsed: Duration) {
+            self.record(format!("exit {:?}", result));
+        }
+    }
+
+    #[derive(Debug)]
+    struct BlockedSink;
+
+    impl EventSink for BlockedSink {
+        fn on_spawn(&self, _pid: u32) {
+            thread::sleep(Duration::from_secs(60));
+        }
+    }
+
+    #[test]
+    fn test_events_are_delivered_in_order() {
+        let sink = Arc::new(RecordingSink::default());
+        let mut dispatcher = EventDispatcher::start(Some(sink.clone()));
+        dispatcher.emit(Event::Spawn(1));
+        dispatcher.emit(Event::Signal(Signal::Int, Duration::ZERO));
+        dispatcher.emit(Event::Kill(Duration::ZERO));
+        dispatcher.emit(Event::Exit(TimeoutResult::Killed, Duration::ZERO));
+        dispatcher.finish(DRAIN_GRACE);
+        assert_eq!(
+            sink.take(),
+            ["spawn", "signal SIGINT", "kill", "exit Killed"]
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_events_are_delivered_in_order_from_a_task() {
+        let sink = Arc::new(RecordingSink::default());
+        let mut dispatcher = EventDispatcher::start_async(Some(sink.clone()));
+        dispatcher.emit(Event::Spawn(1));
+        dispatcher.emit(Event::Signal(Signal::Int, Duration::ZERO));
+        dispatcher.emit(Event::Kill(Duration::ZERO));
+        dispatcher.emit(Event::Exit(TimeoutResult::Killed, Duration::ZERO));
+        dispatcher.finish_async(DRAIN_GRACE).await;
+        assert_eq!(
+            sink.take(),
+            ["spawn", "signal SIGINT", "kill", "exit Killed"]
+        );
+    }
+
+    #[test]
+    fn test_blocked_sink_does_not_block_emit_or_finish() {
+        let mut dispatcher = EventDispatcher::start(Some(Arc::new(BlockedSink)));
+        let start = std::time::Instant::now();
+        for pid in 0..(EVENT_QUEUE_CAPACITY as u32 * 2) {
+            dispatcher.emit(Event::Spawn(pid));
+        }
+        dispatcher.finish(Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -1 +1,57 @@
-// Empty lib.rs to enable integration tests
+//! Run a command with a timeout.
+//!
+//! This crate powers the `timeout` binary and can also be embedded directly. The
+//! [`TimeoutCommand`] builder spawns a command and supervises it in the background, sending the
+//! configured [`Signal`] when the timeout expires and escalating to `SIGKILL` if asked to.
+//!
+//! On Unix, the first spawn installs a process-wide `SIGCHLD` handler so that supervisors wake
+//! as soon as their command exits instead of polling for it. A handler already installed keeps
+//! being called, and if `SIGCHLD` is ignored nothing is installed and supervisors poll.
+//!
+//! Enable the `tokio` feature for `run_with_timeout_async`, which supervises the command on a
+//! tokio runtime instead of background threads.
+//!
+//! Enable the `tracing` feature to have each command's `spawn`, `run`, `term` and `kill` phases
+//! recorded as [`tracing`](https://docs.rs/tracing) spans carrying the PID, signal and elapsed
+//! time, ready for any subscriber (such as an OpenTelemetry exporter) to collect. Without a
+//! subscriber installed the spans cost next to nothing.
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! debug_print {
+    ($verbose:expr, $($arg:tt)*) => {
+        if $verbose {
+            eprintln!("DEBUG: {}", format!($($arg)*));
+        }
+    };
+}
+
+#[cfg(feature = "tokio")]
+mod async_command;
+mod capture;
+mod clock;
+mod command;
+mod error;
+mod events;
+mod outcome;
+#[cfg(unix)]
+#[doc(hidden)]
+pub mod self_pipe;
+#[cfg(unix)]
+mod sigchld;
+mod signal;
+mod supervisor;
+mod trace;
+
+#[cfg(feature = "tokio")]
+pub use async_command::run_with_timeout_async;
+pub use capture::CaptureMode;
+pub use command::{
+    KILL_RETRY_INTERVAL, StdinMode, TERM_GRACE, TimeoutChild, TimeoutClock, TimeoutCommand,
+    TimeoutOptions, Timings, run_with_timeout,
+};
+pub use error::TimeoutError;
+pub use events::{Delivery, EVENT_QUEUE_CAPACITY, EventSink, TimeoutHook};
+pub use outcome::{ResourceUsage, SentSignal, TimeoutOutcome, TimeoutReason, TimeoutResult};
+pub use signal::Signal;
+pub use supervisor::TimeoutHandle;
@@ -1,311 +1,2698 @@
-use clap::Parser;
-use std::io;
-use std::process::{Command, ExitCode};
-use std::sync::Arc;
+mod cli;
+
+use clap::{CommandFactory, Parser};
+use cli::atomic::write_atomic;
+use cli::batch::{self, BatchSummary};
+use cli::color::{self, ColorChoice, error, warning};
+use cli::config;
+use cli::countdown::{self, Countdown};
+use cli::deadline::{self, DEADLINE_VAR, parse_deadline_epoch};
+use cli::debug_info;
+use cli::desktop::{self, SystemNotifier};
+use cli::duration::{format_duration, parse_duration};
+use cli::every::{self, Schedule, parse_deadline, parse_interval};
+use cli::exit_code::{EXIT_TIMEOUT, EXIT_TIMEOUT_FAIL, exit_code, raw_signal_exit_code};
+use cli::healthcheck::{HealthCheck, HealthWatch};
+use cli::heartbeat::Heartbeat;
+use cli::hooks::{self, HOOK_TIMEOUT, Hooks};
+use cli::journald::{JOURNALD_SOCKET, Journald};
+use cli::metrics;
+use cli::notify::notify_pid;
+use cli::output_files::{OpenMode, OutputFiles};
+use cli::pid_file::PidFile;
+use cli::port::{PortAddress, parse_port_address};
+use cli::progress::{Progress, ProgressMode, parse_progress};
+use cli::quiet::HeldOutput;
+use cli::race::{self, RaceMode};
+use cli::ready::{Ready, ReadyWatch};
+use cli::relay::{
+    LineGate, LineRelay, MatchAction, OutputLimit, OutputMatch, PatternStream, Prefix, RelayThread,
+};
+use cli::report::{JsonTarget, Report};
+use cli::report_fd::{ReportFd, result_name};
+use cli::respawn::RespawnSummary;
+use cli::retry::{Backoff, RetryOn, parse_retry_on, should_retry};
+use cli::sd_notify::{NOTIFY_SOCKET, SdNotify};
+use cli::signal_sent::DeliverySink;
+use cli::signals::{
+    FatalSignals, KillAfter, SHIELDED_BY_DEFAULT, parse_kill_after, parse_shielded_signal,
+    parse_signal,
+};
+use cli::size::parse_size;
+use cli::stats::{self, UsageSummary};
+use cli::statsd::{Statsd, parse_tag};
+use cli::status_fd::StatusFd;
+use cli::syslog::{DEFAULT_SYSLOG_SOCKET, Facility, Severity, Syslog};
+use cli::tail::{OutputTail, TailOnTimeout, parse_tail_on_timeout};
+use cli::time::TimeFormat;
+use cli::timestamp::{self, TimestampStyle};
+use cli::trigger::Trigger;
+use cli::verbose::VerboseSink;
+use cli::warnings::{self, WarnAt, Warnings, parse_warn_at};
+use cli::webhook::{HttpUrl, Webhook, parse_header, parse_url};
+use regex::Regex;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc;
-use std::thread;
-use std::time::Duration;
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant, SystemTime};
+use timeout_cli::{
+    CaptureMode, EventSink, Signal, StdinMode, TimeoutChild, TimeoutClock, TimeoutCommand,
+    TimeoutError, TimeoutOutcome, TimeoutReason, TimeoutResult, debug_print,
+};
+
+#[derive(Parser, Clone)]
+#[command(name = "timeout")]
+#[command(group(clap::ArgGroup::new("batch").args(["from_file", "stdin_commands"])))]
+#[command(group(clap::ArgGroup::new("ready").args(["until_pattern", "until_port", "until_file", "until_file_gone"]).multiple(true)))]
+#[command(about = "Run a command with a timeout")]
+#[command(version)]
+#[command(
+    override_usage = "timeout [OPTIONS] <SECONDS> [COMMAND] [ARGS]...\n       timeout --debug-info"
+)]
+struct Args {
+    #[arg(
+        help = "Number of seconds to wait before timing out",
+        required_unless_present = "debug_info",
+        default_value_t = 0,
+        hide_default_value = true
+    )]
+    seconds: u64,
+
+    #[arg(
+        short = 'k',
+        long = "kill-after",
+        value_name = "DURATION[:SIGNAL]",
+        value_parser = parse_kill_after,
+        help = "Also send KILL signal this many seconds after the timeout signal, or SIGNAL instead if given, e.g. 3:QUIT"
+    )]
+    kill_after: Option<KillAfter>,
+
+    #[arg(
+        long = "kill-retries",
+        value_name = "N",
+        default_value_t = 0,
+        help = "Repeat the KILL signal up to N more times while the command has not exited"
+    )]
+    kill_retries: u32,
+
+    #[arg(
+        long = "shield",
+        value_name = "SIGNALS",
+        value_parser = parse_shielded_signal,
+        value_delimiter = ',',
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_values = SHIELDED_BY_DEFAULT,
+        help = "Do not let SIGNALS (a comma-separated list, INT,QUIT,TERM,HUP if not given) stop timeout itself, but pass them on to the command and carry on (Unix only)"
+    )]
+    shield: Vec<Signal>,
+
+    #[arg(
+        long = "retries",
+        value_name = "N",
+        default_value_t = 0,
+        help = "Run the command up to N more times while it keeps timing out, each time with a fresh timeout"
+    )]
+    retries: u32,
+
+    #[arg(
+        long = "retry-on",
+        value_name = "CONDITIONS",
+        value_parser = parse_retry_on,
+        value_delimiter = ',',
+        requires = "retries",
+        help = "Comma-separated exit codes (e.g. 1,111), ranges (1-10) or 'timeout' to retry on, instead of just timeouts"
+    )]
+    retry_on: Vec<RetryOn>,
+
+    #[arg(
+        long = "retry-backoff",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        default_value = "0",
+        requires = "retries",
+        help = "Wait this long before the first retry, doubling the wait for each one after"
+    )]
+    retry_backoff: Duration,
+
+    #[arg(
+        long = "retry-max-backoff",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        requires = "retry_backoff",
+        help = "The longest to wait between attempts"
+    )]
+    retry_max_backoff: Option<Duration>,
+
+    #[arg(
+        long = "retry-jitter",
+        requires = "retry_backoff",
+        help = "Wait a random time between half the backoff and all of it"
+    )]
+    retry_jitter: bool,
+
+    #[arg(
+        long = "respawn",
+        conflicts_with_all = ["retries", "total_timeout", "cpu_time"],
+        help = "Restart the command whenever it exits until SECONDS have passed, then stop it and exit 0"
+    )]
+    respawn: bool,
+
+    #[arg(
+        long = "respawn-delay",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        default_value = "0",
+        requires = "respawn",
+        help = "Wait this long before each restart"
+    )]
+    respawn_delay: Duration,
+
+    #[arg(
+        long = "stop-on-failure",
+        requires = "respawn",
+        help = "With --respawn, stop at the first run that exits non-zero and exit with its code"
+    )]
+    stop_on_failure: bool,
+
+    #[arg(
+        long = "every",
+        value_name = "DURATION",
+        value_parser = parse_interval,
+        conflicts_with_all = ["retries", "respawn", "total_timeout"],
+        help = "Run the command every DURATION (e.g. 60, 30s, 5m) until stopped, each run with its own timeout"
+    )]
+    every: Option<Duration>,
+
+    #[arg(
+        long = "max-runs",
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "every",
+        help = "With --every, stop after N runs"
+    )]
+    max_runs: Option<u32>,
+
+    #[arg(
+        long = "until",
+        value_name = "DEADLINE",
+        value_parser = parse_deadline,
+        requires = "every",
+        help = "With --every, start no more runs from DEADLINE: a duration from now (e.g. 1h) or a UTC time (e.g. 2024-08-09T18:00:00Z)"
+    )]
+    until: Option<SystemTime>,
+
+    #[arg(
+        long = "raw-signal-exit",
+        help = "Exit with the bare signal number (9) instead of 128 + 9 = 137 when the command has to be killed"
+    )]
+    raw_signal_exit: bool,
+
+    #[arg(
+        long = "expect-timeout",
+        conflicts_with_all = ["retries", "respawn"],
+        help = "Invert the result, for testing watchdogs: exit with 0 only if the command timed out, and with 1 if it finished or was stopped before the timeout"
+    )]
+    expect_timeout: bool,
+
+    #[arg(
+        long = "total-timeout",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        conflicts_with = "cpu_time",
+        help = "Overall wall-clock budget (e.g. 90, 1.5s, 10m); takes precedence over SECONDS when it runs out first"
+    )]
+    total_timeout: Option<Duration>,
+
+    #[arg(
+        long = "success-codes",
+        value_name = "CODES",
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(i32).range(0..=255),
+        help = "Comma-separated exit codes of the command to treat as success, exiting with 0 instead"
+    )]
+    success_codes: Vec<i32>,
+
+    #[arg(
+        long = "min-runtime",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        conflicts_with = "respawn",
+        help = "Treat the command exiting before DURATION (e.g. 5s) as a failure, even with 0, exiting with --min-runtime-exit-code instead"
+    )]
+    min_runtime: Option<Duration>,
+
+    #[arg(
+        long = "min-runtime-exit-code",
+        value_name = "CODE",
+        default_value_t = 1,
+        requires = "min_runtime",
+        help = "The exit code when the command exits before --min-runtime (default 1)"
+    )]
+    min_runtime_exit_code: u8,
+
+    #[arg(
+        long = "cpu-time",
+        help = "Measure the timeout against the command's CPU time instead of wall-clock time (Unix only)"
+    )]
+    cpu_time: bool,
+
+    #[arg(
+        long = "wait-all",
+        help = "Run the command in its own process group and wait for every process in it to exit, not just the command itself (Unix only)"
+    )]
+    wait_all: bool,
+
+    #[arg(
+        long = "timestamp-output",
+        help = "Prefix each line of the command's output with an ISO 8601 timestamp"
+    )]
+    timestamp_output: bool,
+
+    #[arg(
+        long = "timestamps",
+        value_name = "STYLE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "absolute",
+        conflicts_with = "timestamp_output",
+        help = "Prefix each line of the command's output with the local time, e.g. [12:31:05.123], or with =relative the time since it started, e.g. [+4.512s]"
+    )]
+    timestamps: Option<TimestampStyle>,
+
+    #[arg(
+        long = "prefix",
+        value_name = "TEXT",
+        help = "Put TEXT at the start of every line of the command's output, e.g. '[db-migrate] ', after any timestamp"
+    )]
+    prefix: Option<String>,
+
+    #[arg(
+        long = "prefix-stderr",
+        value_name = "TEXT",
+        help = "Put TEXT instead of the --prefix at the start of every line the command writes to stderr"
+    )]
+    prefix_stderr: Option<String>,
+
+    #[arg(
+        long = "max-output",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Relay at most SIZE bytes of the command's stdout and stderr combined (e.g. 512K, 10M), then drop the rest after a notice"
+    )]
+    max_output: Option<u64>,
+
+    #[arg(
+        long = "tail-on-timeout",
+        value_name = "LINES[:only-when-quiet]",
+        value_parser = parse_tail_on_timeout,
+        help = "Keep the last LINES lines of the command's output and print them on stderr if it times out; with only-when-quiet (100 lines unless given), only when timeout's stdout is not a terminal"
+    )]
+    tail_on_timeout: Option<TailOnTimeout>,
+
+    #[arg(
+        long = "quiet-success",
+        help = "Hold the command's stdout and stderr back, and pass them on only if it fails or times out; all of it is kept in memory, so consider --max-output"
+    )]
+    quiet_success: bool,
+
+    #[arg(
+        long = "combine-output",
+        conflicts_with_all = ["prefix_stderr", "tee_stderr"],
+        help = "Send the command's stderr to its stdout, as 2>&1 would, so both come out of timeout's stdout in the order they were written (Unix only)"
+    )]
+    combine_output: bool,
+
+    #[arg(
+        long = "discard-output",
+        conflicts_with_all = [
+            "discard_stdout", "discard_stderr", "combine_output", "tee", "tee_stderr",
+            "timestamp_output", "timestamps", "prefix", "prefix_stderr", "max_output",
+            "kill_on_match", "unbuffered", "tail_on_timeout", "quiet_success",
+        ],
+        help = "Connect the command's stdout and stderr to the null device, for when only its exit code matters"
+    )]
+    discard_output: bool,
+
+    #[arg(
+        long = "discard-stdout",
+        conflicts_with_all = ["tee", "kill_on_match"],
+        help = "Connect the command's stdout to the null device"
+    )]
+    discard_stdout: bool,
+
+    #[arg(
+        long = "discard-stderr",
+        conflicts_with_all = ["tee_stderr", "prefix_stderr", "combine_output", "kill_on_match"],
+        help = "Connect the command's stderr to the null device"
+    )]
+    discard_stderr: bool,
+
+    #[arg(
+        long = "tee",
+        value_name = "PATH",
+        help = "Copy the command's stdout to PATH while still passing it on"
+    )]
+    tee: Option<PathBuf>,
+
+    #[arg(
+        long = "tee-stderr",
+        value_name = "PATH",
+        help = "Copy the command's stderr to PATH while still passing it on; may be the same file as --tee"
+    )]
+    tee_stderr: Option<PathBuf>,
+
+    #[arg(
+        long = "pty",
+        conflicts_with_all = [
+            "stdin_file", "no_stdin", "stdin_string", "stdin_string_file", "stdout_file",
+            "stderr_file", "discard_output", "discard_stdout", "discard_stderr", "combine_output",
+            "tee_stderr", "prefix_stderr", "batch", "race",
+        ],
+        help = "Run the command on a pseudo-terminal of its own, passing its output and timeout's input through it, for programs that need a terminal (Unix only)"
+    )]
+    pty: bool,
+
+    #[arg(
+        long = "stdin-file",
+        value_name = "PATH",
+        help = "Give the command PATH as its stdin instead of timeout's own, read from the start by every run"
+    )]
+    stdin_file: Option<PathBuf>,
+
+    #[arg(
+        long = "no-stdin",
+        conflicts_with = "stdin_file",
+        help = "Connect the command's stdin to the null device, so it sees end of file instead of waiting for input"
+    )]
+    no_stdin: bool,
+
+    #[arg(
+        long = "stdin-string",
+        value_name = "STRING",
+        conflicts_with_all = ["stdin_file", "no_stdin"],
+        help = "Write STRING to the command's stdin, then close it so the command sees end of file"
+    )]
+    stdin_string: Option<String>,
+
+    #[arg(
+        long = "stdin-string-file",
+        value_name = "PATH",
+        conflicts_with_all = ["stdin_file", "no_stdin", "stdin_string"],
+        help = "Like --stdin-string, with the contents of PATH as read when timeout starts"
+    )]
+    stdin_string_file: Option<PathBuf>,
+
+    #[arg(
+        long = "stdout-file",
+        value_name = "PATH",
+        conflicts_with_all = ["discard_output", "discard_stdout", "tee"],
+        help = "Send the command's stdout straight to PATH instead of passing it on"
+    )]
+    stdout_file: Option<PathBuf>,
+
+    #[arg(
+        long = "stderr-file",
+        value_name = "PATH",
+        conflicts_with_all = ["discard_output", "discard_stderr", "tee_stderr", "combine_output"],
+        help = "Send the command's stderr straight to PATH instead of passing it on; may be the same file as --stdout-file"
+    )]
+    stderr_file: Option<PathBuf>,
+
+    #[arg(
+        long = "append",
+        help = "Add to the end of the --stdout-file, --stderr-file, --tee and --tee-stderr files instead of truncating them"
+    )]
+    append: bool,
+
+    #[arg(
+        long = "create-dirs",
+        help = "Create any missing parent directories of the --stdout-file, --stderr-file, --tee and --tee-stderr files"
+    )]
+    create_dirs: bool,
+
+    #[arg(
+        long = "kill-on-match",
+        visible_aliases = ["if-output-contains", "kill-on-pattern"],
+        value_name = "REGEX",
+        value_parser = parse_pattern,
+        help = "Stop the command as if it had timed out as soon as a line of its output matches REGEX"
+    )]
+    kill_on_match: Option<Regex>,
+
+    #[arg(
+        long = "until-pattern",
+        value_name = "REGEX",
+        conflicts_with_all = [
+            "discard_output", "discard_stdout", "discard_stderr", "retries", "respawn",
+        ],
+        help = "Finish successfully as soon as a line of the command's output matches REGEX: stop the command with the timeout signal and exit with 0"
+    )]
+    until_pattern: Option<String>,
+
+    #[arg(
+        long = "until-detach",
+        requires = "ready",
+        help = "With --until-pattern, --until-port, --until-file or --until-file-gone, leave the command running once it is ready instead of stopping it"
+    )]
+    until_detach: bool,
+
+    #[arg(
+        long = "until-port",
+        value_name = "HOST:PORT",
+        value_parser = parse_port_address,
+        conflicts_with_all = ["retries", "respawn"],
+        help = "Finish successfully as soon as HOST:PORT accepts a TCP connection: stop the command with the timeout signal and exit with 0"
+    )]
+    until_port: Option<PortAddress>,
+
+    #[arg(
+        long = "until-port-interval",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        default_value = "0.25",
+        requires = "until_port",
+        help = "How often to try connecting to the --until-port address"
+    )]
+    until_port_interval: Duration,
+
+    #[arg(
+        long = "until-file",
+        value_name = "PATH",
+        conflicts_with_all = ["retries", "respawn"],
+        help = "Finish successfully as soon as PATH exists: stop the command with the timeout signal and exit with 0"
+    )]
+    until_file: Option<PathBuf>,
+
+    #[arg(
+        long = "until-file-gone",
+        value_name = "PATH",
+        conflicts_with_all = ["retries", "respawn"],
+        help = "Finish successfully as soon as PATH no longer exists, e.g. a pid file: stop the command with the timeout signal and exit with 0"
+    )]
+    until_file_gone: Option<PathBuf>,
+
+    #[arg(
+        long = "progress",
+        value_name = "DURATION|auto",
+        value_parser = parse_progress,
+        conflicts_with = "cpu_time",
+        help = "Print how long the command has left before it times out on stderr every DURATION (e.g. 30s), or with 'auto' keep a countdown line updated in place while stderr is a terminal"
+    )]
+    progress: Option<ProgressMode>,
+
+    #[arg(
+        long = "soft",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        conflicts_with = "cpu_time",
+        help = "Print a warning on stderr if the command is still running after DURATION (e.g. 25s), less than SECONDS, and leave it running until SECONDS"
+    )]
+    soft: Option<Duration>,
+
+    #[arg(
+        long = "warn-at",
+        value_name = "THRESHOLD",
+        value_parser = parse_warn_at,
+        conflicts_with = "cpu_time",
+        help = "Print a warning on stderr once the command has used THRESHOLD of its timeout, a percentage (80%) or a duration (45s), if it is still running; may be repeated"
+    )]
+    warn_at: Vec<WarnAt>,
+
+    #[arg(
+        long = "heartbeat",
+        value_name = "DURATION",
+        value_parser = parse_interval,
+        help = "Print a line saying the command is still running every DURATION (e.g. 60s), for CI systems that stop jobs that go quiet"
+    )]
+    heartbeat: Option<Duration>,
+
+    #[arg(
+        long = "heartbeat-stream",
+        value_name = "STREAM",
+        value_enum,
+        default_value = "stderr",
+        requires = "heartbeat",
+        help = "Where --heartbeat prints its lines: stderr or stdout"
+    )]
+    heartbeat_stream: JsonTarget,
+
+    #[arg(
+        long = "healthcheck-cmd",
+        value_name = "SCRIPT",
+        conflicts_with_all = ["retries", "respawn"],
+        help = "Run SCRIPT with the shell every --healthcheck-interval while the command runs, and stop the command as if it had timed out once it fails --healthcheck-failures times in a row"
+    )]
+    healthcheck_cmd: Option<String>,
+
+    #[arg(
+        long = "healthcheck-interval",
+        value_name = "DURATION",
+        value_parser = parse_interval,
+        default_value = "10",
+        requires = "healthcheck_cmd",
+        help = "How long to wait before each health check"
+    )]
+    healthcheck_interval: Duration,
+
+    #[arg(
+        long = "healthcheck-timeout",
+        value_name = "DURATION",
+        value_parser = parse_interval,
+        default_value = "5",
+        requires = "healthcheck_cmd",
+        help = "How long each health check may run before it counts as failed"
+    )]
+    healthcheck_timeout: Duration,
+
+    #[arg(
+        long = "healthcheck-failures",
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        default_value_t = 3,
+        requires = "healthcheck_cmd",
+        help = "How many health checks in a row have to fail for the command to be stopped"
+    )]
+    healthcheck_failures: u32,
+
+    #[arg(
+        long = "pattern-exit-code",
+        value_name = "CODE",
+        default_value_t = EXIT_TIMEOUT,
+        requires = "kill_on_match",
+        help = "The exit code when --kill-on-match stops the command (default 124)"
+    )]
+    pattern_exit_code: u8,
+
+    #[arg(
+        long = "pattern-stream",
+        value_name = "STREAM",
+        value_enum,
+        default_value_t,
+        help = "Which of the command's streams --kill-on-match and --until-pattern watch"
+    )]
+    pattern_stream: PatternStream,
+
+    #[arg(
+        long = "unbuffered",
+        help = "Forward relayed output as soon as it arrives, including partial lines (with any option that makes timeout relay the output)"
+    )]
+    unbuffered: bool,
+
+    #[arg(
+        long = "status-fd",
+        value_name = "FD",
+        help = "Write a machine-readable status line (e.g. \"OK 0\", \"TIMEOUT\", \"KILLED\") to file descriptor FD before exiting (Unix only)"
+    )]
+    status_fd: Option<i32>,
+
+    #[arg(
+        long = "report-fd",
+        value_name = "FD",
+        help = "Write a key=value line for each lifecycle event (spawned, timeout, kill, exit) to file descriptor FD (Unix only)"
+    )]
+    report_fd: Option<i32>,
+
+    #[arg(
+        long = "syslog",
+        help = "Also log the spawn, timeout, kill and exit events to the local syslog daemon (Unix only)"
+    )]
+    syslog: bool,
+
+    #[arg(
+        long = "syslog-facility",
+        value_name = "FACILITY",
+        value_enum,
+        default_value = "user",
+        requires = "syslog",
+        help = "The syslog facility to log under"
+    )]
+    syslog_facility: Facility,
+
+    #[arg(
+        long = "syslog-tag",
+        value_name = "TAG",
+        default_value = "timeout",
+        requires = "syslog",
+        help = "The tag (program name) syslog messages are logged with"
+    )]
+    syslog_tag: String,
+
+    #[arg(
+        long = "syslog-socket",
+        value_name = "PATH",
+        default_value = DEFAULT_SYSLOG_SOCKET,
+        requires = "syslog",
+        help = "The datagram socket the syslog daemon listens on"
+    )]
+    syslog_socket: PathBuf,
+
+    #[arg(
+        long = "journald",
+        help = "Also log the spawn, timeout, kill and exit events to the systemd journal as structured entries (Unix only)"
+    )]
+    journald: bool,
+
+    #[arg(
+        long = "report",
+        help = "Before exiting, print one line on stderr saying why, e.g. timeout: report: reason=timed_out exit=124"
+    )]
+    report: bool,
+
+    #[arg(
+        long = "stats",
+        help = "Once the command has been reaped, print its wall time, CPU time, peak memory and context switches on stderr, and add them to the --json report"
+    )]
+    stats: bool,
+
+    #[arg(
+        long = "time",
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "classic",
+        help = "Once the command has been reaped, print its real, user and sys times on stderr as the shell's time does, or with =posix as time -p does"
+    )]
+    time: Option<TimeFormat>,
+
+    #[arg(
+        long = "json",
+        help = "After the command finishes, print a JSON summary of the run to stderr"
+    )]
+    json: bool,
+
+    #[arg(
+        long = "json-to",
+        value_name = "STREAM",
+        value_enum,
+        help = "Print the --json summary to this stream instead (implies --json)"
+    )]
+    json_to: Option<JsonTarget>,
+
+    #[arg(
+        long = "result-file",
+        value_name = "PATH",
+        help = "Atomically write the --json summary to PATH before exiting, whatever the outcome"
+    )]
+    result_file: Option<PathBuf>,
+
+    #[arg(
+        long = "detach",
+        conflicts_with_all = ["pty", "stdin_commands", "exec_replace"],
+        help = "Carry on in the background, still enforcing the timeout once the shell that started timeout has gone; the output is thrown away unless --stdout-file and --stderr-file are given (Unix only)"
+    )]
+    detach: bool,
+
+    #[arg(
+        long = "pid-file",
+        value_name = "PATH",
+        help = "Write the command's PID to PATH once it has started, and remove it when the command has exited"
+    )]
+    pid_file: Option<PathBuf>,
+
+    #[arg(
+        long = "print-pid",
+        value_name = "STREAM",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "stdout",
+        help = "Print the command's PID on a line of its own as soon as it has started, to stdout unless STREAM is stderr"
+    )]
+    print_pid: Option<JsonTarget>,
+
+    #[arg(
+        long = "print-signal-sent",
+        help = "Print each signal sent to the command on stderr, with its number and whether it reached the command or why not"
+    )]
+    print_signal_sent: bool,
+
+    #[arg(
+        long = "login-shell",
+        help = "Run the command through a login shell ($SHELL -l, or sh -l if $SHELL is not a POSIX shell), so that it gets the environment set up by the profile files (Unix only)"
+    )]
+    login_shell: bool,
+
+    #[arg(
+        long = "pid-file-overwrite",
+        requires = "pid_file",
+        help = "Replace the --pid-file even if it names a process that is still running"
+    )]
+    pid_file_overwrite: bool,
+
+    #[arg(
+        long = "metrics-file",
+        value_name = "PATH",
+        help = "Atomically write Prometheus gauges describing the run to PATH before exiting"
+    )]
+    metrics_file: Option<PathBuf>,
+
+    #[arg(
+        long = "metrics-label",
+        value_name = "NAME=VALUE",
+        value_parser = metrics::parse_label,
+        requires = "metrics_file",
+        help = "Add a label to every metric in --metrics-file (repeatable)"
+    )]
+    metrics_labels: Vec<metrics::Label>,
+
+    #[arg(
+        long = "statsd",
+        value_name = "HOST:PORT",
+        help = "Send the run's duration, exit code and whether it timed out to a statsd server over UDP"
+    )]
+    statsd: Option<String>,
+
+    #[arg(
+        long = "statsd-prefix",
+        value_name = "PREFIX",
+        default_value = "timeout",
+        requires = "statsd",
+        help = "Prefix for the --statsd metric names"
+    )]
+    statsd_prefix: String,
+
+    #[arg(
+        long = "dogstatsd",
+        requires = "statsd",
+        help = "Send --statsd metrics in the DogStatsD dialect, which supports tags"
+    )]
+    dogstatsd: bool,
+
+    #[arg(
+        long = "statsd-tag",
+        value_name = "KEY:VALUE",
+        value_parser = parse_tag,
+        requires = "dogstatsd",
+        help = "Attach a tag to every --statsd metric (repeatable, requires --dogstatsd)"
+    )]
+    statsd_tags: Vec<String>,
+
+    #[arg(
+        long = "webhook",
+        value_name = "URL",
+        value_parser = parse_url,
+        help = "POST the --json summary to an http:// URL when the run ends (see --webhook-on)"
+    )]
+    webhook: Option<HttpUrl>,
+
+    #[arg(
+        long = "webhook-on",
+        value_name = "TRIGGERS",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "timeout,failure",
+        requires = "webhook",
+        help = "Comma-separated outcomes to send the webhook for"
+    )]
+    webhook_on: Vec<Trigger>,
+
+    #[arg(
+        long = "webhook-header",
+        value_name = "NAME: VALUE",
+        value_parser = parse_header,
+        requires = "webhook",
+        help = "Add a header to the webhook request, e.g. for an auth token (repeatable)"
+    )]
+    webhook_headers: Vec<(String, String)>,
+
+    #[arg(
+        long = "webhook-timeout",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        default_value = "5s",
+        requires = "webhook",
+        help = "How long each attempt to deliver the webhook may take"
+    )]
+    webhook_timeout: Duration,
+
+    #[arg(
+        long = "notify",
+        help = "Show a desktop notification when the command finishes or times out (Linux and macOS)"
+    )]
+    notify: bool,
+
+    #[arg(
+        long = "notify-on",
+        value_name = "TRIGGERS",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "success,failure",
+        requires = "notify",
+        help = "Comma-separated outcomes to show the desktop notification for"
+    )]
+    notify_on: Vec<Trigger>,
+
+    #[arg(
+        long = "on-timeout",
+        value_name = "SCRIPT",
+        help = "Run SCRIPT with the shell when the timeout expires, before the command is signalled, with its PID in TIMEOUT_PID"
+    )]
+    on_timeout: Option<String>,
+
+    #[arg(
+        long = "on-success",
+        value_name = "SCRIPT",
+        help = "Run SCRIPT with the shell after the command exits with status 0"
+    )]
+    on_success: Option<String>,
+
+    #[arg(
+        long = "on-failure",
+        value_name = "SCRIPT",
+        help = "Run SCRIPT with the shell after the command fails, times out or cannot be started, with timeout's exit code in TIMEOUT_EXIT_CODE"
+    )]
+    on_failure: Option<String>,
+
+    #[arg(
+        long = "on-exit",
+        value_name = "SCRIPT",
+        help = "Run SCRIPT with the shell after every outcome, with TIMEOUT_EXIT_CODE, TIMEOUT_REASON, TIMEOUT_ELAPSED_MS and TIMEOUT_COMMAND set"
+    )]
+    on_exit: Option<String>,
+
+    #[arg(
+        long = "hook-failures-fatal",
+        help = "Exit with 125 if an --on-success, --on-failure or --on-exit hook fails, times out or cannot be started"
+    )]
+    hook_failures_fatal: bool,
+
+    #[arg(
+        long = "hook-timeout",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "How long each hook may run before it is sent SIGTERM (default 10s)"
+    )]
+    hook_timeout: Option<Duration>,
+
+    #[arg(
+        long = "notify-pid",
+        value_name = "PID",
+        value_parser = clap::value_parser!(u32).range(1..=i64::from(i32::MAX)),
+        help = "Send a signal to process PID as timeout exits, whatever the outcome (Unix only)"
+    )]
+    notify_pid: Option<u32>,
+
+    #[arg(
+        long = "notify-signal",
+        value_name = "SIGNAL",
+        value_parser = parse_signal,
+        default_value = "USR1",
+        requires = "notify_pid",
+        help = "The signal --notify-pid sends, by name (e.g. USR2, SIGHUP) or number"
+    )]
+    notify_signal: Signal,
+
+    #[arg(
+        long = "sd-notify",
+        help = "Tell systemd through NOTIFY_SOCKET when the command is ready and stopping, and feed its watchdog while the command runs"
+    )]
+    sd_notify: bool,
+
+    #[arg(
+        long = "notify-passthrough",
+        requires = "sd_notify",
+        help = "With --sd-notify, leave NOTIFY_SOCKET to the command so it can signal readiness itself"
+    )]
+    notify_passthrough: bool,
+
+    #[arg(
+        long = "user",
+        value_name = "USER",
+        help = "Run the command as USER, by name or ID, in their primary group unless --group is given (Unix only)"
+    )]
+    user: Option<String>,
+
+    #[arg(
+        long = "group",
+        value_name = "GROUP",
+        help = "Run the command in GROUP, by name or ID (Unix only)"
+    )]
+    group: Option<String>,
+
+    #[arg(
+        long = "umask",
+        value_name = "MODE",
+        help = "Start the command with the octal file-creation mask MODE, e.g. 022 (Unix only)"
+    )]
+    umask: Option<String>,
+
+    #[arg(
+        long = "color",
+        value_name = "WHEN",
+        value_enum,
+        default_value = "auto",
+        help = "Colour timeout's own messages on stderr: auto (only on a terminal), always or never"
+    )]
+    color: ColorChoice,
+
+    #[arg(
+        long = "config",
+        value_name = "PATH",
+        help = "Read default options from this TOML file instead of ./.timeoutrc"
+    )]
+    config: Option<PathBuf>,
+
+    #[arg(short = 'v', long = "verbose", help = "Print debug information")]
+    verbose: bool,
+
+    #[arg(
+        long = "debug-info",
+        exclusive = true,
+        help = "Print the version, platform, how signals are delivered and the default timings, for a bug report, and exit"
+    )]
+    debug_info: bool,
+
+    #[arg(
+        long = "exec",
+        value_name = "COMMAND",
+        num_args = 1..,
+        allow_hyphen_values = true,
+        conflicts_with = "command",
+        help = "Run COMMAND with the rest of the line as its arguments, without interpreting any more of timeout's own options"
+    )]
+    exec: Vec<String>,
+
+    #[arg(
+        long = "exec-replace",
+        // Everything that needs timeout to stay around while the command runs.
+        conflicts_with_all = [
+            "kill_after", "kill_retries", "retries", "retry_on", "retry_backoff", "respawn",
+            "every", "total_timeout", "success_codes", "raw_signal_exit", "cpu_time", "wait_all",
+            "timestamp_output", "timestamps", "max_output", "kill_on_match", "unbuffered",
+            "status_fd", "report_fd", "syslog", "journald", "json", "json_to", "result_file",
+            "pid_file", "print_pid", "metrics_file", "statsd", "webhook", "notify", "on_timeout",
+            "on_success", "on_failure", "on_exit", "notify_pid", "sd_notify", "user", "group",
+            "tee", "tee_stderr", "prefix", "prefix_stderr", "combine_output", "batch", "race",
+            "stdin_string", "stdin_string_file", "report", "pty", "tail_on_timeout",
+            "quiet_success", "until_pattern", "shield", "until_port", "progress", "soft",
+            "warn_at", "until_file", "until_file_gone", "healthcheck_cmd", "print_signal_sent",
+            "min_runtime", "login_shell", "expect_timeout", "heartbeat", "umask", "stats", "time",
+        ],
+        help = "Enforce no timeout at all and replace timeout with the command, so it runs without any overhead; SECONDS must be 0 (elsewhere than Unix, the command is run and waited for)"
+    )]
+    exec_replace: bool,
+
+    #[arg(
+        long = "from-file",
+        value_name = "FILE",
+        conflicts_with_all = ["command", "exec", "exec_replace"],
+        help = "Run each line of FILE as a command in turn, each with its own timeout"
+    )]
+    from_file: Option<PathBuf>,
+
+    #[arg(
+        long = "stdin-commands",
+        conflicts_with_all = ["command", "exec", "exec_replace"],
+        help = "Read commands from stdin, one per line, and run each in turn with its own timeout and /dev/null as its stdin"
+    )]
+    stdin_commands: bool,
+
+    #[arg(
+        short = '0',
+        long = "null",
+        requires = "stdin_commands",
+        help = "With --stdin-commands, commands are separated by NUL rather than newline, e.g. from find -print0"
+    )]
+    null: bool,
+
+    #[arg(
+        short = 'P',
+        long = "parallel",
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "batch",
+        conflicts_with_all = ["unbuffered", "pid_file", "tee", "tee_stderr"],
+        help = "With --from-file or --stdin-commands, run up to N commands at once, prefixing each line of their output with the line they came from"
+    )]
+    parallel: Option<u32>,
+
+    #[arg(
+        long = "race",
+        visible_alias = "exit-on-first",
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "first",
+        conflicts_with_all = ["batch", "unbuffered", "pid_file", "tee", "tee_stderr"],
+        help = "Run the commands separated by a bare --- at once under the one timeout; once the first finishes, or with =success the first succeeds, stop the rest and exit with its code"
+    )]
+    race: Option<RaceMode>,
+
+    #[arg(
+        long = "shell",
+        requires = "batch",
+        help = "With --from-file or --stdin-commands, run each line with the shell instead of splitting it into words"
+    )]
+    shell: bool,
+
+    #[arg(
+        long = "halt-on-error",
+        requires = "batch",
+        help = "With --from-file or --stdin-commands, stop at the first command that does not succeed"
+    )]
+    halt_on_error: bool,
+
+    #[arg(
+        help = "Command to execute",
+        required_unless_present_any = ["exec", "from_file", "stdin_commands"]
+    )]
+    command: Option<String>,
+
+    #[arg(
+        help = "Arguments for the command; a bare --then starts another command, run after it within the same SECONDS",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    args: Vec<String>,
+
+    /// Put at the start of each line of the command's output, for a `--parallel` job.
+    #[arg(skip)]
+    job_prefix: Option<String>,
+
+    /// The `--tee` and `--tee-stderr` files, once opened.
+    #[arg(skip)]
+    tee_files: Option<Arc<OutputFiles>>,
+
+    /// The `--stdout-file` and `--stderr-file` files, once opened.
+    #[arg(skip)]
+    redirect_files: Option<Arc<OutputFiles>>,
+
+    /// What to write to the command's stdin, from `--stdin-string` or `--stdin-string-file`.
+    #[arg(skip)]
+    stdin_data: Option<Arc<[u8]>>,
+
+    /// The `--until-pattern` regex, once compiled.
+    #[arg(skip)]
+    until_regex: Option<Regex>,
+
+    /// The deadline handed down in `DEADLINE_EPOCH` by an outer timeout, if any.
+    #[arg(skip)]
+    parent_deadline: Option<SystemTime>,
+}
+
+impl Args {
+    /// The command to run, given either positionally or after `--exec`.
+    fn program(&self) -> &str {
+        self.exec
+            .first()
+            .or(self.command.as_ref())
+            .map_or("", String::as_str)
+    }
+
+    /// Whether the command's output has to pass through timeout rather than going straight to
+    /// our own stdout and stderr.
+    fn relays_output(&self) -> bool {
+        self.timestamp_output
+            || self.timestamps.is_some()
+            || self.prefix.is_some()
+            || self.prefix_stderr.is_some()
+            || self.max_output.is_some()
+            || self.kill_on_match.is_some()
+            || self.until_pattern.is_some()
+            || self.tee_files.is_some()
+            || self.job_prefix.is_some()
+            || self.tail_lines().is_some()
+            || self.quiet_success
+    }
+
+    /// Whether `--progress auto` gets its countdown line: not when stderr is not a terminal, nor
+    /// when the line would be in the way of debug output, output held back to show later, or
+    /// other jobs' countdowns.
+    fn shows_countdown(&self) -> bool {
+        self.progress == Some(ProgressMode::Auto)
+            && !self.verbose
+            && !self.quiet_success
+            && self.job_prefix.is_none()
+            && countdown::available()
+    }
+
+    /// How many lines of output to keep for `--tail-on-timeout`, unless it is not wanted
+    /// because the output is going to a terminal already.
+    fn tail_lines(&self) -> Option<usize> {
+        self.tail_on_timeout
+            .filter(|tail| !(tail.only_when_quiet && io::stdout().is_terminal()))
+            .map(|tail| tail.lines)
+    }
+
+    /// Set `command`'s timeout, cut short if the deadline handed down by an outer timeout
+    /// comes first, and hand the resulting deadline down to the command in turn. A CPU-time
+    /// limit is left alone, as it has nothing to do with the time of day.
+    fn set_timeout(&self, command: &mut TimeoutCommand, timeout: Duration) {
+        if self.cpu_time {
+            command.timeout(timeout);
+            return;
+        }
+        let timeout = match self.parent_deadline.map(deadline::remaining) {
+            Some(remaining) if remaining < timeout => {
+                debug_print!(
+                    self.verbose,
+                    "{} leaves {:?} of the {:?} timeout",
+                    DEADLINE_VAR,
+                    remaining,
+                    timeout
+                );
+                remaining
+            }
+            _ => timeout,
+        };
+        command.timeout(timeout);
+        if let Some(at) = SystemTime::now().checked_add(timeout) {
+            command.env(DEADLINE_VAR, deadline::epoch(at));
+        }
+    }
+
+    /// What to do with the command once `--until-pattern` or `--until-port` says it is ready.
+    fn until_action(&self) -> MatchAction {
+        if self.until_detach {
+            MatchAction::Detach
+        } else {
+            MatchAction::Finish
+        }
+    }
+
+    /// Where to print the JSON summary, if one was asked for.
+    fn json_target(&self) -> Option<JsonTarget> {
+        self.json_to.or(self.json.then_some(JsonTarget::Stderr))
+    }
+
+    /// The commands to run one after another: the command line split at each `--then`. After
+    /// `--exec` nothing is taken as a separator.
+    fn steps(&self) -> Vec<Vec<String>> {
+        if !self.exec.is_empty() {
+            return vec![self.exec.clone()];
+        }
+        let argv: Vec<String> = self.command.iter().chain(&self.args).cloned().collect();
+        argv.split(|arg| arg == THEN)
+            .map(<[String]>::to_vec)
+            .collect()
+    }
+
+    /// These arguments with `argv` as the command, for one job from `--from-file` or
+    /// `--stdin-commands`, or one step of a `--then` chain.
+    fn for_job(&self, argv: &[String], prefix: Option<String>) -> Args {
+        let mut args = self.clone();
+        args.from_file = None;
+        args.command = None;
+        args.args = Vec::new();
+        args.exec = argv.to_vec();
+        args.job_prefix = prefix;
+        args
+    }
+
+    /// The arguments for [`program`](Self::program).
+    fn program_args(&self) -> &[String] {
+        match self.exec.split_first() {
+            Some((_, args)) => args,
+            None => &self.args,
+        }
+    }
+}
+
+/// Separates the commands of a chain run within one timeout.
+const THEN: &str = "--then";
+
+/// How often `--until-file` and `--until-file-gone` look for the file.
+const FILE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long to keep relaying output after the command has been reaped.
+const RELAY_DRAIN_GRACE: Duration = Duration::from_millis(200);
+
+/// The relays started for a command, and what they keep track of between them.
+struct Relays {
+    threads: Vec<RelayThread>,
+    watch: Option<OutputMatch>,
+    tail: Option<OutputTail>,
+    held: Option<HeldOutput>,
+}
+
+/// Relay whichever of the command's output pipes were handed to us, timestamping each line if
+/// `--timestamp-output` asks for it, stopping once `--max-output` is reached, watching for
+/// `--kill-on-match`, copying to the `--tee` files, keeping the `--tail-on-timeout` lines and
+/// holding everything back for `--quiet-success`. With `--pty`, the command's output is read
+/// from the terminal's master end, `pty`, instead.
+fn start_relays(
+    child: &mut TimeoutChild,
+    args: &Args,
+    pty: Option<File>,
+    gate: Option<&LineGate>,
+) -> Relays {
+    // Relative stamps count from here, on the monotonic clock, as the command has just started.
+    let started = Instant::now();
+    // Each line starts with the job's prefix, then the timestamp, then the --prefix label.
+    let prefix = |label: Option<&String>| -> Prefix {
+        let job = args.job_prefix.clone().unwrap_or_default();
+        let label = label.cloned().unwrap_or_default();
+        match (args.timestamp_output, args.timestamps) {
+            (true, _) => Box::new(move || {
+                format!("{}{} {}", job, timestamp::iso8601(SystemTime::now()), label)
+            }),
+            (false, Some(TimestampStyle::Absolute)) => Box::new(move || {
+                let now = timestamp::time_of_day(SystemTime::now());
+                format!("{}[{}] {}", job, now, label)
+            }),
+            (false, Some(TimestampStyle::Relative)) => Box::new(move || {
+                let offset = timestamp::offset(started.elapsed());
+                format!("{}[{}] {}", job, offset, label)
+            }),
+            (false, None) => {
+                let fixed = job + &label;
+                Box::new(move || fixed.clone())
+            }
+        }
+    };
+    let stdout_label = args.prefix.as_ref();
+    let stderr_label = args.prefix_stderr.as_ref().or(stdout_label);
+    let limit = args.max_output.map(OutputLimit::new);
+    let until_action = args.until_action();
+    let patterns: Vec<_> = [
+        (args.kill_on_match.as_ref(), MatchAction::Kill),
+        (args.until_regex.as_ref(), until_action),
+    ]
+    .into_iter()
+    .filter_map(|(pattern, action)| Some((pattern?.clone(), action)))
+    .collect();
+    let watch = (!patterns.is_empty()).then(|| OutputMatch::new(patterns, child.handle()));
+    let watched = |watched: bool| watch.clone().filter(|_| watched);
+    let tee = args.tee_files.as_deref();
+    let tail = args.tail_lines().map(OutputTail::new);
+    let held = args.quiet_success.then(HeldOutput::default);
+    let (out, err): (Box<dyn Write + Send>, Box<dyn Write + Send>) = match &held {
+        Some(held) => (Box::new(held.stdout()), Box::new(held.stderr())),
+        None => (Box::new(io::stdout()), Box::new(io::stderr())),
+    };
+    // Held output reaches the terminal only once the command has finished.
+    let gated = |target| {
+        gate.filter(|_| held.is_none() && args.heartbeat_stream == target)
+            .cloned()
+    };
+    let mut relays = Vec::new();
+    // Whatever is on a terminal, such as a prompt or a progress bar, is passed on as it comes.
+    let unbuffered = args.unbuffered || pty.is_some();
+    let stdout: Option<Box<dyn Read + Send>> = match pty {
+        Some(master) => Some(Box::new(master)),
+        None => child.take_stdout().map(|stdout| Box::new(stdout) as _),
+    };
+    if let Some(stdout) = stdout {
+        relays.push(RelayThread::spawn(
+            stdout,
+            LineRelay::new(out, prefix(stdout_label))
+                .carriage_return_lines(stdout_label.is_some())
+                .unbuffered(unbuffered)
+                .limit(limit.clone())
+                .watch(watched(args.pattern_stream.stdout()))
+                .tail(tail.as_ref().map(OutputTail::recorder))
+                .tee(tee.and_then(|tee| tee.stdout()))
+                .gate(gated(JsonTarget::Stdout)),
+        ));
+    }
+    if let Some(stderr) = child.take_stderr() {
+        relays.push(RelayThread::spawn(
+            stderr,
+            LineRelay::new(err, prefix(stderr_label))
+                .carriage_return_lines(stderr_label.is_some())
+                .unbuffered(args.unbuffered)
+                .limit(limit)
+                .watch(watched(args.pattern_stream.stderr()))
+                .tail(tail.as_ref().map(OutputTail::recorder))
+                .tee(tee.and_then(|tee| tee.stderr()))
+                .gate(gated(JsonTarget::Stderr)),
+        ));
+    }
+    Relays {
+        threads: relays,
+        watch,
+        tail,
+        held,
+    }
+}
+
+/// Parse a `--kill-on-match` pattern.
+fn parse_pattern(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| e.to_string())
+}
+
+fn main() -> ExitCode {
+    let argv = match config::with_defaults(Args::command(), std::env::args_os().collect()) {
+        Ok(argv) => argv,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::from(EXIT_TIMEOUT_FAIL);
+        }
+    };
+    let mut args = Args::parse_from(argv);
+    if args.debug_info {
+        println!("{}", debug_info::debug_info(RELAY_DRAIN_GRACE));
+        return ExitCode::SUCCESS;
+    }
+    color::init(args.color);
+    if let Some(string) = &args.stdin_string {
+        args.stdin_data = Some(string.as_bytes().into());
+    } else if let Some(path) = &args.stdin_string_file {
+        match fs::read(path) {
+            Ok(data) => args.stdin_data = Some(data.into()),
+            Err(e) => {
+                error!("cannot read '{}': {}", path.display(), e);
+                return ExitCode::from(EXIT_TIMEOUT_FAIL);
+            }
+        }
+    }
+    let mode = OpenMode {
+        create_dirs: args.create_dirs,
+        append: args.append,
+    };
+    if args.stdout_file.is_some() || args.stderr_file.is_some() {
+        match OutputFiles::open(
+            args.stdout_file.as_deref(),
+            args.stderr_file.as_deref(),
+            mode,
+        ) {
+            Ok(files) => args.redirect_files = Some(Arc::new(files)),
+            Err((path, e)) => {
+                error!("cannot open output file '{}': {}", path.display(), e);
+                return ExitCode::from(EXIT_TIMEOUT_FAIL);
+            }
+        }
+    }
+    if let Some(pattern) = &args.until_pattern {
+        match parse_pattern(pattern) {
+            Ok(regex) => args.until_regex = Some(regex),
+            Err(e) => {
+                error!("invalid --until-pattern '{}': {}", pattern, e);
+                return ExitCode::from(EXIT_TIMEOUT_FAIL);
+            }
+        }
+    }
+    if let Some(value) = std::env::var_os(DEADLINE_VAR) {
+        match parse_deadline_epoch(&value.to_string_lossy()) {
+            Ok(at) => args.parent_deadline = Some(at),
+            Err(e) => warning!("{}, ignoring it", e),
+        }
+    }
+    if args.tee.is_some() || args.tee_stderr.is_some() {
+        match OutputFiles::open(args.tee.as_deref(), args.tee_stderr.as_deref(), mode) {
+            Ok(files) => args.tee_files = Some(Arc::new(files)),
+            Err((path, e)) => {
+                error!("cannot open tee file '{}': {}", path.display(), e);
+                return ExitCode::from(EXIT_TIMEOUT_FAIL);
+            }
+        }
+    }
+    // Forking is only safe while this is the only thread. Whatever goes wrong from here on the
+    // way to running the command is still reported to the caller: see cli::detach::ready.
+    if args.detach {
+        #[cfg(unix)]
+        if let Err(e) = cli::detach::detach() {
+            error!("cannot detach: {}", e);
+            return ExitCode::from(EXIT_TIMEOUT_FAIL);
+        }
+        #[cfg(not(unix))]
+        {
+            error!("--detach is only supported on Unix");
+            return ExitCode::from(EXIT_TIMEOUT_FAIL);
+        }
+    }
+    // A command in a process group of its own, e.g. with --wait-all, would otherwise never
+    // learn that the terminal it draws on was resized, nor would the terminal --pty gives it.
+    #[cfg(unix)]
+    if (io::stdout().is_terminal() || args.pty && io::stdin().is_terminal())
+        && let Err(e) = cli::resize::install()
+    {
+        debug_print!(args.verbose, "Failed to forward terminal resizes: {}", e);
+    }
+    let exit_code = if let Some(path) = &args.from_file {
+        match File::open(path) {
+            Ok(file) => run_batch(&args, "from-file", BufReader::new(file)),
+            Err(e) => {
+                error!("cannot read '{}': {}", path.display(), e);
+                EXIT_TIMEOUT_FAIL
+            }
+        }
+    } else if args.stdin_commands {
+        run_batch(&args, "stdin-commands", BufReader::new(io::stdin()))
+    } else if args.exec_replace {
+        exec_replace(&args)
+    } else if let Some(mode) = args.race {
+        run_race(&args, mode)
+    } else if args.steps().len() > 1 {
+        run_chain(&args)
+    } else {
+        run(&args, None)
+    };
+    #[cfg(unix)]
+    cli::detach::report(exit_code);
+
+    if let Some(pid) = args.notify_pid {
+        match notify_pid(pid, args.notify_signal) {
+            Ok(()) => debug_print!(args.verbose, "Sent {} to PID {}", args.notify_signal, pid),
+            Err(e) => error!(
+                "failed to send {} to PID {}: {}",
+                args.notify_signal, pid, e
+            ),
+        }
+    }
+    ExitCode::from(exit_code)
+}
+
+/// Replace timeout with the command for `--exec-replace`, leaving its exit status to reach our
+/// caller directly. Only returns if the command could not be started, or elsewhere than Unix,
+/// where the command is run and waited for instead.
+fn exec_replace(args: &Args) -> u8 {
+    if args.steps().len() > 1 {
+        error!("--exec-replace cannot be combined with --then");
+        return EXIT_TIMEOUT_FAIL;
+    }
+    // Anything else would look like a timeout that is never enforced.
+    if args.seconds != 0 {
+        error!(
+            "--exec-replace enforces no timeout, so SECONDS must be 0, not {}",
+            args.seconds
+        );
+        return EXIT_TIMEOUT_FAIL;
+    }
+    let program = args.program();
+    debug_print!(
+        args.verbose,
+        "Replacing timeout with: {} {:?}",
+        program,
+        args.program_args()
+    );
+    let error = if program.trim().is_empty() {
+        TimeoutError::EmptyCommand
+    } else {
+        let mut command = std::process::Command::new(program);
+        command.args(args.program_args());
+        if args.discard_output || args.discard_stdout {
+            command.stdout(Stdio::null());
+        }
+        if args.discard_output || args.discard_stderr {
+            command.stderr(Stdio::null());
+        }
+        if let Some(files) = args.redirect_files.as_deref() {
+            if let Some(file) = files.stdout() {
+                command.stdout(file);
+            }
+            if let Some(file) = files.stderr() {
+                command.stderr(file);
+            }
+        }
+        if args.no_stdin {
+            command.stdin(Stdio::null());
+        }
+        match open_stdin_file(args) {
+            Ok(Some(file)) => {
+                command.stdin(file);
+            }
+            Ok(None) => {}
+            Err(code) => return code,
+        }
+        #[cfg(unix)]
+        let e = {
+            use std::os::unix::process::CommandExt;
+            command.exec()
+        };
+        #[cfg(not(unix))]
+        let e = match command.status() {
+            Ok(status) => {
+                return status
+                    .code()
+                    .and_then(|code| u8::try_from(code).ok())
+                    .unwrap_or(1);
+            }
+            Err(e) => e,
+        };
+        TimeoutError::from_spawn(program, e)
+    };
+    error!("{}", error);
+    exit_code(&TimeoutOutcome::failed(&error, Duration::ZERO))
+}
+
+/// Run the `--then` chain one command at a time, each getting whatever is left of `SECONDS`,
+/// and stop at the first that does not exit with 0 or once the time has run out.
+fn run_chain(args: &Args) -> u8 {
+    let verbose = args.verbose;
+    if args.every.is_some() || args.respawn || args.cpu_time {
+        error!("--then cannot be combined with --every, --respawn or --cpu-time");
+        return EXIT_TIMEOUT_FAIL;
+    }
+    let steps = args.steps();
+    if steps.iter().any(Vec::is_empty) {
+        error!("--then must be between two commands");
+        return EXIT_TIMEOUT_FAIL;
+    }
+    let seconds = Duration::from_secs(args.seconds);
+    let budget = args
+        .total_timeout
+        .map_or(seconds, |total| total.min(seconds));
+    let start = Instant::now();
+    // Caught once for the whole chain, so a Ctrl+C stops the command running and starts no more.
+    let signals = FatalSignals::install(&args.shield)
+        .inspect_err(|e| debug_print!(verbose, "Failed to install signal handlers: {}", e))
+        .ok();
+    for (i, argv) in steps.iter().enumerate() {
+        let remaining = budget.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            debug_print!(verbose, "No time left for step {}/{}", i + 1, steps.len());
+            return EXIT_TIMEOUT;
+        }
+        debug_print!(
+            verbose,
+            "Step {}/{}: {}, {:.3}s of the budget left",
+            i + 1,
+            steps.len(),
+            argv[0],
+            remaining.as_secs_f64()
+        );
+        // The step's total timeout is what is left of the budget, so the command still running
+        // when it ends is stopped with the usual signal and --kill-after escalation.
+        let mut step = args.for_job(argv, None);
+        step.total_timeout = Some(remaining);
+        let exit_code = run(&step, signals.as_ref());
+        if exit_code != 0 || signals.as_ref().is_some_and(FatalSignals::caught) {
+            return exit_code;
+        }
+    }
+    0
+}
 
-#[derive(Parser)]
-#[command(name = "timeout")]
-#[command(about = "Run a command with a timeout")]
-#[command(version)]
-struct Args {
-    #[arg(help = "Number of seconds to wait before timing out")]
-    seconds: u64,
+/// Run the `--race` commands at once, each relayed with its number, and stop the rest as soon
+/// as one wins. timeout exits with the winner's code or, if none won, the code of the last to
+/// finish.
+fn run_race(args: &Args, mode: RaceMode) -> u8 {
+    let verbose = args.verbose;
+    if args.every.is_some() || args.respawn || args.steps().len() > 1 {
+        error!("--race cannot be combined with --every, --respawn or --then");
+        return EXIT_TIMEOUT_FAIL;
+    }
+    let commands = match race::commands(&args.steps().concat()) {
+        Ok(commands) => commands,
+        Err(e) => {
+            error!("{}", e);
+            return EXIT_TIMEOUT_FAIL;
+        }
+    };
+    // Also how the losers are stopped once there is a winner.
+    let signals = FatalSignals::install(&args.shield)
+        .inspect_err(|e| debug_print!(verbose, "Failed to install signal handlers: {}", e))
+        .unwrap_or_default();
+    let (tx, results) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for (i, argv) in commands.iter().enumerate() {
+            let (tx, signals) = (tx.clone(), &signals);
+            scope.spawn(move || {
+                let start = Instant::now();
+                let job = args.for_job(argv, Some(format!("[{}] ", i + 1)));
+                let _ = tx.send((i, run(&job, Some(signals)), start.elapsed()));
+            });
+        }
+        drop(tx);
+        let mut winner = None;
+        let mut last = EXIT_TIMEOUT_FAIL;
+        for (i, exit_code, elapsed) in results {
+            debug_print!(verbose, "Command {} finished with {}", i + 1, exit_code);
+            last = exit_code;
+            let wins = mode == RaceMode::First || exit_code == 0;
+            if winner.is_none() && wins && !signals.caught() {
+                eprintln!(
+                    "{}",
+                    race::result_line(i + 1, &commands[i], elapsed, exit_code)
+                );
+                winner = Some(exit_code);
+                signals.cancel_all();
+            }
+        }
+        if winner.is_none() && mode == RaceMode::Success && !signals.caught() {
+            eprintln!("timeout: race: no command succeeded");
+        }
+        winner.unwrap_or(last)
+    })
+}
 
-    #[arg(
-        short = 'k',
-        long = "kill-after",
-        help = "Also send KILL signal after this many seconds"
-    )]
-    kill_after: Option<u64>,
+/// Run the commands read from `reader`, `--parallel` at a time, exiting with 0 if they all
+/// succeed, 124 if any timed out, and otherwise with the highest exit code any came to.
+/// `source` names the option they came from.
+fn run_batch<R: BufRead + Send>(args: &Args, source: &str, reader: R) -> u8 {
+    let delimiter = if args.null { b'\0' } else { b'\n' };
+    let jobs = Mutex::new(batch::Jobs::new(reader, delimiter, args.shell));
+    let parallel = args.parallel.unwrap_or(1);
+    // Caught once for every command, so a Ctrl+C cancels all those running and starts no more.
+    let signals = FatalSignals::install(&args.shield)
+        .inspect_err(|e| debug_print!(args.verbose, "Failed to install signal handlers: {}", e))
+        .ok();
+    let halted = AtomicBool::new(false);
+    let summary = Mutex::new(BatchSummary::default());
+    let record = |exit_code| {
+        summary
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .record(exit_code);
+    };
+    std::thread::scope(|scope| {
+        for _ in 0..parallel {
+            scope.spawn(|| {
+                while !halted.load(Ordering::Relaxed)
+                    && !signals.as_ref().is_some_and(FatalSignals::caught)
+                {
+                    // Read under the lock, so each command goes to exactly one worker.
+                    let job = jobs.lock().unwrap_or_else(|e| e.into_inner()).next();
+                    let job = match job {
+                        Some(Ok(job)) => job,
+                        Some(Err(e)) => {
+                            error!("cannot read commands: {}", e);
+                            record(EXIT_TIMEOUT_FAIL);
+                            halted.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                        None => break,
+                    };
+                    let exit_code = run_job(args, source, &job, signals.as_ref(), parallel > 1);
+                    record(exit_code);
+                    if exit_code != 0 && args.halt_on_error {
+                        debug_print!(args.verbose, "Halting after line {}", job.line);
+                        halted.store(true, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+    });
+    let summary = summary.into_inner().unwrap_or_else(|e| e.into_inner());
+    eprintln!("{}", summary.line(source));
+    summary.exit_code()
+}
 
-    #[arg(short = 'v', long = "verbose", help = "Print debug information")]
-    verbose: bool,
+/// Run one command from a `--from-file` or `--stdin-commands` list and print what it came to.
+fn run_job(
+    args: &Args,
+    source: &str,
+    job: &batch::Job,
+    signals: Option<&FatalSignals>,
+    prefix: bool,
+) -> u8 {
+    let start = Instant::now();
+    let exit_code = match &job.argv {
+        Ok(argv) => {
+            let prefix = prefix.then(|| format!("[line {}] ", job.line));
+            run(&args.for_job(argv, prefix), signals)
+        }
+        Err(e) => {
+            error!("cannot parse line {}: {}", job.line, e);
+            EXIT_TIMEOUT_FAIL
+        }
+    };
+    eprintln!(
+        "{}",
+        batch::result_line(source, job, start.elapsed(), exit_code)
+    );
+    exit_code
+}
 
-    #[arg(help = "Command to execute", required = true)]
-    command: String,
+/// What one run of the command came to.
+struct Attempt {
+    outcome: TimeoutOutcome,
+    pid: Option<u32>,
+    started_at: SystemTime,
+    error: Option<TimeoutError>,
+    /// The line that matched `--kill-on-match` or `--until-pattern`, and which of them it
+    /// matched, if that is what stopped the command.
+    matched: Option<(MatchAction, String)>,
+    /// What `--until-port`, `--until-file` or `--until-file-gone` found, if that is what
+    /// stopped the command.
+    ready: Option<Ready>,
+    /// Whether `--healthcheck-cmd` failing is what stopped the command.
+    unhealthy: bool,
+    /// Whether the command exited before `--min-runtime`.
+    premature: bool,
+}
 
-    #[arg(
-        help = "Arguments for the command",
-        trailing_var_arg = true,
-        allow_hyphen_values = true
-    )]
-    args: Vec<String>,
+impl Attempt {
+    /// The exit code the attempt comes to before anything that only applies to the last one,
+    /// as `--retry-on` and `--every` see it.
+    fn exit_code(&self, args: &Args) -> u8 {
+        if self.premature {
+            args.min_runtime_exit_code
+        } else {
+            exit_code(&self.outcome)
+        }
+    }
+}
+
+/// Spawn `command` once and wait for it, relaying its output as it arrives.
+fn run_attempt(
+    command: &TimeoutCommand,
+    args: &Args,
+    syslog: Option<&Syslog>,
+    journald: Option<&Journald>,
+    signals: Option<&FatalSignals>,
+) -> Attempt {
+    let verbose = args.verbose;
+    let started_at = SystemTime::now();
+    let start = Instant::now();
+    let mut pid = None;
+    let mut error = None;
+    let mut matched = None;
+    let mut ready = None;
+    let mut unhealthy = false;
+    let mut premature = false;
+    #[cfg(unix)]
+    cli::detach::ready();
+    #[cfg(unix)]
+    let (spawned, pty) = if args.pty {
+        match spawn_on_pty(command) {
+            Ok((child, master)) => (Ok(child), Some(master)),
+            Err(e) => (Err(e), None),
+        }
+    } else {
+        (command.spawn(), None)
+    };
+    #[cfg(not(unix))]
+    let (spawned, pty) = (command.spawn(), None);
+    let outcome = match spawned {
+        Ok(mut child) => {
+            pid = Some(child.pid());
+            if let Some(target) = args.print_pid
+                && let Err(e) = target.write_line(&child.pid().to_string())
+            {
+                debug_print!(verbose, "Failed to print PID: {}", e);
+            }
+            if let Some(signals) = signals {
+                signals.supervise(child.handle(), child.pid());
+            }
+            #[cfg(unix)]
+            let _resize = cli::resize::forward_to(
+                child.pid(),
+                pty.as_ref().and_then(|master| master.try_clone().ok()),
+            );
+            // Held until the command has been reaped, then dropped to remove the file.
+            let _pid_file = args.pid_file.as_ref().and_then(|path| {
+                PidFile::create(path, child.pid())
+                    .inspect_err(|e| warning!("cannot write pid file '{}': {}", path.display(), e))
+                    .ok()
+            });
+            // Kept until the command's output has been relayed, then dropped to close the
+            // terminal and give timeout's own back its settings.
+            #[cfg(unix)]
+            let _terminal = pty.as_ref().map(|master| {
+                let input = master.try_clone().map(cli::pty::Input::forward);
+                (input, cli::pty::RawMode::enable())
+            });
+            let gate = args.heartbeat.map(|_| LineGate::new(args.heartbeat_stream));
+            let relays = start_relays(&mut child, args, pty, gate.as_ref());
+            // Shares the relays' watch, if there is one, so that only the first thing to end
+            // the run early counts.
+            let watch = relays
+                .watch
+                .clone()
+                .unwrap_or_else(|| OutputMatch::new(Vec::new(), child.handle()));
+            let ready_watches = start_ready_watches(args, &watch);
+            let health = args.healthcheck_cmd.clone().map(|script| {
+                let check = HealthCheck {
+                    script,
+                    interval: args.healthcheck_interval,
+                    timeout: args.healthcheck_timeout,
+                    failures: args.healthcheck_failures,
+                };
+                HealthWatch::start(check, watch.clone(), verbose)
+            });
+            let progress = args
+                .progress
+                .and_then(ProgressMode::interval)
+                .zip(command.get_options().timeout)
+                .map(|(interval, timeout)| {
+                    let prefix = args.job_prefix.clone().unwrap_or_default();
+                    Progress::start(interval, timeout, start, prefix)
+                });
+            let countdown = command
+                .get_options()
+                .timeout
+                .filter(|_| args.shows_countdown())
+                .map(|timeout| Countdown::start(timeout, start, child.pid()));
+            let warnings = command.get_options().timeout.and_then(|timeout| {
+                let program = args.program();
+                let mut lines: Vec<_> = args
+                    .warn_at
+                    .iter()
+                    .filter_map(|warn_at| warn_at.warning(program, timeout))
+                    .collect();
+                if let Some(soft) = args.soft {
+                    lines.push((soft, warnings::soft_line(program, soft, timeout)));
+                }
+                let prefix = args.job_prefix.clone().unwrap_or_default();
+                (!lines.is_empty()).then(|| Warnings::start(start, lines, prefix))
+            });
+            let heartbeat = args.heartbeat.zip(gate).map(|(interval, gate)| {
+                let prefix = args.job_prefix.clone().unwrap_or_default();
+                let timeout = command.get_options().timeout;
+                Heartbeat::start(interval, timeout, start, child.pid(), prefix, gate)
+            });
+            debug_print!(verbose, "Waiting for command result...");
+            let outcome = child.wait().unwrap_or_else(|e| {
+                error!("{}", e);
+                let outcome = TimeoutOutcome::failed(&e, start.elapsed());
+                error = Some(e);
+                outcome
+            });
+            drop(progress);
+            drop(countdown);
+            drop(warnings);
+            drop(heartbeat);
+            let stopped = matches!(
+                outcome.reason,
+                TimeoutReason::TermExit | TimeoutReason::Killed
+            );
+            for relay in relays.threads {
+                relay.finish(RELAY_DRAIN_GRACE, stopped);
+            }
+            if let (TimeoutResult::Completed(code), Some(min_runtime)) =
+                (outcome.result, args.min_runtime)
+                && outcome.elapsed < min_runtime
+            {
+                warning!(
+                    "command exited with {} after {:.3}s, before the minimum runtime of {}",
+                    code,
+                    outcome.elapsed.as_secs_f64(),
+                    format_duration(min_runtime)
+                );
+                premature = true;
+            }
+            if let Some(held) = relays.held
+                && (premature || !succeeded(&outcome, args))
+            {
+                held.release();
+            }
+            if let Some(tail) = relays.tail
+                && stopped
+            {
+                let _ = io::stderr().write_all(&tail.block());
+            }
+            // A match after the timeout expired changes nothing, and one that had to be
+            // followed by KILL is reported as a kill.
+            if matches!(
+                outcome.reason,
+                TimeoutReason::Cancelled | TimeoutReason::Detached
+            ) {
+                ready = ready_watches.iter().find_map(ReadyWatch::ready);
+                unhealthy = health.as_ref().is_some_and(HealthWatch::failed);
+                if ready.is_none() && !unhealthy {
+                    matched = relays.watch.and_then(|watch| {
+                        watch.matched().map(|(action, line)| (action, line.into()))
+                    });
+                }
+            }
+            outcome
+        }
+        Err(e) => {
+            let message = e.to_string();
+            error!("{}", message);
+            if let Some(syslog) = syslog {
+                syslog.log(Severity::Err, &message);
+            }
+            let outcome = TimeoutOutcome::failed(&e, start.elapsed());
+            if let Some(journald) = journald {
+                let result = ("TIMEOUT_RESULT", result_name(outcome.result).to_string());
+                journald.log(Severity::Err, "exit", &message, &[result]);
+            }
+            error = Some(e);
+            outcome
+        }
+    };
+    Attempt {
+        outcome,
+        pid,
+        started_at,
+        error,
+        matched,
+        ready,
+        unhealthy,
+        premature,
+    }
+}
+
+/// Start checking for whatever `--until-port`, `--until-file` and `--until-file-gone` wait for,
+/// each claiming `watch` once it finds it.
+fn start_ready_watches(args: &Args, watch: &OutputMatch) -> Vec<ReadyWatch> {
+    let start = |reason, interval, check: Box<dyn FnMut() -> Option<String> + Send>| {
+        ReadyWatch::start(reason, interval, watch.clone(), args.until_action(), check)
+    };
+    let mut watches = Vec::new();
+    if let Some(address) = args.until_port.clone() {
+        watches.push(start(
+            "port_open",
+            args.until_port_interval,
+            Box::new(move || address.probe().map(|_| address.to_string())),
+        ));
+    }
+    if let Some(path) = args.until_file.clone() {
+        watches.push(start(
+            "file_exists",
+            FILE_POLL_INTERVAL,
+            Box::new(move || path.exists().then(|| path.display().to_string())),
+        ));
+    }
+    if let Some(path) = args.until_file_gone.clone() {
+        watches.push(start(
+            "file_gone",
+            FILE_POLL_INTERVAL,
+            Box::new(move || (!path.exists()).then(|| path.display().to_string())),
+        ));
+    }
+    watches
 }
 
-// Exit codes following GNU timeout convention
-const EXIT_TIMEOUT: u8 = 124; // Command timed out
-const EXIT_TIMEOUT_FAIL: u8 = 125; // timeout command itself failed
-const EXIT_CANNOT_INVOKE: u8 = 126; // Command found but cannot be invoked
-const EXIT_NOT_FOUND: u8 = 127; // Command not found
-const EXIT_KILLED: u8 = 137; // Command killed with KILL signal (128+9)
-
-#[derive(Debug)]
-enum TimeoutResult {
-    Completed(i32),
-    TimedOut,
-    Killed,
-    NotFound,
-    CannotInvoke,
-    InternalError,
+/// Whether `outcome` counts as a success: the command exited in time with 0 or one of the
+/// `--success-codes`.
+fn succeeded(outcome: &TimeoutOutcome, args: &Args) -> bool {
+    match outcome.result {
+        TimeoutResult::Completed(code) => code == 0 || args.success_codes.contains(&code),
+        _ => false,
+    }
+}
+
+/// Spawn a copy of `command` on a pseudo-terminal of its own, for `--pty`, returning the
+/// terminal's master end with it. The copy goes once the command has started, taking timeout's
+/// handles on the other end with it, so that reading the master ends once the command and
+/// anything it left running are done with the terminal.
+#[cfg(unix)]
+fn spawn_on_pty(command: &TimeoutCommand) -> Result<(TimeoutChild, File), TimeoutError> {
+    let failed = |e: io::Error| TimeoutError::SpawnFailed {
+        program: command.get_program().to_string_lossy().into_owned(),
+        source: io::Error::new(e.kind(), format!("cannot open a pseudo-terminal: {}", e)),
+    };
+    let cli::pty::Pty { master, slave } = cli::pty::Pty::open().map_err(failed)?;
+    let mut command = command.clone();
+    command
+        .stdin_file(slave.try_clone().map_err(failed)?)
+        .stdout_file(slave.try_clone().map_err(failed)?)
+        .stderr_file(slave)
+        .new_session(true);
+    Ok((command.spawn()?, master))
 }
 
-macro_rules! debug_print {
-    ($verbose:expr, $($arg:tt)*) => {
-        if $verbose {
-            eprintln!("DEBUG: {}", format!($($arg)*));
+/// Run `command` over and over for the `--respawn` window, returning the last run and a
+/// summary of them all.
+fn respawn(
+    command: &mut TimeoutCommand,
+    args: &Args,
+    syslog: Option<&Syslog>,
+    journald: Option<&Journald>,
+    signals: Option<&FatalSignals>,
+) -> (Attempt, RespawnSummary) {
+    let verbose = args.verbose;
+    let window = Duration::from_secs(args.seconds);
+    let window_start = Instant::now();
+    let mut summary = RespawnSummary::default();
+    let last = loop {
+        // Each run gets what is left of the window, so the one still going when it ends is
+        // stopped with the usual escalation.
+        args.set_timeout(command, window.saturating_sub(window_start.elapsed()));
+        summary.iterations += 1;
+        debug_print!(verbose, "Iteration {}", summary.iterations);
+        let result = run_attempt(command, args, syslog, journald, signals);
+        if matches!(
+            result.outcome.result,
+            TimeoutResult::TimedOut | TimeoutResult::Killed
+        ) {
+            summary.window_ended = true;
+            break result;
+        }
+        let code = exit_code(&result.outcome);
+        if code != 0 {
+            summary.failures += 1;
+            // A command that cannot be started will not start the next time either.
+            if args.stop_on_failure || result.pid.is_none() {
+                break result;
+            }
+        }
+        if signals.is_some_and(FatalSignals::caught) {
+            break result;
+        }
+        let remaining = window.saturating_sub(window_start.elapsed());
+        if args.respawn_delay >= remaining {
+            summary.window_ended = true;
+            break result;
+        }
+        let waited = match signals {
+            Some(signals) => signals.sleep(args.respawn_delay),
+            None => {
+                std::thread::sleep(args.respawn_delay);
+                true
+            }
+        };
+        if !waited {
+            break result;
         }
     };
+    summary.finish(window_start.elapsed());
+    (last, summary)
 }
 
-fn main() -> ExitCode {
-    let args = Args::parse();
+/// Run `command` on the `--every` schedule until it runs out or a signal arrives, returning
+/// the last run.
+fn run_every(
+    command: &mut TimeoutCommand,
+    args: &Args,
+    schedule: &Schedule,
+    syslog: Option<&Syslog>,
+    journald: Option<&Journald>,
+    signals: Option<&FatalSignals>,
+) -> Attempt {
+    let verbose = args.verbose;
+    let mut run = 1;
+    let mut start = Instant::now();
+    loop {
+        debug_print!(verbose, "Run {}", run);
+        args.set_timeout(command, Duration::from_secs(args.seconds));
+        let result = run_attempt(command, args, syslog, journald, signals);
+        eprintln!(
+            "{}",
+            every::run_line(
+                run,
+                result.started_at,
+                result.outcome.elapsed,
+                result.exit_code(args)
+            )
+        );
+        // A command that cannot be started will not start the next time either.
+        if result.pid.is_none() || signals.is_some_and(FatalSignals::caught) {
+            return result;
+        }
+        let now = Instant::now();
+        let (next, skipped) = schedule.next_start(start, now);
+        if skipped > 0 {
+            debug_print!(
+                verbose,
+                "Run {} overran the interval, skipping {} runs",
+                run,
+                skipped
+            );
+        }
+        let wait = next.saturating_duration_since(now);
+        if !schedule.allows(run + 1, SystemTime::now() + wait) {
+            return result;
+        }
+        let waited = match signals {
+            Some(signals) => signals.sleep(wait),
+            None => {
+                std::thread::sleep(wait);
+                true
+            }
+        };
+        if !waited {
+            debug_print!(verbose, "Interrupted while waiting for the next run");
+            return result;
+        }
+        start = next;
+        run += 1;
+    }
+}
+
+/// Open the `--stdin-file` afresh, so that every run reads it from the start. On failure,
+/// returns the exit code after saying why.
+fn open_stdin_file(args: &Args) -> Result<Option<File>, u8> {
+    let Some(path) = &args.stdin_file else {
+        return Ok(None);
+    };
+    File::open(path).map(Some).map_err(|e| {
+        error!("cannot open stdin file '{}': {}", path.display(), e);
+        EXIT_TIMEOUT_FAIL
+    })
+}
 
+/// Run the command as `args` describe and work out what to exit with. `signals` are the
+/// handlers already installed for a whole `--from-file` run, if any.
+fn run(args: &Args, signals: Option<&FatalSignals>) -> u8 {
+    let budget_start = Instant::now();
     let timeout_duration = Duration::from_secs(args.seconds);
-    let kill_after_duration = args.kill_after.map(Duration::from_secs);
     let verbose = args.verbose;
 
     debug_print!(
         verbose,
         "Starting timeout: {}s, kill-after: {:?}s, command: {}",
         args.seconds,
-        args.kill_after,
-        args.command
+        args.kill_after.map(|kill_after| kill_after.secs),
+        args.program()
     );
 
-    let should_terminate = Arc::new(AtomicBool::new(false));
-    let should_kill = Arc::new(AtomicBool::new(false));
+    let stdin_file = match open_stdin_file(args) {
+        Ok(file) => file,
+        Err(code) => return code,
+    };
 
-    let (tx, rx) = mpsc::channel();
+    let mut status_fd = match args.status_fd.map(StatusFd::open).transpose() {
+        Ok(status_fd) => status_fd,
+        Err(e) => {
+            error!(
+                "invalid status fd {}: {}",
+                args.status_fd.unwrap_or_default(),
+                e
+            );
+            return EXIT_TIMEOUT_FAIL;
+        }
+    };
 
-    // Spawn the command
-    let should_terminate_clone = should_terminate.clone();
-    let should_kill_clone = should_kill.clone();
-    let command_name = args.command.clone();
+    let report_fd = match args.report_fd.map(ReportFd::open).transpose() {
+        Ok(report_fd) => report_fd,
+        Err(e) => {
+            error!(
+                "invalid report fd {}: {}",
+                args.report_fd.unwrap_or_default(),
+                e
+            );
+            return EXIT_TIMEOUT_FAIL;
+        }
+    };
 
-    let command_thread = thread::spawn(move || {
-        let mut cmd = Command::new(&args.command);
-        cmd.args(&args.args);
+    let syslog = match args
+        .syslog
+        .then(|| {
+            Syslog::open(
+                &args.syslog_socket,
+                args.syslog_facility,
+                args.syslog_tag.clone(),
+            )
+        })
+        .transpose()
+    {
+        Ok(syslog) => syslog.map(Arc::new),
+        Err(e) => {
+            error!(
+                "cannot connect to syslog at '{}': {}",
+                args.syslog_socket.display(),
+                e
+            );
+            return EXIT_TIMEOUT_FAIL;
+        }
+    };
 
-        debug_print!(
-            verbose,
-            "Spawning command: {} {:?}",
-            args.command,
-            args.args
-        );
+    let command_line = std::iter::once(args.program())
+        .chain(args.program_args().iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
 
-        let mut child = match cmd.spawn() {
-            Ok(child) => {
-                debug_print!(
-                    verbose,
-                    "Command spawned successfully with PID: {}",
-                    child.id()
-                );
-                child
-            }
+    let journald = if args.journald {
+        match Journald::open(Path::new(JOURNALD_SOCKET), command_line.clone()) {
+            Ok(journald) => Some(Arc::new(journald)),
             Err(e) => {
-                debug_print!(verbose, "Failed to spawn command: {}", e);
-                let exit_code = match e.kind() {
-                    io::ErrorKind::NotFound => {
-                        eprintln!(
-                            "timeout: failed to run command '{}': No such file or directory",
-                            command_name
-                        );
-                        TimeoutResult::NotFound
-                    }
-                    io::ErrorKind::PermissionDenied => {
-                        eprintln!(
-                            "timeout: failed to run command '{}': Permission denied",
-                            command_name
-                        );
-                        TimeoutResult::CannotInvoke
-                    }
-                    _ => {
-                        eprintln!("timeout: failed to run command '{}': {}", command_name, e);
-                        TimeoutResult::InternalError
-                    }
-                };
-                let _ = tx.send(exit_code);
-                return;
+                warning!(
+                    "cannot connect to journald, not logging to the journal: {}",
+                    e
+                );
+                None
             }
-        };
+        }
+    } else {
+        None
+    };
+
+    let sd_notify = if args.sd_notify {
+        SdNotify::from_env(args.notify_passthrough).unwrap_or_else(|e| {
+            warning!(
+                "cannot connect to NOTIFY_SOCKET, not notifying systemd: {}",
+                e
+            );
+            None
+        })
+    } else {
+        None
+    };
+
+    #[cfg(unix)]
+    let credentials = match cli::user::resolve(args.user.as_deref(), args.group.as_deref()) {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            error!("cannot run as the requested user or group: {}", e);
+            return EXIT_TIMEOUT_FAIL;
+        }
+    };
+    #[cfg(not(unix))]
+    if args.user.is_some() || args.group.is_some() {
+        error!("--user and --group are only supported on Unix");
+        return EXIT_TIMEOUT_FAIL;
+    }
+    let umask = match args
+        .umask
+        .as_deref()
+        .map(cli::umask::parse_umask)
+        .transpose()
+    {
+        Ok(umask) => umask,
+        Err(e) => {
+            error!("{}", e);
+            return EXIT_TIMEOUT_FAIL;
+        }
+    };
+    #[cfg(not(unix))]
+    if umask.is_some() {
+        error!("--umask is only supported on Unix");
+        return EXIT_TIMEOUT_FAIL;
+    }
+    #[cfg(not(unix))]
+    if args.pty {
+        error!("--pty is only supported on Unix");
+        return EXIT_TIMEOUT_FAIL;
+    }
+    #[cfg(not(unix))]
+    if !args.shield.is_empty() {
+        error!("--shield is only supported on Unix");
+        return EXIT_TIMEOUT_FAIL;
+    }
+    if let Some(soft) = args.soft
+        && soft >= Duration::from_secs(args.seconds)
+    {
+        error!("--soft must be less than SECONDS ({}s)", args.seconds);
+        return EXIT_TIMEOUT_FAIL;
+    }
+    #[cfg(not(unix))]
+    if args.login_shell {
+        error!("--login-shell is only supported on Unix");
+        return EXIT_TIMEOUT_FAIL;
+    }
+
+    let mut command = TimeoutCommand::new(args.program());
+    command.args(args.program_args());
+    // An empty command is left to fail as it would without the shell.
+    #[cfg(unix)]
+    if args.login_shell && !args.program().trim().is_empty() {
+        let shell = std::env::var("SHELL").ok();
+        let shell = cli::login_shell::login_shell(shell.as_deref());
+        debug_print!(
+            verbose,
+            "Running the command through a login shell: {}",
+            shell
+        );
+        command = cli::login_shell::command(shell, args.program(), args.program_args());
+    }
+    command.kill_retries(args.kill_retries).verbose(verbose);
+    if let Some(KillAfter { secs, signal }) = args.kill_after {
+        command
+            .kill_after(Duration::from_secs(secs))
+            .kill_signal(signal);
+    }
+    if args.cpu_time {
+        command.clock(TimeoutClock::Cpu);
+    }
+    if args.wait_all {
+        command.wait_for_group(true);
+    }
+    #[cfg(unix)]
+    {
+        if let (Some(uid), _) = credentials {
+            command.uid(uid);
+        }
+        if let (_, Some(gid)) = credentials {
+            command.gid(gid);
+        }
+        if let Some(mask) = umask {
+            command.umask(mask);
+        }
+    }
+    if args.relays_output() {
+        command.capture(CaptureMode::Manual);
+    }
+    if args.combine_output {
+        command.merge_stderr(true);
+    }
+    command
+        .discard_stdout(args.discard_output || args.discard_stdout)
+        .discard_stderr(args.discard_output || args.discard_stderr);
+    if let Some(files) = args.redirect_files.as_deref() {
+        if let Some(file) = files.stdout() {
+            command.stdout_file(file);
+        }
+        if let Some(file) = files.stderr() {
+            command.stderr_file(file);
+        }
+    }
+    let hook_timeout = args.hook_timeout.unwrap_or(HOOK_TIMEOUT);
+    if let Some(script) = args.on_timeout.clone() {
+        let command_line = command_line.clone();
+        command.on_timeout(move |pid, elapsed| {
+            hooks::run_on_timeout(&script, pid, &command_line, elapsed, hook_timeout, verbose)
+        });
+    }
+    if args.sd_notify && !args.notify_passthrough {
+        command.env_remove(NOTIFY_SOCKET);
+    }
+    // The commands themselves are being read from stdin.
+    if args.stdin_commands || args.no_stdin {
+        command.stdin(StdinMode::Null);
+    }
+    if let Some(file) = stdin_file {
+        command.stdin_file(file);
+    }
+    if let Some(data) = args.stdin_data.clone() {
+        command.stdin_bytes(data);
+    }
+    let mut sinks: Vec<Box<dyn EventSink>> = Vec::new();
+    if verbose {
+        sinks.push(Box::new(VerboseSink));
+    }
+    if let Some(report_fd) = report_fd {
+        sinks.push(Box::new(report_fd));
+    }
+    if let Some(syslog) = &syslog {
+        sinks.push(Box::new(syslog.clone()));
+    }
+    if let Some(journald) = &journald {
+        sinks.push(Box::new(journald.clone()));
+    }
+    if let Some(sd_notify) = sd_notify {
+        sinks.push(Box::new(sd_notify));
+    }
+    if args.print_signal_sent {
+        sinks.push(Box::new(DeliverySink::default()));
+    }
+    if !sinks.is_empty() {
+        command.events(sinks);
+    }
 
-        let mut term_sent = false;
+    if let Some(path) = &args.pid_file
+        && let Err(e) = PidFile::check(path, args.pid_file_overwrite)
+    {
+        error!("cannot use pid file '{}': {}", path.display(), e);
+        return EXIT_TIMEOUT_FAIL;
+    }
 
+    // A command in its own process group no longer gets the terminal's Ctrl+C, so pass it on
+    // as a cancellation. Catching it also lets a wait between attempts end early, with the
+    // last attempt reported as usual, and with --pty lets timeout's terminal be put back the
+    // way it was however timeout is stopped.
+    let signals = if let Some(signals) = signals {
+        Some(signals.clone())
+    } else if args.result_file.is_some()
+        || args.pid_file.is_some()
+        || args.wait_all
+        || args.pty
+        || !args.shield.is_empty()
+        || !args.retry_backoff.is_zero()
+        || args.respawn
+        || args.every.is_some()
+    {
+        FatalSignals::install(&args.shield)
+            .inspect_err(|e| debug_print!(verbose, "Failed to install signal handlers: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    let backoff = Backoff {
+        initial: args.retry_backoff,
+        max: args.retry_max_backoff,
+        jitter: args.retry_jitter,
+    };
+    let attempts = args.retries + 1;
+    let mut attempt = 1;
+    // Set when retrying is given up because it would overrun --total-timeout.
+    let mut budget_exhausted = false;
+    let mut respawned: Option<RespawnSummary> = None;
+    let Attempt {
+        outcome,
+        pid,
+        started_at,
+        error,
+        matched,
+        ready,
+        unhealthy,
+        premature,
+    } = if args.respawn {
+        let (last, summary) = respawn(
+            &mut command,
+            args,
+            syslog.as_deref(),
+            journald.as_deref(),
+            signals.as_ref(),
+        );
+        eprintln!("{}", summary.line());
+        respawned = Some(summary);
+        last
+    } else if let Some(interval) = args.every {
+        let schedule = Schedule {
+            interval,
+            max_runs: args.max_runs,
+            until: args.until,
+        };
+        run_every(
+            &mut command,
+            args,
+            &schedule,
+            syslog.as_deref(),
+            journald.as_deref(),
+            signals.as_ref(),
+        )
+    } else {
         loop {
-            // Check if we should send KILL signal
-            if should_kill_clone.load(Ordering::Relaxed) {
-                debug_print!(verbose, "Sending KILL signal to PID {}", child.id());
-                let _ = child.kill();
-                let _ = child.wait();
-                let _ = tx.send(TimeoutResult::Killed);
-                debug_print!(verbose, "Command killed with KILL signal");
-                return;
-            }
-
-            // Check if we should send TERM signal
-            if should_terminate_clone.load(Ordering::Relaxed) && !term_sent {
+            // The total budget caps the attempt's own timeout, so whichever runs out first applies.
+            let attempt_timeout = match args.total_timeout {
+                Some(total) => timeout_duration.min(total.saturating_sub(budget_start.elapsed())),
+                None => timeout_duration,
+            };
+            if attempt_timeout < timeout_duration {
                 debug_print!(
                     verbose,
-                    "Timeout reached, sending TERM signal to PID {}",
-                    child.id()
+                    "Total timeout leaves {:?} for this attempt",
+                    attempt_timeout
                 );
-
-                #[cfg(unix)]
-                {
-                    // Send TERM signal first
-                    unsafe {
-                        let result = libc::kill(child.id() as i32, libc::SIGTERM);
-                        debug_print!(verbose, "SIGTERM sent, result: {}", result);
-                    }
-                }
-                #[cfg(not(unix))]
-                {
-                    debug_print!(verbose, "Non-Unix system, using kill()");
-                    let _ = child.kill();
-                }
-
-                term_sent = true;
-
-                // If no kill-after, wait briefly then kill and exit
-                if kill_after_duration.is_none() {
-                    debug_print!(
-                        verbose,
-                        "No kill-after specified, waiting 100ms then killing"
-                    );
-                    thread::sleep(Duration::from_millis(100));
-                    let _ = child.kill();
-                    let _ = child.wait();
-                    let _ = tx.send(TimeoutResult::TimedOut);
-                    debug_print!(verbose, "Command terminated after timeout");
-                    return;
-                }
+            }
+            args.set_timeout(&mut command, attempt_timeout);
+            if attempts > 1 {
+                debug_print!(verbose, "Attempt {}/{}", attempt, attempts);
+            }
+            let result = run_attempt(
+                &command,
+                args,
+                syslog.as_deref(),
+                journald.as_deref(),
+                signals.as_ref(),
+            );
+            if let Some(total) = args.total_timeout {
                 debug_print!(
                     verbose,
-                    "Kill-after specified, waiting for KILL signal or process completion"
+                    "Attempt {} took {:?}, {:?} of the {:?} total timeout used",
+                    attempt,
+                    result.outcome.elapsed,
+                    budget_start.elapsed(),
+                    total
                 );
-                // If kill-after is set, continue loop and wait for KILL signal
-            }
-
-            match child.try_wait() {
-                Ok(Some(status)) => {
-                    let exit_code = status.code().unwrap_or(-1);
-                    debug_print!(
-                        verbose,
-                        "Command exited with code: {}, term_sent: {}",
-                        exit_code,
-                        term_sent
-                    );
-
-                    // If we sent TERM and process exited
-                    if term_sent {
-                        // If kill-after was specified, process responded to TERM - this is still a timeout
-                        if kill_after_duration.is_some() {
-                            debug_print!(
-                                verbose,
-                                "Process responded to TERM signal (kill-after was available) - treating as timeout"
-                            );
-                            let _ = tx.send(TimeoutResult::TimedOut);
-                        } else {
-                            debug_print!(
-                                verbose,
-                                "Process exited after TERM signal - treating as timeout"
-                            );
-                            let _ = tx.send(TimeoutResult::TimedOut);
-                        }
-                    } else {
-                        debug_print!(verbose, "Process completed normally");
-                        let _ = tx.send(TimeoutResult::Completed(exit_code));
-                    }
-                    return;
-                }
-                Ok(None) => {
-                    // Command still running
-                    thread::sleep(Duration::from_millis(10));
-                }
-                Err(e) => {
-                    debug_print!(verbose, "Error waiting for child: {}", e);
-                    eprintln!("timeout: error waiting for child process: {}", e);
-                    let _ = tx.send(TimeoutResult::InternalError);
-                    return;
-                }
             }
-        }
-    });
-
-    // Timeout thread for TERM signal
-    let should_terminate_timer = should_terminate.clone();
-    let _timeout_thread = thread::spawn(move || {
-        debug_print!(
-            verbose,
-            "Timeout thread started, sleeping for {}s",
-            timeout_duration.as_secs()
-        );
-        thread::sleep(timeout_duration);
-        debug_print!(verbose, "Timeout reached, setting terminate flag");
-        should_terminate_timer.store(true, Ordering::Relaxed);
-    });
-
-    // Kill-after thread for KILL signal
-    if let Some(kill_duration) = kill_after_duration {
-        let should_kill_timer = should_kill.clone();
-        let total_duration = timeout_duration + kill_duration;
-        let _kill_thread = thread::spawn(move || {
+            let interrupted = signals.as_ref().is_some_and(FatalSignals::caught);
+            let code = result.exit_code(args);
+            if attempt == attempts
+                || interrupted
+                || !should_retry(&args.retry_on, &result.outcome, code)
+            {
+                break result;
+            }
+            let delay = backoff.delay(attempt);
+            if let Some(total) = args.total_timeout
+                && delay >= total.saturating_sub(budget_start.elapsed())
+            {
+                debug_print!(verbose, "Total timeout leaves no time to retry");
+                budget_exhausted = true;
+                break result;
+            }
             debug_print!(
                 verbose,
-                "Kill-after thread started, sleeping for {}s total",
-                total_duration.as_secs()
+                "Attempt {}/{} ended with exit code {}, retrying in {:?}",
+                attempt,
+                attempts,
+                code,
+                delay
             );
-            thread::sleep(total_duration);
-            debug_print!(verbose, "Kill-after timeout reached, setting kill flag");
-            should_kill_timer.store(true, Ordering::Relaxed);
-        });
-    }
+            let waited = match &signals {
+                Some(signals) => signals.sleep(delay),
+                None => {
+                    std::thread::sleep(delay);
+                    true
+                }
+            };
+            if !waited {
+                debug_print!(verbose, "Interrupted while waiting to retry");
+                break result;
+            }
+            attempt += 1;
+        }
+    };
+    debug_print!(
+        verbose,
+        "Outcome: {:?}, signal sent: {:?}, status: {:?}",
+        outcome.reason,
+        outcome.signal_sent,
+        outcome.status
+    );
 
-    debug_print!(verbose, "Waiting for command result...");
-    let result = rx.recv().unwrap_or(TimeoutResult::InternalError);
-    debug_print!(verbose, "Command result received: {:?}", result);
+    // Running out of total budget counts as a timeout, whatever the last attempt came to, and
+    // reaching the end of the --respawn window as success.
+    let window_ended = respawned
+        .as_ref()
+        .is_some_and(|summary| summary.window_ended);
+    let result = if budget_exhausted {
+        TimeoutResult::TimedOut
+    } else if window_ended {
+        TimeoutResult::Completed(0)
+    } else {
+        outcome.result
+    };
+    if let Some(status_fd) = &mut status_fd
+        && let Err(e) = status_fd.report(result)
+    {
+        debug_print!(verbose, "Failed to write status: {}", e);
+    }
 
-    let _ = command_thread.join();
+    let mut report = Report::new(
+        args.program(),
+        args.program_args(),
+        pid,
+        started_at,
+        &outcome,
+        error.as_ref(),
+    );
+    report.attempts = attempt;
+    report.timed_out |= budget_exhausted;
+    report.respawn = respawned;
+    if args.stats {
+        report.usage = outcome.usage.map(UsageSummary::from);
+    }
+    if matched.is_some() {
+        report.reason = "output_matched";
+    } else if let Some((reason, _)) = &ready {
+        report.reason = reason;
+    } else if unhealthy {
+        report.reason = "unhealthy";
+    } else if premature {
+        report.reason = "premature_exit";
+    }
+    if let Some(target) = args.json_target()
+        && let Err(e) = target.write_line(&report.to_json())
+    {
+        debug_print!(verbose, "Failed to write JSON summary: {}", e);
+    }
+    if let Some(path) = &args.result_file
+        && let Err(e) = report.write_file(path)
+    {
+        error!("failed to write result file '{}': {}", path.display(), e);
+    }
+    if let Some(url) = &args.webhook {
+        let webhook = Webhook {
+            url: url.clone(),
+            triggers: args.webhook_on.clone(),
+            headers: args.webhook_headers.clone(),
+            timeout: args.webhook_timeout,
+        };
+        if webhook.applies(&outcome)
+            && let Err(e) = webhook.send(&report.to_json(), verbose)
+        {
+            warning!("webhook to '{}' failed: {}", url, e);
+        }
+    }
 
-    let exit_code = match result {
-        TimeoutResult::Completed(exit_code) => {
+    let mut exit_code = if args.raw_signal_exit {
+        raw_signal_exit_code(&outcome)
+    } else {
+        exit_code(&outcome)
+    };
+    if let TimeoutResult::Completed(code) = outcome.result
+        && args.success_codes.contains(&code)
+    {
+        debug_print!(verbose, "Treating exit code {} as success", code);
+        exit_code = 0;
+    }
+    if premature {
+        exit_code = args.min_runtime_exit_code;
+    }
+    match matched {
+        Some((MatchAction::Kill, _)) => exit_code = args.pattern_exit_code,
+        Some((MatchAction::Finish | MatchAction::Detach, _)) => {
             debug_print!(
                 verbose,
-                "Command completed normally with exit code {}",
-                exit_code
+                "Output matched --until-pattern, treating as success"
             );
-            if (0..=255).contains(&exit_code) {
-                ExitCode::from(exit_code as u8)
-            } else {
-                ExitCode::from(1)
-            }
+            exit_code = 0;
         }
-        TimeoutResult::TimedOut => {
-            debug_print!(verbose, "Command timed out");
-            ExitCode::from(EXIT_TIMEOUT)
-        }
-        TimeoutResult::Killed => {
-            debug_print!(verbose, "Command killed with KILL signal");
-            ExitCode::from(EXIT_KILLED)
-        }
-        TimeoutResult::NotFound => {
-            debug_print!(verbose, "Command not found");
-            ExitCode::from(EXIT_NOT_FOUND)
-        }
-        TimeoutResult::CannotInvoke => {
-            debug_print!(verbose, "Command cannot be invoked");
-            ExitCode::from(EXIT_CANNOT_INVOKE)
-        }
-        TimeoutResult::InternalError => {
-            debug_print!(verbose, "Internal error occurred");
-            ExitCode::from(EXIT_TIMEOUT_FAIL)
+        None => {}
+    }
+    if let Some((reason, what)) = &ready {
+        debug_print!(verbose, "{}: {}, treating as success", reason, what);
+        exit_code = 0;
+    }
+    if budget_exhausted {
+        exit_code = EXIT_TIMEOUT;
+    }
+    if window_ended {
+        exit_code = 0;
+    }
+    // The reports above keep the real outcome; only the exit code is inverted. A command that
+    // could not be run at all keeps its exit code.
+    if args.expect_timeout {
+        if report.timed_out {
+            debug_print!(verbose, "Timed out as expected");
+            exit_code = 0;
+        } else if error.is_none() {
+            match outcome.result {
+                TimeoutResult::Completed(code) => error!(
+                    "expected a timeout, but the command exited with {} after {:.3}s",
+                    code,
+                    outcome.elapsed.as_secs_f64()
+                ),
+                _ => error!(
+                    "expected a timeout, but the command was stopped early: {}",
+                    report.reason
+                ),
+            }
+            exit_code = 1;
         }
+    }
+    let hooks = Hooks {
+        on_success: args.on_success.clone(),
+        on_failure: args.on_failure.clone(),
+        on_exit: args.on_exit.clone(),
+        timeout: hook_timeout,
+        failures_fatal: args.hook_failures_fatal,
     };
+    if hooks
+        .run(&outcome, pid, exit_code, &command_line, verbose)
+        .is_err()
+    {
+        exit_code = EXIT_TIMEOUT_FAIL;
+    }
+
+    if args.notify {
+        desktop::notify(
+            &SystemNotifier,
+            &args.notify_on,
+            args.program(),
+            &outcome,
+            exit_code,
+            verbose,
+        );
+    }
+
+    if let Some(path) = &args.metrics_file {
+        let metrics = metrics::render(&outcome, exit_code, &args.metrics_labels, SystemTime::now());
+        if let Err(e) = write_atomic(path, metrics.as_bytes()) {
+            warning!("cannot write metrics file '{}': {}", path.display(), e);
+        }
+    }
+
+    if let Some(address) = &args.statsd {
+        let statsd = Statsd {
+            address: address.clone(),
+            prefix: args.statsd_prefix.clone(),
+            tags: args.statsd_tags.clone(),
+            dogstatsd: args.dogstatsd,
+        };
+        statsd.send(&outcome, exit_code, verbose);
+    }
 
-    debug_print!(verbose, "Exiting with code: {:?}", exit_code);
+    if args.stats {
+        eprintln!("{}", stats::stats_block(&report, outcome.usage));
+    }
+    if let Some(format) = args.time {
+        eprintln!("{}", format.block(outcome.elapsed, outcome.usage));
+    }
+    if args.report {
+        eprintln!("{}", report.summary_line(exit_code));
+    }
+    debug_print!(verbose, "Exiting with code: {}", exit_code);
     exit_code
 }
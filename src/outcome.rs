@@ -0,0 +1,234 @@
+//! The result of running a command under a timeout.
+
+use crate::error::TimeoutError;
+use crate::signal::Signal;
+use std::io;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// How a supervised command finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutResult {
+    /// The command exited on its own with the given exit code, or -1 if it
+    /// was terminated by a signal we did not send.
+    Completed(i32),
+    /// The timeout expired and the command was terminated.
+    TimedOut,
+    /// The command was killed with `SIGKILL`.
+    Killed,
+    /// The command was signalled early through [`TimeoutHandle::cancel`](crate::TimeoutHandle::cancel).
+    Cancelled,
+    /// Supervision stopped through [`TimeoutHandle::detach`](crate::TimeoutHandle::detach) and
+    /// the command was left running.
+    Detached,
+    /// The command could not be found.
+    NotFound,
+    /// The command was found but could not be invoked.
+    CannotInvoke,
+    /// Supervising the command failed.
+    InternalError,
+}
+
+/// Why supervision of a command ended.
+///
+/// This is the detailed form that [`TimeoutOutcome::result`] is derived from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutReason {
+    /// The command exited on its own before the timeout expired.
+    CompletedInTime,
+    /// The command exited after the timeout expired and it was sent the timeout signal.
+    TermExit,
+    /// The command exited after being signalled early through
+    /// [`TimeoutHandle::cancel`](crate::TimeoutHandle::cancel).
+    Cancelled,
+    /// The command had to be killed with `SIGKILL`.
+    Killed,
+    /// Supervision stopped and the command was left running.
+    Detached,
+    /// The command could not be started.
+    SpawnFailed {
+        /// What kind of error starting the command produced.
+        kind: io::ErrorKind,
+    },
+    /// Waiting for the command failed.
+    SupervisionFailed {
+        /// What kind of error waiting produced.
+        kind: io::ErrorKind,
+    },
+}
+
+impl TimeoutReason {
+    /// The coarse classification of this reason, given the command's exit status.
+    pub fn result(self, status: Option<ExitStatus>) -> TimeoutResult {
+        match self {
+            TimeoutReason::CompletedInTime => {
+                TimeoutResult::Completed(status.and_then(|status| status.code()).unwrap_or(-1))
+            }
+            TimeoutReason::TermExit => TimeoutResult::TimedOut,
+            TimeoutReason::Cancelled => TimeoutResult::Cancelled,
+            TimeoutReason::Killed => TimeoutResult::Killed,
+            TimeoutReason::Detached => TimeoutResult::Detached,
+            TimeoutReason::SpawnFailed {
+                kind: io::ErrorKind::NotFound,
+            } => TimeoutResult::NotFound,
+            TimeoutReason::SpawnFailed {
+                kind: io::ErrorKind::PermissionDenied | io::ErrorKind::IsADirectory,
+            } => TimeoutResult::CannotInvoke,
+            TimeoutReason::SpawnFailed {
+                kind: io::ErrorKind::TimedOut,
+            } => TimeoutResult::TimedOut,
+            TimeoutReason::SpawnFailed { .. } | TimeoutReason::SupervisionFailed { .. } => {
+                TimeoutResult::InternalError
+            }
+        }
+    }
+}
+
+/// A signal that was sent to the command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SentSignal {
+    /// Which signal was sent.
+    pub signal: Signal,
+    /// Time elapsed between spawning the command and sending the signal.
+    pub elapsed: Duration,
+}
+
+/// What a command used of the machine, as reported when it was reaped. It covers the command
+/// and those of its descendants that were waited for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// CPU time spent running the command's own code.
+    pub user_time: Duration,
+    /// CPU time the kernel spent working for the command.
+    pub system_time: Duration,
+    /// The most memory resident at once, in bytes.
+    pub max_rss: u64,
+    /// How often the command gave up the CPU to wait, e.g. for I/O.
+    pub voluntary_switches: u64,
+    /// How often the command was made to give up the CPU.
+    pub involuntary_switches: u64,
+}
+
+impl ResourceUsage {
+    #[cfg(unix)]
+    pub(crate) fn from_rusage(usage: &libc::rusage) -> Self {
+        let time = |time: libc::timeval| {
+            Duration::from_secs(time.tv_sec.max(0) as u64)
+                + Duration::from_micros(time.tv_usec.max(0) as u64)
+        };
+        // Linux and the BSDs give the maximum in KiB, Apple's systems in bytes.
+        let rss_unit = if cfg!(target_vendor = "apple") {
+            1
+        } else {
+            1024
+        };
+        Self {
+            user_time: time(usage.ru_utime),
+            system_time: time(usage.ru_stime),
+            max_rss: (usage.ru_maxrss.max(0) as u64) * rss_unit,
+            voluntary_switches: usage.ru_nvcsw.max(0) as u64,
+            involuntary_switches: usage.ru_nivcsw.max(0) as u64,
+        }
+    }
+}
+
+/// The outcome of a supervised run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeoutOutcome {
+    /// How the command finished.
+    pub result: TimeoutResult,
+    /// Why supervision ended, in more detail than [`result`](Self::result).
+    pub reason: TimeoutReason,
+    /// Time elapsed between spawning the command and it being reaped.
+    pub elapsed: Duration,
+    /// The first signal sent to the command, if any.
+    pub signal_sent: Option<SentSignal>,
+    /// The command's raw wait status, if it was reaped.
+    pub status: Option<ExitStatus>,
+    /// Output written to stdout, when captured with [`CaptureMode::Piped`](crate::CaptureMode).
+    pub stdout: Vec<u8>,
+    /// Output written to stderr, when captured with [`CaptureMode::Piped`](crate::CaptureMode).
+    pub stderr: Vec<u8>,
+    /// What the command used, if it was reaped on Unix by a command started with
+    /// [`TimeoutCommand::spawn`](crate::TimeoutCommand::spawn).
+    pub usage: Option<ResourceUsage>,
+}
+
+impl TimeoutOutcome {
+    /// An outcome for `reason` with no exit status, signal or output.
+    pub fn new(reason: TimeoutReason, elapsed: Duration) -> Self {
+        Self {
+            result: reason.result(None),
+            reason,
+            elapsed,
+            signal_sent: None,
+            status: None,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            usage: None,
+        }
+    }
+
+    /// The outcome of a command that could not be started, or whose supervision failed,
+    /// because of `error`.
+    pub fn failed(error: &TimeoutError, elapsed: Duration) -> Self {
+        let reason = match error {
+            TimeoutError::WaitFailed(_) => TimeoutReason::SupervisionFailed { kind: error.kind() },
+            _ => TimeoutReason::SpawnFailed { kind: error.kind() },
+        };
+        Self::new(reason, elapsed)
+    }
+
+    /// Record the command's exit status, updating [`result`](Self::result) to match.
+    pub fn with_status(mut self, status: ExitStatus) -> Self {
+        self.status = Some(status);
+        self.result = self.reason.result(self.status);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_failures_map_to_coarse_results() {
+        let kinds = [
+            (io::ErrorKind::NotFound, TimeoutResult::NotFound),
+            (io::ErrorKind::PermissionDenied, TimeoutResult::CannotInvoke),
+            (io::ErrorKind::IsADirectory, TimeoutResult::CannotInvoke),
+            (io::ErrorKind::TimedOut, TimeoutResult::TimedOut),
+            (io::ErrorKind::InvalidInput, TimeoutResult::InternalError),
+        ];
+        for (kind, result) in kinds {
+            let error = TimeoutError::from_spawn("cmd", io::Error::from(kind));
+            let outcome = TimeoutOutcome::failed(&error, Duration::ZERO);
+            assert_eq!(outcome.reason, TimeoutReason::SpawnFailed { kind });
+            assert_eq!(outcome.result, result);
+        }
+        let error = TimeoutError::WaitFailed(io::Error::from(io::ErrorKind::Interrupted));
+        assert_eq!(
+            TimeoutOutcome::failed(&error, Duration::ZERO).reason,
+            TimeoutReason::SupervisionFailed {
+                kind: io::ErrorKind::Interrupted
+            }
+        );
+    }
+
+    #[test]
+    fn test_completed_without_status_reports_minus_one() {
+        let outcome = TimeoutOutcome::new(TimeoutReason::CompletedInTime, Duration::ZERO);
+        assert_eq!(outcome.result, TimeoutResult::Completed(-1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_with_status_updates_result() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let outcome = TimeoutOutcome::new(TimeoutReason::CompletedInTime, Duration::ZERO)
+            .with_status(ExitStatus::from_raw(3 << 8));
+        assert_eq!(outcome.result, TimeoutResult::Completed(3));
+        assert_eq!(outcome.status.and_then(|status| status.code()), Some(3));
+    }
+}
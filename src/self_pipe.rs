@@ -0,0 +1,144 @@
+//! Handing signals over to a thread. A signal handler can only do async-signal-safe work, so it
+//! writes a byte to a pipe and a thread reading the other end does the rest.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{FromRawFd, IntoRawFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+
+/// A pipe from a signal handler to the thread that acts on it, meant to live in a `static` the
+/// handler can reach.
+#[derive(Debug)]
+pub struct SelfPipe {
+    /// The write end, or -1 until [`SelfPipe::open`] succeeds.
+    write_fd: AtomicI32,
+}
+
+impl SelfPipe {
+    pub const fn new() -> Self {
+        Self {
+            write_fd: AtomicI32::new(-1),
+        }
+    }
+
+    /// Open the pipe and start a thread named `name` that calls `on_wake` with whatever
+    /// [`SelfPipe::wake`] writes. Commands inherit neither end, and the write end never blocks:
+    /// while the pipe is full, further bytes are dropped.
+    pub fn open(
+        &self,
+        name: &str,
+        mut on_wake: impl FnMut(&[u8]) + Send + 'static,
+    ) -> io::Result<()> {
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for the two descriptors pipe(2) fills in.
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: both descriptors were just returned by pipe(2) and are owned by the files.
+        let (mut read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        // SAFETY: as above; fcntl only changes the descriptors' flags.
+        unsafe {
+            libc::fcntl(fds[0], libc::F_SETFD, libc::FD_CLOEXEC);
+            libc::fcntl(fds[1], libc::F_SETFD, libc::FD_CLOEXEC);
+            libc::fcntl(fds[1], libc::F_SETFL, libc::O_NONBLOCK);
+        }
+
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let mut bytes = [0u8; 64];
+                loop {
+                    match read.read(&mut bytes) {
+                        Ok(0) => break,
+                        Ok(n) => on_wake(&bytes[..n]),
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(_) => break,
+                    }
+                }
+            })?;
+        // The handler writes to the pipe for the rest of the process's life.
+        self.write_fd.store(write.into_raw_fd(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Whether [`SelfPipe::open`] has succeeded.
+    pub fn is_open(&self) -> bool {
+        self.write_fd.load(Ordering::Relaxed) >= 0
+    }
+
+    /// Write `byte` to the pipe, if it is open. Safe to call from a signal handler, and leaves
+    /// `errno` as it found it.
+    pub fn wake(&self, byte: u8) {
+        let fd = self.write_fd.load(Ordering::Relaxed);
+        if fd < 0 {
+            return;
+        }
+        let errno = errno_location();
+        // SAFETY: `errno` is either null or this thread's errno, which write(2) may change.
+        let saved = (!errno.is_null()).then(|| unsafe { *errno });
+        // SAFETY: write(2) is async-signal-safe, and `fd` stays open once stored.
+        unsafe { libc::write(fd, [byte].as_ptr().cast(), 1) };
+        if let Some(saved) = saved {
+            // SAFETY: as above.
+            unsafe { *errno = saved };
+        }
+    }
+}
+
+impl Default for SelfPipe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where this thread's `errno` lives, so a handler can leave it as it found it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn errno_location() -> *mut libc::c_int {
+    // SAFETY: always safe to call.
+    unsafe { libc::__errno_location() }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+fn errno_location() -> *mut libc::c_int {
+    // SAFETY: always safe to call.
+    unsafe { libc::__error() }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+)))]
+fn errno_location() -> *mut libc::c_int {
+    std::ptr::null_mut()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_wake_reaches_the_thread_and_a_full_pipe_does_not_block() {
+        static PIPE: SelfPipe = SelfPipe::new();
+        assert!(!PIPE.is_open());
+        let (tx, rx) = mpsc::channel();
+        PIPE.open("test-self-pipe", move |bytes| {
+            let _ = tx.send(bytes.to_vec());
+            thread::sleep(Duration::from_secs(60));
+        })
+        .unwrap();
+        assert!(PIPE.is_open());
+
+        PIPE.wake(7);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), [7]);
+        // The thread is stuck, so the pipe fills up; the writes must still return.
+        for _ in 0..1_000_000 {
+            PIPE.wake(0);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Waking supervisors when a child process exits, instead of having them poll for it.
+//!
+//! A `SIGCHLD` handler writes a byte to a pipe, and a thread reading the pipe bumps
+//! [`ControlState::child_events`](crate::supervisor::ControlState::child_events) on every
+//! watched [`Control`], waking any supervisor waiting on it. A handler installed before this
+//! one keeps being called. If `SIGCHLD` is ignored, children are reaped automatically and
+//! installing a handler would change that for the whole process, so nothing is installed and
+//! supervisors keep polling.
+
+use crate::self_pipe::SelfPipe;
+use crate::supervisor::Control;
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+/// The pipe from the handler to the thread that wakes the supervisors.
+static WAKE: SelfPipe = SelfPipe::new();
+
+/// The handler that was installed before ours.
+static PREVIOUS: OnceLock<libc::sigaction> = OnceLock::new();
+
+/// Whether the handler is installed, decided the first time it is needed.
+static INSTALLED: OnceLock<bool> = OnceLock::new();
+
+/// The controls to wake when a child exits.
+static WATCHED: Mutex<Vec<Weak<Control>>> = Mutex::new(Vec::new());
+
+/// Wake `control` whenever a child process exits, from now until it is dropped. Returns
+/// `false` if `SIGCHLD` cannot be caught, in which case the caller has to poll.
+pub(crate) fn watch(control: &Arc<Control>) -> bool {
+    if !*INSTALLED.get_or_init(|| install().is_ok()) {
+        return false;
+    }
+    let mut watched = WATCHED.lock().unwrap_or_else(|e| e.into_inner());
+    watched.retain(|control| control.strong_count() > 0);
+    watched.push(Arc::downgrade(control));
+    true
+}
+
+fn install() -> io::Result<()> {
+    // SAFETY: an all-zero sigaction is a valid value for sigaction(2) to fill in.
+    let mut previous: libc::sigaction = unsafe { std::mem::zeroed() };
+    // SAFETY: a null new action only reads the current one into `previous`.
+    if unsafe { libc::sigaction(libc::SIGCHLD, std::ptr::null(), &mut previous) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if previous.sa_sigaction == libc::SIG_IGN {
+        return Err(io::Error::other("SIGCHLD is ignored"));
+    }
+    let _ = PREVIOUS.set(previous);
+
+    // One pending byte is enough to wake everything, so dropping the rest is fine.
+    WAKE.open("timeout-sigchld", |_| wake_all())?;
+
+    // SAFETY: an all-zero sigaction is valid; the handler is filled in below.
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = on_sigchld as *const () as libc::sighandler_t;
+    action.sa_flags =
+        libc::SA_SIGINFO | libc::SA_RESTART | (previous.sa_flags & libc::SA_NOCLDSTOP);
+    // SAFETY: the handler only wakes the pipe and calls the previous handler.
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+    if unsafe { libc::sigaction(libc::SIGCHLD, &action, std::ptr::null_mut()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn wake_all() {
+    let watched = WATCHED.lock().unwrap_or_else(|e| e.into_inner());
+    for control in watched.iter().filter_map(Weak::upgrade) {
+        control.child_exited();
+    }
+}
+
+extern "C" fn on_sigchld(
+    signum: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    WAKE.wake(0);
+
+    let Some(previous) = PREVIOUS.get() else {
+        return;
+    };
+    let handler = previous.sa_sigaction;
+    if handler == libc::SIG_DFL || handler == libc::SIG_IGN {
+        return;
+    }
+    // SAFETY: the previous handler was installed for SIGCHLD with these flags, so it has the
+    // signature they call for.
+    unsafe {
+        if previous.sa_flags & libc::SA_SIGINFO != 0 {
+            let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                std::mem::transmute(handler);
+            handler(signum, info, context);
+        } else {
+            let handler: extern "C" fn(libc::c_int) = std::mem::transmute(handler);
+            handler(signum);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+    use std::process::Command;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_child_exit_wakes_watched_control() {
+        let control = Arc::new(Control::new(Arc::new(SystemClock), None, None));
+        assert!(watch(&control));
+        let mut child = Command::new("true").spawn().unwrap();
+        let start = Instant::now();
+        while control.snapshot().child_events == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5), "no SIGCHLD seen");
+            thread::sleep(Duration::from_millis(1));
+        }
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! Signals that can be delivered to a supervised command.
+
+use std::fmt;
+use std::io;
+use std::process::Child;
+
+/// A signal sent to the command when its timeout expires.
+///
+/// On non-Unix platforms there is no signal delivery, so every signal is
+/// approximated by terminating the process.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    /// Hangup (`SIGHUP`)
+    Hup,
+    /// Interrupt (`SIGINT`)
+    Int,
+    /// Quit (`SIGQUIT`)
+    Quit,
+    /// Kill (`SIGKILL`), cannot be caught or ignored
+    Kill,
+    /// User-defined signal 1 (`SIGUSR1`)
+    Usr1,
+    /// User-defined signal 2 (`SIGUSR2`)
+    Usr2,
+    /// Alarm clock (`SIGALRM`)
+    Alrm,
+    /// Termination (`SIGTERM`), the default
+    #[default]
+    Term,
+}
+
+impl Signal {
+    /// The signal name without the `SIG` prefix, e.g. `TERM`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::Hup => "HUP",
+            Signal::Int => "INT",
+            Signal::Quit => "QUIT",
+            Signal::Kill => "KILL",
+            Signal::Usr1 => "USR1",
+            Signal::Usr2 => "USR2",
+            Signal::Alrm => "ALRM",
+            Signal::Term => "TERM",
+        }
+    }
+
+    /// The platform signal number.
+    #[cfg(unix)]
+    pub fn as_raw(self) -> libc::c_int {
+        match self {
+            Signal::Hup => libc::SIGHUP,
+            Signal::Int => libc::SIGINT,
+            Signal::Quit => libc::SIGQUIT,
+            Signal::Kill => libc::SIGKILL,
+            Signal::Usr1 => libc::SIGUSR1,
+            Signal::Usr2 => libc::SIGUSR2,
+            Signal::Alrm => libc::SIGALRM,
+            Signal::Term => libc::SIGTERM,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SIG{}", self.name())
+    }
+}
+
+/// Deliver `signal` to `child`.
+pub(crate) fn send(child: &mut Child, signal: Signal) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        send_to_pid(child.id(), signal)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = signal;
+        child.kill()
+    }
+}
+
+/// Deliver `signal` to `child`, or with `group` to every process in the process group it leads.
+///
+/// On Windows a process group started with [`CREATE_NEW_PROCESS_GROUP`] is asked to stop with
+/// `CTRL_BREAK_EVENT` instead of being terminated, for any signal but `KILL`, so that a command
+/// can still outlive it and be killed later by `--kill-after`.
+pub(crate) fn send_to(child: &mut Child, signal: Signal, group: bool) -> io::Result<()> {
+    #[cfg(unix)]
+    if group {
+        // SAFETY: killpg only sends a signal.
+        let result = unsafe { libc::killpg(child.id() as libc::pid_t, signal.as_raw()) };
+        return if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        };
+    }
+    #[cfg(windows)]
+    if group && signal != Signal::Kill && request_stop(child.id()).is_ok() {
+        return Ok(());
+    }
+    let _ = group;
+    send(child, signal)
+}
+
+/// Starts the command in a process group of its own, which console control events can be sent
+/// to without reaching timeout itself.
+#[cfg(windows)]
+pub(crate) const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+/// Send `CTRL_BREAK_EVENT` to the process group `pgid`, the closest Windows has to `SIGTERM`:
+/// console programs stop on it by default, but can handle it and carry on.
+#[cfg(windows)]
+pub(crate) fn request_stop(pgid: u32) -> io::Result<()> {
+    const CTRL_BREAK_EVENT: u32 = 1;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GenerateConsoleCtrlEvent(ctrl_event: u32, process_group_id: u32) -> i32;
+    }
+
+    // SAFETY: GenerateConsoleCtrlEvent only sends an event to the processes in the group.
+    if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pgid) } != 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Whether any process is left in the process group `pgid`.
+///
+/// Processes that have exited but not yet been reaped by their new parent still count as group
+/// members, so on Linux the group is only considered running while a member is not a zombie.
+#[cfg(unix)]
+pub(crate) fn group_exists(pgid: u32) -> bool {
+    // SAFETY: signal 0 only checks whether the processes exist.
+    let result = unsafe { libc::killpg(pgid as libc::pid_t, 0) };
+    let exists = result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    #[cfg(target_os = "linux")]
+    if exists && let Some(running) = group_has_live_member(pgid) {
+        return running;
+    }
+    exists
+}
+
+/// Whether `/proc` lists a process in the group `pgid` that has not exited, or `None` if
+/// `/proc` cannot be read.
+#[cfg(target_os = "linux")]
+fn group_has_live_member(pgid: u32) -> Option<bool> {
+    let entries = std::fs::read_dir("/proc").ok()?;
+    Some(entries.flatten().any(|entry| {
+        // Fields after the parenthesised command name: state, parent PID, process group.
+        let stat = std::fs::read_to_string(entry.path().join("stat")).unwrap_or_default();
+        let mut fields = stat
+            .rsplit_once(')')
+            .map_or("", |(_, rest)| rest)
+            .split_whitespace();
+        let state = fields.next();
+        let pgrp = fields.nth(1).and_then(|pgrp| pgrp.parse::<u32>().ok());
+        pgrp == Some(pgid) && state != Some("Z")
+    }))
+}
+
+/// Process groups are a Unix concept.
+#[cfg(not(unix))]
+pub(crate) fn group_exists(_pgid: u32) -> bool {
+    false
+}
+
+/// Deliver `signal` to the process `pid`.
+#[cfg(unix)]
+pub(crate) fn send_to_pid(pid: u32, signal: Signal) -> io::Result<()> {
+    let result = unsafe { libc::kill(pid as libc::pid_t, signal.as_raw()) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
@@ -0,0 +1,1028 @@
+//! Background supervision of a spawned command.
+
+use crate::capture::StreamCapture;
+use crate::clock::Clock;
+use crate::command::{TimeoutOptions, exit_reason};
+use crate::events::{Delivery, Event, EventDispatcher};
+use crate::outcome::{ResourceUsage, SentSignal, TimeoutOutcome, TimeoutReason};
+use crate::signal::{self, Signal};
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Supervision state shared between the supervisor, the timer thread and any handles.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ControlState {
+    /// When the timer should raise `terminate`.
+    pub(crate) term_deadline: Option<Instant>,
+    /// When the timer should raise `kill`.
+    pub(crate) kill_deadline: Option<Instant>,
+    /// The deadline the timer raised `terminate` for and when it actually did, so the delay
+    /// before the signal went out can be reported.
+    pub(crate) term_fired: Option<(Instant, Instant)>,
+    /// Send the timeout signal.
+    pub(crate) terminate: bool,
+    /// Send `SIGKILL`.
+    pub(crate) kill: bool,
+    /// `kill` was raised by the timer at the kill-after deadline, so the configured
+    /// [`kill_signal`](TimeoutOptions::kill_signal) is sent rather than `SIGKILL`.
+    pub(crate) escalated: bool,
+    /// `terminate` was raised by [`TimeoutHandle::cancel`] rather than the timer.
+    pub(crate) cancelled: bool,
+    /// Stop supervising and leave the command running.
+    pub(crate) detached: bool,
+    /// The command has been reaped.
+    pub(crate) finished: bool,
+    /// Bumped whenever a child of this process exits, so the supervisor can wait for that
+    /// rather than poll.
+    pub(crate) child_events: u64,
+    /// When the first child exit since the supervisor last found the command still running
+    /// was signalled, so a command that beat the deadline can be told from one that did not.
+    pub(crate) child_event_at: Option<Instant>,
+}
+
+/// [`ControlState`] plus a condition variable, so the timer can sleep until the next deadline
+/// and still wake immediately when the command finishes or a handle changes the plan. The
+/// deadlines are measured against `clock`.
+#[derive(Debug)]
+pub(crate) struct Control {
+    state: Mutex<ControlState>,
+    changed: Condvar,
+    clock: Arc<dyn Clock>,
+}
+
+impl Control {
+    pub(crate) fn new(
+        clock: Arc<dyn Clock>,
+        term_deadline: Option<Instant>,
+        kill_deadline: Option<Instant>,
+    ) -> Self {
+        Self {
+            state: Mutex::new(ControlState {
+                term_deadline,
+                kill_deadline,
+                ..Default::default()
+            }),
+            changed: Condvar::new(),
+            clock,
+        }
+    }
+
+    /// The current time on the clock the deadlines are measured against.
+    pub(crate) fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ControlState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn update(&self, f: impl FnOnce(&mut ControlState)) {
+        f(&mut self.lock());
+        self.changed.notify_all();
+    }
+
+    pub(crate) fn snapshot(&self) -> ControlState {
+        *self.lock()
+    }
+
+    /// Bump `child_events`, for a child of this process that has just exited.
+    pub(crate) fn child_exited(&self) {
+        let now = self.now();
+        self.update(|state| {
+            state.child_events = state.child_events.wrapping_add(1);
+            state.child_event_at.get_or_insert(now);
+        });
+    }
+
+    /// Forget the child exits in `seen`, found not to have been the command's, and return the
+    /// state to wait on from here. Anything that has changed since `seen` is kept for the
+    /// caller to see first.
+    fn forget_child_exits(&self, seen: &ControlState) -> ControlState {
+        let mut state = self.lock();
+        if *state != *seen {
+            return *seen;
+        }
+        state.child_event_at = None;
+        *state
+    }
+
+    /// Wait until the state is no longer `seen`, or at most `timeout`.
+    fn wait_for_change(&self, seen: &ControlState, timeout: Duration) {
+        let _ = self
+            .changed
+            .wait_timeout_while(self.lock(), timeout, |state| state == seen)
+            .unwrap_or_else(|e| e.into_inner());
+    }
+
+    /// Raise `terminate` and `kill` as their deadlines pass, until the command is reaped or
+    /// supervision is detached.
+    pub(crate) fn run_timer(&self, verbose: bool) {
+        let mut state = self.lock();
+        debug_print!(
+            verbose,
+            "Timer thread started, terminate deadline: {:?}, kill deadline: {:?}",
+            state.term_deadline,
+            state.kill_deadline
+        );
+        loop {
+            if state.finished || state.detached {
+                return;
+            }
+            let now = self.clock.now();
+            if let Some(deadline) = state.term_deadline.filter(|&deadline| now >= deadline) {
+                debug_print!(verbose, "Timeout reached, setting terminate flag");
+                state.term_deadline = None;
+                state.term_fired = Some((deadline, now));
+                state.terminate = true;
+                self.changed.notify_all();
+            }
+            if state.kill_deadline.is_some_and(|deadline| now >= deadline) {
+                debug_print!(verbose, "Kill-after timeout reached, setting kill flag");
+                state.kill_deadline = None;
+                state.kill = true;
+                state.escalated = true;
+                self.changed.notify_all();
+            }
+            let next = match (state.term_deadline, state.kill_deadline) {
+                (Some(term), Some(kill)) => Some(term.min(kill)),
+                (deadline, None) | (None, deadline) => deadline,
+            };
+            state = match next.and_then(|deadline| self.clock.sleep_for(deadline)) {
+                Some(wait) => {
+                    self.changed
+                        .wait_timeout(state, wait)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self.changed.wait(state).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+}
+
+/// A cloneable handle for controlling a supervised command from other threads.
+///
+/// ```
+/// use std::time::Duration;
+/// use timeout_cli::{TimeoutCommand, TimeoutResult};
+///
+/// let child = TimeoutCommand::new("sleep")
+///     .arg("5")
+///     .timeout(Duration::from_secs(60))
+///     .spawn()?;
+/// let handle = child.handle();
+/// std::thread::spawn(move || handle.cancel());
+/// assert_eq!(child.wait()?.result, TimeoutResult::Cancelled);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TimeoutHandle {
+    control: Arc<Control>,
+}
+
+impl TimeoutHandle {
+    pub(crate) fn new(control: Arc<Control>) -> Self {
+        Self { control }
+    }
+
+    /// Send the configured signal now instead of at the deadline, then escalate exactly as a
+    /// timeout would. The outcome is [`TimeoutResult::Cancelled`](crate::TimeoutResult::Cancelled) unless the command has to be
+    /// killed or has already been signalled.
+    pub fn cancel(&self) {
+        self.control.update(|state| {
+            if state.terminate || state.finished {
+                return;
+            }
+            state.terminate = true;
+            state.cancelled = true;
+            state.term_deadline = None;
+        });
+    }
+
+    /// Stop supervising and let the command keep running. The outcome is
+    /// [`TimeoutResult::Detached`](crate::TimeoutResult::Detached).
+    pub fn detach(&self) {
+        self.control.update(|state| state.detached = true);
+    }
+
+    /// Kill the command immediately. The outcome is [`TimeoutResult::Killed`](crate::TimeoutResult::Killed) unless the command
+    /// had already exited.
+    pub fn kill_now(&self) {
+        self.control.update(|state| state.kill = true);
+    }
+
+    /// Whether the command is still running under supervision.
+    pub fn is_running(&self) -> bool {
+        let state = self.control.snapshot();
+        !state.finished && !state.detached
+    }
+}
+
+/// How often to check anyway when `SIGCHLD` will say so, in case another handler replaced ours
+/// without passing it on.
+#[cfg(unix)]
+const SIGCHLD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Waits for a spawned child, escalating signals as the timer raises its flags.
+pub(crate) struct Supervisor {
+    pub(crate) child: Child,
+    pub(crate) options: TimeoutOptions,
+    pub(crate) start: Instant,
+    pub(crate) control: Arc<Control>,
+    pub(crate) events: EventDispatcher,
+    pub(crate) stdout: Option<StreamCapture>,
+    pub(crate) stderr: Option<StreamCapture>,
+    /// The command's exit status once it has been reaped, with its resource usage where that
+    /// can be had.
+    pub(crate) reaped: Option<(ExitStatus, Option<ResourceUsage>)>,
+    /// When the command was reaped, on the clock the deadlines are measured against.
+    pub(crate) reaped_at: Option<Instant>,
+}
+
+impl Supervisor {
+    pub(crate) fn run(mut self) -> io::Result<TimeoutOutcome> {
+        let verbose = self.options.verbose;
+        let mut term_reason = None;
+        let mut signal_sent = None;
+        let mut draining = false;
+        #[cfg(unix)]
+        let poll_interval = if crate::sigchld::watch(&self.control) {
+            SIGCHLD_POLL_INTERVAL.max(self.options.timings.poll_interval)
+        } else {
+            self.options.timings.poll_interval
+        };
+        #[cfg(not(unix))]
+        let poll_interval = self.options.timings.poll_interval;
+
+        loop {
+            let state = self.control.snapshot();
+
+            if state.detached {
+                debug_print!(verbose, "Detached from PID {}", self.child.id());
+                return Ok(self.outcome(TimeoutReason::Detached, None, signal_sent));
+            }
+
+            // Check if we should send KILL signal
+            if state.kill {
+                let signal = if state.escalated {
+                    self.options.kill_signal.unwrap_or(Signal::Kill)
+                } else {
+                    Signal::Kill
+                };
+                let elapsed = self.start.elapsed();
+                let status = self.kill(signal);
+                let signal_sent = signal_sent.or(Some(SentSignal { signal, elapsed }));
+                return Ok(self.outcome(TimeoutReason::Killed, status, signal_sent));
+            }
+
+            // Check if we should send the timeout signal
+            if state.terminate && term_reason.is_none() {
+                if let Some((deadline, _)) = state.term_fired
+                    && let Some(outcome) = self.exited_unsignalled(deadline)
+                {
+                    return Ok(outcome);
+                }
+                let reason = if state.cancelled {
+                    TimeoutReason::Cancelled
+                } else {
+                    TimeoutReason::TermExit
+                };
+                if let (TimeoutReason::TermExit, Some(hook)) = (reason, &self.options.on_timeout) {
+                    debug_print!(verbose, "Running timeout hook before signalling");
+                    hook.call(self.child.id(), self.start.elapsed());
+                }
+                let sent = signal::send_to(
+                    &mut self.child,
+                    self.options.signal,
+                    self.options.signals_group(),
+                );
+                let sent_at = self.control.now();
+                // The kill-after period runs from when the signal actually went out, however
+                // late a slow hook or a busy machine made that.
+                if let Some(kill_after) = self.options.kill_after {
+                    self.control
+                        .update(|state| state.kill_deadline = Some(sent_at + kill_after));
+                }
+                let elapsed = sent_at - self.start;
+                self.events
+                    .emit(Event::Signal(self.options.signal, elapsed));
+                self.events.emit(Event::Delivery(Delivery::new(
+                    self.options.signal,
+                    self.options.signals_group(),
+                    &sent,
+                )));
+                debug_print!(verbose, "{} sent, result: {:?}", self.options.signal, sent);
+                if let (TimeoutReason::TermExit, Some((deadline, fired))) =
+                    (reason, self.control.snapshot().term_fired)
+                {
+                    debug_print!(
+                        verbose,
+                        "{} sent {:?} after the deadline: the timer woke {:?} late and sending took {:?} more",
+                        self.options.signal,
+                        sent_at - deadline,
+                        fired - deadline,
+                        sent_at - fired
+                    );
+                }
+
+                term_reason = Some(reason);
+                signal_sent = Some(SentSignal {
+                    signal: self.options.signal,
+                    elapsed,
+                });
+
+                // If no kill-after, wait briefly then kill and exit
+                if self.options.kill_after.is_none() {
+                    debug_print!(
+                        verbose,
+                        "No kill-after specified, waiting {:?} then killing",
+                        self.options.timings.term_grace
+                    );
+                    thread::sleep(self.options.timings.term_grace);
+                    if matches!(self.try_wait(), Ok(None)) || self.group_running() {
+                        self.events.emit(Event::Kill(self.start.elapsed()));
+                        self.send_kill(Signal::Kill);
+                    }
+                    let status = self.wait().ok();
+                    debug_print!(verbose, "Command terminated after timeout");
+                    return Ok(self.outcome(reason, status, signal_sent));
+                }
+                debug_print!(
+                    verbose,
+                    "Kill-after specified, waiting for KILL signal or process completion"
+                );
+                // If kill-after is set, continue loop and wait for KILL signal
+            }
+
+            match self.try_wait() {
+                Ok(Some(status)) if self.group_running() => {
+                    if !draining {
+                        debug_print!(
+                            verbose,
+                            "Command exited with status: {}, waiting for the rest of its process group",
+                            status
+                        );
+                        draining = true;
+                    }
+                    // The rest of the group are not our children, so nothing signals their exit.
+                    thread::sleep(self.options.timings.poll_interval);
+                }
+                Ok(Some(status)) => {
+                    debug_print!(
+                        verbose,
+                        "Command exited with status: {}, term_sent: {}",
+                        status,
+                        term_reason.is_some()
+                    );
+
+                    // If we sent the timeout signal and the process exited, the signal ended it
+                    if let Some(reason) = term_reason {
+                        debug_print!(
+                            verbose,
+                            "Process exited after timeout signal - treating as {:?}",
+                            reason
+                        );
+                        return Ok(self.outcome(reason, Some(status), signal_sent));
+                    }
+                    let reason = exit_reason(status, &self.options);
+                    return Ok(self.outcome(reason, Some(status), signal_sent));
+                }
+                Ok(None) => {
+                    // Command still running: wait for it to exit or the timer to act
+                    let seen = self.control.forget_child_exits(&state);
+                    self.control.wait_for_change(&seen, poll_interval);
+                }
+                Err(e) => {
+                    debug_print!(verbose, "Error waiting for child: {}", e);
+                    self.control.update(|state| state.finished = true);
+                    self.events.finish(self.options.timings.drain_grace);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// The outcome of a command that exited on its own before `deadline`, with nothing left of
+    /// its process group when waiting for that, but was only found to have once the timer had
+    /// fired. A timeout noticed a moment too late must not claim a command that beat it, but
+    /// one that exited after the deadline still timed out, however late the signal.
+    fn exited_unsignalled(&mut self, deadline: Instant) -> Option<TimeoutOutcome> {
+        let observed = [self.reaped_at, self.control.snapshot().child_event_at];
+        if !observed.into_iter().flatten().any(|at| at < deadline) {
+            return None;
+        }
+        let status = match self.try_wait() {
+            Ok(Some(status)) if !self.group_running() => status,
+            _ => return None,
+        };
+        debug_print!(
+            self.options.verbose,
+            "Command exited with status: {} before the timeout signal was sent",
+            status
+        );
+        let reason = exit_reason(status, &self.options);
+        Some(self.outcome(reason, Some(status), None))
+    }
+
+    /// Whether other processes are left in the command's process group, when waiting for them.
+    fn group_running(&self) -> bool {
+        self.options.wait_for_group && signal::group_exists(self.child.id())
+    }
+
+    /// Send `SIGKILL`, or the escalation `signal`, to the command, and to its process group
+    /// when waiting for that or running it in a session of its own.
+    fn send_kill(&mut self, signal: Signal) {
+        let group = self.options.signals_group();
+        // Without signals, anything but the timeout signal itself has to terminate the command.
+        let (signal, sent) = if signal != Signal::Kill && cfg!(unix) {
+            (signal, signal::send_to(&mut self.child, signal, group))
+        } else {
+            let group_sent = group.then(|| signal::send_to(&mut self.child, Signal::Kill, true));
+            // Once reaped, the PID may already belong to something else.
+            let sent = match self.reaped {
+                Some(_) => Ok(()),
+                None => self.child.kill(),
+            };
+            (Signal::Kill, group_sent.unwrap_or(sent))
+        };
+        self.events
+            .emit(Event::Delivery(Delivery::new(signal, group, &sent)));
+    }
+
+    /// Send `SIGKILL`, or the escalation `signal`, repeating it as configured until the command
+    /// is reaped.
+    fn kill(&mut self, signal: Signal) -> Option<ExitStatus> {
+        let elapsed = self.start.elapsed();
+        self.events.emit(match signal {
+            Signal::Kill => Event::Kill(elapsed),
+            signal => Event::Signal(signal, elapsed),
+        });
+        self.send_kill(signal);
+        for attempt in 1..=self.options.kill_retries {
+            thread::sleep(self.options.timings.kill_retry_interval);
+            if let Ok(Some(status)) = self.try_wait() {
+                return Some(status);
+            }
+            debug_print!(
+                self.options.verbose,
+                "Command still running, retrying {} signal ({}/{})",
+                signal.name(),
+                attempt,
+                self.options.kill_retries
+            );
+            self.send_kill(signal);
+        }
+        self.wait().ok()
+    }
+
+    /// The command's exit status if it has exited, reaping it if need be.
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.reap(false)
+    }
+
+    /// Wait for the command to exit and reap it.
+    fn wait(&mut self) -> io::Result<ExitStatus> {
+        self.reap(true)?
+            .ok_or_else(|| io::Error::other("the command was not reaped"))
+    }
+
+    /// Reap the command with wait4, waiting for it to exit if `block` is set, so that its
+    /// resource usage is kept with its exit status. It is only ever reaped here, so its PID
+    /// cannot have been reused before then.
+    #[cfg(unix)]
+    fn reap(&mut self, block: bool) -> io::Result<Option<ExitStatus>> {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some((status, _)) = self.reaped {
+            return Ok(Some(status));
+        }
+        let flags = if block { 0 } else { libc::WNOHANG };
+        let mut status = 0;
+        // SAFETY: rusage is plain data, for wait4 to fill in.
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        loop {
+            // SAFETY: wait4 only writes to the status and usage it is given.
+            let pid = unsafe {
+                libc::wait4(
+                    self.child.id() as libc::pid_t,
+                    &mut status,
+                    flags,
+                    &mut usage,
+                )
+            };
+            match pid {
+                0 => return Ok(None),
+                -1 => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+                _ => {
+                    let status = ExitStatus::from_raw(status);
+                    self.reaped = Some((status, Some(ResourceUsage::from_rusage(&usage))));
+                    self.reaped_at = Some(self.control.now());
+                    return Ok(Some(status));
+                }
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn reap(&mut self, block: bool) -> io::Result<Option<ExitStatus>> {
+        let status = if block {
+            Some(self.child.wait()?)
+        } else {
+            self.child.try_wait()?
+        };
+        if let Some(status) = status {
+            self.reaped = Some((status, None));
+            self.reaped_at = Some(self.control.now());
+        }
+        Ok(status)
+    }
+
+    fn outcome(
+        &mut self,
+        reason: TimeoutReason,
+        status: Option<ExitStatus>,
+        signal_sent: Option<SentSignal>,
+    ) -> TimeoutOutcome {
+        let elapsed = self.start.elapsed();
+        self.control.update(|state| state.finished = true);
+        let mut outcome = TimeoutOutcome::new(reason, elapsed);
+        if let Some(status) = status {
+            outcome = outcome.with_status(status);
+        }
+        outcome.signal_sent = signal_sent;
+        outcome.usage = self.reaped.and_then(|(_, usage)| usage);
+        self.events.emit(Event::Exit(outcome.result, elapsed));
+        self.events.finish(self.options.timings.drain_grace);
+        let grace = self.options.timings.drain_grace;
+        let finish = |capture: Option<StreamCapture>| {
+            capture.map_or_else(Vec::new, |capture| capture.finish(grace))
+        };
+        outcome.stdout = finish(self.stdout.take());
+        outcome.stderr = finish(self.stderr.take());
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::events::tests::RecordingSink;
+    use crate::{CaptureMode, TimeoutCommand, TimeoutResult};
+
+    /// Move `clock` on and wake whatever is waiting on `control`.
+    fn advance(control: &Control, clock: &ManualClock, by: Duration) {
+        clock.advance(by);
+        control.update(|_| {});
+    }
+
+    /// Wait for the timer or the supervisor to bring `control` into a state `done` accepts.
+    fn wait_for(control: &Control, done: impl Fn(&ControlState) -> bool) -> ControlState {
+        let (state, result) = control
+            .changed
+            .wait_timeout_while(control.lock(), Duration::from_secs(5), |state| !done(state))
+            .unwrap();
+        assert!(!result.timed_out(), "{:?}", *state);
+        *state
+    }
+
+    #[test]
+    fn test_cancel_reports_cancelled() {
+        let child = TimeoutCommand::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_secs(60))
+            .spawn()
+            .unwrap();
+        let handle = child.handle();
+        assert!(handle.is_running());
+        thread::sleep(Duration::from_millis(100));
+        handle.cancel();
+        let outcome = child.wait().unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Cancelled);
+        assert!(outcome.elapsed < Duration::from_secs(2));
+        assert!(!handle.is_running());
+    }
+
+    #[test]
+    fn test_cancel_without_timeout_reports_cancelled() {
+        let child = TimeoutCommand::new("sleep").arg("5").spawn().unwrap();
+        child.handle().cancel();
+        assert_eq!(child.wait().unwrap().result, TimeoutResult::Cancelled);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cancel_escalates_to_kill_after() {
+        let child = TimeoutCommand::new("sh")
+            .args(["-c", "trap '' TERM; sleep 5"])
+            .timeout(Duration::from_secs(60))
+            .kill_after(Duration::from_millis(300))
+            .spawn()
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        child.handle().cancel();
+        let outcome = child.wait().unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Killed);
+        assert!(outcome.elapsed < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_cancel_after_exit_is_ignored() {
+        let child = TimeoutCommand::new("true").spawn().unwrap();
+        let handle = child.handle();
+        let outcome = child.wait().unwrap();
+        handle.cancel();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert!(!handle.is_running());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_detach_leaves_command_running() {
+        let child = TimeoutCommand::new("sleep")
+            .arg("1")
+            .timeout(Duration::from_millis(200))
+            .spawn()
+            .unwrap();
+        let pid = child.pid();
+        let handle = child.handle();
+        handle.detach();
+        assert!(!handle.is_running());
+        let outcome = child.wait().unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Detached);
+
+        // Well past the timeout the command must still be alive.
+        thread::sleep(Duration::from_millis(400));
+        assert!(signal::send_to_pid(pid, crate::Signal::Kill).is_ok());
+    }
+
+    #[test]
+    fn test_events_for_timed_out_run() {
+        let sink = Arc::new(RecordingSink::default());
+        let outcome = TimeoutCommand::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_millis(100))
+            .kill_after(Duration::from_secs(2))
+            .events(sink.clone())
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::TimedOut);
+        assert_eq!(sink.take(), ["spawn", "signal SIGTERM", "exit TimedOut"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_events_for_killed_run() {
+        let sink = Arc::new(RecordingSink::default());
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "trap '' TERM; sleep 5"])
+            .timeout(Duration::from_millis(100))
+            .kill_after(Duration::from_millis(200))
+            .events(sink.clone())
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Killed);
+        assert_eq!(
+            sink.take(),
+            ["spawn", "signal SIGTERM", "kill", "exit Killed"]
+        );
+    }
+
+    #[test]
+    fn test_events_for_completed_run() {
+        let sink = Arc::new(RecordingSink::default());
+        TimeoutCommand::new("true")
+            .timeout(Duration::from_secs(5))
+            .events(sink.clone())
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(sink.take(), ["spawn", "exit Completed(0)"]);
+    }
+
+    #[test]
+    fn test_on_timeout_hook_runs_before_signal() {
+        let sink = Arc::new(RecordingSink::default());
+        let hook_sink = sink.clone();
+        let signalled_before_hook = Arc::new(Mutex::new(None));
+        let hook_result = signalled_before_hook.clone();
+        let child = TimeoutCommand::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_millis(100))
+            .kill_after(Duration::from_secs(2))
+            .events(sink.clone())
+            .on_timeout(move |_pid, elapsed| {
+                assert!(elapsed >= Duration::from_millis(100));
+                let events = hook_sink.events.lock().unwrap();
+                *hook_result.lock().unwrap() = Some(events.iter().any(|e| e.starts_with("signal")));
+            })
+            .spawn()
+            .unwrap();
+        assert_eq!(child.wait().unwrap().result, TimeoutResult::TimedOut);
+        assert_eq!(*signalled_before_hook.lock().unwrap(), Some(false));
+        assert!(sink.take().contains(&"signal SIGTERM".to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_is_noticed_without_waiting_for_a_poll() {
+        let start = Instant::now();
+        let outcome = TimeoutCommand::new("sleep")
+            .arg("0.3")
+            .timeout(Duration::from_secs(5))
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert!(start.elapsed() < Duration::from_millis(380));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kill_after_counts_from_signal_sent() {
+        let start = Instant::now();
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "trap '' TERM; sleep 5"])
+            .timeout(Duration::from_millis(100))
+            .kill_after(Duration::from_millis(300))
+            .on_timeout(|_, _| std::thread::sleep(Duration::from_millis(500)))
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Killed);
+        assert!(start.elapsed() >= Duration::from_millis(900));
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_after_the_deadline_is_a_timeout_however_late_the_signal() {
+        // The command finishes while the hook holds up the signal, after the deadline.
+        let outcome = TimeoutCommand::new("sleep")
+            .arg("0.1")
+            .timeout(Duration::from_millis(50))
+            .on_timeout(|_, _| std::thread::sleep(Duration::from_millis(500)))
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::TimedOut);
+    }
+
+    #[test]
+    fn test_fast_command_beats_a_tiny_timeout() {
+        let outcome = TimeoutCommand::new("echo")
+            .arg("test")
+            .timeout(Duration::from_secs_f64(0.5))
+            .capture(CaptureMode::Null)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert_eq!(outcome.signal_sent, None);
+    }
+
+    /// Supervise a command that has already exited, unreaped, as the timer fires at
+    /// `deadline` with its exit signalled at `exited_at`.
+    #[cfg(unix)]
+    fn supervise_late(deadline: Duration, exited_at: Duration) -> TimeoutOutcome {
+        let clock = Arc::new(ManualClock::new());
+        let start = clock.now();
+        let control = Arc::new(Control::new(clock.clone(), None, None));
+        let child = std::process::Command::new("true").spawn().unwrap();
+        // SAFETY: waitid only writes to the siginfo given, and WNOWAIT leaves the child
+        // unreaped.
+        unsafe {
+            let mut info: libc::siginfo_t = std::mem::zeroed();
+            libc::waitid(
+                libc::P_PID,
+                child.id(),
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            );
+        }
+        control.update(|state| {
+            state.terminate = true;
+            state.term_fired = Some((start + deadline, start + deadline));
+            state.child_event_at = Some(start + exited_at);
+        });
+        let supervisor = Supervisor {
+            child,
+            options: TimeoutOptions::default(),
+            start,
+            control,
+            events: EventDispatcher::start(None),
+            stdout: None,
+            stderr: None,
+            reaped: None,
+            reaped_at: None,
+        };
+        supervisor.run().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_signalled_before_the_deadline_beats_a_late_timer() {
+        let outcome = supervise_late(Duration::from_secs(2), Duration::from_secs(1));
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert_eq!(outcome.signal_sent, None);
+
+        let outcome = supervise_late(Duration::from_secs(1), Duration::from_secs(2));
+        assert_eq!(outcome.result, TimeoutResult::TimedOut);
+    }
+
+    #[test]
+    fn test_on_timeout_hook_not_run_on_cancel() {
+        let child = TimeoutCommand::new("sleep")
+            .arg("5")
+            .on_timeout(|_, _| panic!("hook ran"))
+            .spawn()
+            .unwrap();
+        child.handle().cancel();
+        assert_eq!(child.wait().unwrap().result, TimeoutResult::Cancelled);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_wait_for_group_waits_for_background_processes() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "sleep 0.5 & exit 0"])
+            .timeout(Duration::from_secs(5))
+            .wait_for_group(true)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Completed(0));
+        assert!(outcome.elapsed >= Duration::from_millis(500));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_wait_for_group_times_out_on_lingering_process() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "sleep 5 & exit 0"])
+            .timeout(Duration::from_millis(300))
+            .kill_after(Duration::from_secs(1))
+            .wait_for_group(true)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.reason, TimeoutReason::TermExit);
+        assert_eq!(outcome.status.and_then(|status| status.code()), Some(0));
+        assert!(outcome.elapsed < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_outcome_records_signal_and_status() {
+        let outcome = TimeoutCommand::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_millis(100))
+            .kill_after(Duration::from_secs(2))
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.reason, TimeoutReason::TermExit);
+        let sent = outcome.signal_sent.unwrap();
+        assert_eq!(sent.signal, Signal::Term);
+        assert!(sent.elapsed >= Duration::from_millis(100));
+        assert!(sent.elapsed <= outcome.elapsed);
+        assert!(!outcome.status.unwrap().success());
+    }
+
+    #[test]
+    fn test_outcome_for_completed_run_has_no_signal() {
+        let outcome = TimeoutCommand::new("sh")
+            .args(["-c", "exit 4"])
+            .timeout(Duration::from_secs(5))
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(outcome.reason, TimeoutReason::CompletedInTime);
+        assert_eq!(outcome.signal_sent, None);
+        assert_eq!(outcome.status.and_then(|status| status.code()), Some(4));
+    }
+
+    #[test]
+    fn test_kill_now_records_kill_signal() {
+        let child = TimeoutCommand::new("sleep").arg("5").spawn().unwrap();
+        child.kill_now();
+        let outcome = child.wait().unwrap();
+        assert_eq!(outcome.reason, TimeoutReason::Killed);
+        assert_eq!(
+            outcome.signal_sent.map(|sent| sent.signal),
+            Some(Signal::Kill)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_usage_is_kept_for_killed_command() {
+        let child = TimeoutCommand::new("sleep").arg("5").spawn().unwrap();
+        child.kill_now();
+        let usage = child.wait().unwrap().usage.unwrap();
+        assert!(usage.max_rss > 0);
+    }
+
+    #[test]
+    fn test_timer_wakes_when_command_finishes() {
+        let clock = Arc::new(ManualClock::new());
+        let control = Arc::new(Control::new(
+            clock.clone(),
+            Some(clock.now() + Duration::from_secs(60)),
+            None,
+        ));
+        let timer = {
+            let control = control.clone();
+            thread::spawn(move || control.run_timer(false))
+        };
+        control.update(|state| state.finished = true);
+        timer.join().unwrap();
+        assert!(!control.snapshot().terminate);
+    }
+
+    #[test]
+    fn test_timer_raises_flags_at_deadlines() {
+        let clock = Arc::new(ManualClock::new());
+        let start = clock.now();
+        let control = Arc::new(Control::new(
+            clock.clone(),
+            Some(start + Duration::from_secs(50)),
+            Some(start + Duration::from_secs(100)),
+        ));
+        let timer = {
+            let control = control.clone();
+            thread::spawn(move || control.run_timer(false))
+        };
+        advance(&control, &clock, Duration::from_secs(49));
+        assert!(!control.snapshot().terminate);
+
+        advance(&control, &clock, Duration::from_secs(2));
+        let state = wait_for(&control, |state| state.terminate);
+        assert!(!state.kill);
+        assert_eq!(
+            state.term_fired,
+            Some((
+                start + Duration::from_secs(50),
+                start + Duration::from_secs(51)
+            ))
+        );
+
+        advance(&control, &clock, Duration::from_secs(49));
+        wait_for(&control, |state| state.kill && state.escalated);
+        control.update(|state| state.finished = true);
+        timer.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_escalation_follows_the_clock() {
+        let clock = Arc::new(ManualClock::new());
+        let mut child = TimeoutCommand::new("sh")
+            .args(["-c", "trap '' TERM; echo ready; exec sleep 30 >/dev/null"])
+            .timeout(Duration::from_secs(3600))
+            .kill_after(Duration::from_secs(3600))
+            .capture(CaptureMode::Manual)
+            .clock_source(clock.clone())
+            .spawn()
+            .unwrap();
+        let control = child.handle().control;
+        // A TERM arriving before the trap is set would end the shell without escalating.
+        let mut ready = [0u8; 6];
+        std::io::Read::read_exact(&mut child.take_stdout().unwrap(), &mut ready).unwrap();
+
+        // The kill-after period is only armed once the timeout signal has been sent.
+        advance(&control, &clock, Duration::from_secs(3600));
+        let state = wait_for(&control, |state| state.kill_deadline.is_some());
+        assert_eq!(
+            state.kill_deadline,
+            Some(clock.now() + Duration::from_secs(3600))
+        );
+        advance(&control, &clock, Duration::from_secs(3599));
+        assert!(!control.snapshot().kill);
+
+        advance(&control, &clock, Duration::from_secs(1));
+        let outcome = child.wait().unwrap();
+        assert_eq!(outcome.result, TimeoutResult::Killed);
+        assert!(outcome.elapsed < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,174 @@
+//! `tracing` spans for the phases of a supervised command, when the `tracing` feature is enabled.
+//!
+//! Without the feature everything here compiles to nothing.
+
+use crate::events::Event;
+use std::ffi::OsStr;
+
+/// Run `spawn` inside a `spawn` span recording the program being started.
+pub(crate) fn in_spawn_span<T>(program: &OsStr, spawn: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!("spawn", program = %program.to_string_lossy()).entered();
+    #[cfg(not(feature = "tracing"))]
+    let _ = program;
+    spawn()
+}
+
+/// Follows the lifecycle events of one command: a `run` span (`pid`, and `result` and
+/// `elapsed_ms` once it exits) is opened when it is spawned, then a `term` span (`signal`,
+/// `elapsed_ms`) when it is sent the timeout signal, replaced by a `kill` span (`elapsed_ms`)
+/// if it has to be killed. Every span is closed when the command exits.
+#[derive(Debug, Default)]
+pub(crate) struct PhaseSpans {
+    #[cfg(feature = "tracing")]
+    spans: std::sync::Mutex<Spans>,
+}
+
+#[cfg(feature = "tracing")]
+#[derive(Debug, Default)]
+struct Spans {
+    run: Option<tracing::Span>,
+    phase: Option<tracing::Span>,
+}
+
+impl PhaseSpans {
+    /// Open or close spans as `event` requires.
+    pub(crate) fn record(&self, event: Event) {
+        #[cfg(feature = "tracing")]
+        {
+            use std::sync::PoisonError;
+            use tracing::field::Empty;
+
+            let mut spans = self.spans.lock().unwrap_or_else(PoisonError::into_inner);
+            let run = spans.run.clone().unwrap_or_else(tracing::Span::none);
+            match event {
+                Event::Spawn(pid) => {
+                    spans.run = Some(tracing::info_span!(
+                        "run",
+                        pid,
+                        result = Empty,
+                        elapsed_ms = Empty
+                    ));
+                }
+                Event::Signal(signal, elapsed) => {
+                    spans.phase = Some(tracing::info_span!(
+                        parent: &run,
+                        "term",
+                        signal = %signal,
+                        elapsed_ms = elapsed.as_millis()
+                    ));
+                }
+                Event::Kill(elapsed) => {
+                    let killing = spans
+                        .phase
+                        .as_ref()
+                        .and_then(tracing::Span::metadata)
+                        .is_some_and(|metadata| metadata.name() == "kill");
+                    if !killing {
+                        spans.phase = Some(tracing::info_span!(
+                            parent: &run,
+                            "kill",
+                            elapsed_ms = elapsed.as_millis()
+                        ));
+                    }
+                }
+                Event::Delivery(_) => {}
+                Event::Exit(result, elapsed) => {
+                    run.record("result", tracing::field::debug(result));
+                    run.record("elapsed_ms", elapsed.as_millis());
+                    *spans = Spans::default();
+                }
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = event;
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+    use crate::outcome::TimeoutResult;
+    use crate::signal::Signal;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event as TracingEvent, Metadata, Subscriber};
+
+    /// Records `open <name>` and `close <name>` as spans are created and closed.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        log: Arc<Mutex<Vec<String>>>,
+        /// The name and number of open handles of every span, indexed by ID - 1.
+        spans: Arc<Mutex<Vec<(&'static str, usize)>>>,
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name(), 1));
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("open {}", span.metadata().name()));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &TracingEvent<'_>) {}
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+
+        fn clone_span(&self, id: &Id) -> Id {
+            self.spans.lock().unwrap()[id.into_u64() as usize - 1].1 += 1;
+            id.clone()
+        }
+
+        fn try_close(&self, id: Id) -> bool {
+            let mut spans = self.spans.lock().unwrap();
+            let (name, handles) = &mut spans[id.into_u64() as usize - 1];
+            *handles -= 1;
+            if *handles > 0 {
+                return false;
+            }
+            self.log.lock().unwrap().push(format!("close {}", name));
+            true
+        }
+    }
+
+    #[test]
+    fn test_phase_spans_follow_events() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            in_spawn_span(OsStr::new("sleep"), || ());
+            let spans = PhaseSpans::default();
+            spans.record(Event::Spawn(1));
+            spans.record(Event::Signal(Signal::Term, Duration::ZERO));
+            spans.record(Event::Kill(Duration::ZERO));
+            spans.record(Event::Kill(Duration::ZERO));
+            spans.record(Event::Exit(TimeoutResult::Killed, Duration::ZERO));
+        });
+        assert_eq!(
+            *recorder.log.lock().unwrap(),
+            [
+                "open spawn",
+                "close spawn",
+                "open run",
+                "open term",
+                "open kill",
+                "close term",
+                "close kill",
+                "close run",
+            ]
+        );
+    }
+}
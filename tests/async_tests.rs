@@ -0,0 +1,235 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use timeout_cli::{
+    CaptureMode, EventSink, Signal, TimeoutClock, TimeoutOptions, TimeoutResult,
+    run_with_timeout_async,
+};
+
+fn with_timeout(timeout: Duration) -> TimeoutOptions {
+    TimeoutOptions {
+        timeout: Some(timeout),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_basic_command_success() {
+    let outcome = run_with_timeout_async(
+        "echo",
+        ["Hello World"],
+        &with_timeout(Duration::from_secs(5)),
+    )
+    .await;
+
+    assert_eq!(outcome.result, TimeoutResult::Completed(0));
+}
+
+#[tokio::test]
+async fn test_exit_code_forwarding_failure() {
+    let outcome = run_with_timeout_async(
+        "sh",
+        ["-c", "exit 42"],
+        &with_timeout(Duration::from_secs(5)),
+    )
+    .await;
+
+    assert_eq!(outcome.result, TimeoutResult::Completed(42));
+}
+
+#[tokio::test]
+async fn test_timeout_kills_long_running_command() {
+    let outcome =
+        run_with_timeout_async("sleep", ["5"], &with_timeout(Duration::from_secs(1))).await;
+
+    assert_eq!(outcome.result, TimeoutResult::TimedOut);
+    assert!(
+        outcome.elapsed < Duration::from_secs(3),
+        "Command took too long: {:?}",
+        outcome.elapsed
+    );
+    assert!(
+        outcome.elapsed >= Duration::from_millis(800),
+        "Command finished too quickly: {:?}",
+        outcome.elapsed
+    );
+}
+
+#[tokio::test]
+async fn test_command_completes_before_timeout() {
+    let outcome = run_with_timeout_async(
+        "echo",
+        ["quick_command"],
+        &with_timeout(Duration::from_secs(3)),
+    )
+    .await;
+
+    assert_eq!(outcome.result, TimeoutResult::Completed(0));
+    assert!(
+        outcome.elapsed < Duration::from_secs(2),
+        "Command took too long: {:?}",
+        outcome.elapsed
+    );
+}
+
+#[tokio::test]
+async fn test_nonexistent_command() {
+    let outcome = run_with_timeout_async(
+        "this_command_does_not_exist",
+        [""; 0],
+        &with_timeout(Duration::from_secs(5)),
+    )
+    .await;
+
+    assert_eq!(outcome.result, TimeoutResult::NotFound);
+}
+
+#[tokio::test]
+async fn test_kill_after_without_timeout_is_rejected() {
+    let options = TimeoutOptions {
+        kill_after: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+    let outcome = run_with_timeout_async("echo", ["test"], &options).await;
+
+    assert_eq!(outcome.result, TimeoutResult::InternalError);
+}
+
+#[tokio::test]
+async fn test_kill_after_with_responsive_process() {
+    let options = TimeoutOptions {
+        kill_after: Some(Duration::from_secs(2)),
+        ..with_timeout(Duration::from_secs(1))
+    };
+    let outcome = run_with_timeout_async("sleep", ["10"], &options).await;
+
+    assert_eq!(outcome.result, TimeoutResult::TimedOut);
+    assert!(
+        outcome.elapsed < Duration::from_secs(2),
+        "Should not wait for kill-after: {:?}",
+        outcome.elapsed
+    );
+}
+
+#[tokio::test]
+async fn test_kill_after_with_unresponsive_process() {
+    let options = TimeoutOptions {
+        kill_after: Some(Duration::from_millis(500)),
+        signal: Signal::Usr1,
+        ..with_timeout(Duration::from_millis(500))
+    };
+    let outcome = run_with_timeout_async("sh", ["-c", "trap '' USR1; sleep 10"], &options).await;
+
+    assert_eq!(outcome.result, TimeoutResult::Killed);
+    assert!(
+        outcome.elapsed < Duration::from_secs(3),
+        "Should not take too long: {:?}",
+        outcome.elapsed
+    );
+}
+
+#[tokio::test]
+async fn test_zero_timeout() {
+    let outcome = run_with_timeout_async("sleep", ["1"], &with_timeout(Duration::ZERO)).await;
+
+    assert_eq!(outcome.result, TimeoutResult::TimedOut);
+}
+
+#[tokio::test]
+async fn test_cpu_time_ignores_sleeping_command() {
+    let options = TimeoutOptions {
+        clock: TimeoutClock::Cpu,
+        ..with_timeout(Duration::from_secs(1))
+    };
+    let outcome = run_with_timeout_async("sleep", ["2"], &options).await;
+
+    assert_eq!(outcome.result, TimeoutResult::Completed(0));
+}
+
+#[tokio::test]
+async fn test_concurrent_runs_share_runtime() {
+    let options = with_timeout(Duration::from_secs(1));
+    let start = std::time::Instant::now();
+    let (first, second) = tokio::join!(
+        run_with_timeout_async("sleep", ["5"], &options),
+        run_with_timeout_async("sleep", ["5"], &options),
+    );
+
+    assert_eq!(first.result, TimeoutResult::TimedOut);
+    assert_eq!(second.result, TimeoutResult::TimedOut);
+    assert!(
+        start.elapsed() < Duration::from_secs(2),
+        "Runs should overlap: {:?}",
+        start.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn test_piped_capture_larger_than_pipe_buffer() {
+    let options = TimeoutOptions {
+        capture: CaptureMode::Piped,
+        ..with_timeout(Duration::from_secs(10))
+    };
+    let outcome = run_with_timeout_async(
+        "sh",
+        ["-c", "head -c 1048576 /dev/zero; echo err >&2"],
+        &options,
+    )
+    .await;
+
+    assert_eq!(outcome.result, TimeoutResult::Completed(0));
+    assert_eq!(outcome.stdout.len(), 1 << 20);
+    assert_eq!(outcome.stderr, b"err\n");
+}
+
+#[tokio::test]
+async fn test_piped_capture_keeps_partial_output_on_timeout() {
+    let options = TimeoutOptions {
+        capture: CaptureMode::Piped,
+        capture_limit: Some(3),
+        ..with_timeout(Duration::from_millis(300))
+    };
+    let outcome = run_with_timeout_async("sh", ["-c", "echo before; exec sleep 5"], &options).await;
+
+    assert_eq!(outcome.result, TimeoutResult::TimedOut);
+    assert_eq!(outcome.stdout, b"bef");
+}
+
+#[derive(Debug, Default)]
+struct RecordingSink(Mutex<Vec<String>>);
+
+impl EventSink for RecordingSink {
+    fn on_spawn(&self, _pid: u32) {
+        self.0.lock().unwrap().push("spawn".to_string());
+    }
+
+    fn on_signal(&self, signal: Signal, _elapsed: Duration) {
+        self.0.lock().unwrap().push(format!("signal {}", signal));
+    }
+
+    fn on_kill(&self, _elapsed: Duration) {
+        self.0.lock().unwrap().push("kill".to_string());
+    }
+
+    fn on_exit(&self, result: TimeoutResult, _elapsed: Duration) {
+        self.0.lock().unwrap().push(format!("exit {:?}", result));
+    }
+}
+
+#[tokio::test]
+async fn test_events_for_timed_out_run() {
+    let sink = Arc::new(RecordingSink::default());
+    let options = TimeoutOptions {
+        kill_after: Some(Duration::from_secs(2)),
+        events: Some(sink.clone()),
+        ..with_timeout(Duration::from_millis(100))
+    };
+    let outcome = run_with_timeout_async("sleep", ["5"], &options).await;
+
+    assert_eq!(outcome.result, TimeoutResult::TimedOut);
+    assert_eq!(
+        *sink.0.lock().unwrap(),
+        ["spawn", "signal SIGTERM", "exit TimedOut"]
+    );
+}
@@ -311,6 +311,19 @@ fn test_verbose_mode() {
         .stderr(predicate::str::contains("DEBUG:"));
 }
 
+#[test]
+fn test_verbose_prints_argv_verbatim() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--verbose", "5", "echo", "a b", "*", "say \"hi\""]);
+
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains(r#"DEBUG: argv[0]: "echo""#))
+        .stderr(predicate::str::contains(r#"DEBUG: argv[1]: "a b""#))
+        .stderr(predicate::str::contains(r#"DEBUG: argv[2]: "*""#))
+        .stderr(predicate::str::contains(r#"DEBUG: argv[3]: "say \"hi\"""#));
+}
+
 #[test]
 fn test_exit_code_127_command_not_found() {
     let mut cmd = Command::cargo_bin("timeout").unwrap();
@@ -339,3 +352,4189 @@ fn test_normal_timeout_without_kill_after() {
         elapsed
     );
 }
+
+#[test]
+fn test_cpu_time_ignores_sleeping_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--cpu-time", "1", "sleep", "2"]);
+
+    cmd.assert().code(0);
+}
+
+#[test]
+fn test_cpu_time_stops_busy_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--cpu-time", "1", "sh", "-c", "while :; do :; done"]);
+
+    let start = std::time::Instant::now();
+    cmd.assert().code(124);
+    let elapsed = start.elapsed();
+
+    assert!(
+        elapsed < Duration::from_secs(5),
+        "Command took too long: {:?}",
+        elapsed
+    );
+}
+
+#[test]
+fn test_timestamp_output_prefixes_each_line() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--timestamp-output",
+        "5",
+        "sh",
+        "-c",
+        "echo first; echo second; echo oops >&2; printf partial",
+    ]);
+
+    let output = cmd.assert().success().get_output().clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<&str> = stdout.split_inclusive('\n').collect();
+    assert_eq!(lines.len(), 3, "{stdout:?}");
+    for (line, text) in lines.iter().zip(["first\n", "second\n", "partial"]) {
+        let (stamp, rest) = line.split_once(' ').unwrap();
+        assert_eq!(rest, text);
+        assert_eq!(stamp.len(), "2024-08-09T12:34:56.789Z".len(), "{stamp}");
+        assert!(stamp.ends_with('Z'), "{stamp}");
+    }
+    assert!(stderr.ends_with("Z oops\n"), "{stderr:?}");
+}
+
+#[test]
+fn test_timestamp_output_keeps_output_on_timeout() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--timestamp-output",
+        "1",
+        "sh",
+        "-c",
+        "echo before; exec sleep 5",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stdout(predicate::str::is_match(r"^\d{4}-\d{2}-\d{2}T\S+Z before\n$").unwrap());
+}
+
+#[test]
+fn test_kill_retries_with_unresponsive_process() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--kill-retries",
+        "3",
+        "-k",
+        "1",
+        "1",
+        "sh",
+        "-c",
+        "trap '' TERM; sleep 10 >/dev/null 2>&1",
+    ]);
+
+    let start = std::time::Instant::now();
+    cmd.assert().code(137);
+    let elapsed = start.elapsed();
+
+    assert!(
+        elapsed < Duration::from_secs(4),
+        "Should not take too long: {:?}",
+        elapsed
+    );
+}
+
+#[test]
+fn test_help_shows_kill_retries() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--help");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("--kill-retries"));
+}
+
+#[cfg(unix)]
+fn run_with_status_fd(args: &str) -> std::process::Output {
+    let timeout = assert_cmd::cargo::cargo_bin("timeout");
+    Command::new("sh")
+        .arg("-c")
+        .arg(format!("\"$0\" --status-fd 3 {args} 3>&1 >/dev/null"))
+        .arg(timeout)
+        .output()
+        .unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn test_status_fd_reports_completion() {
+    let output = run_with_status_fd("5 sh -c 'exit 3'");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "OK 3\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_status_fd_reports_timeout() {
+    let output = run_with_status_fd("1 sleep 5");
+    assert_eq!(output.status.code(), Some(124));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "TIMEOUT\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_status_fd_rejects_unopened_fd() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--status-fd", "97", "5", "echo", "never"]);
+
+    cmd.assert()
+        .code(125)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("invalid status fd 97"));
+}
+
+#[test]
+fn test_double_dash_separates_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["5", "--", "ls", "-la"]);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Cargo.toml"));
+}
+
+#[test]
+fn test_exec_separates_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["5", "--exec", "ls", "-la"]);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Cargo.toml"));
+}
+
+#[test]
+fn test_exec_passes_timeout_flags_to_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["5", "--exec", "echo", "--verbose", "-k", "1"]);
+
+    cmd.assert()
+        .success()
+        .stdout("--verbose -k 1\n")
+        .stderr(predicate::str::contains("DEBUG:").not());
+}
+
+/// Run timeout with `--json` and parse the summary it prints as the last line of stderr.
+fn json_summary(args: &[&str]) -> (Option<i32>, serde_json::Value) {
+    let output = Command::cargo_bin("timeout")
+        .unwrap()
+        .arg("--json")
+        .args(args)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let line = stderr.lines().last().expect("no JSON summary on stderr");
+    (output.status.code(), serde_json::from_str(line).unwrap())
+}
+
+#[test]
+fn test_json_summary_for_completed_command() {
+    let (code, json) = json_summary(&["5", "sh", "-c", "exit 3"]);
+
+    assert_eq!(code, Some(3));
+    assert_eq!(json["command"], "sh");
+    assert_eq!(json["args"], serde_json::json!(["-c", "exit 3"]));
+    assert!(json["pid"].as_u64().unwrap() > 0);
+    assert!(json["started_at"].as_str().unwrap().ends_with('Z'));
+    assert!(json["elapsed_ms"].as_u64().unwrap() < 5000);
+    assert_eq!(json["timed_out"], false);
+    assert_eq!(json["signal_sent"], serde_json::Value::Null);
+    assert_eq!(json["kill_sent"], false);
+    assert_eq!(json["exit_code"], 3);
+    assert_eq!(json["term_signal"], serde_json::Value::Null);
+    assert_eq!(json["reason"], "completed");
+    assert_eq!(json["error"], serde_json::Value::Null);
+}
+
+#[test]
+fn test_json_summary_for_timeout() {
+    let (code, json) = json_summary(&["1", "sleep", "5"]);
+
+    assert_eq!(code, Some(124));
+    assert_eq!(json["timed_out"], true);
+    assert_eq!(json["signal_sent"], "SIGTERM");
+    assert_eq!(json["reason"], "timed_out");
+    assert!(json["elapsed_ms"].as_u64().unwrap() >= 1000);
+}
+
+#[test]
+fn test_json_summary_for_not_found() {
+    let (code, json) = json_summary(&["5", "nonexistent_command_12345"]);
+
+    assert_eq!(code, Some(127));
+    assert_eq!(json["pid"], serde_json::Value::Null);
+    assert_eq!(json["exit_code"], serde_json::Value::Null);
+    assert_eq!(json["reason"], "not_found");
+    assert!(json["error"].is_string());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_json_summary_for_permission_denied() {
+    let script = std::env::temp_dir().join(format!("timeout-json-{}", std::process::id()));
+    std::fs::write(&script, "#!/bin/sh\n").unwrap();
+    let (code, json) = json_summary(&["5", script.to_str().unwrap()]);
+    std::fs::remove_file(&script).unwrap();
+
+    assert_eq!(code, Some(126));
+    assert_eq!(json["pid"], serde_json::Value::Null);
+    assert_eq!(json["reason"], "permission_denied");
+}
+
+#[test]
+fn test_json_to_stdout_leaves_stderr_alone() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--json-to", "stdout", "5", "echo", "hello"]);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with("hello\n{\"command\":\"echo\""))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn test_unbuffered_forwards_partial_line_promptly() {
+    use std::io::Read;
+    use std::process::Stdio;
+    use std::time::Instant;
+
+    let start = Instant::now();
+    let mut child = Command::cargo_bin("timeout")
+        .unwrap()
+        .args([
+            "--timestamp-output",
+            "--unbuffered",
+            "3",
+            "sh",
+            "-c",
+            "printf 'prompt> '; exec sleep 5",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut chunk = [0u8; 256];
+    let n = stdout.read(&mut chunk).unwrap();
+    let first_output = start.elapsed();
+    let _ = child.wait();
+
+    assert!(String::from_utf8_lossy(&chunk[..n]).ends_with("Z prompt> "));
+    assert!(
+        first_output < Duration::from_secs(2),
+        "partial line took {first_output:?} to arrive"
+    );
+}
+
+/// Run timeout with `--result-file` and parse the file it leaves behind.
+fn result_file(name: &str, args: &[&str]) -> (Option<i32>, serde_json::Value) {
+    let path = std::env::temp_dir().join(format!("timeout-{}-{}.json", name, std::process::id()));
+    let output = Command::cargo_bin("timeout")
+        .unwrap()
+        .arg("--result-file")
+        .arg(&path)
+        .args(args)
+        .output()
+        .unwrap();
+    let contents = std::fs::read_to_string(&path).expect("result file not written");
+    std::fs::remove_file(&path).unwrap();
+    (
+        output.status.code(),
+        serde_json::from_str(&contents).unwrap(),
+    )
+}
+
+#[test]
+fn test_result_file_for_success() {
+    let (code, json) = result_file("success", &["5", "echo", "hello"]);
+
+    assert_eq!(code, Some(0));
+    assert_eq!(json["command"], "echo");
+    assert_eq!(json["exit_code"], 0);
+    assert_eq!(json["reason"], "completed");
+}
+
+#[test]
+fn test_result_file_for_timeout() {
+    let (code, json) = result_file("timeout", &["1", "sleep", "5"]);
+
+    assert_eq!(code, Some(124));
+    assert_eq!(json["timed_out"], true);
+    assert_eq!(json["signal_sent"], "SIGTERM");
+    assert_eq!(json["reason"], "timed_out");
+}
+
+#[test]
+fn test_result_file_for_not_found() {
+    let (code, json) = result_file("not-found", &["5", "nonexistent_command_12345"]);
+
+    assert_eq!(code, Some(127));
+    assert_eq!(json["pid"], serde_json::Value::Null);
+    assert_eq!(json["reason"], "not_found");
+}
+
+#[test]
+fn test_result_file_error_keeps_exit_code() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--result-file",
+        "/nonexistent/dir/result.json",
+        "5",
+        "sh",
+        "-c",
+        "exit 3",
+    ]);
+
+    cmd.assert()
+        .code(3)
+        .stderr(predicate::str::contains("failed to write result file"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_result_file_written_when_timeout_is_terminated() {
+    let path = std::env::temp_dir().join(format!("timeout-signalled-{}.json", std::process::id()));
+    let mut child = Command::cargo_bin("timeout")
+        .unwrap()
+        .arg("--result-file")
+        .arg(&path)
+        .args(["30", "sleep", "10"])
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    let status = child.wait().unwrap();
+
+    let contents = std::fs::read_to_string(&path).expect("result file not written");
+    std::fs::remove_file(&path).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    assert_eq!(status.code(), Some(124));
+    assert_eq!(json["reason"], "cancelled");
+    assert_eq!(json["signal_sent"], "SIGTERM");
+}
+
+#[test]
+fn test_on_timeout_runs_with_child_pid() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--on-timeout",
+        "echo cleanup $TIMEOUT_PID",
+        "1",
+        "sleep",
+        "5",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stdout(predicate::str::is_match(r"^cleanup \d+\n$").unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_on_timeout_runs_before_command_is_signalled() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--on-timeout",
+        "echo \"hook $TIMEOUT_COMMAND $TIMEOUT_ELAPSED\"",
+        "1",
+        "sh",
+        "-c",
+        "trap 'echo got TERM; exit 1' TERM; sleep 5 & wait",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stdout(predicate::str::is_match(r"^hook sh -c trap .* 1\.\d{3}\ngot TERM\n$").unwrap());
+}
+
+#[test]
+fn test_hook_timeout_bounds_on_timeout() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--on-timeout",
+        "exec sleep 10",
+        "--hook-timeout",
+        "0.5s",
+        "1",
+        "sleep",
+        "10",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stderr(predicate::str::contains("on-timeout hook timed out"));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_on_timeout_that_cannot_start_keeps_exit_code() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.env("PATH", "/nonexistent")
+        .args(["--on-timeout", "true", "1", "/bin/sleep", "5"]);
+
+    cmd.assert()
+        .code(124)
+        .stderr(predicate::str::contains("failed to run on-timeout hook"));
+}
+
+#[test]
+fn test_on_timeout_not_run_on_success() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--on-timeout", "echo cleanup", "5", "echo", "done"]);
+
+    cmd.assert().success().stdout("done\n");
+}
+
+#[test]
+fn test_on_timeout_failure_keeps_exit_code() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--on-timeout", "exit 7", "1", "sleep", "5"]);
+
+    cmd.assert().code(124).stderr(predicate::str::contains(
+        "on-timeout hook exited with status 7",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_report_fd_streams_events() {
+    let timeout = assert_cmd::cargo::cargo_bin("timeout");
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg("\"$0\" --report-fd 3 -k 5 1 sleep 5 3>&1 >/dev/null")
+        .arg(timeout)
+        .output()
+        .unwrap();
+    let report = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+
+    assert_eq!(output.status.code(), Some(124));
+    assert_eq!(lines.len(), 3, "{report}");
+    assert!(lines[0].starts_with("spawned pid="), "{report}");
+    assert!(
+        lines[1].starts_with("timeout signal=SIGTERM elapsed_ms="),
+        "{report}"
+    );
+    assert!(
+        lines[2].starts_with("exit result=timed_out elapsed_ms="),
+        "{report}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_report_fd_reports_exit_code() {
+    let timeout = assert_cmd::cargo::cargo_bin("timeout");
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg("\"$0\" --report-fd 3 5 sh -c 'exit 2' 3>&1 >/dev/null")
+        .arg(timeout)
+        .output()
+        .unwrap();
+    let report = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        report.contains("\nexit result=completed code=2 elapsed_ms="),
+        "{report}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_report_fd_rejects_unopened_fd() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--report-fd", "97", "5", "echo", "never"]);
+
+    cmd.assert()
+        .code(125)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("invalid report fd 97"));
+}
+
+#[test]
+fn test_on_success_runs_after_zero_exit() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--on-success",
+        "echo success $TIMEOUT_EXIT_CODE",
+        "--on-failure",
+        "echo failure",
+        "5",
+        "true",
+    ]);
+
+    cmd.assert().success().stdout("success 0\n");
+}
+
+#[test]
+fn test_on_failure_receives_exit_code() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--on-success",
+        "echo success",
+        "--on-failure",
+        "echo failure $TIMEOUT_EXIT_CODE",
+        "5",
+        "sh",
+        "-c",
+        "exit 3",
+    ]);
+
+    cmd.assert().code(3).stdout("failure 3\n");
+}
+
+#[test]
+fn test_on_failure_runs_after_on_timeout() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--on-timeout",
+        "echo timeout",
+        "--on-failure",
+        "echo failure $TIMEOUT_EXIT_CODE",
+        "1",
+        "sleep",
+        "5",
+    ]);
+
+    cmd.assert().code(124).stdout("timeout\nfailure 124\n");
+}
+
+#[test]
+fn test_failing_hook_keeps_exit_code() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--on-success", "exit 9", "5", "true"]);
+
+    cmd.assert().success().stderr(predicate::str::contains(
+        "on-success hook exited with status 9",
+    ));
+}
+
+#[test]
+fn test_metrics_file_for_timeout() {
+    let path = std::env::temp_dir().join(format!("timeout-metrics-{}.prom", std::process::id()));
+    Command::cargo_bin("timeout")
+        .unwrap()
+        .arg("--metrics-file")
+        .arg(&path)
+        .args(["--metrics-label", "job=nightly", "1", "sleep", "5"])
+        .assert()
+        .code(124);
+
+    let contents = std::fs::read_to_string(&path).expect("metrics file not written");
+    std::fs::remove_file(&path).unwrap();
+    assert!(contents.contains("# TYPE timeout_last_run_exit_code gauge\n"));
+    assert!(contents.contains("timeout_last_run_exit_code{job=\"nightly\"} 124\n"));
+    assert!(contents.contains("timeout_last_run_timed_out{job=\"nightly\"} 1\n"));
+}
+
+#[test]
+fn test_metrics_file_error_keeps_exit_code() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--metrics-file",
+        "/nonexistent/dir/timeout.prom",
+        "5",
+        "sh",
+        "-c",
+        "exit 3",
+    ]);
+
+    cmd.assert().code(3).stderr(predicate::str::contains(
+        "timeout: warning: cannot write metrics file",
+    ));
+}
+
+#[test]
+fn test_metrics_label_rejects_invalid_name() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--metrics-file",
+        "/tmp/unused.prom",
+        "--metrics-label",
+        "bad-name=x",
+        "5",
+        "true",
+    ]);
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid label name"));
+}
+
+#[test]
+fn test_total_timeout_caps_the_timeout() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--total-timeout", "1s", "30", "sleep", "10"]);
+
+    cmd.assert().code(124);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_total_timeout_longer_than_seconds_has_no_effect() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--total-timeout", "10m", "5", "echo", "hello"]);
+
+    cmd.assert().success().stdout("hello\n");
+}
+
+#[test]
+fn test_total_timeout_rejects_invalid_duration() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--total-timeout", "5x", "5", "true"]);
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid duration unit"));
+}
+
+#[test]
+fn test_statsd_metrics_are_sent() {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let address = socket.local_addr().unwrap().to_string();
+
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--statsd",
+        &address,
+        "--statsd-prefix",
+        "jobs.backup",
+        "--dogstatsd",
+        "--statsd-tag",
+        "env:test",
+        "1",
+        "sleep",
+        "5",
+    ]);
+    cmd.assert().code(124);
+
+    let mut buf = [0; 1024];
+    let len = socket.recv(&mut buf).unwrap();
+    let packet = String::from_utf8_lossy(&buf[..len]);
+    let lines: Vec<&str> = packet.lines().collect();
+    assert_eq!(lines.len(), 3, "{packet}");
+    assert!(lines[0].starts_with("jobs.backup.duration_ms:"));
+    assert!(lines[0].ends_with("|ms|#env:test"));
+    assert_eq!(lines[1], "jobs.backup.exit_code:124|g|#env:test");
+    assert_eq!(lines[2], "jobs.backup.timeout:1|c|#env:test");
+}
+
+#[test]
+fn test_statsd_unreachable_does_not_change_exit_code() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--statsd", "host.invalid:8125", "5", "sh", "-c", "exit 3"]);
+
+    cmd.assert().code(3).stderr("");
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn test_success_codes_maps_listed_code_to_zero() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--success-codes",
+        "1,2",
+        "5",
+        "grep",
+        "no-such-pattern",
+        "/dev/null",
+    ]);
+
+    cmd.assert().success();
+}
+
+#[test]
+fn test_success_codes_forwards_other_codes() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--success-codes", "1", "5", "sh", "-c", "exit 3"]);
+
+    cmd.assert().code(3);
+}
+
+#[test]
+fn test_success_codes_does_not_hide_timeout() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--success-codes", "124,143", "1", "sleep", "5"]);
+
+    cmd.assert().code(124);
+}
+
+/// Run timeout with `--syslog` pointed at a temporary socket and collect what it logs.
+#[cfg(unix)]
+fn syslog_messages(name: &str, args: &[&str]) -> (Option<i32>, Vec<String>) {
+    use std::os::unix::net::UnixDatagram;
+
+    let path = std::env::temp_dir().join(format!("timeout-syslog-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let socket = UnixDatagram::bind(&path).unwrap();
+    socket.set_nonblocking(true).unwrap();
+
+    let output = Command::cargo_bin("timeout")
+        .unwrap()
+        .args(["--syslog", "--syslog-facility", "local0", "--syslog-socket"])
+        .arg(&path)
+        .args(args)
+        .output()
+        .unwrap();
+
+    let mut messages = Vec::new();
+    let mut buf = [0; 1024];
+    while let Ok(len) = socket.recv(&mut buf) {
+        messages.push(String::from_utf8_lossy(&buf[..len]).into_owned());
+    }
+    std::fs::remove_file(&path).unwrap();
+    (output.status.code(), messages)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_syslog_logs_timeout_events() {
+    let (code, messages) = syslog_messages("timeout", &["1", "sleep", "5"]);
+
+    assert_eq!(code, Some(124));
+    assert_eq!(messages.len(), 3, "{messages:?}");
+    // local0 (16) * 8 + info (6), then warning (4)
+    assert!(messages[0].starts_with("<134>timeout["), "{messages:?}");
+    assert!(messages[0].contains("Command spawned successfully"));
+    assert!(messages[1].starts_with("<132>timeout["), "{messages:?}");
+    assert!(messages[1].contains("Sent SIGTERM"));
+    assert!(messages[2].starts_with("<132>timeout["), "{messages:?}");
+    assert!(messages[2].contains("TimedOut"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_syslog_logs_spawn_failure_as_error() {
+    let (code, messages) = syslog_messages("not-found", &["5", "nonexistent_command_12345"]);
+
+    assert_eq!(code, Some(127));
+    assert_eq!(messages.len(), 1, "{messages:?}");
+    // local0 (16) * 8 + err (3)
+    assert!(messages[0].starts_with("<131>timeout["), "{messages:?}");
+    assert!(messages[0].contains("failed to run command 'nonexistent_command_12345'"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_syslog_missing_socket_fails() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--syslog",
+        "--syslog-socket",
+        "/nonexistent/dir/log",
+        "5",
+        "true",
+    ]);
+
+    cmd.assert()
+        .code(125)
+        .stderr(predicate::str::contains("cannot connect to syslog"));
+}
+
+#[test]
+fn test_empty_command() {
+    for command in ["", "   "] {
+        let mut cmd = Command::cargo_bin("timeout").unwrap();
+        cmd.args(["5", command]);
+
+        cmd.assert().code(127).stderr("timeout: empty command\n");
+    }
+}
+
+#[test]
+fn test_journald_unavailable_only_warns() {
+    if std::path::Path::new("/run/systemd/journal/socket").exists() {
+        return;
+    }
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--journald", "5", "echo", "hello"]);
+
+    cmd.assert()
+        .success()
+        .stdout("hello\n")
+        .stderr(predicate::str::contains("cannot connect to journald"));
+}
+
+/// Only runs where journald is up and `journalctl` can read the entries back.
+#[test]
+fn test_journald_entries_reach_the_journal() {
+    if !std::path::Path::new("/run/systemd/journal/socket").exists() {
+        return;
+    }
+    let marker = format!("timeout-journald-test-{}", std::process::id());
+    Command::cargo_bin("timeout")
+        .unwrap()
+        .args(["--journald", "1", "sh", "-c", "sleep 5", &marker])
+        .assert()
+        .code(124);
+
+    let filter = format!("TIMEOUT_COMMAND=sh -c sleep 5 {}", marker);
+    for _ in 0..20 {
+        let Ok(output) = Command::new("journalctl")
+            .args([
+                "--quiet",
+                "--output=cat",
+                "TIMEOUT_RESULT=timed_out",
+                &filter,
+            ])
+            .output()
+        else {
+            return;
+        };
+        if !output.status.success() {
+            return;
+        }
+        if String::from_utf8_lossy(&output.stdout).contains("TimedOut") {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    panic!("no timed_out entry for {} in the journal", marker);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_notify_pid_signals_process_on_exit() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut target = Command::new("sleep").arg("30").spawn().unwrap();
+    let pid = target.id().to_string();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--notify-pid", &pid, "--notify-signal", "TERM", "5", "true"]);
+
+    cmd.assert().success();
+    assert_eq!(target.wait().unwrap().signal(), Some(libc::SIGTERM));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_notify_pid_ignores_missing_process() {
+    let mut gone = Command::new("true").spawn().unwrap();
+    gone.wait().unwrap();
+    let pid = gone.id().to_string();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--notify-pid", &pid, "5", "sh", "-c", "exit 3"]);
+
+    cmd.assert().code(3).stderr("");
+}
+
+#[test]
+fn test_notify_signal_rejects_unknown_signal() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--notify-pid", "1", "--notify-signal", "FOO", "5", "true"]);
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown signal 'FOO'"));
+}
+
+/// Accept `responses.len()` HTTP requests on a local port, answering each with the matching
+/// status line (or dropping the connection for `None`), and return the port and the requests.
+fn webhook_server(
+    responses: Vec<Option<&'static str>>,
+) -> (u16, std::thread::JoinHandle<Vec<String>>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            if let Some(status) = response {
+                let mut stream = reader.into_inner();
+                write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+            }
+            requests.push(request);
+        }
+        requests
+    });
+    (port, server)
+}
+
+#[test]
+fn test_webhook_posts_summary_on_timeout() {
+    let (port, server) = webhook_server(vec![Some("204 No Content")]);
+    let url = format!("http://127.0.0.1:{}/hooks/timeout", port);
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--webhook",
+        &url,
+        "--webhook-header",
+        "Authorization: Bearer secret",
+        "1",
+        "sleep",
+        "5",
+    ]);
+
+    cmd.assert().code(124).stderr("");
+    let requests = server.join().unwrap();
+    let (head, body) = requests[0].split_once("\r\n\r\n").unwrap();
+    assert!(
+        head.starts_with("POST /hooks/timeout HTTP/1.1\r\n"),
+        "{head}"
+    );
+    assert!(head.contains("\r\nAuthorization: Bearer secret"), "{head}");
+    assert!(
+        head.contains("\r\nContent-Type: application/json"),
+        "{head}"
+    );
+    let json: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(json["reason"], "timed_out");
+    assert_eq!(json["command"], "sleep");
+}
+
+#[test]
+fn test_webhook_retries_once_then_warns() {
+    let (port, server) = webhook_server(vec![Some("500 Internal Server Error"), None]);
+    let url = format!("http://127.0.0.1:{}/", port);
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--webhook", &url, "5", "sh", "-c", "exit 3"]);
+
+    cmd.assert()
+        .code(3)
+        .stderr(predicate::str::contains(format!(
+            "webhook to '{}' failed",
+            url
+        )));
+    assert_eq!(server.join().unwrap().len(), 2);
+}
+
+#[test]
+fn test_webhook_not_sent_for_unselected_outcome() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    // Nothing listens on port 9, so sending would warn.
+    cmd.args(["--webhook", "http://127.0.0.1:9/", "5", "true"]);
+
+    cmd.assert().success().stderr("");
+}
+
+#[test]
+fn test_webhook_rejects_https() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--webhook", "https://example.com/", "5", "true"]);
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("only http:// URLs are supported"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_raw_signal_exit_reports_bare_kill_signal() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--raw-signal-exit",
+        "-k",
+        "1",
+        "1",
+        "sh",
+        "-c",
+        "trap '' TERM; sleep 10 >/dev/null 2>&1",
+    ]);
+
+    cmd.assert().code(9);
+}
+
+#[test]
+fn test_raw_signal_exit_keeps_timeout_code() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--raw-signal-exit", "1", "sleep", "5"]);
+
+    cmd.assert().code(124);
+}
+
+#[test]
+fn test_notify_without_notification_daemon_keeps_exit_code() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    // With no notification tool on PATH the notification quietly fails.
+    cmd.env("PATH", "/nonexistent")
+        .args(["--notify", "5", "/bin/sh", "-c", "exit 3"]);
+
+    cmd.assert().code(3).stderr("");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unknown_user_fails_before_running() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--user", "no-such-user-12345", "5", "echo", "ran"]);
+
+    cmd.assert()
+        .code(125)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "no such user 'no-such-user-12345'",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unknown_group_fails_before_running() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--group", "no-such-group-12345", "5", "echo", "ran"]);
+
+    cmd.assert()
+        .code(125)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "no such group 'no-such-group-12345'",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_user_and_group_switch() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--user",
+        "65534",
+        "--group",
+        "65534",
+        "5",
+        "sh",
+        "-c",
+        "id -u; id -g",
+    ]);
+
+    // Only root may switch user; anyone else is refused before the command starts.
+    if unsafe { libc::geteuid() } == 0 {
+        cmd.assert().success().stdout("65534\n65534\n");
+    } else {
+        cmd.assert().code(125).stdout("");
+    }
+}
+
+/// A bound unix datagram socket standing in for systemd's notification socket, and its path.
+#[cfg(unix)]
+fn fake_notify_socket(name: &str) -> (std::os::unix::net::UnixDatagram, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("timeout-notify-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let socket = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+    socket.set_nonblocking(true).unwrap();
+    (socket, path)
+}
+
+/// Every datagram waiting on `socket`.
+#[cfg(unix)]
+fn received_datagrams(socket: &std::os::unix::net::UnixDatagram) -> Vec<String> {
+    let mut buf = [0; 256];
+    std::iter::from_fn(|| {
+        let len = socket.recv(&mut buf).ok()?;
+        Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+    })
+    .collect()
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sd_notify_reports_ready_watchdog_and_stopping() {
+    let (socket, path) = fake_notify_socket("sd");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.env("NOTIFY_SOCKET", &path)
+        .env("WATCHDOG_USEC", "400000")
+        .env_remove("WATCHDOG_PID")
+        .args([
+            "--sd-notify",
+            "1",
+            "sh",
+            "-c",
+            "echo \"[$NOTIFY_SOCKET]\"; exec sleep 5",
+        ]);
+
+    cmd.assert().code(124).stdout("[]\n");
+    let messages = received_datagrams(&socket);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(messages.first().map(String::as_str), Some("READY=1"));
+    assert!(messages.iter().any(|message| message == "WATCHDOG=1"));
+    assert_eq!(
+        messages
+            .iter()
+            .filter(|message| *message == "STOPPING=1")
+            .count(),
+        1
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_notify_passthrough_leaves_readiness_to_command() {
+    let (socket, path) = fake_notify_socket("passthrough");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.env("NOTIFY_SOCKET", &path)
+        .env_remove("WATCHDOG_USEC")
+        .args([
+            "--sd-notify",
+            "--notify-passthrough",
+            "5",
+            "sh",
+            "-c",
+            "test -n \"$NOTIFY_SOCKET\"",
+        ]);
+
+    cmd.assert().success();
+    assert!(received_datagrams(&socket).is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_sd_notify_without_socket_is_a_no_op() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.env_remove("NOTIFY_SOCKET")
+        .args(["--sd-notify", "5", "echo", "hello"]);
+
+    cmd.assert().success().stdout("hello\n").stderr("");
+}
+
+fn pid_file_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("timeout-{}-{}.pid", name, std::process::id()))
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pid_file_holds_child_pid_while_running() {
+    let path = pid_file_path("running");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--pid-file").arg(&path).args([
+        "5",
+        "sh",
+        "-c",
+        "sleep 0.5; test \"$(cat \"$0\")\" = $$ && echo recorded",
+    ]);
+    cmd.arg(&path);
+
+    cmd.assert().success().stdout("recorded\n");
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_pid_file_removed_after_timeout() {
+    let path = pid_file_path("timeout");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--pid-file").arg(&path).args(["1", "sleep", "5"]);
+
+    cmd.assert().code(124);
+    assert!(!path.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pid_file_removed_when_timeout_is_terminated() {
+    let path = pid_file_path("signalled");
+    let mut child = Command::cargo_bin("timeout")
+        .unwrap()
+        .arg("--pid-file")
+        .arg(&path)
+        .args(["30", "sleep", "10"])
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    assert!(path.exists());
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    let status = child.wait().unwrap();
+
+    assert_eq!(status.code(), Some(124));
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_pid_file_of_running_process_refused() {
+    let path = pid_file_path("live");
+    std::fs::write(&path, format!("{}\n", std::process::id())).unwrap();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--pid-file").arg(&path).args(["5", "echo", "ran"]);
+
+    let assert = cmd.assert();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert
+        .code(125)
+        .stdout("")
+        .stderr(predicate::str::contains("running process"));
+    assert_eq!(contents, format!("{}\n", std::process::id()));
+}
+
+#[test]
+fn test_pid_file_overwrite_replaces_running_process() {
+    let path = pid_file_path("overwrite");
+    std::fs::write(&path, format!("{}\n", std::process::id())).unwrap();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--pid-file")
+        .arg(&path)
+        .args(["--pid-file-overwrite", "5", "echo", "ran"]);
+
+    cmd.assert().success().stdout("ran\n");
+    assert!(!path.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stale_pid_file_is_replaced() {
+    let path = pid_file_path("stale");
+    std::fs::write(&path, format!("{}\n", i32::MAX)).unwrap();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--pid-file").arg(&path).args(["5", "echo", "ran"]);
+
+    cmd.assert().success().stdout("ran\n");
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_max_output_truncates_with_notice() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--max-output",
+        "10",
+        "5",
+        "sh",
+        "-c",
+        "echo 12345; echo 67890; echo more",
+    ]);
+
+    cmd.assert()
+        .success()
+        .stdout("12345\n6789\n[timeout: output truncated after 10 bytes]\n");
+}
+
+#[test]
+fn test_max_output_keeps_draining_runaway_output() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--max-output",
+        "1K",
+        "10",
+        "sh",
+        "-c",
+        "yes | head -c 10000000",
+    ]);
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    assert_eq!(
+        output.len(),
+        1024 + "[timeout: output truncated after 1024 bytes]\n".len()
+    );
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn test_max_output_under_limit_is_untouched() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--max-output", "1M", "5", "echo", "hello"]);
+
+    cmd.assert().success().stdout("hello\n");
+}
+
+#[test]
+fn test_directory_as_command_cannot_be_invoked() {
+    let dir = std::env::temp_dir();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("5").arg(&dir);
+
+    cmd.assert()
+        .code(126)
+        .stderr(format!("timeout: '{}' is a directory\n", dir.display()));
+}
+
+/// Run timeout with an `--on-exit` hook that saves its environment, and return the hook's
+/// `TIMEOUT_*` variables as `KEY=value` lines, sorted.
+#[cfg(unix)]
+fn on_exit_env(name: &str, args: &[&str]) -> (Option<i32>, Vec<String>) {
+    let path =
+        std::env::temp_dir().join(format!("timeout-on-exit-{}-{}", name, std::process::id()));
+    let output = Command::cargo_bin("timeout")
+        .unwrap()
+        .arg("--on-exit")
+        .arg(format!("env > '{}'", path.display()))
+        .args(args)
+        .output()
+        .unwrap();
+    let env = std::fs::read_to_string(&path).expect("on-exit hook did not run");
+    std::fs::remove_file(&path).unwrap();
+    let mut vars: Vec<String> = env
+        .lines()
+        .filter(|line| line.starts_with("TIMEOUT_"))
+        .map(str::to_string)
+        .collect();
+    vars.sort();
+    (output.status.code(), vars)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_on_exit_after_completion() {
+    let (code, vars) = on_exit_env("completed", &["5", "sh", "-c", "exit 3"]);
+
+    assert_eq!(code, Some(3));
+    assert!(
+        vars.contains(&"TIMEOUT_EXIT_CODE=3".to_string()),
+        "{vars:?}"
+    );
+    assert!(
+        vars.contains(&"TIMEOUT_REASON=completed".to_string()),
+        "{vars:?}"
+    );
+    assert!(
+        vars.contains(&"TIMEOUT_COMMAND=sh -c exit 3".to_string()),
+        "{vars:?}"
+    );
+    assert!(
+        vars.iter().any(|var| var.starts_with("TIMEOUT_PID=")),
+        "{vars:?}"
+    );
+    assert!(
+        vars.iter()
+            .any(|var| var.starts_with("TIMEOUT_ELAPSED_MS=")),
+        "{vars:?}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_on_exit_after_timeout() {
+    let (code, vars) = on_exit_env("timeout", &["1", "sleep", "5"]);
+
+    assert_eq!(code, Some(124));
+    assert!(
+        vars.contains(&"TIMEOUT_EXIT_CODE=124".to_string()),
+        "{vars:?}"
+    );
+    assert!(
+        vars.contains(&"TIMEOUT_REASON=timeout".to_string()),
+        "{vars:?}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_on_exit_after_kill() {
+    let (code, vars) = on_exit_env(
+        "killed",
+        &[
+            "-k",
+            "1",
+            "1",
+            "sh",
+            "-c",
+            "trap '' TERM; sleep 10 >/dev/null 2>&1",
+        ],
+    );
+
+    assert_eq!(code, Some(137));
+    assert!(
+        vars.contains(&"TIMEOUT_REASON=killed".to_string()),
+        "{vars:?}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_on_exit_after_spawn_failure() {
+    let (code, vars) = on_exit_env("not-found", &["5", "nonexistent-command-12345"]);
+
+    assert_eq!(code, Some(127));
+    assert!(
+        vars.contains(&"TIMEOUT_EXIT_CODE=127".to_string()),
+        "{vars:?}"
+    );
+    assert!(
+        vars.contains(&"TIMEOUT_REASON=not-found".to_string()),
+        "{vars:?}"
+    );
+    assert!(
+        !vars.iter().any(|var| var.starts_with("TIMEOUT_PID=")),
+        "{vars:?}"
+    );
+}
+
+#[test]
+fn test_on_exit_output_passes_through_and_failure_is_not_fatal() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--on-exit", "echo hook; exit 4", "5", "echo", "done"]);
+
+    cmd.assert()
+        .success()
+        .stdout("done\nhook\n")
+        .stderr(predicate::str::contains(
+            "on-exit hook exited with status 4",
+        ));
+}
+
+#[test]
+fn test_hook_failures_fatal() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--on-exit",
+        "exit 4",
+        "--hook-failures-fatal",
+        "5",
+        "echo",
+        "done",
+    ]);
+
+    cmd.assert().code(125).stdout("done\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_wait_all_waits_for_background_work() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--wait-all",
+        "5",
+        "sh",
+        "-c",
+        "(sleep 1; echo late) & echo early",
+    ]);
+
+    cmd.assert().success().stdout("early\nlate\n");
+    assert!(start.elapsed() >= Duration::from_secs(1));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_wait_all_times_out_on_lingering_background_process() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--wait-all", "1", "sh", "-c", "sleep 10 & exit 0"]);
+
+    cmd.assert().code(124);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+/// A shell script that sleeps past the timeout the first time it runs and finishes at once
+/// after that, using a marker file to tell the runs apart.
+#[cfg(unix)]
+fn flaky_script(name: &str) -> (String, std::path::PathBuf) {
+    let marker =
+        std::env::temp_dir().join(format!("timeout-retries-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_file(&marker);
+    let script = format!(
+        "if [ -e '{0}' ]; then echo second; else touch '{0}'; echo first; exec sleep 10; fi",
+        marker.display()
+    );
+    (script, marker)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_retries_rerun_a_command_that_timed_out() {
+    let (script, marker) = flaky_script("success");
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["-v", "--retries", "2", "1", "sh", "-c", &script]);
+
+    cmd.assert()
+        .success()
+        .stdout("first\nsecond\n")
+        .stderr(predicate::str::contains("Attempt 1/3"))
+        .stderr(predicate::str::contains("Attempt 2/3"))
+        .stderr(predicate::str::contains("Attempt 3/3").not());
+    let _ = std::fs::remove_file(marker);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_retries_give_up_after_the_last_attempt() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["-v", "--retries", "1", "1", "sleep", "10"]);
+
+    cmd.assert()
+        .code(124)
+        .stderr(predicate::str::contains("Attempt 2/2"));
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_secs(2) && elapsed < Duration::from_secs(6));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_retries_do_not_rerun_a_failed_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["-v", "--retries", "3", "5", "sh", "-c", "echo once; exit 3"]);
+
+    cmd.assert()
+        .code(3)
+        .stdout("once\n")
+        .stderr(predicate::str::contains("Attempt 2/4").not());
+}
+
+#[test]
+fn test_color_always_paints_errors() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--color", "always", "5", "nonexistent_command_12345"]);
+
+    cmd.assert()
+        .code(127)
+        .stderr(predicate::str::starts_with(
+            "\x1b[31mtimeout: failed to run command",
+        ))
+        .stderr(predicate::str::ends_with("\x1b[0m\n"));
+}
+
+#[test]
+fn test_color_never_and_auto_leave_messages_plain() {
+    for choice in ["never", "auto"] {
+        let mut cmd = Command::cargo_bin("timeout").unwrap();
+        cmd.args(["--color", choice, "5", "nonexistent_command_12345"]);
+
+        cmd.assert()
+            .code(127)
+            .stderr(predicate::str::starts_with(
+                "timeout: failed to run command",
+            ))
+            .stderr(predicate::str::contains("\x1b[").not());
+    }
+}
+
+#[test]
+fn test_color_does_not_touch_command_output() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--color", "always", "5", "echo", "plain"]);
+
+    cmd.assert().success().stdout("plain\n").stderr("");
+}
+
+/// A shell script that fails with exit code 1 the first `failures` times it runs and succeeds
+/// after that, counting its runs in a file.
+#[cfg(unix)]
+fn failing_script(name: &str, failures: u32) -> (String, std::path::PathBuf) {
+    let counter =
+        std::env::temp_dir().join(format!("timeout-retry-on-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_file(&counter);
+    let script = format!(
+        "echo x >> '{0}'; runs=$(wc -l < '{0}'); echo run $runs; [ $runs -gt {1} ]",
+        counter.display(),
+        failures
+    );
+    (script, counter)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_retry_on_exit_code_backs_off() {
+    let (script, counter) = failing_script("backoff", 2);
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "-v",
+        "--retries",
+        "3",
+        "--retry-on",
+        "1-10,timeout",
+        "--retry-backoff",
+        "0.3",
+        "--json",
+        "5",
+        "sh",
+        "-c",
+        &script,
+    ]);
+
+    cmd.assert()
+        .success()
+        .stdout("run 1\nrun 2\nrun 3\n")
+        .stderr(predicate::str::contains(
+            "Attempt 1/4 ended with exit code 1, retrying in 300ms",
+        ))
+        .stderr(predicate::str::contains(
+            "Attempt 2/4 ended with exit code 1, retrying in 600ms",
+        ))
+        .stderr(predicate::str::contains("\"attempts\":3"));
+    let _ = std::fs::remove_file(counter);
+    assert!(start.elapsed() >= Duration::from_millis(900));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_retry_on_reports_the_last_attempt() {
+    let (script, counter) = failing_script("exhausted", 5);
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--retries",
+        "1",
+        "--retry-on",
+        "1",
+        "--json",
+        "5",
+        "sh",
+        "-c",
+        &script,
+    ]);
+
+    cmd.assert()
+        .code(1)
+        .stdout("run 1\nrun 2\n")
+        .stderr(predicate::str::contains("\"exit_code\":1"))
+        .stderr(predicate::str::contains("\"attempts\":2"));
+    let _ = std::fs::remove_file(counter);
+}
+
+#[test]
+fn test_retry_on_rejects_invalid_codes() {
+    for arg in ["256", "10-1", "never"] {
+        let mut cmd = Command::cargo_bin("timeout").unwrap();
+        cmd.args(["--retries", "1", "--retry-on", arg, "5", "true"]);
+
+        cmd.assert()
+            .code(2)
+            .stderr(predicate::str::contains("--retry-on"));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_retry_backoff_is_cut_short_by_sigint() {
+    let start = std::time::Instant::now();
+    let mut child = Command::cargo_bin("timeout")
+        .unwrap()
+        .args([
+            "--retries",
+            "2",
+            "--retry-on",
+            "3",
+            "--retry-backoff",
+            "30",
+            "5",
+            "sh",
+            "-c",
+            "exit 3",
+        ])
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    let status = child.wait().unwrap();
+
+    assert_eq!(status.code(), Some(3));
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+/// A fresh directory holding a `.timeoutrc` with `contents`.
+fn config_dir(name: &str, contents: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("timeout-rc-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(".timeoutrc"), contents).unwrap();
+    dir
+}
+
+#[test]
+fn test_timeoutrc_supplies_defaults() {
+    let dir = config_dir("defaults", "kill-after = 7\nverbose = true\n");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.current_dir(&dir).args(["5", "echo", "hello"]);
+
+    cmd.assert()
+        .success()
+        .stdout("hello\n")
+        .stderr(predicate::str::contains("kill-after: Some(7)s"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_command_line_and_env_override_timeoutrc() {
+    let dir = config_dir("precedence", "kill-after = 7\nverbose = true\n");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.current_dir(&dir)
+        .env("TIMEOUT_KILL_AFTER", "8")
+        .args(["5", "true"]);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("kill-after: Some(8)s"));
+
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.current_dir(&dir)
+        .env("TIMEOUT_KILL_AFTER", "8")
+        .args(["-k", "9", "5", "true"]);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("kill-after: Some(9)s"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_config_file_warns_about_unknown_keys() {
+    let dir = config_dir("unknown", "kill-afterr = 7\n");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--config")
+        .arg(dir.join(".timeoutrc"))
+        .args(["5", "echo", "hello"]);
+
+    cmd.assert()
+        .success()
+        .stdout("hello\n")
+        .stderr(predicate::str::contains(
+            "timeout: warning: ignoring unknown option 'kill-afterr' in config file",
+        ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_invalid_config_file_fails() {
+    let dir = config_dir("invalid", "kill-after = \n");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.current_dir(&dir).args(["5", "true"]);
+    cmd.assert()
+        .code(125)
+        .stderr(predicate::str::contains("invalid config file '.timeoutrc'"));
+
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--config")
+        .arg(dir.join("missing.toml"))
+        .args(["5", "true"]);
+    cmd.assert()
+        .code(125)
+        .stderr(predicate::str::contains("cannot read config file"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_total_timeout_bounds_all_retries() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "-v",
+        "--retries",
+        "5",
+        "--total-timeout",
+        "2.5s",
+        "1",
+        "sleep",
+        "10",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stderr(predicate::str::contains("Attempt 3/6"))
+        .stderr(predicate::str::contains("Attempt 4/6").not())
+        .stderr(predicate::str::contains("of the 2.5s total timeout used"));
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(2400) && elapsed < Duration::from_secs(4));
+}
+
+#[test]
+fn test_total_timeout_caps_an_attempt_longer_than_the_budget() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--retries",
+        "3",
+        "--total-timeout",
+        "1.5s",
+        "10",
+        "sleep",
+        "10",
+    ]);
+
+    cmd.assert().code(124);
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_total_timeout_stops_retrying_when_backoff_would_overrun() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "-v",
+        "--retries",
+        "3",
+        "--retry-on",
+        "1",
+        "--retry-backoff",
+        "5s",
+        "--total-timeout",
+        "2s",
+        "--json",
+        "10",
+        "sh",
+        "-c",
+        "exit 1",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stderr(predicate::str::contains(
+            "Total timeout leaves no time to retry",
+        ))
+        .stderr(predicate::str::contains("\"timed_out\":true"))
+        .stderr(predicate::str::contains("\"attempts\":1"));
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn test_respawn_restarts_the_command_for_the_whole_window() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--respawn", "--json", "1", "true"]);
+
+    let output = cmd.assert().success().get_output().clone();
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("timeout: respawn: "), "{stderr}");
+    let json: serde_json::Value = serde_json::from_str(stderr.lines().last().unwrap()).unwrap();
+    assert!(json["respawn"]["iterations"].as_u64().unwrap() > 1);
+    assert_eq!(json["respawn"]["failures"], 0);
+    assert!(json["respawn"]["total_ms"].as_u64().unwrap() >= 1000);
+}
+
+#[test]
+fn test_respawn_stops_a_command_still_running_when_the_window_ends() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--respawn", "1", "sleep", "10"]);
+
+    cmd.assert().success().stderr(predicate::str::contains(
+        "timeout: respawn: 1 iterations, 0 failed",
+    ));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_respawn_stop_on_failure() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--respawn",
+        "--respawn-delay",
+        "0.1",
+        "--stop-on-failure",
+        "5",
+        "sh",
+        "-c",
+        "exit 4",
+    ]);
+
+    cmd.assert().code(4).stderr(predicate::str::contains(
+        "timeout: respawn: 1 iterations, 1 failed",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_respawn_counts_failures() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--respawn",
+        "--respawn-delay",
+        "0.4",
+        "1",
+        "sh",
+        "-c",
+        "echo run; exit 1",
+    ]);
+
+    cmd.assert()
+        .success()
+        .stdout("run\nrun\nrun\n")
+        .stderr(predicate::str::contains("3 iterations, 3 failed"));
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn test_every_runs_on_a_schedule() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--every", "0.3", "--max-runs", "3", "5", "echo", "tick"]);
+
+    let output = cmd
+        .assert()
+        .success()
+        .stdout("tick\ntick\ntick\n")
+        .get_output()
+        .clone();
+    assert!(start.elapsed() >= Duration::from_millis(600));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for run in 1..=3 {
+        assert!(
+            stderr.contains(&format!("timeout: every: run {} at ", run)),
+            "{stderr}"
+        );
+    }
+    assert!(!stderr.contains("run 4"), "{stderr}");
+    assert!(stderr.contains("s, exit 0"), "{stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_every_times_out_each_run_and_stops_at_the_deadline() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--every", "0.5", "--until", "2s", "1", "sleep", "10"]);
+
+    cmd.assert()
+        .code(124)
+        .stderr(predicate::str::contains("run 2 at "))
+        .stderr(predicate::str::contains("run 3 at ").not())
+        .stderr(predicate::str::contains("exit 124"));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_every_stops_on_sigint() {
+    let child = Command::cargo_bin("timeout")
+        .unwrap()
+        .args(["--every", "0.2", "5", "true"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(700));
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("run 3 at "), "{stderr}");
+}
+
+#[cfg(unix)]
+fn jobs_file(name: &str, contents: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("timeout-jobs-{}-{}", name, std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[cfg(unix)]
+#[test]
+fn test_from_file_runs_every_command_and_exits_with_the_worst_code() {
+    let path = jobs_file(
+        "mixed",
+        "# fast, failing and timing out\necho one\n\nsh -c 'exit 3'\nsleep 10\necho 'two words'\n",
+    );
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--from-file").arg(&path).arg("1");
+
+    let assert = cmd.assert().code(124).stdout("one\ntwo words\n");
+    std::fs::remove_file(&path).unwrap();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("line 2, "), "{stderr}");
+    assert!(stderr.contains("exit 3: sh -c 'exit 3'"), "{stderr}");
+    assert!(stderr.contains("exit 124: sleep 10"), "{stderr}");
+    assert!(stderr.contains("exit 0: echo 'two words'"), "{stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_from_file_halt_on_error() {
+    let path = jobs_file("halt", "true\nfalse\necho unreachable\n");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--from-file")
+        .arg(&path)
+        .args(["--halt-on-error", "5"]);
+
+    cmd.assert()
+        .code(1)
+        .stdout("")
+        .stderr(predicate::str::contains("line 3").not());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_from_file_shell() {
+    let path = jobs_file("shell", "echo a | tr a b\n");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--from-file").arg(&path).args(["--shell", "5"]);
+
+    cmd.assert().success().stdout("b\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_from_file_missing_file() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--from-file", "/nonexistent/jobs.txt", "5"]);
+
+    cmd.assert().code(125).stderr(predicate::str::contains(
+        "cannot read '/nonexistent/jobs.txt'",
+    ));
+}
+
+#[test]
+fn test_print_pid() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--print-pid", "5", "sleep", "0.2"]);
+
+    let output = cmd.assert().success().get_output().clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let pid: u32 = stdout.trim().parse().unwrap();
+    assert!(pid > 0);
+}
+
+#[test]
+fn test_print_pid_to_stderr() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--print-pid=stderr", "5", "echo", "out"]);
+
+    let output = cmd.assert().success().stdout("out\n").get_output().clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.lines().any(|line| line.parse::<u32>().is_ok()),
+        "{stderr}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_parallel_runs_commands_at_once() {
+    let path = jobs_file("parallel", "sleep 1\nsleep 1\nsleep 1\necho hi\n");
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["-P", "4", "--from-file"]).arg(&path).arg("5");
+
+    cmd.assert()
+        .success()
+        .stdout("[line 4] hi\n")
+        .stderr(predicate::str::contains(
+            "4 commands, 4 succeeded, 0 failed, 0 timed out",
+        ));
+    std::fs::remove_file(&path).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(2500));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_parallel_exits_124_if_any_command_timed_out() {
+    let path = jobs_file(
+        "parallel-mixed",
+        "sleep 10\nsh -c 'exit 3'\nsh -c 'echo a; echo b'\nsleep 0.5\n",
+    );
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["-P", "2", "--from-file"]).arg(&path).arg("1");
+
+    cmd.assert()
+        .code(124)
+        .stdout("[line 3] a\n[line 3] b\n")
+        .stderr(predicate::str::contains(
+            "4 commands, 2 succeeded, 1 failed, 1 timed out",
+        ));
+    std::fs::remove_file(&path).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(2500));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_parallel_sigint_cancels_every_command() {
+    let path = jobs_file("parallel-sigint", "sleep 10\nsleep 10\nsleep 10\n");
+    let start = std::time::Instant::now();
+    let child = Command::cargo_bin("timeout")
+        .unwrap()
+        .args(["-P", "2", "--from-file"])
+        .arg(&path)
+        .arg("30")
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(124));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("2 commands, 0 succeeded, 0 failed, 2 timed out"),
+        "{stderr}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdin_commands_runs_each_line_in_order() {
+    // std::process::Command has no way to feed stdin to assert().
+    let mut cmd = assert_cmd::Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--stdin-commands", "5"])
+        .write_stdin("echo one\nsh -c 'exit 3'\necho two\n");
+
+    let assert = cmd.assert().code(3).stdout("one\ntwo\n");
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    let results: Vec<_> = stderr
+        .lines()
+        .filter(|line| line.starts_with("timeout: stdin-commands: line"))
+        .collect();
+    assert_eq!(results.len(), 3, "{stderr}");
+    assert!(results[0].ends_with("exit 0: echo one"), "{stderr}");
+    assert!(results[1].ends_with("exit 3: sh -c 'exit 3'"), "{stderr}");
+    assert!(results[2].ends_with("exit 0: echo two"), "{stderr}");
+    assert!(
+        stderr.contains("3 commands, 2 succeeded, 1 failed, 0 timed out"),
+        "{stderr}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdin_commands_null_delimited() {
+    let mut cmd = assert_cmd::Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--stdin-commands", "-0", "5"])
+        .write_stdin("printf 'a\\nb\\n'\0echo c\0");
+
+    cmd.assert().success().stdout("a\nb\nc\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdin_commands_gives_commands_null_stdin() {
+    let mut cmd = assert_cmd::Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--stdin-commands", "5"])
+        .write_stdin("wc -c\necho after\n");
+
+    cmd.assert().success().stdout("0\nafter\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_kill_on_match_stops_the_command_early() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--kill-on-match",
+        "FATAL",
+        "--json",
+        "30",
+        "sh",
+        "-c",
+        "echo starting; echo 'FATAL: disk full' >&2; sleep 30",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stdout("starting\n")
+        .stderr(predicate::str::contains(
+            "timeout: output matched 'FATAL', stopping the command: FATAL: disk full",
+        ))
+        .stderr(predicate::str::contains("\"reason\":\"output_matched\""));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_kill_on_match_without_a_match_times_out_as_usual() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--if-output-contains",
+        "FATAL",
+        "--json",
+        "1",
+        "sh",
+        "-c",
+        "echo fine; sleep 10",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stdout("fine\n")
+        .stderr(predicate::str::contains("output matched").not())
+        .stderr(predicate::str::contains("\"reason\":\"timed_out\""));
+}
+
+#[test]
+fn test_kill_on_match_rejects_an_invalid_pattern() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--kill-on-match", "(", "5", "true"]);
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--kill-on-match"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_kill_on_pattern_exits_with_the_pattern_exit_code() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--kill-on-pattern",
+        "FATAL",
+        "--pattern-exit-code",
+        "3",
+        "30",
+        "sh",
+        "-c",
+        "echo 'FATAL: out of memory'; sleep 30",
+    ]);
+
+    cmd.assert().code(3).stderr(predicate::str::contains(
+        "timeout: output matched 'FATAL', stopping the command: FATAL: out of memory",
+    ));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pattern_stream_only_watches_the_chosen_stream() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--kill-on-pattern",
+        "FATAL",
+        "--pattern-stream",
+        "stdout",
+        "1",
+        "sh",
+        "-c",
+        "echo 'FATAL: on stderr' >&2; sleep 10",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stderr(predicate::str::contains("FATAL: on stderr"))
+        .stderr(predicate::str::contains("output matched").not());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_kill_on_pattern_and_until_pattern_first_match_wins() {
+    let script = |first: &str, second: &str| {
+        let mut cmd = Command::cargo_bin("timeout").unwrap();
+        cmd.args([
+            "--kill-on-pattern",
+            "FATAL",
+            "--pattern-exit-code",
+            "3",
+            "--until-pattern",
+            "ready",
+            "30",
+            "sh",
+            "-c",
+            &format!("echo {first}; echo {second}; sleep 30"),
+        ]);
+        cmd
+    };
+
+    script("FATAL", "ready").assert().code(3);
+    script("ready", "FATAL").assert().code(0);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_then_shares_one_budget_between_commands() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["2", "sleep", "1", "--then", "sleep", "10"]);
+
+    cmd.assert().code(124);
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(1900), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(4), "{elapsed:?}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_then_stops_at_the_first_failure() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "5",
+        "echo",
+        "one",
+        "--then",
+        "sh",
+        "-c",
+        "exit 3",
+        "--then",
+        "echo",
+        "unreachable",
+    ]);
+
+    cmd.assert().code(3).stdout("one\n");
+}
+
+#[test]
+fn test_then_without_a_following_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["5", "echo", "one", "--then"]);
+
+    cmd.assert()
+        .code(125)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "--then must be between two commands",
+        ));
+}
+
+#[test]
+fn test_then_is_passed_through_after_exec() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["5", "--exec", "echo", "a", "--then", "b"]);
+
+    cmd.assert().success().stdout("a --then b\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_exec_replace_runs_the_command_in_place_of_timeout() {
+    let child = Command::cargo_bin("timeout")
+        .unwrap()
+        .args(["--exec-replace", "0", "sh", "-c", "echo $$; exit 7"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+    let output = child.wait_with_output().unwrap();
+    // The shell has timeout's own PID.
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{pid}\n")
+    );
+}
+
+#[test]
+fn test_exec_replace_command_not_found() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--exec-replace", "0", "nonexistent_command_12345"]);
+
+    cmd.assert().code(127).stderr(predicate::str::contains(
+        "failed to run command 'nonexistent_command_12345'",
+    ));
+}
+
+#[test]
+fn test_exec_replace_rejects_a_timeout() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--exec-replace", "1", "sleep", "3"]);
+
+    cmd.assert().code(125).stderr(predicate::str::contains(
+        "--exec-replace enforces no timeout, so SECONDS must be 0, not 1",
+    ));
+}
+
+#[test]
+fn test_exec_replace_conflicts_with_supervision() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--exec-replace", "--kill-after", "1", "5", "true"]);
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "'--exec-replace' cannot be used with",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tee_copies_stdout_to_a_file() {
+    let dir = std::env::temp_dir().join(format!("timeout-tee-{}", std::process::id()));
+    let path = dir.join("logs").join("out.log");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--tee").arg(&path).args([
+        "--create-dirs",
+        "5",
+        "sh",
+        "-c",
+        "echo one; echo two >&2; echo three; printf 'no newline'",
+    ]);
+
+    let output = cmd.assert().success().get_output().clone();
+    let contents = std::fs::read(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(output.stdout, b"one\nthree\nno newline");
+    assert_eq!(contents, output.stdout);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tee_keeps_partial_output_when_the_command_times_out() {
+    let path = std::env::temp_dir().join(format!("timeout-tee-partial-{}", std::process::id()));
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--tee")
+        .arg(&path)
+        .arg("--tee-stderr")
+        .arg(&path)
+        .args([
+            "1",
+            "sh",
+            "-c",
+            "echo out; echo err >&2; printf partial; exec sleep 10",
+        ]);
+
+    cmd.assert().code(124);
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    // The two streams are relayed by separate threads, so only the order within each is fixed.
+    assert!(contents.contains("err\n"), "{contents:?}");
+    assert_eq!(
+        contents.replacen("err\n", "", 1),
+        "out\npartial\n[output truncated by timeout]\n"
+    );
+}
+
+#[test]
+fn test_tee_missing_directory() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--tee", "/nonexistent/dir/out.log", "5", "true"]);
+
+    cmd.assert().code(125).stderr(predicate::str::contains(
+        "cannot open tee file '/nonexistent/dir/out.log'",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_timestamps_relative_increase_with_the_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--timestamps=relative",
+        "5",
+        "sh",
+        "-c",
+        "echo first; sleep 0.5; echo second; sleep 0.5; echo third",
+    ]);
+
+    let output = cmd.assert().success().get_output().clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stamps: Vec<f64> = stdout
+        .lines()
+        .map(|line| {
+            let stamp = line
+                .strip_prefix("[+")
+                .and_then(|rest| rest.split_once("s] "))
+                .unwrap_or_else(|| panic!("unstamped line {line:?}"));
+            stamp.0.parse().unwrap()
+        })
+        .collect();
+    assert_eq!(stamps.len(), 3, "{stdout}");
+    assert!(stamps[0] < 0.4, "{stdout}");
+    assert!(stamps[1] - stamps[0] >= 0.45, "{stdout}");
+    assert!(stamps[2] - stamps[1] >= 0.45, "{stdout}");
+    assert!(stdout.ends_with("s] third\n"), "{stdout}");
+}
+
+#[test]
+fn test_timestamps_absolute() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--timestamps", "5", "echo", "hello"]);
+
+    let output = cmd.assert().success().get_output().clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // e.g. "[12:31:05.123] hello"
+    let (stamp, line) = stdout.split_once("] ").unwrap();
+    assert_eq!(line, "hello\n");
+    let stamp = stamp.strip_prefix('[').unwrap();
+    assert_eq!(stamp.len(), 12, "{stdout}");
+    assert_eq!(stamp.matches(':').count(), 2, "{stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_prefix_on_both_streams() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--prefix",
+        "[db] ",
+        "--prefix-stderr",
+        "[db!] ",
+        "5",
+        "sh",
+        "-c",
+        "echo out; echo err >&2; printf '10%%\\r100%%\\n'",
+    ]);
+
+    cmd.assert()
+        .success()
+        .stdout("[db] out\n[db] 10%\r[db] 100%\n")
+        .stderr("[db!] err\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_prefix_on_partial_line_of_killed_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--prefix",
+        "[job] ",
+        "1",
+        "sh",
+        "-c",
+        "echo done; printf partial; exec sleep 5",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stdout("[job] done\n[job] partial\n[output truncated by timeout]\n");
+}
+
+#[test]
+fn test_prefix_follows_timestamp() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--timestamps=relative",
+        "--prefix",
+        "[job] ",
+        "5",
+        "echo",
+        "hello",
+    ]);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::is_match(r"^\[\+\d+\.\d{3}s\] \[job\] hello\n$").unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_kill_after_with_signal() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--kill-after",
+        "1:QUIT",
+        "1",
+        "sh",
+        "-c",
+        "trap '' TERM; exec sleep 10",
+    ]);
+
+    // Exits as the command did, killed by SIGQUIT (128+3)
+    cmd.assert().code(131);
+}
+
+#[test]
+fn test_kill_after_with_unknown_signal() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--kill-after", "1:NOPE", "1", "true"]);
+
+    cmd.assert()
+        .code(2)
+        .stderr(predicate::str::contains("unknown signal 'NOPE'"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_combine_output_keeps_write_order() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--combine-output",
+        "5",
+        "sh",
+        "-c",
+        "for i in 1 2 3; do echo out $i; echo err $i >&2; done",
+    ]);
+
+    cmd.assert()
+        .success()
+        .stdout("out 1\nerr 1\nout 2\nerr 2\nout 3\nerr 3\n")
+        .stderr("");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_combine_output_with_prefix_and_tee() {
+    let path = std::env::temp_dir().join(format!("timeout-combine-{}", std::process::id()));
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--combine-output")
+        .arg("--prefix")
+        .arg("[job] ")
+        .arg("--tee")
+        .arg(&path)
+        .args(["5", "sh", "-c", "echo one; echo two >&2; echo three"]);
+
+    cmd.assert()
+        .success()
+        .stdout("[job] one\n[job] two\n[job] three\n")
+        .stderr("");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\nthree\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_discard_output_keeps_exit_code() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--discard-output",
+        "5",
+        "sh",
+        "-c",
+        "echo out; echo err >&2; exit 3",
+    ]);
+
+    cmd.assert().code(3).stdout("").stderr("");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_discard_output_on_timeout() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--discard-output",
+        "1",
+        "sh",
+        "-c",
+        "echo out; echo err >&2; exec sleep 10",
+    ]);
+
+    cmd.assert().code(124).stdout("").stderr("");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_discard_one_stream() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--discard-stdout",
+        "5",
+        "sh",
+        "-c",
+        "echo out; echo err >&2",
+    ]);
+
+    cmd.assert().success().stdout("").stderr("err\n");
+}
+
+#[test]
+fn test_discard_output_conflicts_with_tee() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--discard-output", "--tee", "out.log", "5", "true"]);
+
+    cmd.assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_race_first_to_finish_wins() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "10",
+        "--race",
+        "sleep 10",
+        "---",
+        "sh",
+        "-c",
+        "echo fast; exit 3",
+    ]);
+
+    let start = std::time::Instant::now();
+    cmd.assert()
+        .code(3)
+        .stdout("[2] fast\n")
+        .stderr(predicate::str::contains("timeout: race: command 2 won, "))
+        .stderr(predicate::str::contains(
+            "exit 3: sh -c 'echo fast; exit 3'",
+        ));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_race_for_success_skips_failures() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "10",
+        "--race=success",
+        "false",
+        "---",
+        "sh -c 'sleep 0.5; echo ok'",
+    ]);
+
+    cmd.assert()
+        .success()
+        .stdout("[2] ok\n")
+        .stderr(predicate::str::contains("timeout: race: command 2 won"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_race_without_a_success_times_out() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["1", "--race=success", "false", "---", "sleep 10"]);
+
+    cmd.assert().code(124).stderr(predicate::str::contains(
+        "timeout: race: no command succeeded",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdout_file_and_stderr_file() {
+    let dir = std::env::temp_dir().join(format!("timeout-stdout-file-{}", std::process::id()));
+    let stdout_path = dir.join("out.log");
+    let stderr_path = dir.join("err.log");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--stdout-file")
+        .arg(&stdout_path)
+        .arg("--stderr-file")
+        .arg(&stderr_path)
+        .args(["--create-dirs", "5", "sh", "-c", "echo out; echo err >&2"]);
+
+    cmd.assert().success().stdout("").stderr("");
+    let stdout = std::fs::read_to_string(&stdout_path).unwrap();
+    let stderr = std::fs::read_to_string(&stderr_path).unwrap();
+    let mode = std::os::unix::fs::PermissionsExt::mode(
+        &std::fs::metadata(&stdout_path).unwrap().permissions(),
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(stdout, "out\n");
+    assert_eq!(stderr, "err\n");
+    // Less whatever the umask takes away.
+    assert_eq!(mode & 0o777 & !0o644, 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdout_file_append() {
+    let path = std::env::temp_dir().join(format!("timeout-append-keep-{}", std::process::id()));
+    std::fs::write(&path, "before\n").unwrap();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--append")
+        .arg("--stdout-file")
+        .arg(&path)
+        .args(["5", "echo", "after"]);
+
+    cmd.assert().success();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(contents, "before\nafter\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdout_file_and_stderr_file_share_a_path() {
+    let path = std::env::temp_dir().join(format!("timeout-shared-file-{}", std::process::id()));
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--stdout-file")
+        .arg(&path)
+        .arg("--stderr-file")
+        .arg(&path)
+        .args(["5", "sh", "-c", "echo 1; echo 2 >&2; echo 3; echo 4 >&2"]);
+
+    cmd.assert().success();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    // One handle, so nothing is overwritten and the write order is kept.
+    assert_eq!(contents, "1\n2\n3\n4\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdout_file_cannot_be_opened() {
+    let marker = std::env::temp_dir().join(format!("timeout-not-run-{}", std::process::id()));
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--stdout-file", "/nonexistent/dir/out.log", "5", "touch"])
+        .arg(&marker);
+
+    cmd.assert().code(125).stderr(predicate::str::contains(
+        "cannot open output file '/nonexistent/dir/out.log'",
+    ));
+    assert!(!marker.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdin_file() {
+    let path = std::env::temp_dir().join(format!("timeout-stdin-file-{}", std::process::id()));
+    std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+    let mut cat = Command::cargo_bin("timeout").unwrap();
+    cat.arg("--stdin-file").arg(&path).args(["5", "cat"]);
+    let mut wc = Command::cargo_bin("timeout").unwrap();
+    wc.arg("--stdin-file").arg(&path).args(["5", "wc", "-l"]);
+
+    cat.assert().success().stdout("one\ntwo\nthree\n");
+    wc.assert()
+        .success()
+        .stdout(predicate::str::is_match(r"^\s*3\n$").unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_no_stdin() {
+    let mut cat = assert_cmd::Command::cargo_bin("timeout").unwrap();
+    cat.args(["--no-stdin", "5", "cat"])
+        .write_stdin("ignored\n");
+    let mut wc = assert_cmd::Command::cargo_bin("timeout").unwrap();
+    wc.args(["--no-stdin", "5", "wc", "-l"])
+        .write_stdin("ignored\n");
+
+    cat.assert().success().stdout("");
+    wc.assert()
+        .success()
+        .stdout(predicate::str::is_match(r"^\s*0\n$").unwrap());
+}
+
+#[test]
+fn test_stdin_file_cannot_be_opened() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--stdin-file", "/nonexistent/input.txt", "5", "echo", "ran"]);
+
+    cmd.assert()
+        .code(125)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "cannot open stdin file '/nonexistent/input.txt'",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_relayed_output_into_a_closed_pipe() {
+    // `yes` never stops by itself: it has to get SIGPIPE once head has its line and exits.
+    let start = std::time::Instant::now();
+    let output = Command::new("sh")
+        .args(["-c", r#""$0" --prefix '> ' 10 yes | head -n1"#])
+        .arg(assert_cmd::cargo::cargo_bin("timeout"))
+        .output()
+        .unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(output.stdout, b"> y\n");
+    assert!(
+        !String::from_utf8_lossy(&output.stderr).contains("panicked"),
+        "{:?}",
+        output.stderr
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_timeout_still_enforced_after_the_output_pipe_closes() {
+    use std::io::Read;
+
+    let mut child = Command::cargo_bin("timeout")
+        .unwrap()
+        .args([
+            "--timestamps",
+            "1",
+            "sh",
+            "-c",
+            "echo first; sleep 0.2; echo second; exec sleep 10",
+        ])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut first = [0u8; 1];
+    child.stdout.take().unwrap().read_exact(&mut first).unwrap();
+
+    // Our end of the pipe is closed now; the command ignores it and has to be timed out.
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), Some(124));
+}
+
+#[test]
+fn test_stdin_string() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--stdin-string", "line one\nline two", "5", "cat"]);
+
+    cmd.assert().success().stdout("line one\nline two");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdin_string_file() {
+    let path = std::env::temp_dir().join(format!("timeout-stdin-string-{}", std::process::id()));
+    std::fs::write(&path, "from a file\n").unwrap();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--stdin-string-file").arg(&path).args(["5", "cat"]);
+
+    cmd.assert().success().stdout("from a file\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdin_string_ignored_by_the_command() {
+    // More than a pipe holds, to a command that exits without reading any of it.
+    let path = std::env::temp_dir().join(format!("timeout-stdin-ignored-{}", std::process::id()));
+    std::fs::write(&path, "x".repeat(1 << 20)).unwrap();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--stdin-string-file")
+        .arg(&path)
+        .args(["5", "sh", "-c", "echo done"]);
+    cmd.assert().success().stdout("done\n");
+
+    // Or never exits at all, and is timed out as usual.
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--stdin-string-file")
+        .arg(&path)
+        .args(["1", "sleep", "10"]);
+    cmd.assert().code(124);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_report_line() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--report", "5", "true"]);
+    cmd.assert()
+        .success()
+        .stderr("timeout: report: reason=completed exit=0\n");
+
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--report", "5", "nonexistent_command_12345"]);
+    cmd.assert().code(127).stderr(predicate::str::ends_with(
+        "timeout: report: reason=not_found exit=127\n",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_report_line_on_timeout() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--report", "1", "sleep", "10"]);
+
+    cmd.assert()
+        .code(124)
+        .stderr("timeout: report: reason=timed_out exit=124\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pty_gives_the_command_a_terminal() {
+    let script = "if [ -t 0 ] && [ -t 1 ] && [ -t 2 ]; then echo tty; else echo no tty; fi";
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["5", "sh", "-c", script]);
+    cmd.assert().success().stdout("no tty\n");
+
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--pty", "5", "sh", "-c", script]);
+    // The terminal ends each line with a carriage return too.
+    cmd.assert().success().stdout("tty\r\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pty_passes_input_on_and_ends_it() {
+    let mut cmd = assert_cmd::Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--pty",
+        "5",
+        "sh",
+        "-c",
+        "read line; echo \"got $line\"; cat",
+    ])
+    .write_stdin("hello\n");
+
+    // The terminal echoes the input back, as it would to someone typing it.
+    cmd.assert().success().stdout("hello\r\ngot hello\r\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pty_timeout_and_hangup() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--pty",
+        "1",
+        "sh",
+        "-c",
+        "echo started; trap '' TERM; exec sleep 10",
+    ]);
+    cmd.assert().code(124).stdout("started\r\n");
+
+    // A process left behind on the terminal is hung up on when the command exits.
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--pty", "5", "sh", "-c", "sleep 30 & echo started"]);
+    cmd.assert().success().stdout("started\r\n");
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+/// Open a pseudo-terminal for timeout itself to run on, returning its master and slave ends.
+#[cfg(unix)]
+fn open_terminal() -> (std::fs::File, std::fs::File) {
+    use std::os::unix::io::FromRawFd;
+
+    let (mut master, mut slave) = (-1, -1);
+    // SAFETY: openpty only fills in the two descriptors, which nothing else owns.
+    unsafe {
+        let result = libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        );
+        assert_eq!(result, 0);
+        (
+            std::fs::File::from_raw_fd(master),
+            std::fs::File::from_raw_fd(slave),
+        )
+    }
+}
+
+/// The input, output and local mode flags of the terminal `file` is open on.
+#[cfg(unix)]
+fn terminal_modes(file: &std::fs::File) -> [libc::tcflag_t; 3] {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: tcgetattr only writes the termios given.
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    assert_eq!(
+        unsafe { libc::tcgetattr(file.as_raw_fd(), &mut termios) },
+        0
+    );
+    [termios.c_iflag, termios.c_oflag, termios.c_lflag]
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pty_puts_the_terminal_back() {
+    let (_master, terminal) = open_terminal();
+    let before = terminal_modes(&terminal);
+    let timeout = |args: &[&str]| {
+        Command::cargo_bin("timeout")
+            .unwrap()
+            .args(args)
+            .stdin(terminal.try_clone().unwrap())
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap()
+    };
+
+    // A full-screen program that ignores TERM, is killed, and never gets to tidy up.
+    let mut child = timeout(&[
+        "--pty",
+        "--kill-after",
+        "1",
+        "1",
+        "sh",
+        "-c",
+        "stty raw -echo; trap '' TERM; exec sleep 10",
+    ]);
+    assert_eq!(child.wait().unwrap().code(), Some(137));
+    assert_eq!(terminal_modes(&terminal), before);
+
+    // timeout itself stopped while its terminal is in raw mode.
+    let mut child = timeout(&["--pty", "10", "sleep", "10"]);
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while terminal_modes(&terminal)[2] & libc::ICANON != 0 {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "terminal never went raw"
+        );
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    // SAFETY: kill only sends a signal.
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    child.wait().unwrap();
+    assert_eq!(terminal_modes(&terminal), before);
+}
+
+/// Wait up to five seconds for `path` to be written, and return what it holds.
+#[cfg(unix)]
+fn wait_for_file(path: &std::path::Path) -> String {
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while std::time::Instant::now() < deadline {
+        if let Ok(contents) = std::fs::read_to_string(path)
+            && !contents.is_empty()
+        {
+            return contents;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    panic!("{} was never written", path.display());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_detach_returns_at_once_and_carries_on() {
+    let dir = std::env::temp_dir().join(format!("timeout-detach-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--detach")
+        .arg("--stdout-file")
+        .arg(dir.join("out.log"))
+        .arg("--result-file")
+        .arg(dir.join("result.json"))
+        .args(["5", "sh", "-c", "sleep 1; echo done"]);
+
+    cmd.assert().success().stdout("").stderr("");
+    assert!(start.elapsed() < Duration::from_secs(1));
+    let result = wait_for_file(&dir.join("result.json"));
+    let output = std::fs::read_to_string(dir.join("out.log")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(result.contains(r#""reason":"completed""#), "{result}");
+    assert_eq!(output, "done\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_detach_still_enforces_the_timeout() {
+    let path = std::env::temp_dir().join(format!("timeout-detach-{}.json", std::process::id()));
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--detach")
+        .arg("--result-file")
+        .arg(&path)
+        .args(["1", "sleep", "30"]);
+
+    cmd.assert().success();
+    let result = wait_for_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.contains(r#""reason":"timed_out""#), "{result}");
+}
+
+#[test]
+fn test_detach_reports_failures_before_detaching() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--detach",
+        "--stdout-file",
+        "/nonexistent/dir/out.log",
+        "5",
+        "true",
+    ]);
+
+    cmd.assert().code(125).stderr(predicate::str::contains(
+        "cannot open output file '/nonexistent/dir/out.log'",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_detach_reports_an_unknown_user() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--detach", "--user", "nosuchuser", "5", "true"]);
+
+    cmd.assert()
+        .code(125)
+        .stderr(predicate::str::contains("no such user 'nosuchuser'"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_detach_reports_an_unusable_pid_file() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--detach", "--pid-file", "/nonexistent/dir/p", "5", "true"]);
+
+    cmd.assert().code(125).stderr(predicate::str::contains(
+        "cannot use pid file '/nonexistent/dir/p'",
+    ));
+}
+
+#[test]
+#[cfg(windows)]
+fn test_kill_after_kills_command_surviving_ctrl_break() {
+    // ping prints its statistics on Ctrl-Break and carries on, so only the kill stops it
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--kill-after", "1", "1", "ping", "-n", "30", "127.0.0.1"]);
+
+    let start = std::time::Instant::now();
+    cmd.assert().code(137);
+    let elapsed = start.elapsed();
+    assert!(
+        elapsed >= Duration::from_secs(2),
+        "Killed too early: {:?}",
+        elapsed
+    );
+    assert!(
+        elapsed < Duration::from_secs(10),
+        "Not killed: {:?}",
+        elapsed
+    );
+}
+
+#[test]
+#[cfg(windows)]
+fn test_timeout_without_kill_after_terminates_on_windows() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["1", "ping", "-n", "30", "127.0.0.1"]);
+
+    let start = std::time::Instant::now();
+    cmd.assert().code(124);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_tail_on_timeout_prints_the_last_lines() {
+    let script = "i=1; while [ $i -le 500 ]; do echo line $i; i=$((i + 1)); done; exec sleep 10";
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--tail-on-timeout", "100", "1", "sh", "-c", script]);
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(124));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 500);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let expected: String = (401..=500).map(|i| format!("line {}\n", i)).collect();
+    let block = format!(
+        "timeout: ---- last 100 lines before timeout ----\n{}timeout: ---- end of output ----\n",
+        expected
+    );
+    assert!(stderr.contains(&block), "no tail block in: {}", stderr);
+
+    // Nothing is printed when the command finishes in time.
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--tail-on-timeout", "100", "5", "sh", "-c", "echo done"]);
+    cmd.assert().success().stdout("done\n").stderr("");
+}
+
+#[test]
+fn test_quiet_success_holds_output_back_unless_the_command_fails() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--quiet-success", "5", "sh", "-c", "echo out; echo err >&2"]);
+    cmd.assert().success().stdout("").stderr("");
+
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--quiet-success",
+        "5",
+        "sh",
+        "-c",
+        "echo out; echo err >&2; exit 3",
+    ]);
+    cmd.assert().code(3).stdout("out\n").stderr("err\n");
+
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--quiet-success",
+        "1",
+        "sh",
+        "-c",
+        "echo started; exec sleep 10",
+    ]);
+    cmd.assert().code(124).stdout("started\n");
+
+    // An exit code treated as success is kept quiet too.
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--quiet-success",
+        "--success-codes",
+        "3",
+        "5",
+        "sh",
+        "-c",
+        "echo out; exit 3",
+    ]);
+    cmd.assert().success().stdout("");
+}
+
+#[test]
+fn test_until_pattern_finishes_successfully_on_a_match() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--until-pattern",
+        "^listening on",
+        "10",
+        "sh",
+        "-c",
+        "echo starting; echo listening on :8080; exec sleep 10",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout("starting\nlistening on :8080\n");
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // Without a match the timeout applies as usual.
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--until-pattern",
+        "^listening on",
+        "1",
+        "sh",
+        "-c",
+        "echo starting; exec sleep 10",
+    ]);
+    cmd.assert().code(124).stdout("starting\n");
+
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--until-pattern", "(", "1", "true"]);
+    cmd.assert()
+        .code(125)
+        .stderr(predicate::str::contains("invalid --until-pattern '('"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_until_detach_leaves_the_command_running() {
+    let path = std::env::temp_dir().join(format!("timeout-until-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let script = format!("echo ready; sleep 1; echo done >'{}'", path.display());
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--until-pattern",
+        "ready",
+        "--until-detach",
+        "10",
+        "sh",
+        "-c",
+        &script,
+    ]);
+    cmd.assert().success().stdout("ready\n");
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(!path.exists());
+    assert_eq!(wait_for_file(&path), "done\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_shield_passes_signals_on_and_reports_the_exit_code() {
+    use std::io::Read;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Stdio;
+
+    let script = "trap 'echo got INT; exit 3' INT; echo ready; sleep 10 & wait";
+    let start = |args: &[&str]| {
+        let mut child = Command::cargo_bin("timeout")
+            .unwrap()
+            .args(args)
+            .args(["10", "sh", "-c", script])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut ready = [0u8; 6];
+        child
+            .stdout
+            .as_mut()
+            .unwrap()
+            .read_exact(&mut ready)
+            .unwrap();
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+        child
+    };
+
+    let child = start(&["--shield"]);
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(output.stdout, b"got INT\n");
+
+    // Unshielded, timeout itself is stopped by the signal, or stops the command as if it had
+    // timed out when it catches signals for other reasons.
+    let mut child = start(&[]);
+    assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGINT));
+    let mut child = start(&["--shield=TERM"]);
+    assert_eq!(child.wait().unwrap().code(), Some(124));
+}
+
+fn epoch_in(offset: f64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap();
+    format!("{:.3}", now.as_secs_f64() + offset)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_deadline_epoch_cuts_the_timeout_short() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.env("DEADLINE_EPOCH", epoch_in(1.0))
+        .args(["30", "sleep", "10"]);
+
+    cmd.assert().code(124);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_deadline_epoch_in_the_past_has_expired() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.env("DEADLINE_EPOCH", epoch_in(-60.0))
+        .args(["5", "sleep", "1"]);
+
+    cmd.assert().code(124);
+}
+
+#[test]
+fn test_zero_timeout_times_out_the_started_command() {
+    let output = Command::cargo_bin("timeout")
+        .unwrap()
+        .args(["--json", "0", "sleep", "5"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(124));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("timed out starting"), "{}", stderr);
+    let json: serde_json::Value = serde_json::from_str(stderr.trim_end()).unwrap();
+    assert_eq!(json["reason"], "timed_out");
+    assert!(json["pid"].is_u64(), "{}", json);
+}
+
+#[test]
+fn test_deadline_epoch_in_the_past_times_out_the_started_command() {
+    let output = Command::cargo_bin("timeout")
+        .unwrap()
+        .env("DEADLINE_EPOCH", epoch_in(-60.0))
+        .args(["--json", "5", "sleep", "5"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(124));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("timed out starting"), "{}", stderr);
+    let json: serde_json::Value = serde_json::from_str(stderr.trim_end()).unwrap();
+    assert_eq!(json["reason"], "timed_out");
+    assert!(json["pid"].is_u64(), "{}", json);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_deadline_epoch_is_handed_down_to_the_command() {
+    let before: f64 = epoch_in(0.0).parse().unwrap();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.env_remove("DEADLINE_EPOCH")
+        .args(["5", "sh", "-c", "echo $DEADLINE_EPOCH"]);
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let deadline: f64 = String::from_utf8(output).unwrap().trim().parse().unwrap();
+    assert!(
+        deadline >= before + 5.0 && deadline < before + 10.0,
+        "{deadline}"
+    );
+}
+
+#[test]
+fn test_deadline_epoch_that_is_not_a_number_is_ignored() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.env("DEADLINE_EPOCH", "soon").args(["5", "true"]);
+
+    cmd.assert().success().stderr(predicate::str::contains(
+        "timeout: warning: invalid DEADLINE_EPOCH 'soon', ignoring it",
+    ));
+}
+
+/// A local port nothing is listening on, for now.
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[test]
+fn test_until_port_finishes_once_the_port_opens() {
+    let port = free_port();
+    let listener = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(500));
+        let listener = std::net::TcpListener::bind(("127.0.0.1", port)).unwrap();
+        std::thread::sleep(Duration::from_secs(5));
+        drop(listener);
+    });
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--until-port",
+        &format!("127.0.0.1:{port}"),
+        "--until-port-interval",
+        "0.1",
+        "--json",
+        "30",
+        "sleep",
+        "30",
+    ]);
+
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("\"reason\":\"port_open\""));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    listener.join().unwrap();
+}
+
+#[test]
+fn test_until_port_tries_every_address_of_a_host() {
+    // localhost may resolve to ::1 before 127.0.0.1; only the latter is listening.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--until-port",
+        &format!("localhost:{port}"),
+        "30",
+        "sleep",
+        "30",
+    ]);
+
+    cmd.assert().success();
+}
+
+#[test]
+fn test_until_port_accepts_ipv6_literals() {
+    let Ok(listener) = std::net::TcpListener::bind("[::1]:0") else {
+        return;
+    };
+    let port = listener.local_addr().unwrap().port();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--until-port",
+        &format!("[::1]:{port}"),
+        "30",
+        "sleep",
+        "30",
+    ]);
+
+    cmd.assert().success();
+}
+
+#[test]
+fn test_until_port_that_never_opens_times_out() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--until-port",
+        &format!("127.0.0.1:{}", free_port()),
+        "1",
+        "sleep",
+        "10",
+    ]);
+
+    cmd.assert().code(124);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_until_port_passes_on_a_failure_before_the_port_opens() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--until-port",
+        &format!("127.0.0.1:{}", free_port()),
+        "10",
+        "sh",
+        "-c",
+        "exit 3",
+    ]);
+
+    cmd.assert().code(3);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_until_port_with_until_detach_leaves_the_command_running() {
+    let path = std::env::temp_dir().join(format!("timeout-until-port-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--until-port",
+        &format!("127.0.0.1:{port}"),
+        "--until-detach",
+        "30",
+        "sh",
+        "-c",
+        // Let go of the test's pipes, which would otherwise keep it waiting for the command.
+        &format!(
+            "exec >/dev/null 2>&1; sleep 1; echo done >'{}'",
+            path.display()
+        ),
+    ]);
+
+    cmd.assert().success();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(wait_for_file(&path), "done\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_progress_reports_the_time_left() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--progress", "0.4", "1", "sleep", "5"]);
+
+    cmd.assert().code(124).stderr(predicate::str::contains(
+        "timeout: progress: 0.4s elapsed, 0.6s left of 1.0s",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_progress_stops_when_the_command_exits() {
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--progress", "10", "60", "sleep", "0.2"]);
+
+    cmd.assert().success().stderr("");
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+fn sentinel(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("timeout-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[cfg(unix)]
+#[test]
+fn test_until_file_that_already_exists_finishes_straight_away() {
+    let path = sentinel("until-file-before");
+    std::fs::write(&path, "").unwrap();
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--until-file")
+        .arg(&path)
+        .args(["30", "sleep", "30"]);
+
+    cmd.assert().success();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_until_file_finishes_once_the_command_creates_it() {
+    let path = sentinel("until-file-during");
+    let script = format!("sleep 0.5; touch '{}'; exec sleep 30", path.display());
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--until-file")
+        .arg(&path)
+        .args(["--json", "30", "sh", "-c", &script]);
+
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("\"reason\":\"file_exists\""));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_until_file_that_never_appears() {
+    let path = sentinel("until-file-never");
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--until-file")
+        .arg(&path)
+        .args(["1", "sleep", "10"]);
+    cmd.assert().code(124);
+
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--until-file")
+        .arg(&path)
+        .args(["10", "sh", "-c", "exit 3"]);
+    cmd.assert().code(3);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_until_file_gone_finishes_once_the_file_is_removed() {
+    let path = sentinel("until-file-gone");
+    std::fs::write(&path, "1234\n").unwrap();
+    let script = format!("sleep 0.5; rm '{}'; exec sleep 30", path.display());
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--until-file-gone")
+        .arg(&path)
+        .args(["--json", "30", "sh", "-c", &script]);
+
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("\"reason\":\"file_gone\""));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_healthcheck_stops_the_command_once_it_keeps_failing() {
+    let path = sentinel("healthcheck");
+    std::fs::write(&path, "").unwrap();
+    let probe = format!("echo probing; test -f '{}'", path.display());
+    let script = format!("sleep 1; rm '{}'; exec sleep 30", path.display());
+    let start = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--healthcheck-cmd",
+        &probe,
+        "--healthcheck-interval",
+        "0.2",
+        "--healthcheck-failures",
+        "2",
+        "--json",
+        "30",
+        "sh",
+        "-c",
+        &script,
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stdout("")
+        .stderr(predicate::str::contains(format!(
+            "timeout: health check failed 2 times in a row, stopping the command: {probe}"
+        )))
+        .stderr(predicate::str::contains("\"reason\":\"unhealthy\""));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_healthcheck_that_passes_leaves_the_command_alone() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--healthcheck-cmd",
+        "true",
+        "--healthcheck-interval",
+        "0.1",
+        "--healthcheck-failures",
+        "1",
+        "10",
+        "sh",
+        "-c",
+        "sleep 1; exit 3",
+    ]);
+
+    cmd.assert().code(3);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_print_signal_sent() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--print-signal-sent",
+        "--kill-after",
+        "1",
+        "1",
+        "sh",
+        "-c",
+        "trap '' TERM; exec sleep 10",
+    ]);
+
+    cmd.assert()
+        .code(137)
+        .stderr(predicate::str::is_match(r"timeout: sent SIGTERM \(15\) to PID \d+\n").unwrap())
+        .stderr(predicate::str::is_match(r"timeout: sent SIGKILL \(9\) to PID \d+\n").unwrap());
+}
+
+#[test]
+fn test_min_runtime_fails_an_exit_that_comes_too_soon() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--min-runtime", "1", "--report", "5", "true"]);
+
+    cmd.assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "timeout: warning: command exited with 0 after",
+        ))
+        .stderr(predicate::str::contains(
+            "before the minimum runtime of 1.0s",
+        ))
+        .stderr(predicate::str::contains(
+            "timeout: report: reason=premature_exit exit=1",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_min_runtime_leaves_a_long_enough_run_alone() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--min-runtime",
+        "1",
+        "--min-runtime-exit-code",
+        "3",
+        "5",
+        "sleep",
+        "2",
+    ]);
+
+    cmd.assert().code(0).stderr("");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_min_runtime_exit_is_retried_with_its_exit_code() {
+    let marker = sentinel("min-runtime-retry");
+    let script = format!(
+        "if [ -e '{0}' ]; then sleep 1.2; else touch '{0}'; fi",
+        marker.display()
+    );
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--min-runtime",
+        "1",
+        "--min-runtime-exit-code",
+        "3",
+        "--retries",
+        "1",
+        "--retry-on",
+        "3",
+        "5",
+        "sh",
+        "-c",
+        &script,
+    ]);
+
+    cmd.assert().code(0).stderr(predicate::str::contains(
+        "before the minimum runtime of 1.0s",
+    ));
+    let _ = std::fs::remove_file(marker);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_login_shell_reads_the_profile() {
+    let home = sentinel("login-shell-home");
+    let _ = std::fs::remove_dir_all(&home);
+    std::fs::create_dir(&home).unwrap();
+    std::fs::write(home.join(".profile"), "export GREETING='from profile'\n").unwrap();
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.env("HOME", &home).env("SHELL", "/bin/sh").args([
+        "--login-shell",
+        "5",
+        "sh",
+        "-c",
+        "echo \"$GREETING\"; exit 3",
+    ]);
+
+    cmd.assert().code(3).stdout("from profile\n");
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_login_shell_passes_arguments_through_untouched() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.env("SHELL", "/usr/bin/fish").args([
+        "--login-shell",
+        "5",
+        "printf",
+        "%s|",
+        "a b",
+        "$HOME",
+        "*",
+    ]);
+
+    cmd.assert().success().stdout("a b|$HOME|*|");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_expect_timeout_succeeds_when_the_command_times_out() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--expect-timeout", "--json", "1", "sleep", "10"]);
+
+    cmd.assert()
+        .code(0)
+        .stderr(predicate::str::contains("\"timed_out\":true"))
+        .stderr(predicate::str::contains("\"reason\":\"timed_out\""));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_expect_timeout_still_escalates_to_kill() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--expect-timeout",
+        "--kill-after",
+        "1",
+        "--raw-signal-exit",
+        "1",
+        "sh",
+        "-c",
+        "trap '' TERM; exec sleep 10",
+    ]);
+
+    cmd.assert().code(0);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_expect_timeout_fails_when_the_command_finishes() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--expect-timeout", "--json", "5", "sh", "-c", "exit 3"]);
+
+    cmd.assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "timeout: expected a timeout, but the command exited with 3 after",
+        ))
+        .stderr(predicate::str::contains("\"exit_code\":3"))
+        .stderr(predicate::str::contains("\"timed_out\":false"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_soft_timeout_warns_and_leaves_the_command_running() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--soft", "0.5", "5", "sh", "-c", "sleep 1; echo done"]);
+
+    cmd.assert().code(0).stdout("done\n").stderr(
+        "timeout: warning: 'sh' is still running after its soft timeout of 0.5s, it will be stopped at 5.0s\n",
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_soft_timeout_says_nothing_when_the_command_finishes_first() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--soft", "2", "5", "sleep", "0.2"]);
+
+    cmd.assert().code(0).stderr("");
+}
+
+#[test]
+fn test_soft_timeout_must_come_before_the_timeout() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--soft", "5", "5", "true"]);
+
+    cmd.assert()
+        .code(125)
+        .stderr(predicate::str::contains("--soft must be less than SECONDS"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_warn_at_fires_each_threshold_in_order() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--warn-at",
+        "50%",
+        "--warn-at",
+        "0.2s",
+        "--total-timeout",
+        "0.8",
+        "5",
+        "sleep",
+        "10",
+    ]);
+
+    cmd.assert().code(124).stderr(
+        "timeout: warning: 'sleep' has used 25% of its 0.8s budget (0.2s elapsed)\n\
+         timeout: warning: 'sleep' has used 50% of its 0.8s budget (0.4s elapsed)\n",
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_warn_at_says_nothing_when_the_command_finishes_first() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--warn-at", "50%", "--total-timeout", "0.8", "5", "true"]);
+
+    cmd.assert().code(0).stderr("");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_heartbeat_prints_a_line_each_interval() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--heartbeat", "1", "10", "sleep", "3"]);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let beats = stderr
+        .lines()
+        .filter(|line| line.starts_with("timeout: still running ("))
+        .count();
+    assert!((2..=3).contains(&beats), "{}", stderr);
+    assert!(stderr.contains("1.0s elapsed, 9.0s remaining, pid "));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_heartbeat_waits_for_a_relayed_line_to_end() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--heartbeat",
+        "0.2",
+        "--heartbeat-stream",
+        "stdout",
+        "--prefix",
+        "> ",
+        "--unbuffered",
+        "10",
+        "sh",
+        "-c",
+        "printf part; sleep 0.7; echo ' end'",
+    ]);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("> part end\n"), "{}", stdout);
+    assert!(
+        stdout
+            .lines()
+            .skip(1)
+            .all(|line| line.starts_with("timeout: still running (")),
+        "{}",
+        stdout
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_partial_line_of_a_timed_out_command_is_delivered_with_a_marker() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--quiet-success",
+        "1",
+        "sh",
+        "-c",
+        "echo started; printf 'half a line'; exec sleep 10",
+    ]);
+
+    cmd.assert()
+        .code(124)
+        .stdout("started\nhalf a line\n[output truncated by timeout]\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_umask_is_set_for_the_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--umask", "077", "5", "sh", "-c", "umask"]);
+
+    cmd.assert().success().stdout("0077\n");
+}
+
+#[test]
+fn test_invalid_umask_fails_before_running_the_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--umask", "089", "5", "echo", "ran"]);
+
+    cmd.assert()
+        .code(125)
+        .stdout("")
+        .stderr(predicate::str::contains("invalid umask '089'"));
+}
+
+#[test]
+fn test_progress_auto_is_silent_when_stderr_is_not_a_terminal() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args([
+        "--progress",
+        "auto",
+        "5",
+        "sh",
+        "-c",
+        "sleep 1.5; echo done",
+    ]);
+
+    cmd.assert().success().stdout("done\n").stderr("");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stats_are_printed_once_and_added_to_the_json_report() {
+    let output = Command::cargo_bin("timeout")
+        .unwrap()
+        .args(["--stats", "--json", "--json-to", "stdout", "--retries", "1"])
+        .args([
+            "5",
+            "sh",
+            "-c",
+            "x=$(head -c 4000000 /dev/zero | tr '\\0' a); exit 3",
+        ])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.matches("timeout: stats for 'sh':").count(),
+        1,
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("  timed out:        no\n"), "{}", stderr);
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json["usage"]["max_rss_bytes"].as_u64().unwrap() > 4_000_000);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stats_cover_a_timed_out_command() {
+    let output = Command::cargo_bin("timeout")
+        .unwrap()
+        .args(["--stats", "--json", "--json-to", "stdout"])
+        .args(["1", "sh", "-c", "exec sleep 10"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(124));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("  timed out:        yes\n"), "{}", stderr);
+    assert!(stderr.contains("  signals sent:     SIGTERM"), "{}", stderr);
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json["usage"]["max_rss_bytes"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn test_json_report_has_no_usage_without_stats() {
+    let (_, json) = json_summary(&["5", "sh", "-c", "exit 0"]);
+
+    assert!(json.get("usage").is_none());
+}
+
+#[test]
+fn test_debug_info_prints_build_details_and_exits() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.arg("--debug-info").env("TIMEOUT_KILL_AFTER", "5");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!(
+            "version: {}\nplatform: ",
+            env!("CARGO_PKG_VERSION")
+        )))
+        .stdout(predicate::str::contains("\npoll interval: 10ms\n"))
+        .stdout(predicate::str::contains("\nterm grace: 100ms\n"))
+        .stderr("");
+}
+
+#[test]
+fn test_debug_info_takes_no_command() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--debug-info", "5", "echo", "ran"]);
+
+    cmd.assert().code(2).stdout("");
+}
+
+#[test]
+fn test_time_prints_real_user_and_sys_like_the_shell() {
+    let mut cmd = Command::cargo_bin("timeout").unwrap();
+    cmd.args(["--time", "5", "sh", "-c", "exit 3"]);
+
+    cmd.assert().code(3).stderr(
+        predicate::str::is_match(
+            r"^\nreal\t\d+m\d+\.\d{3}s\nuser\t\d+m\d+\.\d{3}s\nsys\t\d+m\d+\.\d{3}s\n$",
+        )
+        .unwrap(),
+    );
+}
+
+#[test]
+fn test_time_posix_covers_a_timed_out_command() {
+    let output = Command::cargo_bin("timeout")
+        .unwrap()
+        .args(["--time=posix", "1", "sh", "-c", "exec sleep 10"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(124));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let times = regex::Regex::new(r"^real (\d+\.\d{2})\nuser (\d+\.\d{2})\nsys (\d+\.\d{2})\n$")
+        .unwrap()
+        .captures(&stderr)
+        .unwrap_or_else(|| panic!("unexpected output: {:?}", stderr));
+    let time = |i: usize| times[i].parse::<f64>().unwrap();
+    assert!(time(1) >= 1.0, "{}", stderr);
+    assert!(time(1) >= time(2), "{}", stderr);
+}